@@ -1,180 +1,392 @@
-use serde::Serialize;
+use std::cell::{Cell, RefCell};
 
-use crate::token::{RelopKind, TokenKind};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Serialize)]
-pub(crate) struct TranslationUnit {
-    pub(crate) external_declarations: Vec<ExternalDeclaration>,
+use crate::token::{RelopKind, Span, TokenKind};
+
+/// How AST nodes are serialized. The default leaves spans out and keeps
+/// identifiers as indices into the identifier table.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SerdeContext {
+    pub spans: bool,
+    /// Serialize identifiers as these names instead of as indices. Such
+    /// output cannot be deserialized.
+    pub names: Option<Vec<String>>,
+}
+
+/// How deeply expressions, statements, declarators and initializers may
+/// nest in a deserialized AST, so that a malformed one cannot overflow the
+/// stack.
+pub(crate) const MAX_DEPTH: usize = 512;
+
+thread_local! {
+    static CONTEXT: RefCell<SerdeContext> = RefCell::default();
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Run `f` with AST nodes serialized and deserialized as `context` says.
+pub(crate) fn with_context<T>(
+    context: SerdeContext,
+    f: impl FnOnce() -> T,
+) -> T {
+    let old = CONTEXT.with(|c| c.replace(context));
+    let t = f();
+    CONTEXT.with(|c| c.replace(old));
+    t
+}
+
+/// Run `f` with spans included when AST nodes are serialized or
+/// deserialized.
+pub fn with_spans<T>(f: impl FnOnce() -> T) -> T {
+    let context = SerdeContext {
+        spans: true,
+        names: None,
+    };
+    with_context(context, f)
+}
+
+fn spans() -> bool {
+    CONTEXT.with(|c| c.borrow().spans)
+}
+
+fn skip_span(_: &Span) -> bool {
+    !spans()
+}
+
+fn serialize_identifier<S: Serializer>(
+    id: &usize,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    CONTEXT.with(|c| match &c.borrow().names {
+        Some(names) => s.serialize_str(&names[*id]),
+        None => id.serialize(s),
+    })
+}
+
+struct IdentifierRef(usize);
+
+impl Serialize for IdentifierRef {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_identifier(&self.0, s)
+    }
+}
+
+/// Serialize a `(node, span)` variant as a newtype variant of `node` unless
+/// spans are included, like the `span` fields.
+fn serialize_spanned<S: Serializer, T: Serialize>(
+    t: &T,
+    span: &Span,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    if skip_span(span) {
+        t.serialize(s)
+    } else {
+        (t, span).serialize(s)
+    }
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum ExternalDeclaration {
-    FunctionDeclaration(FunctionDefinition),
+fn serialize_spanned_identifier<S: Serializer>(
+    id: &usize,
+    span: &Span,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    serialize_spanned(&IdentifierRef(*id), span, s)
+}
+
+fn deserialize_spanned<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    d: D,
+) -> Result<(T, Span), D::Error> {
+    if spans() {
+        <(T, Span)>::deserialize(d)
+    } else {
+        Ok((T::deserialize(d)?, Span::default()))
+    }
+}
+
+/// Deserialize a node with `f`, one level deeper than its parent.
+fn deserialize_nested<'de, D: Deserializer<'de>, T>(
+    d: D,
+    f: impl FnOnce(D) -> Result<T, D::Error>,
+) -> Result<T, D::Error> {
+    let depth = NESTING.with(Cell::get);
+    if depth >= MAX_DEPTH {
+        return Err(D::Error::custom("AST nested too deeply"));
+    }
+    NESTING.with(|d| d.set(depth + 1));
+    let t = f(d);
+    NESTING.with(|d| d.set(depth));
+    t
+}
+
+/// Implement `Serialize` and `Deserialize` for recursive types whose derived
+/// implementations are `#[serde(remote = "Self")]`, limiting their depth.
+macro_rules! impl_nested_serde {
+    ($($t:ident),*) => {
+        $(
+            impl Serialize for $t {
+                fn serialize<S: Serializer>(
+                    &self,
+                    s: S,
+                ) -> Result<S::Ok, S::Error> {
+                    $t::serialize(self, s)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $t {
+                fn deserialize<D: Deserializer<'de>>(
+                    d: D,
+                ) -> Result<Self, D::Error> {
+                    deserialize_nested(d, $t::deserialize)
+                }
+            }
+        )*
+    };
+}
+
+impl_nested_serde!(Declarator, Initializer, Statement, Expression);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranslationUnit {
+    pub external_declarations: Vec<ExternalDeclaration>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ExternalDeclaration {
+    FunctionDeclaration(Box<FunctionDefinition>),
     Declaration(Declaration),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct FunctionDefinition {
-    pub(crate) declaration_specifier: DeclarationSpecifier,
-    pub(crate) declarator: Declarator,
-    pub(crate) compound_statement: CompoundStatement,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub declaration_specifier: DeclarationSpecifier,
+    pub declarator: Declarator,
+    pub compound_statement: CompoundStatement,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct Declaration {
-    pub(crate) declaration_specifier: DeclarationSpecifier,
-    pub(crate) init_declarator_list: InitDeclaratorList,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Declaration {
+    pub declaration_specifier: DeclarationSpecifier,
+    pub init_declarator_list: InitDeclaratorList,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct InitDeclaratorList(pub(crate) Vec<InitDeclarator>);
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InitDeclaratorList(pub Vec<InitDeclarator>);
 
-#[derive(Debug, Serialize)]
-pub(crate) enum DeclarationSpecifier {
-    TypeSpecifier(TypeSpecifier),
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DeclarationSpecifier {
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    TypeSpecifier(TypeSpecifier, Span),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum TypeSpecifier {
+#[derive(Debug, Serialize, Deserialize)]
+pub enum TypeSpecifier {
     Void,
     Int,
     Double,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct InitDeclarator {
-    pub(crate) declarator: Declarator,
-    pub(crate) initializer: Option<Initializer>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InitDeclarator {
+    pub declarator: Declarator,
+    pub initializer: Option<Initializer>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct Declarator {
-    pub(crate) pointer: Pointer,
-    pub(crate) direct: DirectDeclarator,
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub struct Declarator {
+    pub pointer: Pointer,
+    pub direct: DirectDeclarator,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct Pointer(pub(crate) usize);
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Pointer(pub usize);
 
-#[derive(Debug, Serialize)]
-pub(crate) struct DirectDeclarator {
-    pub(crate) simple_declarator: SimpleDirectDeclarator,
-    pub(crate) modifiers: Vec<DirectDeclaratorModifier>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectDeclarator {
+    pub simple_declarator: SimpleDirectDeclarator,
+    pub modifiers: Vec<DirectDeclaratorModifier>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum DirectDeclaratorModifier {
-    Array(usize),
-    Function(Vec<ParameterDeclaration>),
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DirectDeclaratorModifier {
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    Array(usize, Span),
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    Function(Vec<ParameterDeclaration>, Span),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum SimpleDirectDeclarator {
-    Identifier(usize),
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SimpleDirectDeclarator {
+    #[serde(
+        serialize_with = "serialize_spanned_identifier",
+        deserialize_with = "deserialize_spanned"
+    )]
+    Identifier(usize, Span),
     Declarator(Box<Declarator>),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct ParameterDeclaration {
-    pub(crate) specifier: DeclarationSpecifier,
-    pub(crate) declarator: Declarator,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParameterDeclaration {
+    pub specifier: DeclarationSpecifier,
+    pub declarator: Declarator,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum Initializer {
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub enum Initializer {
     Expression(Expression),
-    List(Vec<Initializer>),
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    List(Vec<Initializer>, Span),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum Statement {
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub enum Statement {
     Compound(CompoundStatement),
-    Expression(Option<Expression>),
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    Expression(Option<Expression>, Span),
     Selection(SelectionStatement),
     Iteration(IterationStatement),
-    Jump(JumpStatement),
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    Jump(JumpStatement, Span),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct CompoundStatement(pub(crate) Vec<BlockItem>);
+#[derive(Debug)]
+pub struct CompoundStatement {
+    pub block_items: Vec<BlockItem>,
+    pub span: Span,
+}
 
-#[derive(Debug, Serialize)]
-pub(crate) enum BlockItem {
+#[derive(Debug, Serialize, Deserialize)]
+pub enum BlockItem {
     Declaration(Declaration),
     Statement(Statement),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct SelectionStatement {
-    pub(crate) condition: Expression,
-    pub(crate) consequent: Box<Statement>,
-    pub(crate) alternative: Option<Box<Statement>>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelectionStatement {
+    pub condition: Expression,
+    pub consequent: Box<Statement>,
+    pub alternative: Option<Box<Statement>>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum IterationStatement {
+#[derive(Debug, Serialize, Deserialize)]
+pub enum IterationStatement {
     While(WhileStatement),
     Do(DoStatement),
-    For(ForStatement),
+    For(Box<ForStatement>),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct WhileStatement {
-    pub(crate) condition: Expression,
-    pub(crate) body: Box<Statement>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WhileStatement {
+    pub condition: Expression,
+    pub body: Box<Statement>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct DoStatement {
-    pub(crate) body: Box<Statement>,
-    pub(crate) condition: Expression,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DoStatement {
+    pub body: Box<Statement>,
+    pub condition: Expression,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct ForStatement {
-    pub(crate) initialization: ForInitialization,
-    pub(crate) condition: Option<Expression>,
-    pub(crate) update: Option<Expression>,
-    pub(crate) body: Box<Statement>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForStatement {
+    pub initialization: ForInitialization,
+    pub condition: Option<Expression>,
+    pub update: Option<Expression>,
+    pub body: Box<Statement>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum ForInitialization {
-    Expression(Option<Expression>),
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ForInitialization {
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    Expression(Option<Expression>, Span),
     Declaration(Declaration),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum JumpStatement {
+#[derive(Debug, Serialize, Deserialize)]
+pub enum JumpStatement {
     Continue,
     Break,
     Return(Option<Expression>),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum Expression {
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub enum Expression {
     Assignment(AssignmentExpression),
     Binary(BinaryExpression),
     Unary(UnaryExpression),
     Postfix(PostfixExpression),
-    Atom(AtomExpression),
+    #[serde(
+        serialize_with = "serialize_spanned",
+        deserialize_with = "deserialize_spanned"
+    )]
+    Atom(AtomExpression, Span),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct AssignmentExpression {
-    pub(crate) left: Box<Expression>,
-    pub(crate) right: Box<Expression>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignmentExpression {
+    pub left: Box<Expression>,
+    pub right: Box<Expression>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct BinaryExpression {
-    pub(crate) operator: BinaryOperator,
-    pub(crate) left: Box<Expression>,
-    pub(crate) right: Box<Expression>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BinaryExpression {
+    pub operator: BinaryOperator,
+    pub left: Box<Expression>,
+    pub right: Box<Expression>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum BinaryOperator {
+#[derive(Debug, Serialize, Deserialize)]
+pub enum BinaryOperator {
     Add,
     Minus,
     Multiply,
@@ -189,6 +401,8 @@ pub(crate) enum BinaryOperator {
     Ge,
     Eq,
     Neq,
+    /// `a, b`, which evaluates `a` for its side effects and gives `b`
+    Comma,
 }
 
 trace::init_depth_var!();
@@ -210,19 +424,22 @@ impl From<&TokenKind> for BinaryOperator {
             TokenKind::Relop(RelopKind::Ge) => BinaryOperator::Ge,
             TokenKind::Relop(RelopKind::Eq) => BinaryOperator::Eq,
             TokenKind::Relop(RelopKind::Neq) => BinaryOperator::Neq,
+            TokenKind::Comma => BinaryOperator::Comma,
             _ => unreachable!(),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct UnaryExpression {
-    pub(crate) operator: UnaryOperator,
-    pub(crate) operand: Box<Expression>,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnaryExpression {
+    pub operator: UnaryOperator,
+    pub operand: Box<Expression>,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum UnaryOperator {
+#[derive(Debug, Serialize, Deserialize)]
+pub enum UnaryOperator {
     Positive,
     Negative,
     Address,
@@ -245,21 +462,759 @@ impl From<&TokenKind> for UnaryOperator {
     }
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) struct PostfixExpression {
-    pub(crate) operand: Box<Expression>,
-    pub(crate) postfix: PostfixExpressionPostfix,
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PostfixExpression {
+    pub operand: Box<Expression>,
+    pub postfix: PostfixExpressionPostfix,
+    #[serde(default, skip_serializing_if = "skip_span")]
+    pub span: Span,
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum PostfixExpressionPostfix {
+#[derive(Debug, Serialize, Deserialize)]
+pub enum PostfixExpressionPostfix {
     Subscript(Box<Expression>),
     Call(Vec<Expression>),
 }
 
-#[derive(Debug, Serialize)]
-pub(crate) enum AtomExpression {
-    Identifier(usize),
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AtomExpression {
+    Identifier(#[serde(serialize_with = "serialize_identifier")] usize),
     Integer(u32),
     Floating(f64),
 }
+
+impl Serialize for CompoundStatement {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_spanned(&self.block_items, &self.span, s)
+    }
+}
+
+impl<'de> Deserialize<'de> for CompoundStatement {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let (block_items, span) = deserialize_spanned(d)?;
+        Ok(CompoundStatement { block_items, span })
+    }
+}
+
+impl ExternalDeclaration {
+    pub fn span(&self) -> Span {
+        match self {
+            ExternalDeclaration::FunctionDeclaration(f) => f.span,
+            ExternalDeclaration::Declaration(d) => d.span,
+        }
+    }
+}
+
+impl DeclarationSpecifier {
+    pub fn span(&self) -> Span {
+        match self {
+            DeclarationSpecifier::TypeSpecifier(_, span) => *span,
+        }
+    }
+}
+
+impl Declarator {
+    /// The identifier being declared, with its span.
+    pub fn identifier(&self) -> (usize, Span) {
+        match &self.direct.simple_declarator {
+            SimpleDirectDeclarator::Identifier(id, span) => (*id, *span),
+            SimpleDirectDeclarator::Declarator(d) => d.identifier(),
+        }
+    }
+
+    /// The parameters if the identifier is declared as a function, e.g. `a`
+    /// but not `b` in `void (*f(int a))(int b)`.
+    pub fn parameters(&self) -> Option<&[ParameterDeclaration]> {
+        match &self.direct.simple_declarator {
+            SimpleDirectDeclarator::Identifier(..) => {
+                match self.direct.modifiers.first() {
+                    Some(DirectDeclaratorModifier::Function(params, _)) => {
+                        Some(params)
+                    }
+                    _ => None,
+                }
+            }
+            SimpleDirectDeclarator::Declarator(d) => d.parameters(),
+        }
+    }
+}
+
+impl DirectDeclaratorModifier {
+    pub fn span(&self) -> Span {
+        match self {
+            DirectDeclaratorModifier::Array(_, span)
+            | DirectDeclaratorModifier::Function(_, span) => *span,
+        }
+    }
+}
+
+impl SimpleDirectDeclarator {
+    pub fn span(&self) -> Span {
+        match self {
+            SimpleDirectDeclarator::Identifier(_, span) => *span,
+            SimpleDirectDeclarator::Declarator(d) => d.span,
+        }
+    }
+}
+
+impl Initializer {
+    pub fn span(&self) -> Span {
+        match self {
+            Initializer::Expression(e) => e.span(),
+            Initializer::List(_, span) => *span,
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Compound(s) => s.span,
+            Statement::Expression(_, span) | Statement::Jump(_, span) => *span,
+            Statement::Selection(s) => s.span,
+            Statement::Iteration(s) => s.span(),
+        }
+    }
+}
+
+impl BlockItem {
+    pub fn span(&self) -> Span {
+        match self {
+            BlockItem::Declaration(d) => d.span,
+            BlockItem::Statement(s) => s.span(),
+        }
+    }
+}
+
+impl IterationStatement {
+    pub fn span(&self) -> Span {
+        match self {
+            IterationStatement::While(s) => s.span,
+            IterationStatement::Do(s) => s.span,
+            IterationStatement::For(s) => s.span,
+        }
+    }
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Assignment(e) => e.span,
+            Expression::Binary(e) => e.span,
+            Expression::Unary(e) => e.span,
+            Expression::Postfix(e) => e.span,
+            Expression::Atom(_, span) => *span,
+        }
+    }
+}
+
+/// Defines a visitor trait in module `$module`, with a `visit_*` method for
+/// every node type that by default calls the `walk_*` function of the same
+/// name to visit the node's children. `mut` makes it take mutable
+/// references.
+macro_rules! visitor {
+    ($module:ident, $Visitor:ident $(, $mut:tt)?) => {
+        pub mod $module {
+            use super::*;
+
+            pub trait $Visitor {
+                fn visit_translation_unit(
+                    &mut self,
+                    t: & $($mut)? TranslationUnit,
+                ) {
+                    walk_translation_unit(self, t)
+                }
+
+                fn visit_external_declaration(
+                    &mut self,
+                    d: & $($mut)? ExternalDeclaration,
+                ) {
+                    walk_external_declaration(self, d)
+                }
+
+                fn visit_function_definition(
+                    &mut self,
+                    f: & $($mut)? FunctionDefinition,
+                ) {
+                    walk_function_definition(self, f)
+                }
+
+                fn visit_declaration(&mut self, d: & $($mut)? Declaration) {
+                    walk_declaration(self, d)
+                }
+
+                fn visit_declaration_specifier(
+                    &mut self,
+                    s: & $($mut)? DeclarationSpecifier,
+                ) {
+                    walk_declaration_specifier(self, s)
+                }
+
+                fn visit_init_declarator(
+                    &mut self,
+                    d: & $($mut)? InitDeclarator,
+                ) {
+                    walk_init_declarator(self, d)
+                }
+
+                fn visit_declarator(&mut self, d: & $($mut)? Declarator) {
+                    walk_declarator(self, d)
+                }
+
+                fn visit_direct_declarator(
+                    &mut self,
+                    d: & $($mut)? DirectDeclarator,
+                ) {
+                    walk_direct_declarator(self, d)
+                }
+
+                fn visit_direct_declarator_modifier(
+                    &mut self,
+                    m: & $($mut)? DirectDeclaratorModifier,
+                ) {
+                    walk_direct_declarator_modifier(self, m)
+                }
+
+                fn visit_parameter_declaration(
+                    &mut self,
+                    p: & $($mut)? ParameterDeclaration,
+                ) {
+                    walk_parameter_declaration(self, p)
+                }
+
+                fn visit_initializer(&mut self, i: & $($mut)? Initializer) {
+                    walk_initializer(self, i)
+                }
+
+                fn visit_compound_statement(
+                    &mut self,
+                    c: & $($mut)? CompoundStatement,
+                ) {
+                    walk_compound_statement(self, c)
+                }
+
+                fn visit_block_item(&mut self, i: & $($mut)? BlockItem) {
+                    walk_block_item(self, i)
+                }
+
+                fn visit_statement(&mut self, s: & $($mut)? Statement) {
+                    walk_statement(self, s)
+                }
+
+                fn visit_selection_statement(
+                    &mut self,
+                    s: & $($mut)? SelectionStatement,
+                ) {
+                    walk_selection_statement(self, s)
+                }
+
+                fn visit_iteration_statement(
+                    &mut self,
+                    s: & $($mut)? IterationStatement,
+                ) {
+                    walk_iteration_statement(self, s)
+                }
+
+                fn visit_while_statement(
+                    &mut self,
+                    s: & $($mut)? WhileStatement,
+                ) {
+                    walk_while_statement(self, s)
+                }
+
+                fn visit_do_statement(&mut self, s: & $($mut)? DoStatement) {
+                    walk_do_statement(self, s)
+                }
+
+                fn visit_for_statement(&mut self, s: & $($mut)? ForStatement) {
+                    walk_for_statement(self, s)
+                }
+
+                fn visit_for_initialization(
+                    &mut self,
+                    i: & $($mut)? ForInitialization,
+                ) {
+                    walk_for_initialization(self, i)
+                }
+
+                fn visit_jump_statement(
+                    &mut self,
+                    s: & $($mut)? JumpStatement,
+                ) {
+                    walk_jump_statement(self, s)
+                }
+
+                fn visit_expression(&mut self, e: & $($mut)? Expression) {
+                    walk_expression(self, e)
+                }
+
+                fn visit_assignment_expression(
+                    &mut self,
+                    e: & $($mut)? AssignmentExpression,
+                ) {
+                    walk_assignment_expression(self, e)
+                }
+
+                fn visit_binary_expression(
+                    &mut self,
+                    e: & $($mut)? BinaryExpression,
+                ) {
+                    walk_binary_expression(self, e)
+                }
+
+                fn visit_unary_expression(
+                    &mut self,
+                    e: & $($mut)? UnaryExpression,
+                ) {
+                    walk_unary_expression(self, e)
+                }
+
+                fn visit_postfix_expression(
+                    &mut self,
+                    e: & $($mut)? PostfixExpression,
+                ) {
+                    walk_postfix_expression(self, e)
+                }
+
+                fn visit_atom_expression(
+                    &mut self,
+                    e: & $($mut)? AtomExpression,
+                    span: Span,
+                ) {
+                    walk_atom_expression(self, e, span)
+                }
+
+                /// An identifier, declared or used.
+                fn visit_identifier(
+                    &mut self,
+                    _id: & $($mut)? usize,
+                    _span: Span,
+                ) {
+                }
+
+                /// The span of a node, before its children are walked.
+                fn visit_span(&mut self, _span: & $($mut)? Span) {}
+            }
+
+            pub fn walk_translation_unit<V: $Visitor + ?Sized>(
+                v: &mut V,
+                t: & $($mut)? TranslationUnit,
+            ) {
+                v.visit_span(& $($mut)? t.span);
+                for d in & $($mut)? t.external_declarations {
+                    v.visit_external_declaration(d);
+                }
+            }
+
+            pub fn walk_external_declaration<V: $Visitor + ?Sized>(
+                v: &mut V,
+                d: & $($mut)? ExternalDeclaration,
+            ) {
+                match d {
+                    ExternalDeclaration::FunctionDeclaration(f) => {
+                        v.visit_function_definition(f)
+                    }
+                    ExternalDeclaration::Declaration(d) => {
+                        v.visit_declaration(d)
+                    }
+                }
+            }
+
+            pub fn walk_function_definition<V: $Visitor + ?Sized>(
+                v: &mut V,
+                f: & $($mut)? FunctionDefinition,
+            ) {
+                v.visit_span(& $($mut)? f.span);
+                v.visit_declaration_specifier(& $($mut)? f.declaration_specifier);
+                v.visit_declarator(& $($mut)? f.declarator);
+                v.visit_compound_statement(& $($mut)? f.compound_statement);
+            }
+
+            pub fn walk_declaration<V: $Visitor + ?Sized>(
+                v: &mut V,
+                d: & $($mut)? Declaration,
+            ) {
+                v.visit_span(& $($mut)? d.span);
+                v.visit_declaration_specifier(& $($mut)? d.declaration_specifier);
+                for i in & $($mut)? d.init_declarator_list.0 {
+                    v.visit_init_declarator(i);
+                }
+            }
+
+            pub fn walk_declaration_specifier<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? DeclarationSpecifier,
+            ) {
+                match s {
+                    DeclarationSpecifier::TypeSpecifier(_, span) => {
+                        v.visit_span(span)
+                    }
+                }
+            }
+
+            pub fn walk_init_declarator<V: $Visitor + ?Sized>(
+                v: &mut V,
+                d: & $($mut)? InitDeclarator,
+            ) {
+                v.visit_span(& $($mut)? d.span);
+                v.visit_declarator(& $($mut)? d.declarator);
+                if let Some(i) = & $($mut)? d.initializer {
+                    v.visit_initializer(i);
+                }
+            }
+
+            pub fn walk_declarator<V: $Visitor + ?Sized>(
+                v: &mut V,
+                d: & $($mut)? Declarator,
+            ) {
+                v.visit_span(& $($mut)? d.span);
+                v.visit_direct_declarator(& $($mut)? d.direct);
+            }
+
+            pub fn walk_direct_declarator<V: $Visitor + ?Sized>(
+                v: &mut V,
+                d: & $($mut)? DirectDeclarator,
+            ) {
+                v.visit_span(& $($mut)? d.span);
+                match & $($mut)? d.simple_declarator {
+                    SimpleDirectDeclarator::Identifier(id, span) => {
+                        v.visit_span(span);
+                        v.visit_identifier(id, *span)
+                    }
+                    SimpleDirectDeclarator::Declarator(d) => {
+                        v.visit_declarator(d)
+                    }
+                }
+                for m in & $($mut)? d.modifiers {
+                    v.visit_direct_declarator_modifier(m);
+                }
+            }
+
+            pub fn walk_direct_declarator_modifier<
+                V: $Visitor + ?Sized,
+            >(
+                v: &mut V,
+                m: & $($mut)? DirectDeclaratorModifier,
+            ) {
+                match m {
+                    DirectDeclaratorModifier::Array(_, span) => v.visit_span(span),
+                    DirectDeclaratorModifier::Function(parameters, span) => {
+                        v.visit_span(span);
+                        for p in parameters {
+                            v.visit_parameter_declaration(p);
+                        }
+                    }
+                }
+            }
+
+            pub fn walk_parameter_declaration<V: $Visitor + ?Sized>(
+                v: &mut V,
+                p: & $($mut)? ParameterDeclaration,
+            ) {
+                v.visit_span(& $($mut)? p.span);
+                v.visit_declaration_specifier(& $($mut)? p.specifier);
+                v.visit_declarator(& $($mut)? p.declarator);
+            }
+
+            pub fn walk_initializer<V: $Visitor + ?Sized>(
+                v: &mut V,
+                i: & $($mut)? Initializer,
+            ) {
+                match i {
+                    Initializer::Expression(e) => v.visit_expression(e),
+                    Initializer::List(list, span) => {
+                        v.visit_span(span);
+                        for i in list {
+                            v.visit_initializer(i);
+                        }
+                    }
+                }
+            }
+
+            pub fn walk_compound_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                c: & $($mut)? CompoundStatement,
+            ) {
+                v.visit_span(& $($mut)? c.span);
+                for i in & $($mut)? c.block_items {
+                    v.visit_block_item(i);
+                }
+            }
+
+            pub fn walk_block_item<V: $Visitor + ?Sized>(
+                v: &mut V,
+                i: & $($mut)? BlockItem,
+            ) {
+                match i {
+                    BlockItem::Declaration(d) => v.visit_declaration(d),
+                    BlockItem::Statement(s) => v.visit_statement(s),
+                }
+            }
+
+            pub fn walk_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? Statement,
+            ) {
+                match s {
+                    Statement::Compound(c) => v.visit_compound_statement(c),
+                    Statement::Expression(e, span) => {
+                        v.visit_span(span);
+                        if let Some(e) = e {
+                            v.visit_expression(e);
+                        }
+                    }
+                    Statement::Selection(s) => v.visit_selection_statement(s),
+                    Statement::Iteration(s) => v.visit_iteration_statement(s),
+                    Statement::Jump(s, span) => {
+                        v.visit_span(span);
+                        v.visit_jump_statement(s)
+                    }
+                }
+            }
+
+            pub fn walk_selection_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? SelectionStatement,
+            ) {
+                v.visit_span(& $($mut)? s.span);
+                v.visit_expression(& $($mut)? s.condition);
+                v.visit_statement(& $($mut)? s.consequent);
+                if let Some(alternative) = & $($mut)? s.alternative {
+                    v.visit_statement(alternative);
+                }
+            }
+
+            pub fn walk_iteration_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? IterationStatement,
+            ) {
+                match s {
+                    IterationStatement::While(s) => v.visit_while_statement(s),
+                    IterationStatement::Do(s) => v.visit_do_statement(s),
+                    IterationStatement::For(s) => v.visit_for_statement(s),
+                }
+            }
+
+            pub fn walk_while_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? WhileStatement,
+            ) {
+                v.visit_span(& $($mut)? s.span);
+                v.visit_expression(& $($mut)? s.condition);
+                v.visit_statement(& $($mut)? s.body);
+            }
+
+            pub fn walk_do_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? DoStatement,
+            ) {
+                v.visit_span(& $($mut)? s.span);
+                v.visit_statement(& $($mut)? s.body);
+                v.visit_expression(& $($mut)? s.condition);
+            }
+
+            pub fn walk_for_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? ForStatement,
+            ) {
+                v.visit_span(& $($mut)? s.span);
+                v.visit_for_initialization(& $($mut)? s.initialization);
+                if let Some(e) = & $($mut)? s.condition {
+                    v.visit_expression(e);
+                }
+                if let Some(e) = & $($mut)? s.update {
+                    v.visit_expression(e);
+                }
+                v.visit_statement(& $($mut)? s.body);
+            }
+
+            pub fn walk_for_initialization<V: $Visitor + ?Sized>(
+                v: &mut V,
+                i: & $($mut)? ForInitialization,
+            ) {
+                match i {
+                    ForInitialization::Expression(e, span) => {
+                        v.visit_span(span);
+                        if let Some(e) = e {
+                            v.visit_expression(e);
+                        }
+                    }
+                    ForInitialization::Declaration(d) => v.visit_declaration(d),
+                }
+            }
+
+            pub fn walk_jump_statement<V: $Visitor + ?Sized>(
+                v: &mut V,
+                s: & $($mut)? JumpStatement,
+            ) {
+                if let JumpStatement::Return(Some(e)) = s {
+                    v.visit_expression(e);
+                }
+            }
+
+            pub fn walk_expression<V: $Visitor + ?Sized>(
+                v: &mut V,
+                e: & $($mut)? Expression,
+            ) {
+                match e {
+                    Expression::Assignment(e) => v.visit_assignment_expression(e),
+                    Expression::Binary(e) => v.visit_binary_expression(e),
+                    Expression::Unary(e) => v.visit_unary_expression(e),
+                    Expression::Postfix(e) => v.visit_postfix_expression(e),
+                    Expression::Atom(e, span) => {
+                        v.visit_span(span);
+                        v.visit_atom_expression(e, *span)
+                    }
+                }
+            }
+
+            pub fn walk_assignment_expression<V: $Visitor + ?Sized>(
+                v: &mut V,
+                e: & $($mut)? AssignmentExpression,
+            ) {
+                v.visit_span(& $($mut)? e.span);
+                v.visit_expression(& $($mut)? e.left);
+                v.visit_expression(& $($mut)? e.right);
+            }
+
+            pub fn walk_binary_expression<V: $Visitor + ?Sized>(
+                v: &mut V,
+                e: & $($mut)? BinaryExpression,
+            ) {
+                v.visit_span(& $($mut)? e.span);
+                v.visit_expression(& $($mut)? e.left);
+                v.visit_expression(& $($mut)? e.right);
+            }
+
+            pub fn walk_unary_expression<V: $Visitor + ?Sized>(
+                v: &mut V,
+                e: & $($mut)? UnaryExpression,
+            ) {
+                v.visit_span(& $($mut)? e.span);
+                v.visit_expression(& $($mut)? e.operand);
+            }
+
+            pub fn walk_postfix_expression<V: $Visitor + ?Sized>(
+                v: &mut V,
+                e: & $($mut)? PostfixExpression,
+            ) {
+                v.visit_span(& $($mut)? e.span);
+                v.visit_expression(& $($mut)? e.operand);
+                match & $($mut)? e.postfix {
+                    PostfixExpressionPostfix::Subscript(index) => {
+                        v.visit_expression(index)
+                    }
+                    PostfixExpressionPostfix::Call(arguments) => {
+                        for a in arguments {
+                            v.visit_expression(a);
+                        }
+                    }
+                }
+            }
+
+            pub fn walk_atom_expression<V: $Visitor + ?Sized>(
+                v: &mut V,
+                e: & $($mut)? AtomExpression,
+                span: Span,
+            ) {
+                if let AtomExpression::Identifier(id) = e {
+                    v.visit_identifier(id, span);
+                }
+            }
+        }
+    };
+}
+
+visitor!(visit, Visitor);
+visitor!(visit_mut, VisitorMut, mut);
+
+pub use visit::Visitor;
+pub use visit_mut::VisitorMut;
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::{visit_mut::walk_expression, *};
+    use crate::{
+        lexer::{scan, LexerOutput},
+        parser::parse,
+        printer::print,
+    };
+
+    fn parse_src(src: &str) -> (TranslationUnit, LexerOutput) {
+        let lexer_output = scan(src).unwrap();
+        (parse(&lexer_output.tokens).unwrap(), lexer_output)
+    }
+
+    /// The identifiers in order, declared or used.
+    struct Identifiers(Vec<usize>);
+
+    impl Visitor for Identifiers {
+        fn visit_identifier(&mut self, id: &usize, _span: Span) {
+            self.0.push(*id);
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let (ast, lexer_output) = parse_src(
+            "int a[2] = {b, c}; int f(int d) { for (e; f(g[h]); ) return -i; }",
+        );
+        let mut identifiers = Identifiers(vec![]);
+        identifiers.visit_translation_unit(&ast);
+        let names: Vec<_> = identifiers
+            .0
+            .iter()
+            .map(|&id| lexer_output.name(id))
+            .collect();
+        assert_eq!(names, ["a", "b", "c", "f", "d", "e", "f", "g", "h", "i"]);
+    }
+
+    /// Folds additions of integer constants and renames identifier `from`
+    /// to `to`.
+    struct Fold {
+        from: usize,
+        to: usize,
+    }
+
+    impl VisitorMut for Fold {
+        fn visit_expression(&mut self, e: &mut Expression) {
+            walk_expression(self, e);
+            if let Expression::Binary(BinaryExpression {
+                operator: BinaryOperator::Add,
+                left,
+                right,
+                span,
+            }) = e
+            {
+                if let (
+                    Expression::Atom(AtomExpression::Integer(l), _),
+                    Expression::Atom(AtomExpression::Integer(r), _),
+                ) = (&**left, &**right)
+                {
+                    *e =
+                        Expression::Atom(AtomExpression::Integer(l + r), *span);
+                }
+            }
+        }
+
+        fn visit_identifier(&mut self, id: &mut usize, _span: Span) {
+            if *id == self.from {
+                *id = self.to;
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        let (mut ast, lexer_output) =
+            parse_src("int f(int a, int b) { return a * (1 + 2 + 3) + b; }");
+        let mut fold = Fold { from: 1, to: 2 };
+        fold.visit_translation_unit(&mut ast);
+        assert_eq!(
+            print(&ast, &lexer_output),
+            "int f(int b, int b)\n{\n    return b * 6 + b;\n}\n"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! The `.ast` file format: the magic number `MINICAST`, the format version
+//! as a little-endian `u32`, then the bincode encoding of the path of the
+//! source file, the names in the identifier table and the AST with spans.
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::*,
+    lexer::LexerOutput,
+    token::{Identifier, Span},
+};
+
+pub const MAGIC: &[u8; 8] = b"MINICAST";
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AstFileError {
+    #[error("cannot read AST file")]
+    Io(#[from] std::io::Error),
+    #[error("not an AST file")]
+    BadMagic,
+    #[error("unsupported format version {0}, expect {VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("malformed AST")]
+    Malformed(#[from] bincode::Error),
+    #[error("identifier {0} is not in the identifier table")]
+    DanglingIdentifier(usize),
+}
+
+/// An AST read from a file, with what later stages need to go on from it.
+#[derive(Debug)]
+pub struct SavedAst {
+    /// The file the AST was parsed from
+    pub source: PathBuf,
+    /// Has no tokens, only the identifier table
+    pub lexer_output: LexerOutput,
+    pub ast: TranslationUnit,
+}
+
+#[derive(Serialize)]
+struct AstFileRef<'a> {
+    source: &'a str,
+    names: Vec<&'a str>,
+    ast: &'a TranslationUnit,
+}
+
+#[derive(Deserialize)]
+struct AstFile {
+    source: String,
+    names: Vec<String>,
+    ast: TranslationUnit,
+}
+
+pub fn output_ast(
+    w: &mut impl Write,
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+    source: &Path,
+) -> Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    let file = AstFileRef {
+        source: &source.to_string_lossy(),
+        names: (0..lexer_output.id_table.len())
+            .map(|id| lexer_output.name(id))
+            .collect(),
+        ast,
+    };
+    with_spans(|| bincode::DefaultOptions::new().serialize_into(w, &file))?;
+    Ok(())
+}
+
+pub fn load_ast(mut r: impl Read) -> Result<SavedAst, AstFileError> {
+    let mut header = [0; 12];
+    r.read_exact(&mut header)
+        .map_err(|_| AstFileError::BadMagic)?;
+    if &header[..8] != MAGIC {
+        return Err(AstFileError::BadMagic);
+    }
+    let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+    if version != VERSION {
+        return Err(AstFileError::UnsupportedVersion(version));
+    }
+    let mut file = Vec::new();
+    r.read_to_end(&mut file)?;
+    // Nothing in a well-formed file is longer than the file, so a bogus
+    // length cannot make us allocate more.
+    let options = bincode::DefaultOptions::new().with_limit(file.len() as u64);
+    let AstFile { source, names, ast } =
+        with_spans(|| options.deserialize(&file))?;
+    let mut check = IdentifierCheck {
+        len: names.len(),
+        dangling: None,
+    };
+    check.visit_translation_unit(&ast);
+    if let Some(id) = check.dangling {
+        return Err(AstFileError::DanglingIdentifier(id));
+    }
+    let mut id_table = Vec::with_capacity(names.len());
+    let mut text = String::new();
+    for name in names {
+        id_table.push(Identifier {
+            text_begin: text.len(),
+            text_len: name.len(),
+        });
+        text.push_str(&name);
+    }
+    Ok(SavedAst {
+        source: source.into(),
+        lexer_output: LexerOutput {
+            tokens: vec![],
+            id_table,
+            text,
+        },
+        ast,
+    })
+}
+
+/// Finds the first identifier in an AST that is not in an identifier table
+/// of `len` names.
+struct IdentifierCheck {
+    len: usize,
+    dangling: Option<usize>,
+}
+
+impl Visitor for IdentifierCheck {
+    fn visit_identifier(&mut self, id: &usize, _span: Span) {
+        if *id >= self.len && self.dangling.is_none() {
+            self.dangling = Some(*id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{lexer::scan, parser::parse, preprocess::preprocess};
+
+    fn save(src: &str) -> (Vec<u8>, TranslationUnit, LexerOutput) {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let mut v = Vec::new();
+        output_ast(&mut v, &ast, &lexer_output, Path::new("a.c")).unwrap();
+        (v, ast, lexer_output)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let src = include_str!("../testcase/c/2.c");
+        let src = preprocess(src.char_indices()).unwrap();
+        let (v, ast, lexer_output) = save(&src);
+        let saved = load_ast(v.as_slice()).unwrap();
+        assert_eq!(saved.source, Path::new("a.c"));
+        // Spans are not in the JSON, so compare them separately.
+        assert_eq!(
+            serde_json::to_string(&saved.ast).unwrap(),
+            serde_json::to_string(&ast).unwrap()
+        );
+        assert_eq!(
+            with_spans(|| serde_json::to_string(&saved.ast).unwrap()),
+            with_spans(|| serde_json::to_string(&ast).unwrap())
+        );
+        for id in 0..lexer_output.id_table.len() {
+            assert_eq!(saved.lexer_output.name(id), lexer_output.name(id));
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let (_, ast, _) = save("int f(int a) { return a + 1; }");
+        let json = serde_json::to_string(&ast).unwrap();
+        let ast: TranslationUnit = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&ast).unwrap(), json);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let (mut v, ..) = save("int a;");
+        v[0] = b'X';
+        assert!(matches!(
+            load_ast(v.as_slice()),
+            Err(AstFileError::BadMagic)
+        ));
+        assert!(matches!(
+            load_ast(&b"MINIC"[..]),
+            Err(AstFileError::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let (mut v, ..) = save("int a;");
+        v[8] = 2;
+        assert!(matches!(
+            load_ast(v.as_slice()),
+            Err(AstFileError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let (v, ..) = save("int a;");
+        assert!(matches!(
+            load_ast(&v[..v.len() - 1]),
+            Err(AstFileError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_huge_length() {
+        let mut v = MAGIC.to_vec();
+        v.extend(VERSION.to_le_bytes());
+        // A varint string length of 2^40
+        v.push(253);
+        v.extend((1u64 << 40).to_le_bytes());
+        assert!(matches!(
+            load_ast(v.as_slice()),
+            Err(AstFileError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let sum = |terms: usize| vec!["1"; terms].join("+");
+        let (v, ..) = save(&format!("int a = {};", sum(MAX_DEPTH - 10)));
+        load_ast(v.as_slice()).unwrap();
+        let (v, ..) = save(&format!("int a = {};", sum(MAX_DEPTH + 10)));
+        assert!(matches!(
+            load_ast(v.as_slice()),
+            Err(AstFileError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_dangling_identifier() {
+        let (_, ast, lexer_output) = save("int f(int a) { return b; }");
+        let mut names = lexer_output.clone();
+        // Drop `b` from the identifier table.
+        names.id_table.pop();
+        let mut v = Vec::new();
+        output_ast(&mut v, &ast, &names, Path::new("a.c")).unwrap();
+        assert!(matches!(
+            load_ast(v.as_slice()),
+            Err(AstFileError::DanglingIdentifier(2))
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+//! The JSON AST for other tools: a versioned document where identifiers are
+//! names rather than indices into the identifier table, optionally with
+//! spans. `schema/ast.schema.json` describes it.
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{
+    ast::{with_context, SerdeContext, TranslationUnit},
+    lexer::LexerOutput,
+};
+
+pub const VERSION: u32 = 1;
+
+/// How the AST is printed after checking it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// The AST alone, with identifiers as indices and without spans
+    Compact,
+    /// The versioned document
+    Named { spans: bool },
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u32,
+    file: &'a str,
+    spans: bool,
+    translation_unit: &'a TranslationUnit,
+}
+
+pub fn to_json(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+    file_path: &Path,
+    format: JsonFormat,
+) -> String {
+    let spans = match format {
+        JsonFormat::Compact => return serde_json::to_string(ast).unwrap(),
+        JsonFormat::Named { spans } => spans,
+    };
+    let context = SerdeContext {
+        spans,
+        names: Some(
+            (0..lexer_output.id_table.len())
+                .map(|id| lexer_output.name(id).to_owned())
+                .collect(),
+        ),
+    };
+    let document = Document {
+        version: VERSION,
+        file: &file_path.to_string_lossy(),
+        spans,
+        translation_unit: ast,
+    };
+    with_context(context, || serde_json::to_string(&document).unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Map, Value};
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{lexer::scan, parser::parse, preprocess::preprocess};
+
+    const SCHEMA: &str = include_str!("../schema/ast.schema.json");
+
+    /// Checks a value against the subset of JSON Schema the AST schema uses.
+    struct Validator<'a> {
+        defs: &'a Map<String, Value>,
+    }
+
+    impl Validator<'_> {
+        fn check(&self, schema: &Value, v: &Value, path: &str) -> bool {
+            let Value::Object(schema) = schema else {
+                return schema.as_bool().unwrap();
+            };
+            schema.iter().all(|(keyword, arg)| match keyword.as_str() {
+                "$ref" => {
+                    let name =
+                        arg.as_str().unwrap().strip_prefix("#/$defs/").unwrap();
+                    self.check(&self.defs[name], v, path)
+                }
+                "type" => match arg.as_str().unwrap() {
+                    "object" => v.is_object(),
+                    "array" => v.is_array(),
+                    "string" => v.is_string(),
+                    "integer" => v.is_u64() || v.is_i64(),
+                    "number" => v.is_number(),
+                    "boolean" => v.is_boolean(),
+                    "null" => v.is_null(),
+                    t => panic!("unknown type {t}"),
+                },
+                "const" => v == arg,
+                "enum" => arg.as_array().unwrap().contains(v),
+                // Keywords for other types accept the value, as in JSON
+                // Schema.
+                "minimum" => {
+                    v.as_f64().is_none_or(|n| n >= arg.as_f64().unwrap())
+                }
+                "minItems" => v
+                    .as_array()
+                    .is_none_or(|a| a.len() as u64 >= arg.as_u64().unwrap()),
+                "required" => v.as_object().is_none_or(|o| {
+                    arg.as_array()
+                        .unwrap()
+                        .iter()
+                        .all(|k| o.contains_key(k.as_str().unwrap()))
+                }),
+                "properties" => v.as_object().is_none_or(|o| {
+                    let properties = arg.as_object().unwrap();
+                    o.iter().all(|(k, v)| match properties.get(k) {
+                        Some(s) => self.check(s, v, &format!("{path}/{k}")),
+                        None => {
+                            schema.get("additionalProperties")
+                                != Some(&Value::Bool(false))
+                        }
+                    })
+                }),
+                "prefixItems" => v.as_array().is_none_or(|a| {
+                    let prefix = arg.as_array().unwrap();
+                    prefix.iter().zip(a).enumerate().all(|(i, (s, v))| {
+                        self.check(s, v, &format!("{path}/{i}"))
+                    })
+                }),
+                "items" => v.as_array().is_none_or(|a| {
+                    let skip = schema
+                        .get("prefixItems")
+                        .map_or(0, |p| p.as_array().unwrap().len());
+                    a.iter().enumerate().skip(skip).all(|(i, v)| {
+                        self.check(arg, v, &format!("{path}/{i}"))
+                    })
+                }),
+                "anyOf" => arg
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .any(|s| self.check(s, v, path)),
+                "oneOf" => {
+                    arg.as_array()
+                        .unwrap()
+                        .iter()
+                        .filter(|s| self.check(s, v, path))
+                        .count()
+                        == 1
+                }
+                "$schema"
+                | "$id"
+                | "title"
+                | "description"
+                | "additionalProperties"
+                | "$defs" => true,
+                k => panic!("unsupported keyword {k} at {path}"),
+            })
+        }
+    }
+
+    fn validate(v: &Value) -> bool {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        let validator = Validator {
+            defs: schema["$defs"].as_object().unwrap(),
+        };
+        validator.check(&schema, v, "")
+    }
+
+    fn json(src: &str, format: JsonFormat) -> Value {
+        let src = preprocess(src.char_indices()).unwrap();
+        let lexer_output = scan(&src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let json = to_json(&ast, &lexer_output, Path::new("a.c"), format);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test_case(false)]
+    #[test_case(true)]
+    fn test_schema(spans: bool) {
+        let v = json(
+            include_str!("../testcase/c/2.c"),
+            JsonFormat::Named { spans },
+        );
+        assert!(validate(&v));
+    }
+
+    #[test]
+    fn test_schema_rejects() {
+        let mut v = json("int a;", JsonFormat::Named { spans: false });
+        assert!(validate(&v));
+        v["version"] = json!(2);
+        assert!(!validate(&v));
+        let mut v = json("int a;", JsonFormat::Named { spans: false });
+        v["translation_unit"]["external_declarations"][0]["Declaration"]
+            ["declaration_specifier"]["TypeSpecifier"] = json!("Long");
+        assert!(!validate(&v));
+        // The compact JSON has indices instead of names.
+        let compact = json("int a;", JsonFormat::Compact);
+        let mut v = json("int a;", JsonFormat::Named { spans: false });
+        v["translation_unit"] = compact;
+        assert!(!validate(&v));
+    }
+
+    #[test]
+    fn test_names() {
+        let v = json(
+            "int main(int x) { int a; a = b; }",
+            JsonFormat::Named { spans: true },
+        );
+        let main = &v["translation_unit"]["external_declarations"][0]
+            ["FunctionDeclaration"];
+        assert_eq!(
+            main["declarator"]["direct"]["simple_declarator"]["Identifier"][0],
+            "main"
+        );
+        let statement = &main["compound_statement"][0][1]["Statement"];
+        let assignment = &statement["Expression"][0]["Assignment"];
+        assert_eq!(assignment["right"]["Atom"][0]["Identifier"], "b");
+        assert_eq!(assignment["right"]["Atom"][1]["start_pos"]["col"], 30);
+        assert_eq!(v["version"], VERSION);
+        assert_eq!(v["file"], "a.c");
+    }
+
+    #[test]
+    fn test_compact_unchanged() {
+        let src = include_str!("../testcase/c/2.c");
+        let src = preprocess(src.char_indices()).unwrap();
+        let lexer_output = scan(&src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        assert_eq!(
+            to_json(&ast, &lexer_output, Path::new("a.c"), JsonFormat::Compact),
+            include_str!("../testcase/c/2.c.json").trim_end()
+        );
+    }
+}
@@ -0,0 +1,727 @@
+//! Control-flow graphs of function bodies, built from the AST, and their
+//! dominator trees.
+//!
+//! A basic block holds the declarations and expression statements run one
+//! after another, and ends in a terminator that says where control goes
+//! next. Block 0 is the entry. Code that cannot be reached, such as the
+//! statements after a `return`, gets no block.
+
+use std::fmt::Write;
+
+use crate::{
+    ast::*,
+    error::{Error, ErrorKind},
+    lexer::LexerOutput,
+    printer::{print_declaration, print_expression},
+    token::Span,
+};
+
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Instruction<'a> {
+    Declaration(&'a Declaration),
+    Expression(&'a Expression),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Terminator<'a> {
+    Goto(BlockId),
+    /// Go to `then` if `condition` is true and to `otherwise` if not
+    Branch {
+        condition: &'a Expression,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    /// Also ends a function that runs off its end, without a value
+    Return(Option<&'a Expression>),
+}
+
+impl Terminator<'_> {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match *self {
+            Terminator::Goto(b) => vec![b],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            Terminator::Return(_) => vec![],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BasicBlock<'a> {
+    pub instructions: Vec<Instruction<'a>>,
+    pub terminator: Terminator<'a>,
+}
+
+#[derive(Debug)]
+pub struct Cfg<'a> {
+    pub function: &'a FunctionDefinition,
+    pub blocks: Vec<BasicBlock<'a>>,
+}
+
+impl<'a> Cfg<'a> {
+    /// The CFG of `f`. Fails on a `break` or `continue` outside any loop.
+    pub fn build(f: &'a FunctionDefinition) -> Result<Self, Error> {
+        let mut builder = Builder {
+            blocks: vec![Block::default()],
+            current: 0,
+            loops: vec![],
+        };
+        builder.compound_statement(&f.compound_statement)?;
+        builder.terminate(Terminator::Return(None));
+        Ok(Cfg {
+            function: f,
+            blocks: builder.finish(),
+        })
+    }
+
+    pub fn successors(&self, b: BlockId) -> Vec<BlockId> {
+        self.blocks[b].terminator.successors()
+    }
+
+    /// The predecessors of each block, in order.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for b in 0..self.blocks.len() {
+            for s in self.successors(b) {
+                predecessors[s].push(b);
+            }
+        }
+        predecessors
+    }
+
+    /// The successors of each block.
+    pub fn successor_lists(&self) -> Vec<Vec<BlockId>> {
+        (0..self.blocks.len()).map(|b| self.successors(b)).collect()
+    }
+
+    /// The blocks in reverse postorder, so that every block comes before
+    /// the blocks it reaches other than through a back edge.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        reverse_postorder(&self.successor_lists())
+    }
+
+    pub fn dominators(&self) -> Dominators {
+        Dominators::new(&self.successor_lists())
+    }
+}
+
+/// The blocks reachable from block 0 of the graph with `successors`, in
+/// reverse postorder.
+pub fn reverse_postorder(successors: &[Vec<BlockId>]) -> Vec<BlockId> {
+    let mut visited = vec![false; successors.len()];
+    let mut postorder = vec![];
+    // Each entry is a block and how many of its successors are done. They
+    // are visited last first, so that the first comes first in the order,
+    // e.g. the `then` branch of an `if` before the `else`.
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((b, i)) = stack.pop() {
+        match successors[b].iter().rev().nth(i) {
+            Some(&s) => {
+                stack.push((b, i + 1));
+                if !visited[s] {
+                    visited[s] = true;
+                    stack.push((s, 0));
+                }
+            }
+            None => postorder.push(b),
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+/// A block while the graph is built, which may not be terminated yet.
+#[derive(Default)]
+struct Block<'a> {
+    instructions: Vec<Instruction<'a>>,
+    terminator: Option<Terminator<'a>>,
+}
+
+struct Builder<'a> {
+    blocks: Vec<Block<'a>>,
+    /// Where instructions go
+    current: BlockId,
+    /// Where `continue` and `break` go in each enclosing loop, innermost
+    /// last
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block::default());
+        self.blocks.len() - 1
+    }
+
+    fn push(&mut self, i: Instruction<'a>) {
+        self.blocks[self.current].instructions.push(i);
+    }
+
+    /// End the current block with `t`.
+    fn terminate(&mut self, t: Terminator<'a>) {
+        let terminator = &mut self.blocks[self.current].terminator;
+        debug_assert!(terminator.is_none());
+        *terminator = Some(t);
+    }
+
+    /// End the current block with a jump, and put what follows in a new
+    /// block, which nothing jumps to.
+    fn jump(&mut self, t: Terminator<'a>) {
+        self.terminate(t);
+        self.current = self.new_block();
+    }
+
+    fn compound_statement(
+        &mut self,
+        c: &'a CompoundStatement,
+    ) -> Result<(), Error> {
+        for item in &c.block_items {
+            match item {
+                BlockItem::Declaration(d) => {
+                    self.push(Instruction::Declaration(d))
+                }
+                BlockItem::Statement(s) => self.statement(s)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, s: &'a Statement) -> Result<(), Error> {
+        match s {
+            Statement::Compound(c) => self.compound_statement(c)?,
+            Statement::Expression(e, _) => {
+                if let Some(e) = e {
+                    self.push(Instruction::Expression(e));
+                }
+            }
+            Statement::Selection(s) => {
+                let then = self.new_block();
+                let otherwise = self.new_block();
+                self.terminate(Terminator::Branch {
+                    condition: &s.condition,
+                    then,
+                    otherwise,
+                });
+                self.current = then;
+                self.statement(&s.consequent)?;
+                let join = match &s.alternative {
+                    Some(alternative) => {
+                        let join = self.new_block();
+                        self.terminate(Terminator::Goto(join));
+                        self.current = otherwise;
+                        self.statement(alternative)?;
+                        join
+                    }
+                    None => otherwise,
+                };
+                self.terminate(Terminator::Goto(join));
+                self.current = join;
+            }
+            Statement::Iteration(IterationStatement::While(s)) => {
+                let header = self.new_block();
+                let body = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Goto(header));
+                self.current = header;
+                self.terminate(Terminator::Branch {
+                    condition: &s.condition,
+                    then: body,
+                    otherwise: exit,
+                });
+                self.current = body;
+                self.loop_body(&s.body, header, exit)?;
+                self.terminate(Terminator::Goto(header));
+                self.current = exit;
+            }
+            Statement::Iteration(IterationStatement::Do(s)) => {
+                let body = self.new_block();
+                let condition = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Goto(body));
+                self.current = body;
+                self.loop_body(&s.body, condition, exit)?;
+                self.terminate(Terminator::Goto(condition));
+                self.current = condition;
+                self.terminate(Terminator::Branch {
+                    condition: &s.condition,
+                    then: body,
+                    otherwise: exit,
+                });
+                self.current = exit;
+            }
+            Statement::Iteration(IterationStatement::For(s)) => {
+                match &s.initialization {
+                    ForInitialization::Expression(Some(e), _) => {
+                        self.push(Instruction::Expression(e))
+                    }
+                    ForInitialization::Expression(None, _) => {}
+                    ForInitialization::Declaration(d) => {
+                        self.push(Instruction::Declaration(d))
+                    }
+                }
+                let header = self.new_block();
+                let body = self.new_block();
+                let update = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Goto(header));
+                self.current = header;
+                self.terminate(match &s.condition {
+                    Some(condition) => Terminator::Branch {
+                        condition,
+                        then: body,
+                        otherwise: exit,
+                    },
+                    None => Terminator::Goto(body),
+                });
+                self.current = body;
+                self.loop_body(&s.body, update, exit)?;
+                self.terminate(Terminator::Goto(update));
+                self.current = update;
+                if let Some(e) = &s.update {
+                    self.push(Instruction::Expression(e));
+                }
+                self.terminate(Terminator::Goto(header));
+                self.current = exit;
+            }
+            Statement::Jump(JumpStatement::Return(e), _) => {
+                self.jump(Terminator::Return(e.as_ref()))
+            }
+            Statement::Jump(JumpStatement::Break, span) => {
+                let (_, exit) = self.innermost_loop("break", *span)?;
+                self.jump(Terminator::Goto(exit));
+            }
+            Statement::Jump(JumpStatement::Continue, span) => {
+                let (next, _) = self.innermost_loop("continue", *span)?;
+                self.jump(Terminator::Goto(next));
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_body(
+        &mut self,
+        body: &'a Statement,
+        next: BlockId,
+        exit: BlockId,
+    ) -> Result<(), Error> {
+        self.loops.push((next, exit));
+        let result = self.statement(body);
+        self.loops.pop();
+        result
+    }
+
+    fn innermost_loop(
+        &self,
+        jump: &'static str,
+        span: Span,
+    ) -> Result<(BlockId, BlockId), Error> {
+        self.loops
+            .last()
+            .copied()
+            .ok_or(Error::new(span, ErrorKind::MisplacedJump(jump)))
+    }
+
+    /// The reachable blocks, numbered in reverse postorder. Blocks that
+    /// only jump on, like the one a `break` ends, are left out.
+    fn finish(self) -> Vec<BasicBlock<'a>> {
+        let blocks = self.blocks;
+        let forward = |mut b: BlockId| {
+            // Bounded, as an empty loop is a cycle of such blocks.
+            for _ in 0..blocks.len() {
+                match (&blocks[b].instructions[..], blocks[b].terminator) {
+                    ([], Some(Terminator::Goto(next))) if b != 0 => b = next,
+                    _ => break,
+                }
+            }
+            b
+        };
+        let forwarded = |t: Terminator<'a>| match t {
+            Terminator::Goto(b) => Terminator::Goto(forward(b)),
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => Terminator::Branch {
+                condition,
+                then: forward(then),
+                otherwise: forward(otherwise),
+            },
+            Terminator::Return(e) => Terminator::Return(e),
+        };
+        let terminators: Vec<_> = blocks
+            .iter()
+            .map(|b| forwarded(b.terminator.unwrap()))
+            .collect();
+        let successors: Vec<_> =
+            terminators.iter().map(Terminator::successors).collect();
+        let order = reverse_postorder(&successors);
+        let mut number = vec![0; blocks.len()];
+        for (n, &b) in order.iter().enumerate() {
+            number[b] = n;
+        }
+        let renumber = |t: Terminator<'a>| match t {
+            Terminator::Goto(b) => Terminator::Goto(number[b]),
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => Terminator::Branch {
+                condition,
+                then: number[then],
+                otherwise: number[otherwise],
+            },
+            Terminator::Return(e) => Terminator::Return(e),
+        };
+        let mut blocks: Vec<_> = blocks.into_iter().map(Some).collect();
+        order
+            .iter()
+            .map(|&b| BasicBlock {
+                instructions: blocks[b].take().unwrap().instructions,
+                terminator: renumber(terminators[b]),
+            })
+            .collect()
+    }
+}
+
+/// The dominator tree: block `a` dominates block `b` if every path from the
+/// entry to `b` goes through `a`.
+#[derive(Debug)]
+pub struct Dominators {
+    /// The immediate dominator of each block, the entry's being itself
+    idom: Vec<BlockId>,
+    /// The position of each block in reverse postorder
+    order: Vec<usize>,
+}
+
+impl Dominators {
+    /// The dominators of the graph with `successors` and entry 0, all of
+    /// whose blocks are reachable, as computed by Cooper, Harvey and
+    /// Kennedy's "A Simple, Fast Dominance Algorithm".
+    pub fn new(successors: &[Vec<BlockId>]) -> Self {
+        let rpo = reverse_postorder(successors);
+        assert_eq!(rpo.len(), successors.len(), "unreachable blocks");
+        let mut order = vec![0; successors.len()];
+        for (i, &b) in rpo.iter().enumerate() {
+            order[b] = i;
+        }
+        let mut predecessors = vec![vec![]; successors.len()];
+        for (b, s) in successors.iter().enumerate() {
+            for &s in s {
+                predecessors[s].push(b);
+            }
+        }
+
+        const UNDEFINED: BlockId = usize::MAX;
+        let mut idom = vec![UNDEFINED; successors.len()];
+        idom[0] = 0;
+        let intersect = |idom: &[BlockId], mut a: BlockId, mut b: BlockId| {
+            while a != b {
+                while order[a] > order[b] {
+                    a = idom[a];
+                }
+                while order[b] > order[a] {
+                    b = idom[b];
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in &rpo[1..] {
+                let mut processed =
+                    predecessors[b].iter().filter(|&&p| idom[p] != UNDEFINED);
+                let first = *processed.next().unwrap();
+                let new_idom =
+                    processed.fold(first, |d, &p| intersect(&idom, d, p));
+                if idom[b] != new_idom {
+                    idom[b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        Dominators { idom, order }
+    }
+
+    /// The immediate dominator of `b`, or `None` for the entry.
+    pub fn idom(&self, b: BlockId) -> Option<BlockId> {
+        (b != 0).then_some(self.idom[b])
+    }
+
+    pub fn dominates(&self, a: BlockId, mut b: BlockId) -> bool {
+        // Dominators come earlier in reverse postorder.
+        while self.order[b] > self.order[a] {
+            b = self.idom[b];
+        }
+        a == b
+    }
+
+    /// The children of each block in the dominator tree.
+    pub fn children(&self) -> Vec<Vec<BlockId>> {
+        let mut children = vec![vec![]; self.idom.len()];
+        for b in 1..self.idom.len() {
+            children[self.idom[b]].push(b);
+        }
+        children
+    }
+
+    /// The dominance frontier of each block of the graph with `successors`:
+    /// the blocks it does not strictly dominate but one of whose
+    /// predecessors it dominates, where its definitions may meet others.
+    pub fn frontiers(&self, successors: &[Vec<BlockId>]) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![vec![]; successors.len()];
+        for (b, s) in successors.iter().enumerate() {
+            for &s in s {
+                predecessors[s].push(b);
+            }
+        }
+        let mut frontiers = vec![vec![]; successors.len()];
+        for (b, predecessors) in predecessors.iter().enumerate() {
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for &p in predecessors {
+                let mut runner = p;
+                while runner != self.idom[b] {
+                    if !frontiers[runner].contains(&b) {
+                        frontiers[runner].push(b);
+                    }
+                    runner = self.idom[runner];
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The CFGs of the functions in `ast` as a Graphviz graph, one cluster per
+/// function. Each block is labelled with its immediate dominator.
+pub fn to_dot(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+) -> Result<String, Error> {
+    let mut out = String::from(
+        "digraph CFG {\n    node [shape=box, fontname=\"monospace\"];\n",
+    );
+    let functions = ast.external_declarations.iter().filter_map(|d| match d {
+        ExternalDeclaration::FunctionDeclaration(f) => Some(f),
+        ExternalDeclaration::Declaration(_) => None,
+    });
+    for (n, f) in functions.enumerate() {
+        let cfg = Cfg::build(f)?;
+        let dominators = cfg.dominators();
+        let (id, _) = f.declarator.identifier();
+        writeln!(out, "    subgraph cluster_{n} {{").unwrap();
+        writeln!(out, "        label=\"{}\";", lexer_output.name(id)).unwrap();
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            let mut label = match dominators.idom(b) {
+                Some(idom) => format!("B{b} (idom B{idom})\\l"),
+                None => format!("B{b} (entry)\\l"),
+            };
+            for i in &block.instructions {
+                let line = match i {
+                    Instruction::Declaration(d) => {
+                        print_declaration(d, lexer_output)
+                    }
+                    Instruction::Expression(e) => {
+                        format!("{};", print_expression(e, lexer_output))
+                    }
+                };
+                write!(label, "{}\\l", escape(&line)).unwrap();
+            }
+            match block.terminator {
+                Terminator::Goto(_) => {}
+                Terminator::Branch { condition, .. } => {
+                    let condition = print_expression(condition, lexer_output);
+                    write!(label, "if ({})\\l", escape(&condition)).unwrap();
+                }
+                Terminator::Return(None) => label.push_str("return;\\l"),
+                Terminator::Return(Some(e)) => {
+                    let e = print_expression(e, lexer_output);
+                    write!(label, "return {};\\l", escape(&e)).unwrap();
+                }
+            }
+            writeln!(out, "        f{n}_b{b} [label=\"{label}\"];").unwrap();
+        }
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            match block.terminator {
+                Terminator::Goto(s) => {
+                    writeln!(out, "        f{n}_b{b} -> f{n}_b{s};").unwrap()
+                }
+                Terminator::Branch {
+                    then, otherwise, ..
+                } => {
+                    writeln!(
+                        out,
+                        "        f{n}_b{b} -> f{n}_b{then} [label=\"true\"];"
+                    )
+                    .unwrap();
+                    writeln!(
+                        out,
+                        "        f{n}_b{b} -> f{n}_b{otherwise} \
+                         [label=\"false\"];"
+                    )
+                    .unwrap();
+                }
+                Terminator::Return(_) => {}
+            }
+        }
+        out.push_str("    }\n");
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{lexer::scan, parser::parse};
+
+    fn parse_source(src: &str) -> (TranslationUnit, LexerOutput) {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        (ast, lexer_output)
+    }
+
+    fn function(ast: &TranslationUnit) -> &FunctionDefinition {
+        match &ast.external_declarations[0] {
+            ExternalDeclaration::FunctionDeclaration(f) => f,
+            ExternalDeclaration::Declaration(_) => panic!("expect a function"),
+        }
+    }
+
+    /// The successors of each block, and its immediate dominator.
+    fn shape(src: &str) -> Vec<(Vec<BlockId>, Option<BlockId>)> {
+        let (ast, _) = parse_source(src);
+        let cfg = Cfg::build(function(&ast)).unwrap();
+        let dominators = cfg.dominators();
+        (0..cfg.blocks.len())
+            .map(|b| (cfg.successors(b), dominators.idom(b)))
+            .collect()
+    }
+
+    #[test_case(
+        "int f(int a) { a = 1; return a; }",
+        vec![(vec![], None)];
+        "straight line"
+    )]
+    #[test_case(
+        "int f(int a) { if (a) a = 1; return a; }",
+        vec![(vec![1, 2], None), (vec![2], Some(0)), (vec![], Some(0))]
+    )]
+    #[test_case(
+        "int f(int a) { if (a) return 1; else return 2; a = 3; }",
+        vec![(vec![1, 2], None), (vec![], Some(0)), (vec![], Some(0))];
+        "unreachable code dropped"
+    )]
+    #[test_case(
+        "int f(int a) { while (a) a = a - 1; return a; }",
+        vec![
+            (vec![1], None),
+            (vec![2, 3], Some(0)),
+            (vec![1], Some(1)),
+            (vec![], Some(1)),
+        ]
+    )]
+    #[test_case(
+        "int f(int a) { do a = a - 1; while (a); return a; }",
+        vec![
+            (vec![1], None),
+            (vec![2], Some(0)),
+            (vec![1, 3], Some(1)),
+            (vec![], Some(2)),
+        ]
+    )]
+    #[test_case(
+        "int f(int a) { int i; for (i = 0; i < a; i = i + 1) { \
+         if (i) continue; if (a) break; } return i; }",
+        vec![
+            (vec![1], None),
+            (vec![2, 5], Some(0)),
+            (vec![4, 3], Some(1)),
+            (vec![5, 4], Some(2)),
+            (vec![1], Some(2)),
+            (vec![], Some(1)),
+        ]
+    )]
+    #[test_case(
+        "int f(int a) { for (;;) ; }",
+        vec![(vec![1], None), (vec![1], Some(0))];
+        "empty infinite loop"
+    )]
+    fn test_build(src: &str, expected: Vec<(Vec<BlockId>, Option<BlockId>)>) {
+        assert_eq!(shape(src), expected);
+    }
+
+    #[test_case("int f(int a) { break; }", "break")]
+    #[test_case("int f(int a) { if (a) continue; }", "continue")]
+    fn test_misplaced_jump(src: &str, jump: &str) {
+        let (ast, _) = parse_source(src);
+        let e = Cfg::build(function(&ast)).unwrap_err();
+        assert_eq!(
+            e.error_kind.message(),
+            format!("'{jump}' cannot be used here")
+        );
+    }
+
+    #[test]
+    fn test_dominates() {
+        // 0 -> 1 -> 2 -> 4, 0 -> 3 -> 4 and 4 -> 1
+        let dominators =
+            Dominators::new(&[vec![1, 3], vec![2], vec![4], vec![4], vec![1]]);
+        let idoms: Vec<_> = (0..5).map(|b| dominators.idom(b)).collect();
+        assert_eq!(idoms, [None, Some(0), Some(1), Some(0), Some(0)]);
+        assert!(dominators.dominates(0, 4));
+        assert!(dominators.dominates(1, 2));
+        assert!(dominators.dominates(2, 2));
+        assert!(!dominators.dominates(3, 4));
+        assert!(!dominators.dominates(2, 1));
+        assert_eq!(
+            dominators.children(),
+            [vec![1, 3, 4], vec![2], vec![], vec![], vec![]]
+        );
+    }
+
+    #[test]
+    fn test_frontiers() {
+        // 0 -> 1 -> 2 -> 1, 1 -> 3, 0 -> 3
+        let successors = [vec![1, 3], vec![2, 3], vec![1], vec![]];
+        let dominators = Dominators::new(&successors);
+        assert_eq!(
+            dominators.frontiers(&successors),
+            [vec![], vec![1, 3], vec![1], vec![]]
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let (ast, lexer_output) = parse_source(
+            "int g; int f(int a) { int b = a; if (a > 0) return b; g = a; }",
+        );
+        assert_eq!(
+            to_dot(&ast, &lexer_output).unwrap(),
+            r#"digraph CFG {
+    node [shape=box, fontname="monospace"];
+    subgraph cluster_0 {
+        label="f";
+        f0_b0 [label="B0 (entry)\lint b = a;\lif (a > 0)\l"];
+        f0_b1 [label="B1 (idom B0)\lreturn b;\l"];
+        f0_b2 [label="B2 (idom B0)\lg = a;\lreturn;\l"];
+        f0_b0 -> f0_b1 [label="true"];
+        f0_b0 -> f0_b2 [label="false"];
+    }
+}
+"#
+        );
+    }
+}
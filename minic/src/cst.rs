@@ -0,0 +1,570 @@
+//! The concrete syntax tree: every token of the source, trivia included,
+//! grouped into nodes by the parser functions that consumed them.
+//!
+//! The parser reports the token range of each node it builds to the
+//! `Recorder` that [`parse_cst`] passes it. The ranges nest, so they give a
+//! tree whose green nodes only know their width and whose red
+//! [`SyntaxNode`]s add positions and parents on top.
+
+use std::{cell::RefCell, cmp::Reverse, fmt::Write, ops::Range, rc::Rc};
+
+use crate::{
+    ast::TranslationUnit,
+    error::Error,
+    parser::{parse, parse_recording},
+    token::{Position, Span, Token, TokenKind},
+    token_dump::kind_name,
+    trivia::{LosslessOutput, LosslessToken, Trivia, TriviaKind},
+};
+
+/// The kinds of nodes, named after the AST types they correspond to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    TranslationUnit,
+    FunctionDefinition,
+    Declaration,
+    DeclarationSpecifier,
+    InitDeclarator,
+    InitializerList,
+    Declarator,
+    DirectDeclarator,
+    FunctionModifier,
+    ArrayModifier,
+    ParameterDeclaration,
+    CompoundStatement,
+    ExpressionStatement,
+    SelectionStatement,
+    WhileStatement,
+    ForStatement,
+    DoStatement,
+    JumpStatement,
+    AssignmentExpression,
+    BinaryExpression,
+    UnaryExpression,
+    SubscriptExpression,
+    CallExpression,
+    ParenthesizedExpression,
+    AtomExpression,
+}
+
+/// A node and the range of tokens it covers.
+type Event = (SyntaxKind, Range<usize>);
+
+/// The nodes the parser reports while [`parse_cst`] runs.
+#[derive(Debug, Default)]
+pub(crate) struct Recorder(RefCell<Vec<Event>>);
+
+impl Recorder {
+    /// Record that the tokens `start..end` form a node of `kind`. Nodes must
+    /// be recorded after the nodes inside them.
+    pub(crate) fn node(&self, kind: SyntaxKind, start: usize, end: usize) {
+        if start < end {
+            self.0.borrow_mut().push((kind, start..end));
+        }
+    }
+
+    /// The number of nodes recorded so far, to [`Recorder::rollback`] to
+    /// when the parser backtracks.
+    pub(crate) fn mark(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    /// Forget the nodes recorded since `mark`.
+    pub(crate) fn rollback(&self, mark: usize) {
+        self.0.borrow_mut().truncate(mark);
+    }
+}
+
+#[derive(Debug)]
+pub struct GreenTrivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct GreenToken {
+    /// `None` for the empty token at the end of the file, which holds the
+    /// trivia after the last token's line
+    pub kind: Option<TokenKind>,
+    pub leading: Vec<GreenTrivia>,
+    pub text: String,
+    pub trailing: Vec<GreenTrivia>,
+}
+
+impl GreenToken {
+    fn leading_width(&self) -> usize {
+        self.leading.iter().map(|t| t.text.len()).sum()
+    }
+
+    fn width(&self) -> usize {
+        let trailing: usize = self.trailing.iter().map(|t| t.text.len()).sum();
+        self.leading_width() + self.text.len() + trailing
+    }
+}
+
+#[derive(Debug)]
+pub struct GreenNode {
+    pub kind: SyntaxKind,
+    /// The length of the text, trivia included
+    pub width: usize,
+    pub children: Vec<GreenElement>,
+}
+
+#[derive(Debug, Clone)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(n) => n.width,
+            GreenElement::Token(t) => t.width(),
+        }
+    }
+}
+
+/// The start of each line, to turn offsets into positions.
+#[derive(Debug)]
+pub(crate) struct LineIndex(Vec<usize>);
+
+impl LineIndex {
+    pub(crate) fn new(src: &str) -> Self {
+        let starts = src.match_indices('\n').map(|(i, _)| i + 1);
+        LineIndex([0].into_iter().chain(starts).collect())
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.0.partition_point(|&start| start <= offset);
+        Position {
+            line,
+            col: offset - self.0[line - 1] + 1,
+        }
+    }
+
+    pub(crate) fn span(&self, range: Range<usize>) -> Span {
+        Span {
+            start: range.start,
+            end: range.end,
+            start_pos: self.position(range.start),
+            end_pos: self.position(range.end),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NodeData {
+    green: Rc<GreenNode>,
+    /// Where the node's text starts, leading trivia included
+    offset: usize,
+    parent: Option<SyntaxNode>,
+    lines: Rc<LineIndex>,
+}
+
+/// A node of the CST, with its position in the file.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    pub fn children(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut children = vec![];
+        for child in &self.0.green.children {
+            children.push(match child {
+                GreenElement::Node(green) => {
+                    SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                        green: green.clone(),
+                        offset,
+                        parent: Some(self.clone()),
+                        lines: self.0.lines.clone(),
+                    })))
+                }
+                GreenElement::Token(green) => {
+                    SyntaxElement::Token(SyntaxToken {
+                        green: green.clone(),
+                        offset,
+                        parent: self.clone(),
+                    })
+                }
+            });
+            offset += child.width();
+        }
+        children
+    }
+
+    pub fn child_nodes(&self) -> impl Iterator<Item = SyntaxNode> {
+        self.children().into_iter().filter_map(|c| match c {
+            SyntaxElement::Node(n) => Some(n),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// The node and the nodes inside it, in preorder.
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut v = vec![self.clone()];
+        for child in self.child_nodes() {
+            v.extend(child.descendants());
+        }
+        v
+    }
+
+    /// The tokens inside the node, in order, without the end of file token.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut v = vec![];
+        for child in self.children() {
+            match child {
+                SyntaxElement::Node(n) => v.extend(n.tokens()),
+                SyntaxElement::Token(t) if t.kind().is_some() => v.push(t),
+                SyntaxElement::Token(_) => {}
+            }
+        }
+        v
+    }
+
+    /// The byte range of the text, trivia included.
+    pub fn full_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width
+    }
+
+    /// The span from the first token to the last, like the spans in the
+    /// AST.
+    pub fn span(&self) -> Span {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span().to(last.span()),
+            _ => Span::default(),
+        }
+    }
+
+    /// The text, trivia included.
+    pub fn text(&self) -> String {
+        let mut s = String::new();
+        write_text(&self.0.green, &mut s);
+        s
+    }
+
+    /// The node of `kind` with the same span as the AST node with `span`.
+    pub fn find(&self, kind: SyntaxKind, span: Span) -> Option<Self> {
+        self.descendants().into_iter().find(|n| {
+            n.kind() == kind && {
+                let s = n.span();
+                (s.start, s.end) == (span.start, span.end)
+            }
+        })
+    }
+
+    /// The AST of the tokens under this node as a translation unit. The
+    /// green tree does not keep the AST, so this parses the tokens again
+    /// rather than converting the tree, at the cost of a full parse.
+    pub fn to_ast(&self) -> Result<TranslationUnit, Error> {
+        let tokens: Vec<_> =
+            self.tokens().iter().map(|t| t.to_token()).collect();
+        parse(&tokens)
+    }
+
+    /// One line per node and token, indented by depth, e.g.
+    /// `AtomExpression@4..5` and `Id "a"`.
+    pub fn dump(&self) -> String {
+        let mut s = String::new();
+        self.dump_to(&mut s, 0);
+        s
+    }
+
+    fn dump_to(&self, s: &mut String, depth: usize) {
+        let range = self.full_range();
+        let indent = "  ".repeat(depth);
+        writeln!(
+            s,
+            "{indent}{:?}@{}..{}",
+            self.kind(),
+            range.start,
+            range.end
+        )
+        .unwrap();
+        for child in self.children() {
+            match child {
+                SyntaxElement::Node(n) => n.dump_to(s, depth + 1),
+                SyntaxElement::Token(t) => {
+                    let kind = t.kind().map_or("Eof", kind_name);
+                    writeln!(s, "{indent}  {kind} {:?}", t.text()).unwrap();
+                }
+            }
+        }
+    }
+}
+
+fn write_text(green: &GreenNode, s: &mut String) {
+    for child in &green.children {
+        match child {
+            GreenElement::Node(n) => write_text(n, s),
+            GreenElement::Token(t) => {
+                t.leading.iter().for_each(|t| s.push_str(&t.text));
+                s.push_str(&t.text);
+                t.trailing.iter().for_each(|t| s.push_str(&t.text));
+            }
+        }
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> Option<&TokenKind> {
+        self.green.kind.as_ref()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+
+    pub fn leading(&self) -> &[GreenTrivia] {
+        &self.green.leading
+    }
+
+    pub fn trailing(&self) -> &[GreenTrivia] {
+        &self.green.trailing
+    }
+
+    /// The span of the token without its trivia.
+    pub fn span(&self) -> Span {
+        let start = self.offset + self.green.leading_width();
+        self.parent
+            .0
+            .lines
+            .span(start..start + self.green.text.len())
+    }
+
+    /// The token as the lexer gives it.
+    pub fn to_token(&self) -> Token {
+        Token {
+            kind: self.kind().expect("the end of file is no token").clone(),
+            span: self.span(),
+        }
+    }
+}
+
+/// Parse `output`, giving both the AST and the CST.
+pub fn parse_cst(
+    output: &LosslessOutput,
+) -> Result<(TranslationUnit, SyntaxNode), Error> {
+    let recorder = Recorder::default();
+    let ast = parse_recording(&output.lexer_output.tokens, &recorder)?;
+    let events = recorder.0.into_inner();
+    let mut events: Vec<_> = events.into_iter().enumerate().collect();
+    // Outer nodes first. Of nodes with the same range, the outer one is
+    // recorded last.
+    events.sort_by_key(|(n, (_, range))| {
+        (range.start, Reverse(range.end), Reverse(*n))
+    });
+    let mut events = events.into_iter().map(|(_, e)| e).peekable();
+    let builder = Builder {
+        src: &output.src,
+        tokens: &output.tokens,
+    };
+    let mut root = builder.node(
+        SyntaxKind::TranslationUnit,
+        0..output.tokens.len(),
+        &mut events,
+    );
+    debug_assert!(events.next().is_none());
+    let eof = GreenElement::Token(Rc::new(GreenToken {
+        kind: None,
+        leading: builder.trivia(&output.end),
+        text: String::new(),
+        trailing: vec![],
+    }));
+    root.width += eof.width();
+    root.children.push(eof);
+    let root = SyntaxNode(Rc::new(NodeData {
+        green: Rc::new(root),
+        offset: 0,
+        parent: None,
+        lines: Rc::new(LineIndex::new(&output.src)),
+    }));
+    Ok((ast, root))
+}
+
+struct Builder<'a> {
+    src: &'a str,
+    tokens: &'a [LosslessToken],
+}
+
+impl Builder<'_> {
+    /// The node of `kind` over the tokens in `range`, taking the nodes
+    /// inside it from `events`.
+    fn node(
+        &self,
+        kind: SyntaxKind,
+        range: Range<usize>,
+        events: &mut std::iter::Peekable<impl Iterator<Item = Event>>,
+    ) -> GreenNode {
+        let mut children = vec![];
+        let mut next = range.start;
+        while let Some((_, child)) = events.peek() {
+            if child.start >= range.end {
+                break;
+            }
+            debug_assert!(child.end <= range.end, "nodes must nest");
+            let (child_kind, child) = events.next().unwrap();
+            children.extend((next..child.start).map(|i| self.token(i)));
+            next = child.end;
+            let node = self.node(child_kind, child, events);
+            children.push(GreenElement::Node(Rc::new(node)));
+        }
+        children.extend((next..range.end).map(|i| self.token(i)));
+        GreenNode {
+            kind,
+            width: children.iter().map(GreenElement::width).sum(),
+            children,
+        }
+    }
+
+    fn token(&self, i: usize) -> GreenElement {
+        let t = &self.tokens[i];
+        GreenElement::Token(Rc::new(GreenToken {
+            kind: Some(t.token.kind.clone()),
+            leading: self.trivia(&t.leading),
+            text: self.src[t.token.span.start..t.token.span.end].to_owned(),
+            trailing: self.trivia(&t.trailing),
+        }))
+    }
+
+    fn trivia(&self, trivia: &[Trivia]) -> Vec<GreenTrivia> {
+        trivia
+            .iter()
+            .map(|t| GreenTrivia {
+                kind: t.kind,
+                text: self.src[t.span.start..t.span.end].to_owned(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{
+        ast::{with_spans, ExternalDeclaration},
+        trivia::scan_lossless,
+    };
+
+    fn cst(src: &str) -> (TranslationUnit, SyntaxNode) {
+        parse_cst(&scan_lossless(src).unwrap()).unwrap()
+    }
+
+    fn json(ast: &TranslationUnit) -> String {
+        with_spans(|| serde_json::to_string(ast).unwrap())
+    }
+
+    fn check(src: &str) {
+        let (ast, root) = cst(src);
+        assert_eq!(root.text(), src);
+        assert_eq!(json(&root.to_ast().unwrap()), json(&ast));
+        for node in root.descendants() {
+            let text = node.text();
+            assert_eq!(&src[node.full_range()], text);
+        }
+    }
+
+    #[test_case(""; "empty")]
+    #[test_case("// only a comment\n"; "only_trivia")]
+    #[test_case("int a = (1), b[2] = {1, 2};\n\n// end\n"; "declarations")]
+    #[test_case("int f(int a) { a = g(a)[1]; if (!a) return; }"; "function")]
+    #[test_case("int a = b = c;"; "assignment")]
+    fn test_lossless(src: &str) {
+        check(src);
+    }
+
+    #[test]
+    fn test_testcases() {
+        let mut count = 0;
+        for entry in fs::read_dir("testcase/c").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "c") {
+                check(&fs::read_to_string(&path).unwrap());
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_dump() {
+        let (_, root) = cst("int a = (1 + b); // c\n");
+        let expected = r#"TranslationUnit@0..22
+  Declaration@0..22
+    DeclarationSpecifier@0..4
+      Qualifier "int"
+    InitDeclarator@4..15
+      Declarator@4..6
+        DirectDeclarator@4..6
+          Id "a"
+      Relop "="
+      ParenthesizedExpression@8..15
+        LeftParen "("
+        BinaryExpression@9..14
+          AtomExpression@9..11
+            IntegerConstant "1"
+          Plus "+"
+          AtomExpression@13..14
+            Id "b"
+        RightParen ")"
+    Semicolon ";"
+  Eof ""
+"#;
+        assert_eq!(root.dump(), expected);
+    }
+
+    #[test]
+    fn test_find() {
+        let src = "int f(int a) {\n    return (a);\n}\n";
+        let (ast, root) = cst(src);
+        let ExternalDeclaration::FunctionDeclaration(f) =
+            &ast.external_declarations[0]
+        else {
+            panic!("expect a function");
+        };
+        let node = root.find(SyntaxKind::FunctionDefinition, f.span).unwrap();
+        assert_eq!(node.parent().unwrap().kind(), SyntaxKind::TranslationUnit);
+        let body = node
+            .find(SyntaxKind::CompoundStatement, f.compound_statement.span)
+            .unwrap();
+        let tokens = body.tokens();
+        let texts: Vec<_> = tokens.iter().map(|t| t.text()).collect();
+        assert_eq!(texts, ["{", "return", "(", "a", ")", ";", "}"]);
+        let a = &tokens[3];
+        assert_eq!(a.parent().kind(), SyntaxKind::AtomExpression);
+        assert_eq!(a.span().start_pos, Position { line: 2, col: 13 });
+        assert_eq!(tokens[0].trailing()[0].kind, TriviaKind::Newline);
+        assert_eq!(tokens[1].leading()[0].text, "    ");
+    }
+}
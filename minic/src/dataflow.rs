@@ -0,0 +1,856 @@
+//! Dataflow analyses over [`Cfg`]s: a worklist solver for any analysis
+//! whose facts form a [`Lattice`], and three analyses of the local
+//! variables of a function built on it.
+//!
+//! The variables tracked are the parameters and locals of scalar type
+//! whose address is never taken, so that only assignments to their names
+//! change them.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use crate::{
+    ast::{visit::walk_unary_expression, *},
+    cfg::{reverse_postorder, BlockId, Cfg, Instruction, Terminator},
+    diagnostic::{FixIt, Note},
+    error::{Details, Error, ErrorKind},
+    lexer::LexerOutput,
+    resolve::Reference,
+    token::Span,
+    types::Type,
+};
+
+/// Facts about a program point, where paths meet.
+pub trait Lattice: Clone + PartialEq {
+    /// Combine the facts that hold on another path into these.
+    fn join(&mut self, other: &Self);
+}
+
+/// A set of facts any of which may hold, e.g. definitions that may reach a
+/// point.
+impl<T: Ord + Clone> Lattice for BTreeSet<T> {
+    fn join(&mut self, other: &Self) {
+        self.extend(other.iter().cloned());
+    }
+}
+
+/// A set of facts that must hold on every path. `None` is the set of all
+/// facts, which holds at a point no path has reached yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Must<T>(pub Option<BTreeSet<T>>);
+
+impl<T: Ord + Clone> Must<T> {
+    pub fn contains(&self, x: &T) -> bool {
+        self.0.as_ref().is_none_or(|set| set.contains(x))
+    }
+
+    pub fn insert(&mut self, x: T) {
+        if let Some(set) = &mut self.0 {
+            set.insert(x);
+        }
+    }
+
+    pub fn remove(&mut self, x: &T) {
+        if let Some(set) = &mut self.0 {
+            set.remove(x);
+        }
+    }
+}
+
+impl<T: Ord + Clone> Lattice for Must<T> {
+    fn join(&mut self, other: &Self) {
+        match (&mut self.0, &other.0) {
+            (_, None) => {}
+            (None, Some(other)) => self.0 = Some(other.clone()),
+            (Some(set), Some(other)) => set.retain(|x| other.contains(x)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Facts flow from the entry along edges
+    Forward,
+    /// Facts flow from the exits against edges
+    Backward,
+}
+
+pub trait Analysis {
+    type Domain: Lattice;
+    const DIRECTION: Direction;
+
+    /// The facts at the entry of a forward analysis or at the exits of a
+    /// backward one.
+    fn boundary(&self) -> Self::Domain;
+
+    /// The facts a block starts with, which joining leaves unchanged.
+    fn bottom(&self) -> Self::Domain;
+
+    /// Update `state` across block `b`, in the direction of the analysis.
+    fn transfer(&self, b: BlockId, state: &mut Self::Domain);
+}
+
+/// The facts that hold before and after each block, in program order.
+#[derive(Debug)]
+pub struct Results<D> {
+    pub before: Vec<D>,
+    pub after: Vec<D>,
+}
+
+/// Run `analysis` to a fixed point on the graph with `successors`, whose
+/// blocks are all reachable from block 0.
+pub fn solve<A: Analysis>(
+    analysis: &A,
+    successors: &[Vec<BlockId>],
+) -> Results<A::Domain> {
+    let mut predecessors = vec![vec![]; successors.len()];
+    for (b, s) in successors.iter().enumerate() {
+        for &s in s {
+            predecessors[s].push(b);
+        }
+    }
+    // Facts flow into a block from `sources` and out of it to `targets`.
+    let (sources, targets) = match A::DIRECTION {
+        Direction::Forward => (&predecessors[..], successors),
+        Direction::Backward => (successors, &predecessors[..]),
+    };
+    let is_boundary = |b: BlockId| match A::DIRECTION {
+        Direction::Forward => b == 0,
+        Direction::Backward => successors[b].is_empty(),
+    };
+    // Visiting sources first makes most blocks settle in one pass.
+    let mut order = reverse_postorder(successors);
+    if A::DIRECTION == Direction::Backward {
+        order.reverse();
+    }
+
+    let mut input = vec![analysis.bottom(); successors.len()];
+    let mut output = input.clone();
+    let mut queued = vec![true; successors.len()];
+    let mut worklist: VecDeque<_> = order.into();
+    while let Some(b) = worklist.pop_front() {
+        queued[b] = false;
+        let mut state = if is_boundary(b) {
+            analysis.boundary()
+        } else {
+            analysis.bottom()
+        };
+        for &s in &sources[b] {
+            state.join(&output[s]);
+        }
+        input[b] = state.clone();
+        analysis.transfer(b, &mut state);
+        if state != output[b] {
+            output[b] = state;
+            for &t in &targets[b] {
+                if !queued[t] {
+                    queued[t] = true;
+                    worklist.push_back(t);
+                }
+            }
+        }
+    }
+    match A::DIRECTION {
+        Direction::Forward => Results {
+            before: input,
+            after: output,
+        },
+        Direction::Backward => Results {
+            before: output,
+            after: input,
+        },
+    }
+}
+
+pub type VariableId = usize;
+pub type DefinitionId = usize;
+
+#[derive(Debug)]
+pub struct Variable {
+    pub id: usize,
+    pub declarator: Span,
+    pub ty: Type,
+    pub is_parameter: bool,
+    pub has_initializer: bool,
+}
+
+/// A parameter, an initializer or an assignment that stores a value in a
+/// variable.
+#[derive(Debug, Clone, Copy)]
+pub struct Definition {
+    pub variable: VariableId,
+    pub span: Span,
+}
+
+/// What a block does to the variables, in the order it happens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The value of the variable is read at `span`.
+    Use(VariableId, Span),
+    /// A value is stored, for sure or, as in the right operand of `&&`,
+    /// maybe.
+    Def {
+        definition: DefinitionId,
+        definite: bool,
+    },
+    /// The variable comes into scope without a value.
+    Declare(VariableId),
+}
+
+/// The variables of a function and the events of each block of its CFG.
+#[derive(Debug)]
+pub struct Facts {
+    pub variables: Vec<Variable>,
+    pub definitions: Vec<Definition>,
+    /// The definitions of the parameters by the caller, which hold on entry
+    pub parameters: Vec<DefinitionId>,
+    pub events: Vec<Vec<Event>>,
+}
+
+impl Facts {
+    /// The facts of `cfg`, whose identifiers refer to their declarations as
+    /// `references` say.
+    pub fn new(cfg: &Cfg, references: &[Reference]) -> Self {
+        let declarations: HashMap<_, _> = references
+            .iter()
+            .map(|r| (r.span.start, r.declaration.start))
+            .collect();
+        let mut builder = FactsBuilder {
+            declarations,
+            variables: HashMap::new(),
+            facts: Facts {
+                variables: vec![],
+                definitions: vec![],
+                parameters: vec![],
+                events: vec![],
+            },
+        };
+        builder.declare_variables(cfg);
+
+        let parameters = cfg.function.declarator.parameters().unwrap_or(&[]);
+        for p in parameters {
+            let (_, span) = p.declarator.identifier();
+            if let Some(&v) = builder.variables.get(&span.start) {
+                let definitions = &mut builder.facts.definitions;
+                builder.facts.parameters.push(definitions.len());
+                definitions.push(Definition { variable: v, span });
+            }
+        }
+        let mut events = vec![];
+        for block in &cfg.blocks {
+            for &i in &block.instructions {
+                match i {
+                    Instruction::Declaration(d) => {
+                        builder.declaration(d, &mut events)
+                    }
+                    Instruction::Expression(e) => {
+                        builder.expression(e, true, &mut events)
+                    }
+                }
+            }
+            match block.terminator {
+                Terminator::Branch { condition: e, .. }
+                | Terminator::Return(Some(e)) => {
+                    builder.expression(e, true, &mut events)
+                }
+                Terminator::Goto(_) | Terminator::Return(None) => {}
+            }
+            builder.facts.events.push(std::mem::take(&mut events));
+        }
+        builder.facts
+    }
+}
+
+struct FactsBuilder {
+    /// The start of the declaration each identifier refers to
+    declarations: HashMap<usize, usize>,
+    /// The variable declared at each start
+    variables: HashMap<usize, VariableId>,
+    facts: Facts,
+}
+
+impl FactsBuilder {
+    /// Find the variables to track.
+    fn declare_variables(&mut self, cfg: &Cfg) {
+        let mut declare = |specifier,
+                           declarator: &Declarator,
+                           is_parameter,
+                           has_initializer| {
+            let ty = Type::of(specifier, declarator);
+            if ty.is_scalar() {
+                let (id, span) = declarator.identifier();
+                self.variables
+                    .insert(span.start, self.facts.variables.len());
+                self.facts.variables.push(Variable {
+                    id,
+                    declarator: declarator.span,
+                    ty,
+                    is_parameter,
+                    has_initializer,
+                });
+            }
+        };
+        let parameters = cfg.function.declarator.parameters().unwrap_or(&[]);
+        for p in parameters {
+            declare(&p.specifier, &p.declarator, true, false);
+        }
+        let mut address_taken = AddressTaken {
+            declarations: &self.declarations,
+            starts: vec![],
+        };
+        for block in &cfg.blocks {
+            for &i in &block.instructions {
+                match i {
+                    Instruction::Declaration(d) => {
+                        for i in &d.init_declarator_list.0 {
+                            declare(
+                                &d.declaration_specifier,
+                                &i.declarator,
+                                false,
+                                i.initializer.is_some(),
+                            );
+                        }
+                        address_taken.visit_declaration(d);
+                    }
+                    Instruction::Expression(e) => {
+                        address_taken.visit_expression(e)
+                    }
+                }
+            }
+            if let Terminator::Branch { condition: e, .. }
+            | Terminator::Return(Some(e)) = block.terminator
+            {
+                address_taken.visit_expression(e);
+            }
+        }
+        // Anything may happen to them through a pointer.
+        for start in address_taken.starts {
+            self.variables.remove(&start);
+        }
+    }
+
+    /// The variable `span` refers to, if it is tracked.
+    fn variable(&self, span: Span) -> Option<VariableId> {
+        let declaration = self.declarations.get(&span.start)?;
+        self.variables.get(declaration).copied()
+    }
+
+    fn def(
+        &mut self,
+        variable: VariableId,
+        span: Span,
+        definite: bool,
+    ) -> Event {
+        self.facts.definitions.push(Definition { variable, span });
+        Event::Def {
+            definition: self.facts.definitions.len() - 1,
+            definite,
+        }
+    }
+
+    fn declaration(&mut self, d: &Declaration, events: &mut Vec<Event>) {
+        for i in &d.init_declarator_list.0 {
+            if let Some(initializer) = &i.initializer {
+                self.initializer(initializer, events);
+            }
+            let (_, span) = i.declarator.identifier();
+            let Some(&v) = self.variables.get(&span.start) else {
+                continue;
+            };
+            let event = match &i.initializer {
+                Some(_) => self.def(v, i.span, true),
+                None => Event::Declare(v),
+            };
+            events.push(event);
+        }
+    }
+
+    fn initializer(&mut self, i: &Initializer, events: &mut Vec<Event>) {
+        match i {
+            Initializer::Expression(e) => self.expression(e, true, events),
+            Initializer::List(list, _) => {
+                for i in list {
+                    self.initializer(i, events);
+                }
+            }
+        }
+    }
+
+    /// Push the events of `e` in the order they happen. Its definitions are
+    /// `definite` if it is sure to be evaluated.
+    fn expression(
+        &mut self,
+        e: &Expression,
+        definite: bool,
+        events: &mut Vec<Event>,
+    ) {
+        match e {
+            Expression::Assignment(a) => {
+                let target = match &*a.left {
+                    Expression::Atom(AtomExpression::Identifier(_), span) => {
+                        self.variable(*span)
+                    }
+                    _ => None,
+                };
+                if target.is_none() {
+                    self.expression(&a.left, definite, events);
+                }
+                self.expression(&a.right, definite, events);
+                if let Some(v) = target {
+                    let event = self.def(v, a.span, definite);
+                    events.push(event);
+                }
+            }
+            Expression::Binary(b) => {
+                self.expression(&b.left, definite, events);
+                let short_circuit = matches!(
+                    b.operator,
+                    BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr
+                );
+                self.expression(&b.right, definite && !short_circuit, events);
+            }
+            Expression::Unary(u) => {
+                self.expression(&u.operand, definite, events)
+            }
+            Expression::Postfix(p) => {
+                self.expression(&p.operand, definite, events);
+                match &p.postfix {
+                    PostfixExpressionPostfix::Subscript(i) => {
+                        self.expression(i, definite, events)
+                    }
+                    PostfixExpressionPostfix::Call(arguments) => {
+                        for a in arguments {
+                            self.expression(a, definite, events);
+                        }
+                    }
+                }
+            }
+            Expression::Atom(AtomExpression::Identifier(_), span) => {
+                if let Some(v) = self.variable(*span) {
+                    events.push(Event::Use(v, *span));
+                }
+            }
+            Expression::Atom(..) => {}
+        }
+    }
+}
+
+/// Collects the declarations of the identifiers whose address is taken.
+pub(crate) struct AddressTaken<'a> {
+    /// The start of the declaration each identifier refers to
+    pub(crate) declarations: &'a HashMap<usize, usize>,
+    pub(crate) starts: Vec<usize>,
+}
+
+impl Visitor for AddressTaken<'_> {
+    fn visit_unary_expression(&mut self, e: &UnaryExpression) {
+        if let (
+            UnaryOperator::Address,
+            Expression::Atom(AtomExpression::Identifier(_), span),
+        ) = (&e.operator, &*e.operand)
+        {
+            if let Some(&start) = self.declarations.get(&span.start) {
+                self.starts.push(start);
+            }
+        }
+        walk_unary_expression(self, e);
+    }
+}
+
+/// The variables whose value may be read later, computed backward.
+pub struct LiveVariables<'a>(pub &'a Facts);
+
+impl LiveVariables<'_> {
+    pub fn step(&self, state: &mut BTreeSet<VariableId>, event: &Event) {
+        match *event {
+            Event::Use(v, _) => {
+                state.insert(v);
+            }
+            Event::Def {
+                definition,
+                definite: true,
+            } => {
+                state.remove(&self.0.definitions[definition].variable);
+            }
+            Event::Def { .. } => {}
+            Event::Declare(v) => {
+                state.remove(&v);
+            }
+        }
+    }
+}
+
+impl Analysis for LiveVariables<'_> {
+    type Domain = BTreeSet<VariableId>;
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn bottom(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn transfer(&self, b: BlockId, state: &mut Self::Domain) {
+        for event in self.0.events[b].iter().rev() {
+            self.step(state, event);
+        }
+    }
+}
+
+/// The definitions whose value a variable may still hold.
+pub struct ReachingDefinitions<'a>(pub &'a Facts);
+
+impl ReachingDefinitions<'_> {
+    pub fn step(&self, state: &mut BTreeSet<DefinitionId>, event: &Event) {
+        let definitions = &self.0.definitions;
+        match *event {
+            Event::Use(..) => {}
+            Event::Def {
+                definition,
+                definite,
+            } => {
+                let v = definitions[definition].variable;
+                if definite {
+                    state.retain(|&d| definitions[d].variable != v);
+                }
+                state.insert(definition);
+            }
+            Event::Declare(v) => {
+                state.retain(|&d| definitions[d].variable != v)
+            }
+        }
+    }
+}
+
+impl Analysis for ReachingDefinitions<'_> {
+    type Domain = BTreeSet<DefinitionId>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Domain {
+        self.0.parameters.iter().copied().collect()
+    }
+
+    fn bottom(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn transfer(&self, b: BlockId, state: &mut Self::Domain) {
+        for event in &self.0.events[b] {
+            self.step(state, event);
+        }
+    }
+}
+
+/// The variables assigned a value on every path.
+pub struct DefiniteAssignment<'a>(pub &'a Facts);
+
+impl DefiniteAssignment<'_> {
+    pub fn step(&self, state: &mut Must<VariableId>, event: &Event) {
+        match *event {
+            Event::Use(..)
+            | Event::Def {
+                definite: false, ..
+            } => {}
+            Event::Def { definition, .. } => {
+                state.insert(self.0.definitions[definition].variable)
+            }
+            Event::Declare(v) => state.remove(&v),
+        }
+    }
+}
+
+impl Analysis for DefiniteAssignment<'_> {
+    type Domain = Must<VariableId>;
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Domain {
+        let definitions = &self.0.definitions;
+        let parameters = self.0.parameters.iter();
+        Must(Some(parameters.map(|&d| definitions[d].variable).collect()))
+    }
+
+    fn bottom(&self) -> Self::Domain {
+        Must(None)
+    }
+
+    fn transfer(&self, b: BlockId, state: &mut Self::Domain) {
+        for event in &self.0.events[b] {
+            self.step(state, event);
+        }
+    }
+}
+
+/// Warnings about reads of local variables that are declared without an
+/// initializer and may not have been assigned on some path to the read,
+/// one for each variable. Functions with a misplaced `break` or `continue`
+/// are skipped.
+pub fn uninitialized(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+    references: &[Reference],
+) -> Vec<Error> {
+    let mut errors = vec![];
+    for d in &ast.external_declarations {
+        let ExternalDeclaration::FunctionDeclaration(f) = d else {
+            continue;
+        };
+        let Ok(cfg) = Cfg::build(f) else {
+            continue;
+        };
+        let facts = Facts::new(&cfg, references);
+        let analysis = DefiniteAssignment(&facts);
+        let results = solve(&analysis, &cfg.successor_lists());
+        let mut warned = vec![false; facts.variables.len()];
+        for (b, events) in facts.events.iter().enumerate() {
+            let mut state = results.before[b].clone();
+            for event in events {
+                if let Event::Use(v, span) = *event {
+                    // Reads in a variable's own initializer are not reported.
+                    let tracked = !facts.variables[v].has_initializer;
+                    if tracked && !state.contains(&v) && !warned[v] {
+                        warned[v] = true;
+                        errors.push(uninitialized_read(
+                            &facts.variables[v],
+                            span,
+                            lexer_output,
+                        ));
+                    }
+                }
+                analysis.step(&mut state, event);
+            }
+        }
+    }
+    errors.sort_by_key(|e| e.span.start);
+    errors
+}
+
+fn uninitialized_read(
+    variable: &Variable,
+    span: Span,
+    lexer_output: &LexerOutput,
+) -> Error {
+    let name = lexer_output.name(variable.id);
+    Error {
+        span,
+        error_kind: ErrorKind::UninitializedVariable(name.to_owned()),
+        details: Box::new(Details {
+            notes: vec![Note {
+                span: variable.declarator,
+                message: format!(
+                    "initialize the variable '{name}' to silence this warning"
+                ),
+            }],
+            fix_its: vec![FixIt::insert_after(variable.declarator, " = 0")],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{lexer::scan, parser::parse, resolve::analyze};
+
+    /// Run `f` on the facts of the only function in `src`, with its CFG.
+    fn with_facts<T>(
+        src: &str,
+        f: impl FnOnce(&Facts, &Cfg, &LexerOutput) -> T,
+    ) -> T {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let references = analyze(&ast, &lexer_output).references;
+        let ExternalDeclaration::FunctionDeclaration(function) =
+            &ast.external_declarations[0]
+        else {
+            panic!("expect a function");
+        };
+        let cfg = Cfg::build(function).unwrap();
+        let facts = Facts::new(&cfg, &references);
+        f(&facts, &cfg, &lexer_output)
+    }
+
+    /// The variables live on entry to each block.
+    fn live_in(src: &str) -> Vec<String> {
+        with_facts(src, |facts, cfg, lexer_output| {
+            let results = solve(&LiveVariables(facts), &cfg.successor_lists());
+            results
+                .before
+                .iter()
+                .map(|live| {
+                    let names: Vec<_> = live
+                        .iter()
+                        .map(|&v| lexer_output.name(facts.variables[v].id))
+                        .collect();
+                    names.join(" ")
+                })
+                .collect()
+        })
+    }
+
+    #[test_case(
+        "int f(int a, int b) { int c = a; a = 2; return c; }",
+        &["a"];
+        "straight line"
+    )]
+    #[test_case(
+        "int f(int a, int b) { if (a) b = 1; return b; }",
+        &["a b", "", "b"];
+        "branch"
+    )]
+    #[test_case(
+        "int f(int n) { int s = 0; while (n) { s = s + n; n = n - 1; } \
+         return s; }",
+        &["n", "n s", "n s", "s"];
+        "while loop"
+    )]
+    #[test_case(
+        "int f(int *p) { int a; *p = 1; a = 2; return a; }",
+        &["p"];
+        "store through a pointer"
+    )]
+    fn test_live_variables(src: &str, expected: &[&str]) {
+        assert_eq!(live_in(src), expected);
+    }
+
+    /// The definitions that reach the entry of each block, by source text.
+    fn reaching(src: &str) -> Vec<Vec<&str>> {
+        with_facts(src, |facts, cfg, _| {
+            let results =
+                solve(&ReachingDefinitions(facts), &cfg.successor_lists());
+            results
+                .before
+                .iter()
+                .map(|reaching| {
+                    let mut definitions: Vec<_> = reaching
+                        .iter()
+                        .map(|&d| {
+                            let span = facts.definitions[d].span;
+                            &src[span.start..span.end]
+                        })
+                        .collect();
+                    definitions.sort();
+                    definitions
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_reaching_definitions() {
+        let src = "int f(int n) { int i = 0; while (i < n) i = i + 1; \
+                   return i; }";
+        let loop_entry = vec!["i = 0", "i = i + 1", "n"];
+        assert_eq!(
+            reaching(src),
+            [
+                vec!["n"],
+                loop_entry.clone(),
+                loop_entry.clone(),
+                loop_entry
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reaching_definitions_maybe() {
+        let src = "int f(int a) { int b = 1; if (a && (b = 2)) a = 3; \
+                   return b; }";
+        assert_eq!(
+            reaching(src)[1..],
+            [
+                vec!["a", "b = 1", "b = 2"],
+                vec!["a", "a = 3", "b = 1", "b = 2"]
+            ]
+        );
+    }
+
+    /// The codes and line:col of the warnings about `src`.
+    fn warnings(src: &str) -> Vec<String> {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let references = analyze(&ast, &lexer_output).references;
+        uninitialized(&ast, &lexer_output, &references)
+            .iter()
+            .map(|e| {
+                let pos = e.span.start_pos;
+                format!("{} {}:{}", e.error_kind.code(), pos.line, pos.col)
+            })
+            .collect()
+    }
+
+    #[test_case("int f(int u) { int a; return a; }", &["E0030 1:30"])]
+    #[test_case("int f(int u) { int a = 1; return a; }", &[])]
+    #[test_case("int f(int b) { return b; }", &[]; "parameter")]
+    #[test_case(
+        "int f(int b) { int a; if (b) a = 1; return a; }",
+        &["E0030 1:44"];
+        "assigned on one path"
+    )]
+    #[test_case(
+        "int f(int b) { int a; if (b) a = 1; else a = 2; return a; }",
+        &[];
+        "assigned on both paths"
+    )]
+    #[test_case(
+        "int f(int b) { int a; while (b) { a = b; b = b - 1; } return a; }",
+        &["E0030 1:62"];
+        "loop may not run"
+    )]
+    #[test_case(
+        "int f(int u) { int a; do a = 1; while (0); return a; }",
+        &[];
+        "do loop runs once"
+    )]
+    #[test_case(
+        "int f(int b) { int a; if (b && (a = b)) return a; return 0; }",
+        &["E0030 1:48"];
+        "short circuit"
+    )]
+    #[test_case("int f(int u) { int a; a = a + 1; return a; }", &["E0030 1:27"])]
+    #[test_case(
+        "int f(int u) { int a = a; int *p = g(sizeof(*p)); return a; }",
+        &[];
+        "self-referencing initializer"
+    )]
+    #[test_case(
+        "int g(int *p); int f(int u) { int a; g(&a); return a; }",
+        &[];
+        "address taken"
+    )]
+    #[test_case("int f(int u) { int a[2]; return a[0]; }", &[]; "array")]
+    #[test_case(
+        "int f(int u) { int a; return a + a; }",
+        &["E0030 1:30"];
+        "one warning per variable"
+    )]
+    #[test_case(
+        "int f(int b) { while (b) { int a; if (b) a = 1; b = a; } \
+         return 0; }",
+        &["E0030 1:53"];
+        "declared in a loop"
+    )]
+    fn test_uninitialized(src: &str, expected: &[&str]) {
+        assert_eq!(warnings(src), expected);
+    }
+
+    #[test]
+    fn test_uninitialized_fix_it() {
+        let src = "int f(int u) { double a, b = 1.0; return a + b; }";
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let references = analyze(&ast, &lexer_output).references;
+        let errors = uninitialized(&ast, &lexer_output, &references);
+        let fixed =
+            crate::diagnostic::apply_fix_its(src, &errors[0].details.fix_its);
+        assert_eq!(
+            fixed,
+            "int f(int u) { double a = 0, b = 1.0; return a + b; }"
+        );
+        assert_eq!(
+            errors[0].error_kind.message(),
+            "variable 'a' may be uninitialized when used here"
+        );
+    }
+}
@@ -0,0 +1,587 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use colored::{ColoredString, Colorize};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    error::Error,
+    token::{Position, Span},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiagnosticsFormat {
+    /// Coloured source excerpts on stderr
+    Human,
+    /// A JSON document on stderr
+    Json,
+    /// A SARIF 2.1.0 log on stderr
+    Sarif,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, strum_macros::Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn paint(self, s: &str) -> ColoredString {
+        match self {
+            Severity::Error => s.red(),
+            Severity::Warning => s.yellow(),
+        }
+    }
+}
+
+/// A machine-applicable edit: replace the source covered by `span` with
+/// `replacement`. An empty span is an insertion and an empty replacement is
+/// a deletion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FixIt {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl FixIt {
+    pub fn insert_after(span: Span, text: impl Into<String>) -> Self {
+        FixIt {
+            span: span.after(),
+            replacement: text.into(),
+        }
+    }
+
+    pub fn insert_before(span: Span, text: impl Into<String>) -> Self {
+        FixIt {
+            span: Span {
+                end: span.start,
+                end_pos: span.start_pos,
+                ..span
+            },
+            replacement: text.into(),
+        }
+    }
+
+    pub fn replace(span: Span, text: impl Into<String>) -> Self {
+        FixIt {
+            span,
+            replacement: text.into(),
+        }
+    }
+
+    pub fn delete(span: Span) -> Self {
+        FixIt {
+            span,
+            replacement: String::new(),
+        }
+    }
+
+    fn describe(&self, src: &str) -> String {
+        let deleted = &src[self.span.start..self.span.end];
+        let inserted = self.replacement.trim();
+        if self.replacement.is_empty() {
+            format!("remove '{deleted}'")
+        } else if deleted.is_empty() {
+            format!("insert '{inserted}'")
+        } else {
+            format!("replace '{deleted}' with '{inserted}'")
+        }
+    }
+}
+
+/// Apply `fix_its` to `src`. A fix-it overlapping an earlier one is skipped.
+pub fn apply_fix_its(src: &str, fix_its: &[FixIt]) -> String {
+    let mut fix_its: Vec<_> = fix_its.iter().collect();
+    fix_its.sort_by_key(|f| (f.span.start, f.span.end));
+    let mut fixed = String::with_capacity(src.len());
+    let mut copied = 0;
+    for f in fix_its {
+        if f.span.start < copied {
+            continue;
+        }
+        fixed.push_str(&src[copied..f.span.start]);
+        fixed.push_str(&f.replacement);
+        copied = f.span.end;
+    }
+    fixed.push_str(&src[copied..]);
+    fixed
+}
+
+/// Secondary information attached to another location in the source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Note {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<Note>,
+    pub fix_its: Vec<FixIt>,
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(e: &Error) -> Self {
+        Diagnostic {
+            severity: e.error_kind.severity(),
+            code: e.error_kind.code(),
+            message: e.error_kind.message(),
+            span: e.span,
+            notes: e.details.notes.clone(),
+            fix_its: e.details.fix_its.clone(),
+        }
+    }
+}
+
+/// Print `diagnostics` about the file at `file_path` in the given format.
+pub fn emit(
+    diagnostics: &[Diagnostic],
+    file_path: &Path,
+    format: DiagnosticsFormat,
+) -> Result<()> {
+    match format {
+        DiagnosticsFormat::Human => {
+            let src = fs::read_to_string(file_path)?;
+            for d in diagnostics {
+                render_human(d, file_path, &src)?;
+            }
+        }
+        DiagnosticsFormat::Json => {
+            eprintln!("{}", to_json(diagnostics, file_path))
+        }
+        DiagnosticsFormat::Sarif => {
+            let src = fs::read_to_string(file_path)?;
+            eprintln!("{}", to_sarif(diagnostics, file_path, &src))
+        }
+    }
+    Ok(())
+}
+
+/// Print `d` about `src`, the contents of the file at `file_path`, for
+/// people.
+pub fn render_human(d: &Diagnostic, file_path: &Path, src: &str) -> Result<()> {
+    let Position { line, col } = d.span.start_pos;
+    eprintln!(
+        "{}:{}:{}: {}",
+        file_path.display(),
+        line,
+        col,
+        d.severity
+            .paint(&format!("{}[{}]", d.severity, d.code))
+            .bold()
+    );
+    let prefix_len =
+        render_excerpt(d.span, &d.severity.paint(&d.message), src)?;
+    for note in &d.notes {
+        let Position { line, col } = note.span.start_pos;
+        eprintln!(
+            "{}:{}:{}: {}",
+            file_path.display(),
+            line,
+            col,
+            "note".cyan().bold()
+        );
+        render_excerpt(note.span, &note.message.cyan(), src)?;
+    }
+    for f in &d.fix_its {
+        render_fix_it(f, src, prefix_len);
+    }
+    Ok(())
+}
+
+/// Print the lines covered by `span`, underlining the span and labelling it
+/// with `message`. Returns the width of the line number gutter.
+fn render_excerpt(
+    span: Span,
+    message: &ColoredString,
+    src: &str,
+) -> Result<usize> {
+    let Span {
+        start_pos, end_pos, ..
+    } = span;
+    // An empty span still gets a single caret.
+    let last_line = if span.is_empty() || end_pos.col > 1 {
+        end_pos.line
+    } else {
+        end_pos.line - 1
+    };
+    let lines: Vec<_> = src
+        .split('\n')
+        .skip(start_pos.line - 1)
+        .take(last_line - start_pos.line + 1)
+        .collect();
+    if lines.len() != last_line - start_pos.line + 1 {
+        return Err(anyhow!("cannot find line {}", last_line));
+    }
+    let prefix_len = format!("{} | ", last_line).len();
+    for (line_num, line) in (start_pos.line..).zip(lines) {
+        let error_line =
+            format!("{:>width$} | ", line_num, width = prefix_len - 3);
+        eprintln!("{}{}", error_line.blue().bold(), line);
+        let begin = if line_num == start_pos.line {
+            start_pos.col
+        } else {
+            1
+        };
+        let end = if line_num == end_pos.line {
+            end_pos.col
+        } else {
+            line.len() + 1
+        };
+        let (indent, width) = display_columns(line, begin, end);
+        eprint!(
+            "{:>indent$}{}",
+            "",
+            "^".repeat(width.max(1)).yellow().bold(),
+            indent = prefix_len + indent
+        );
+        if line_num == last_line {
+            eprintln!(" {}", message.clone().bold().italic());
+        } else {
+            eprintln!();
+        }
+    }
+    Ok(prefix_len)
+}
+
+/// Show the line `f` applies to as it would read after the fix, marking the
+/// inserted text with `+`, or the removed text with `-` on the original line.
+fn render_fix_it(f: &FixIt, src: &str, prefix_len: usize) {
+    eprintln!("{}: {}", "help".cyan().bold(), f.describe(src));
+    let Span {
+        start_pos, end_pos, ..
+    } = f.span;
+    if start_pos.line != end_pos.line || f.replacement.contains('\n') {
+        return;
+    }
+    let Some(line) = src.split('\n').nth(start_pos.line - 1) else {
+        return;
+    };
+    let (Some(before), Some(after)) =
+        (line.get(..start_pos.col - 1), line.get(end_pos.col - 1..))
+    else {
+        return;
+    };
+    let (shown, marker, width) = if f.replacement.is_empty() {
+        let (_, width) = display_columns(line, start_pos.col, end_pos.col);
+        (line.to_owned(), "-", width)
+    } else {
+        (
+            format!("{before}{}{after}", f.replacement),
+            "+",
+            f.replacement.chars().count(),
+        )
+    };
+    let line_num =
+        format!("{:>width$} | ", start_pos.line, width = prefix_len - 3);
+    eprintln!("{}{}", line_num.blue().bold(), shown);
+    eprintln!(
+        "{:>indent$}{}",
+        "",
+        marker.repeat(width).green().bold(),
+        indent = prefix_len + before.chars().count()
+    );
+}
+
+/// Convert the 1-based byte columns `begin..end` of `line` into the number
+/// of characters before `begin` and the number of characters between them,
+/// so that markers line up under non-ASCII text.
+fn display_columns(line: &str, begin: usize, end: usize) -> (usize, usize) {
+    let count = |from: usize, to: usize| {
+        let to = to.min(line.len());
+        line.get(from.min(to)..to).map_or(0, |s| s.chars().count())
+    };
+    let begin = begin.saturating_sub(1);
+    (count(0, begin), count(begin, end.saturating_sub(1)))
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    file: &'a Path,
+    #[serde(flatten)]
+    diagnostic: &'a Diagnostic,
+}
+
+pub fn to_json(diagnostics: &[Diagnostic], file_path: &Path) -> String {
+    let diagnostics: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| JsonDiagnostic {
+            file: file_path,
+            diagnostic,
+        })
+        .collect();
+    json!({ "version": 1, "diagnostics": diagnostics }).to_string()
+}
+
+fn sarif_location(
+    span: Span,
+    file_path: &Path,
+    src: &str,
+) -> serde_json::Value {
+    json!({
+        "artifactLocation": {
+            "uri": file_path.to_string_lossy(),
+        },
+        "region": {
+            "startLine": span.start_pos.line,
+            "startColumn": char_column(src, span.start),
+            "endLine": span.end_pos.line,
+            "endColumn": char_column(src, span.end),
+            "byteOffset": span.start,
+            "byteLength": span.end - span.start,
+        }
+    })
+}
+
+/// The 1-based column of byte `offset` in `src`, counted in characters.
+fn char_column(src: &str, offset: usize) -> usize {
+    let before = &src[..offset.min(src.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    before[line_start..].chars().count() + 1
+}
+
+/// Serialize `diagnostics` about `src`, the contents of the file at
+/// `file_path`, as a SARIF log. Columns are counted in Unicode code points.
+pub fn to_sarif(
+    diagnostics: &[Diagnostic],
+    file_path: &Path,
+    src: &str,
+) -> String {
+    let mut rules: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+    rules.sort_unstable();
+    rules.dedup();
+    let results: Vec<_> = diagnostics
+        .iter()
+        .map(|d| {
+            let related_locations: Vec<_> = d
+                .notes
+                .iter()
+                .enumerate()
+                .map(|(id, note)| {
+                    json!({
+                        "id": id,
+                        "message": { "text": note.message },
+                        "physicalLocation":
+                            sarif_location(note.span, file_path, src),
+                    })
+                })
+                .collect();
+            let fixes: Vec<_> = d
+                .fix_its
+                .iter()
+                .map(|f| {
+                    json!({
+                        "artifactChanges": [{
+                            "artifactLocation": {
+                                "uri": file_path.to_string_lossy(),
+                            },
+                            "replacements": [{
+                                "deletedRegion": {
+                                    "byteOffset": f.span.start,
+                                    "byteLength": f.span.end - f.span.start,
+                                },
+                                "insertedContent": { "text": f.replacement },
+                            }]
+                        }]
+                    })
+                })
+                .collect();
+            json!({
+                "ruleId": d.code,
+                "ruleIndex": rules.binary_search(&d.code).unwrap(),
+                "level": d.severity.to_string(),
+                "message": { "text": d.message },
+                "locations": [{
+                    "physicalLocation":
+                        sarif_location(d.span, file_path, src),
+                }],
+                "relatedLocations": related_locations,
+                "fixes": fixes,
+            })
+        })
+        .collect();
+    let rules: Vec<_> = rules.iter().map(|id| json!({ "id": id })).collect();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "minic",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }]
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{error::ErrorKind, lexer::scan};
+
+    const SRC: &str = "int a\n = .;";
+
+    fn diagnostics() -> Vec<Diagnostic> {
+        let e = scan(SRC).unwrap_err();
+        assert_eq!(e.error_kind, ErrorKind::ExpectDigit);
+        vec![(&e).into()]
+    }
+
+    #[test]
+    fn test_json() {
+        let v: Value =
+            serde_json::from_str(&to_json(&diagnostics(), Path::new("a.c")))
+                .unwrap();
+        assert_eq!(
+            v,
+            json!({
+                "version": 1,
+                "diagnostics": [{
+                    "file": "a.c",
+                    "severity": "error",
+                    "code": "E0002",
+                    "message": "expect a digit before or after '.'",
+                    "span": {
+                        "start": 9,
+                        "end": 10,
+                        "start_pos": { "line": 2, "col": 4 },
+                        "end_pos": { "line": 2, "col": 5 },
+                    },
+                    "notes": [],
+                    "fix_its": [],
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_sarif() {
+        let v: Value = serde_json::from_str(&to_sarif(
+            &diagnostics(),
+            Path::new("a.c"),
+            SRC,
+        ))
+        .unwrap();
+        assert_eq!(v["version"], "2.1.0");
+        let run = &v["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "E0002");
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "E0002");
+        assert_eq!(result["level"], "error");
+        let region = &result["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 2);
+        assert_eq!(region["startColumn"], 4);
+        assert_eq!(region["byteOffset"], 9);
+        assert_eq!(region["byteLength"], 1);
+    }
+
+    #[test]
+    fn test_sarif_columns() {
+        let src = "int f(int a) { /* héé */ return a }";
+        let preprocessed =
+            crate::preprocess::preprocess(src.char_indices()).unwrap();
+        let tokens = scan(&preprocessed).unwrap().tokens;
+        let e = crate::parser::parse(&tokens).unwrap_err();
+        let v: Value = serde_json::from_str(&to_sarif(
+            &[(&e).into()],
+            Path::new("a.c"),
+            src,
+        ))
+        .unwrap();
+        let run = &v["runs"][0];
+        assert_eq!(run["columnKind"], "unicodeCodePoints");
+        let region =
+            &run["results"][0]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startColumn"], 33);
+        assert_eq!(region["endColumn"], 34);
+        assert_eq!(region["byteOffset"], 34);
+    }
+
+    #[test]
+    fn test_sarif_fixes() {
+        let src = "int a = 1\n";
+        let tokens = scan(src).unwrap().tokens;
+        let e = crate::parser::parse(&tokens).unwrap_err();
+        let v: Value = serde_json::from_str(&to_sarif(
+            &[(&e).into()],
+            Path::new("a.c"),
+            src,
+        ))
+        .unwrap();
+        let replacement = &v["runs"][0]["results"][0]["fixes"][0]
+            ["artifactChanges"][0]["replacements"][0];
+        assert_eq!(replacement["deletedRegion"]["byteOffset"], 9);
+        assert_eq!(replacement["deletedRegion"]["byteLength"], 0);
+        assert_eq!(replacement["insertedContent"]["text"], ";");
+    }
+
+    #[test_case("int a = 1\n", "int a = 1;\n")]
+    #[test_case(
+        "int f(int x) { return f(1)); }",
+        "int f(int x) { return f(1); }"
+    )]
+    #[test_case(
+        "int f(int x) { return (1 + 2; }",
+        "int f(int x) { return (1 + 2); }"
+    )]
+    #[test_case(
+        "int f(int x) { if (1) { return 1; }",
+        "int f(int x) { if (1) { return 1; }}"
+    )]
+    #[test_case("int a[2] = {1, 2;", "int a[2] = {1, 2};")]
+    fn test_fix_it(src: &str, fixed: &str) {
+        let tokens = scan(src).unwrap().tokens;
+        let e = crate::parser::parse(&tokens).unwrap_err();
+        assert_eq!(apply_fix_its(src, &e.details.fix_its), fixed);
+    }
+
+    #[test_case("return a }", 8, 9, (7, 1))]
+    #[test_case("/* héé */ return a }", 20, 21, (17, 1))]
+    #[test_case("/* héé */", 4, 9, (3, 3))]
+    #[test_case("héé", 1, 10, (0, 3))]
+    fn test_display_columns(
+        line: &str,
+        begin: usize,
+        end: usize,
+        expected: (usize, usize),
+    ) {
+        assert_eq!(display_columns(line, begin, end), expected);
+    }
+
+    #[test]
+    fn test_apply_fix_its() {
+        let src = "abcdef";
+        let span = |start, end| Span {
+            start,
+            end,
+            ..Default::default()
+        };
+        let fix_its = [
+            FixIt::delete(span(4, 5)),
+            FixIt::insert_after(span(0, 1), "x"),
+            FixIt::delete(span(4, 6)),
+            FixIt {
+                span: span(2, 3),
+                replacement: "yy".to_owned(),
+            },
+        ];
+        assert_eq!(apply_fix_its(src, &fix_its), "axbyydf");
+    }
+}
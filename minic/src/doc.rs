@@ -0,0 +1,230 @@
+//! Documents for pretty-printing in the style of Wadler's "A prettier
+//! printer": text with optional line breaks that a [`Doc::Group`] takes
+//! either all or none of, depending on whether it fits in the line.
+
+#[derive(Debug, Clone)]
+pub(crate) enum Doc {
+    Text(String),
+    /// A space, or a line break if the enclosing group is broken
+    Line,
+    /// Nothing, or a line break if the enclosing group is broken
+    SoftLine,
+    /// Always a line break; a group containing one is always broken
+    HardLine,
+    Concat(Vec<Doc>),
+    /// Indent the line breaks inside by one more level
+    Nest(Box<Doc>),
+    /// Lay out the inside flat if it fits in the rest of the line
+    Group(Box<Doc>),
+}
+
+pub(crate) const INDENT: &str = "    ";
+
+pub(crate) fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+pub(crate) fn concat(docs: impl IntoIterator<Item = Doc>) -> Doc {
+    Doc::Concat(docs.into_iter().collect())
+}
+
+pub(crate) fn nest(doc: Doc) -> Doc {
+    Doc::Nest(Box::new(doc))
+}
+
+pub(crate) fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+/// `docs` with `separator` between each two.
+pub(crate) fn join(
+    docs: impl IntoIterator<Item = Doc>,
+    separator: impl Fn() -> Doc,
+) -> Doc {
+    let mut v = vec![];
+    for (i, doc) in docs.into_iter().enumerate() {
+        if i > 0 {
+            v.push(separator());
+        }
+        v.push(doc);
+    }
+    Doc::Concat(v)
+}
+
+/// `open`, the comma separated `items` and `close`, with the items on lines
+/// of their own if they do not fit on one.
+pub(crate) fn list(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    if items.is_empty() {
+        return text(format!("{open}{close}"));
+    }
+    group(concat([
+        text(open),
+        nest(concat([
+            Doc::SoftLine,
+            join(items, || concat([text(","), Doc::Line])),
+        ])),
+        Doc::SoftLine,
+        text(close),
+    ]))
+}
+
+/// A stack entry: the indentation level, whether the enclosing group is
+/// flat and the document.
+type Entry<'a> = (usize, bool, &'a Doc);
+
+/// Whether `doc` laid out flat, and what follows it up to the next line
+/// break, fits in `remaining` columns.
+fn fits(mut remaining: usize, doc: &Doc, rest: &[Entry]) -> bool {
+    let mut stack = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+    loop {
+        let (flat, doc) = match stack.pop() {
+            Some(entry) => entry,
+            None => match rest.next() {
+                Some(&(_, flat, doc)) => (flat, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(s) => {
+                let (first_line, broken) = match s.split_once('\n') {
+                    Some((first_line, _)) => (first_line, true),
+                    None => (s.as_str(), false),
+                };
+                let len = first_line.chars().count();
+                if len > remaining {
+                    return false;
+                }
+                remaining -= len;
+                if broken {
+                    return !flat;
+                }
+            }
+            Doc::Line if flat => {
+                if remaining == 0 {
+                    return false;
+                }
+                remaining -= 1;
+            }
+            Doc::SoftLine if flat => {}
+            Doc::Line | Doc::SoftLine => return true,
+            Doc::HardLine => return !flat,
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|d| (flat, d)))
+            }
+            Doc::Nest(doc) | Doc::Group(doc) => stack.push((flat, doc)),
+        }
+    }
+}
+
+/// Lay out `doc` in lines of at most `width` columns where possible.
+/// Lines have no trailing whitespace.
+pub(crate) fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut col = 0;
+    // Indentation is written with the first text after a line break, so
+    // empty lines stay empty.
+    let mut pending_indent = None;
+    let mut stack: Vec<Entry> = vec![(0, false, doc)];
+    while let Some((indent, flat, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                if s.is_empty() {
+                    continue;
+                }
+                if let Some(indent) = pending_indent.take() {
+                    out.push_str(&INDENT.repeat(indent));
+                    col = indent * INDENT.len();
+                }
+                out.push_str(s);
+                col = match s.rsplit_once('\n') {
+                    Some((_, last_line)) => last_line.chars().count(),
+                    None => col + s.chars().count(),
+                };
+            }
+            Doc::Line if flat => {
+                out.push(' ');
+                col += 1;
+            }
+            Doc::SoftLine if flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                while out.ends_with(' ') {
+                    out.pop();
+                }
+                out.push('\n');
+                col = 0;
+                pending_indent = Some(indent);
+            }
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|d| (indent, flat, d)))
+            }
+            Doc::Nest(doc) => stack.push((indent + 1, flat, doc)),
+            Doc::Group(doc) => {
+                let flat = flat
+                    || fits(
+                        width.saturating_sub(
+                            col.max(pending_indent.unwrap_or(0) * INDENT.len()),
+                        ),
+                        doc,
+                        &stack,
+                    );
+                stack.push((indent, flat, doc));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn call(width: usize) -> String {
+        let args = ["first", "second", "third"].map(text).to_vec();
+        let doc = concat([
+            text("result ="),
+            nest(concat([Doc::Line, text("f"), list("(", args, ")")])),
+        ]);
+        render(&group(doc), width)
+    }
+
+    #[test_case(80 => "result = f(first, second, third)")]
+    #[test_case(32 => "result = f(first, second, third)")]
+    #[test_case(31 => "result =\n    f(first, second, third)")]
+    #[test_case(26 => "result =\n    f(\n        first,\n        second,\n        third\n    )")]
+    fn test_render(width: usize) -> String {
+        call(width)
+    }
+
+    #[test]
+    fn test_hard_line() {
+        let doc = group(concat([
+            text("{"),
+            nest(concat([Doc::HardLine, text("a;")])),
+            Doc::HardLine,
+            text("}"),
+        ]));
+        assert_eq!(render(&doc, 80), "{\n    a;\n}");
+    }
+
+    #[test]
+    fn test_empty_line() {
+        let doc = nest(concat([
+            text("a;"),
+            Doc::HardLine,
+            text(""),
+            Doc::HardLine,
+            text("b; "),
+            Doc::HardLine,
+        ]));
+        assert_eq!(render(&doc, 80), "a;\n\n    b;\n");
+    }
+
+    #[test]
+    fn test_multi_line_text() {
+        let doc = group(concat([text("/* a\n b */"), Doc::Line, text("c")]));
+        assert_eq!(render(&doc, 80), "/* a\n b */\nc");
+    }
+}
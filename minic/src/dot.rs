@@ -0,0 +1,497 @@
+//! Renders an AST as a Graphviz graph. Each node is labelled with the AST
+//! type or enum variant it comes from, identifier names and constants, and
+//! the position where it starts; each edge with the field it comes from.
+
+use std::fmt::Write;
+
+use crate::{ast::*, lexer::LexerOutput, token::Span};
+
+#[derive(Debug)]
+struct Node {
+    label: String,
+    span: Option<Span>,
+    /// Says nothing its only child does not, e.g. `Statement::Iteration`
+    /// around a `WhileStatement`
+    wrapper: bool,
+    /// `Pointer(0)`, an empty list or `None`
+    trivial: bool,
+    children: Vec<(&'static str, Node)>,
+}
+
+impl Node {
+    fn new(label: impl Into<String>, span: Span) -> Node {
+        Node {
+            label: label.into(),
+            span: Some(span),
+            wrapper: false,
+            trivial: false,
+            children: vec![],
+        }
+    }
+
+    fn unspanned(label: impl Into<String>) -> Node {
+        Node {
+            label: label.into(),
+            span: None,
+            wrapper: false,
+            trivial: false,
+            children: vec![],
+        }
+    }
+
+    fn trivial(label: impl Into<String>) -> Node {
+        Node {
+            trivial: true,
+            ..Node::unspanned(label)
+        }
+    }
+
+    fn wrapper(label: impl Into<String>, child: Node) -> Node {
+        Node {
+            wrapper: true,
+            ..Node::unspanned(label)
+        }
+        .child("", child)
+    }
+
+    fn child(mut self, edge: &'static str, child: Node) -> Node {
+        self.children.push((edge, child));
+        self
+    }
+
+    fn option(self, edge: &'static str, child: Option<Node>) -> Node {
+        self.child(edge, child.unwrap_or_else(|| Node::trivial("None")))
+    }
+
+    fn list(
+        mut self,
+        edge: &'static str,
+        children: impl IntoIterator<Item = Node>,
+    ) -> Node {
+        let len = self.children.len();
+        self.children
+            .extend(children.into_iter().map(|c| (edge, c)));
+        if self.children.len() == len {
+            self.children.push((edge, Node::trivial("[]")));
+        }
+        self
+    }
+
+    /// Drop trivial leaves and replace wrappers left with one child by the
+    /// child.
+    fn collapse(mut self) -> Option<Node> {
+        self.children = self
+            .children
+            .into_iter()
+            .filter_map(|(edge, c)| Some((edge, c.collapse()?)))
+            .collect();
+        if self.trivial && self.children.is_empty() {
+            None
+        } else if self.wrapper && self.children.len() == 1 {
+            self.children.pop().map(|(_, c)| c)
+        } else {
+            Some(self)
+        }
+    }
+}
+
+struct TreeBuilder<'a> {
+    lexer_output: &'a LexerOutput,
+}
+
+impl TreeBuilder<'_> {
+    fn translation_unit(&self, ast: &TranslationUnit) -> Node {
+        Node::new("TranslationUnit", ast.span).list(
+            "external_declarations",
+            ast.external_declarations.iter().map(|d| match d {
+                ExternalDeclaration::FunctionDeclaration(f) => Node::wrapper(
+                    "ExternalDeclaration::FunctionDeclaration",
+                    self.function_definition(f),
+                ),
+                ExternalDeclaration::Declaration(d) => Node::wrapper(
+                    "ExternalDeclaration::Declaration",
+                    self.declaration(d),
+                ),
+            }),
+        )
+    }
+
+    fn function_definition(&self, f: &FunctionDefinition) -> Node {
+        Node::new("FunctionDefinition", f.span)
+            .child(
+                "declaration_specifier",
+                self.declaration_specifier(&f.declaration_specifier),
+            )
+            .child("declarator", self.declarator(&f.declarator))
+            .child(
+                "compound_statement",
+                self.compound_statement(&f.compound_statement),
+            )
+    }
+
+    fn declaration_specifier(&self, d: &DeclarationSpecifier) -> Node {
+        match d {
+            DeclarationSpecifier::TypeSpecifier(t, span) => {
+                Node::new(format!("TypeSpecifier::{t:?}"), *span)
+            }
+        }
+    }
+
+    fn declaration(&self, d: &Declaration) -> Node {
+        Node::new("Declaration", d.span)
+            .child(
+                "declaration_specifier",
+                self.declaration_specifier(&d.declaration_specifier),
+            )
+            .list(
+                "init_declarator_list",
+                d.init_declarator_list.0.iter().map(|i| {
+                    Node::new("InitDeclarator", i.span)
+                        .child("declarator", self.declarator(&i.declarator))
+                        .option(
+                            "initializer",
+                            i.initializer.as_ref().map(|i| self.initializer(i)),
+                        )
+                }),
+            )
+    }
+
+    fn declarator(&self, d: &Declarator) -> Node {
+        let pointer = match d.pointer.0 {
+            0 => Node::trivial("Pointer(0)"),
+            n => Node::new(format!("Pointer({n})"), d.span),
+        };
+        let simple_declarator = match &d.direct.simple_declarator {
+            SimpleDirectDeclarator::Identifier(id, span) => Node::new(
+                format!("Identifier {}", self.lexer_output.name(*id)),
+                *span,
+            ),
+            SimpleDirectDeclarator::Declarator(d) => Node::wrapper(
+                "SimpleDirectDeclarator::Declarator",
+                self.declarator(d),
+            ),
+        };
+        let direct = Node {
+            wrapper: true,
+            ..Node::new("DirectDeclarator", d.direct.span)
+        }
+        .child("simple_declarator", simple_declarator)
+        .list(
+            "modifiers",
+            d.direct.modifiers.iter().map(|m| match m {
+                DirectDeclaratorModifier::Array(len, span) => {
+                    Node::new(format!("Array[{len}]"), *span)
+                }
+                DirectDeclaratorModifier::Function(params, span) => Node::new(
+                    "Function", *span,
+                )
+                .list(
+                    "parameters",
+                    params.iter().map(|p| {
+                        Node::new("ParameterDeclaration", p.span)
+                            .child(
+                                "specifier",
+                                self.declaration_specifier(&p.specifier),
+                            )
+                            .child("declarator", self.declarator(&p.declarator))
+                    }),
+                ),
+            }),
+        );
+        Node {
+            wrapper: true,
+            ..Node::new("Declarator", d.span)
+        }
+        .child("pointer", pointer)
+        .child("direct", direct)
+    }
+
+    fn initializer(&self, i: &Initializer) -> Node {
+        match i {
+            Initializer::Expression(e) => {
+                Node::wrapper("Initializer::Expression", self.expression(e))
+            }
+            Initializer::List(list, span) => {
+                Node::new("Initializer::List", *span)
+                    .list("list", list.iter().map(|i| self.initializer(i)))
+            }
+        }
+    }
+
+    fn compound_statement(&self, c: &CompoundStatement) -> Node {
+        Node::new("CompoundStatement", c.span).list(
+            "block_items",
+            c.block_items.iter().map(|item| match item {
+                BlockItem::Declaration(d) => {
+                    Node::wrapper("BlockItem::Declaration", self.declaration(d))
+                }
+                BlockItem::Statement(s) => {
+                    Node::wrapper("BlockItem::Statement", self.statement(s))
+                }
+            }),
+        )
+    }
+
+    fn optional_expression(&self, e: &Option<Expression>) -> Option<Node> {
+        e.as_ref().map(|e| self.expression(e))
+    }
+
+    fn statement(&self, s: &Statement) -> Node {
+        match s {
+            Statement::Compound(c) => {
+                Node::wrapper("Statement::Compound", self.compound_statement(c))
+            }
+            Statement::Expression(e, span) => {
+                Node::new("Statement::Expression", *span)
+                    .option("expression", self.optional_expression(e))
+            }
+            Statement::Selection(s) => Node::wrapper(
+                "Statement::Selection",
+                Node::new("SelectionStatement", s.span)
+                    .child("condition", self.expression(&s.condition))
+                    .child("consequent", self.statement(&s.consequent))
+                    .option(
+                        "alternative",
+                        s.alternative.as_ref().map(|s| self.statement(s)),
+                    ),
+            ),
+            Statement::Iteration(i) => {
+                Node::wrapper("Statement::Iteration", self.iteration(i))
+            }
+            Statement::Jump(JumpStatement::Continue, span) => {
+                Node::new("JumpStatement::Continue", *span)
+            }
+            Statement::Jump(JumpStatement::Break, span) => {
+                Node::new("JumpStatement::Break", *span)
+            }
+            Statement::Jump(JumpStatement::Return(e), span) => {
+                Node::new("JumpStatement::Return", *span)
+                    .option("value", self.optional_expression(e))
+            }
+        }
+    }
+
+    fn iteration(&self, i: &IterationStatement) -> Node {
+        match i {
+            IterationStatement::While(s) => Node::wrapper(
+                "IterationStatement::While",
+                Node::new("WhileStatement", s.span)
+                    .child("condition", self.expression(&s.condition))
+                    .child("body", self.statement(&s.body)),
+            ),
+            IterationStatement::Do(s) => Node::wrapper(
+                "IterationStatement::Do",
+                Node::new("DoStatement", s.span)
+                    .child("body", self.statement(&s.body))
+                    .child("condition", self.expression(&s.condition)),
+            ),
+            IterationStatement::For(s) => {
+                let initialization = match &s.initialization {
+                    ForInitialization::Expression(e, span) => {
+                        Node::new("ForInitialization::Expression", *span)
+                            .option("expression", self.optional_expression(e))
+                    }
+                    ForInitialization::Declaration(d) => Node::wrapper(
+                        "ForInitialization::Declaration",
+                        self.declaration(d),
+                    ),
+                };
+                Node::wrapper(
+                    "IterationStatement::For",
+                    Node::new("ForStatement", s.span)
+                        .child("initialization", initialization)
+                        .option(
+                            "condition",
+                            self.optional_expression(&s.condition),
+                        )
+                        .option("update", self.optional_expression(&s.update))
+                        .child("body", self.statement(&s.body)),
+                )
+            }
+        }
+    }
+
+    fn expression(&self, e: &Expression) -> Node {
+        match e {
+            Expression::Assignment(e) => Node::wrapper(
+                "Expression::Assignment",
+                Node::new("AssignmentExpression", e.span)
+                    .child("left", self.expression(&e.left))
+                    .child("right", self.expression(&e.right)),
+            ),
+            Expression::Binary(e) => Node::wrapper(
+                "Expression::Binary",
+                Node::new(format!("BinaryExpression {:?}", e.operator), e.span)
+                    .child("left", self.expression(&e.left))
+                    .child("right", self.expression(&e.right)),
+            ),
+            Expression::Unary(e) => Node::wrapper(
+                "Expression::Unary",
+                Node::new(format!("UnaryExpression {:?}", e.operator), e.span)
+                    .child("operand", self.expression(&e.operand)),
+            ),
+            Expression::Postfix(e) => {
+                let postfix = match &e.postfix {
+                    PostfixExpressionPostfix::Subscript(index) => {
+                        Node::wrapper(
+                            "PostfixExpressionPostfix::Subscript",
+                            self.expression(index),
+                        )
+                    }
+                    PostfixExpressionPostfix::Call(arguments) => {
+                        Node::unspanned("PostfixExpressionPostfix::Call").list(
+                            "arguments",
+                            arguments.iter().map(|a| self.expression(a)),
+                        )
+                    }
+                };
+                Node::wrapper(
+                    "Expression::Postfix",
+                    Node::new("PostfixExpression", e.span)
+                        .child("operand", self.expression(&e.operand))
+                        .child("postfix", postfix),
+                )
+            }
+            Expression::Atom(atom, span) => {
+                let label = match atom {
+                    AtomExpression::Identifier(id) => {
+                        format!("Identifier {}", self.lexer_output.name(*id))
+                    }
+                    AtomExpression::Integer(n) => format!("Integer {n}"),
+                    AtomExpression::Floating(f) => format!("Floating {f}"),
+                };
+                Node::wrapper("Expression::Atom", Node::new(label, *span))
+            }
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn render(out: &mut String, node: &Node, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let mut label = escape(&node.label);
+    if let Some(span) = node.span {
+        let pos = span.start_pos;
+        write!(label, "\\n{}:{}", pos.line, pos.col).unwrap();
+    }
+    writeln!(out, "    n{id} [label=\"{label}\"];").unwrap();
+    for (edge, child) in &node.children {
+        let child_id = render(out, child, next_id);
+        if edge.is_empty() {
+            writeln!(out, "    n{id} -> n{child_id};").unwrap();
+        } else {
+            writeln!(out, "    n{id} -> n{child_id} [label=\"{edge}\"];")
+                .unwrap();
+        }
+    }
+    id
+}
+
+/// The Graphviz graph of `ast`. With `collapse`, leave out trivial nodes like
+/// `Pointer(0)` and empty `modifiers`, and enum variants that only wrap
+/// another node.
+pub fn to_dot(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+    collapse: bool,
+) -> String {
+    let mut root = TreeBuilder { lexer_output }.translation_unit(ast);
+    if collapse {
+        // The root is never trivial or a wrapper.
+        root = root.collapse().unwrap();
+    }
+    let mut out = String::from(
+        "digraph AST {\n    node [shape=box, fontname=\"monospace\"];\n",
+    );
+    render(&mut out, &root, &mut 0);
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{lexer::scan, parser::parse, preprocess::preprocess};
+
+    fn dot(src: &str, collapse: bool) -> String {
+        let src = preprocess(src.char_indices()).unwrap();
+        let lexer_output = scan(&src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        to_dot(&ast, &lexer_output, collapse)
+    }
+
+    #[test]
+    fn test_collapsed() {
+        assert_eq!(
+            dot("int a = b;", true),
+            r#"digraph AST {
+    node [shape=box, fontname="monospace"];
+    n0 [label="TranslationUnit\n1:1"];
+    n1 [label="Declaration\n1:1"];
+    n2 [label="TypeSpecifier::Int\n1:1"];
+    n1 -> n2 [label="declaration_specifier"];
+    n3 [label="InitDeclarator\n1:5"];
+    n4 [label="Identifier a\n1:5"];
+    n3 -> n4 [label="declarator"];
+    n5 [label="Identifier b\n1:9"];
+    n3 -> n5 [label="initializer"];
+    n1 -> n3 [label="init_declarator_list"];
+    n0 -> n1 [label="external_declarations"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_full() {
+        let full = dot("int a = b;", false);
+        for label in [
+            "ExternalDeclaration::Declaration",
+            "Pointer(0)",
+            "DirectDeclarator\\n1:5",
+            "[]",
+            "Initializer::Expression",
+            "Expression::Atom",
+        ] {
+            assert!(full.contains(&format!("[label=\"{label}")), "{label}");
+        }
+        assert!(full.contains("[label=\"modifiers\"]"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+
+    #[test]
+    fn test_pointer_kept() {
+        let collapsed = dot("int *f(int n) { return; }", true);
+        assert!(collapsed.contains("[label=\"Declarator\\n1:5\"]"));
+        assert!(collapsed.contains("[label=\"Pointer(1)\\n1:5\"]"));
+        assert!(collapsed.contains("[label=\"Function\\n1:7\"]"));
+        assert!(collapsed.contains("[label=\"JumpStatement::Return\\n1:17\"]"));
+        assert!(!collapsed.contains("None"));
+    }
+
+    #[test]
+    fn test_testcase() {
+        let src = include_str!("../testcase/c/1.c");
+        let full = dot(src, false);
+        let collapsed = dot(src, true);
+        let nodes = |dot: &str| {
+            dot.lines()
+                .filter(|l| l.contains("[label=") && !l.contains("->"))
+                .count()
+        };
+        assert!(nodes(&collapsed) < nodes(&full));
+        assert!(collapsed.contains("Identifier remove_duplicates"));
+        assert!(!collapsed.contains("Pointer(0)"));
+        assert!(!collapsed.contains("Expression::Atom"));
+    }
+}
@@ -1,41 +1,33 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::Path,
-    process::exit,
+use crate::{
+    diagnostic::{FixIt, Note, Severity},
+    token::Span,
 };
 
-use crate::token::Position;
-use anyhow::{Context, Result};
-use colored::Colorize;
-
 #[derive(Debug, thiserror::Error)]
-#[error("{}:{}: {:?}", .pos.line, .pos.col, .error_kind)]
+#[error("{}:{}: {:?}", .span.start_pos.line, .span.start_pos.col, .error_kind)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct Error {
-    pub pos: Position,
+    pub span: Span,
     pub error_kind: ErrorKind,
+    /// Boxed to keep `Result`s small, as most errors have no details
+    pub details: Box<Details>,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct Details {
+    pub notes: Vec<Note>,
+    /// Edits that would make this error go away
+    pub fix_its: Vec<FixIt>,
 }
 
 impl Error {
-    pub fn report(&self, file_path: &Path) -> Result<!> {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-        eprintln!("{}:{}", file_path.display(), self);
-        let line = reader
-            .lines()
-            .nth(self.pos.line - 1)
-            .with_context(|| format!("cannot find line {}", self.pos.line))??;
-        let error_line = format!("{} | ", self.pos.line);
-        let prefix_len = error_line.len();
-        eprintln!("{}{}", error_line.blue().bold(), line);
-        eprint!(
-            "{:>width$}",
-            "^".yellow().bold(),
-            width = prefix_len + self.pos.col
-        );
-        eprintln!(" {}", self.error_kind.to_string().red().bold().italic());
-        exit(1)
+    pub fn new(span: Span, error_kind: ErrorKind) -> Self {
+        Error {
+            span,
+            error_kind,
+            details: Box::default(),
+        }
     }
 }
 
@@ -46,16 +38,198 @@ pub enum ErrorKind {
     UnterminatedComment,
     #[strum(serialize = "expect a digit before or after \'.\'")]
     ExpectDigit,
+    #[strum(serialize = "expect a type specifier")]
     ExpectDeclarationSpecifier,
+    #[strum(serialize = "expect a declarator after this")]
     ExpectDeclarator,
     #[strum(serialize = "expect an expression after this")]
     ExpectExpression,
+    #[strum(serialize = "expect an identifier or '(' after this")]
     ExpectDirectDeclarator,
+    #[strum(serialize = "expect an integer constant after this")]
     ExpectIntegerConstant,
+    #[strum(serialize = "expect a statement after this")]
     ExpectStatement,
+    #[strum(serialize = "expect a compound statement after this")]
     ExpectCompoundStatement,
+    #[strum(serialize = "expect a declaration or expression after this")]
     ExpectForInitialization,
-    #[strum(serialize = "it's not closed")]
-    UnmatchedParenthesis(Position),
+    /// The closing bracket that is missing
+    UnmatchedParenthesis(char),
     ExpectStr(String),
+    UndeclaredIdentifier(String),
+    /// A call to a function that has not been declared
+    ImplicitFunctionDeclaration(String),
+    /// Operands or a use that the types do not allow, described
+    TypeMismatch(String),
+    #[strum(serialize = "division by zero")]
+    DivisionByZero,
+    /// A call to a function that is declared but not defined
+    UndefinedFunction(String),
+    #[strum(serialize = "access outside of any object")]
+    InvalidMemoryAccess,
+    #[strum(serialize = "too many nested calls")]
+    StackOverflow,
+    /// `break`, `continue` or `return` where it cannot jump to
+    MisplacedJump(&'static str),
+    #[strum(serialize = "assignment used as a condition")]
+    AssignmentInCondition,
+    UnusedVariable(String),
+    UnusedParameter(String),
+    #[strum(serialize = "code will never be executed")]
+    UnreachableCode,
+    /// A non-void function that control can run off the end of
+    MissingReturn(String),
+    #[strum(serialize = "assignment of a value to itself")]
+    SelfAssignment,
+    #[strum(serialize = "loop body is empty")]
+    EmptyLoopBody,
+    /// A declaration that hides one in an enclosing scope
+    Shadowing(String),
+    /// A `minic-lint:` comment that cannot be followed, and why
+    InvalidLintComment(String),
+    /// A read of a local variable that may not have been assigned
+    UninitializedVariable(String),
+    /// A construct the compiler cannot translate yet
+    Unsupported(String),
+}
+
+impl ErrorKind {
+    /// A stable identifier of the kind of error, used by machine-readable
+    /// diagnostics. Never reuse or renumber a code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::UnterminatedComment => "E0001",
+            ErrorKind::ExpectDigit => "E0002",
+            ErrorKind::ExpectDeclarationSpecifier => "E0003",
+            ErrorKind::ExpectDeclarator => "E0004",
+            ErrorKind::ExpectExpression => "E0005",
+            ErrorKind::ExpectDirectDeclarator => "E0006",
+            ErrorKind::ExpectIntegerConstant => "E0007",
+            ErrorKind::ExpectStatement => "E0008",
+            ErrorKind::ExpectCompoundStatement => "E0009",
+            ErrorKind::ExpectForInitialization => "E0010",
+            ErrorKind::UnmatchedParenthesis(_) => "E0011",
+            ErrorKind::ExpectStr(_) => "E0012",
+            ErrorKind::UndeclaredIdentifier(_) => "E0013",
+            ErrorKind::ImplicitFunctionDeclaration(_) => "E0014",
+            ErrorKind::TypeMismatch(_) => "E0015",
+            ErrorKind::DivisionByZero => "E0016",
+            ErrorKind::UndefinedFunction(_) => "E0017",
+            ErrorKind::InvalidMemoryAccess => "E0018",
+            ErrorKind::StackOverflow => "E0019",
+            ErrorKind::MisplacedJump(_) => "E0020",
+            ErrorKind::AssignmentInCondition => "E0021",
+            ErrorKind::UnusedVariable(_) => "E0022",
+            ErrorKind::UnusedParameter(_) => "E0023",
+            ErrorKind::UnreachableCode => "E0024",
+            ErrorKind::MissingReturn(_) => "E0025",
+            ErrorKind::SelfAssignment => "E0026",
+            ErrorKind::EmptyLoopBody => "E0027",
+            ErrorKind::Shadowing(_) => "E0028",
+            ErrorKind::InvalidLintComment(_) => "E0029",
+            ErrorKind::UninitializedVariable(_) => "E0030",
+            ErrorKind::Unsupported(_) => "E0031",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            ErrorKind::ImplicitFunctionDeclaration(_)
+            | ErrorKind::AssignmentInCondition
+            | ErrorKind::UnusedVariable(_)
+            | ErrorKind::UnusedParameter(_)
+            | ErrorKind::UnreachableCode
+            | ErrorKind::MissingReturn(_)
+            | ErrorKind::SelfAssignment
+            | ErrorKind::EmptyLoopBody
+            | ErrorKind::Shadowing(_)
+            | ErrorKind::InvalidLintComment(_)
+            | ErrorKind::UninitializedVariable(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ErrorKind::ExpectStr(s) => format!("expect '{s}' after this"),
+            ErrorKind::UnmatchedParenthesis(c) => format!("expect '{c}'"),
+            ErrorKind::UndeclaredIdentifier(name) => {
+                format!("use of undeclared identifier '{name}'")
+            }
+            ErrorKind::ImplicitFunctionDeclaration(name) => {
+                format!("implicit declaration of function '{name}'")
+            }
+            ErrorKind::TypeMismatch(s) => s.clone(),
+            ErrorKind::UndefinedFunction(name) => {
+                format!("function '{name}' is declared but not defined")
+            }
+            ErrorKind::MisplacedJump(s) => format!("'{s}' cannot be used here"),
+            ErrorKind::UnusedVariable(name) => {
+                format!("unused variable '{name}'")
+            }
+            ErrorKind::UnusedParameter(name) => {
+                format!("unused parameter '{name}'")
+            }
+            ErrorKind::MissingReturn(name) => format!(
+                "control can reach the end of non-void function '{name}'"
+            ),
+            ErrorKind::Shadowing(name) => {
+                format!("declaration of '{name}' shadows an earlier one")
+            }
+            ErrorKind::InvalidLintComment(reason) => {
+                format!("invalid lint comment: {reason}")
+            }
+            ErrorKind::UninitializedVariable(name) => {
+                format!("variable '{name}' may be uninitialized when used here")
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_code_unique() {
+        let kinds = [
+            ErrorKind::UnterminatedComment,
+            ErrorKind::ExpectDigit,
+            ErrorKind::ExpectDeclarationSpecifier,
+            ErrorKind::ExpectDeclarator,
+            ErrorKind::ExpectExpression,
+            ErrorKind::ExpectDirectDeclarator,
+            ErrorKind::ExpectIntegerConstant,
+            ErrorKind::ExpectStatement,
+            ErrorKind::ExpectCompoundStatement,
+            ErrorKind::ExpectForInitialization,
+            ErrorKind::UnmatchedParenthesis(')'),
+            ErrorKind::ExpectStr(";".to_owned()),
+            ErrorKind::UndeclaredIdentifier("x".to_owned()),
+            ErrorKind::ImplicitFunctionDeclaration("f".to_owned()),
+            ErrorKind::TypeMismatch("x".to_owned()),
+            ErrorKind::DivisionByZero,
+            ErrorKind::UndefinedFunction("f".to_owned()),
+            ErrorKind::InvalidMemoryAccess,
+            ErrorKind::StackOverflow,
+            ErrorKind::MisplacedJump("break"),
+            ErrorKind::AssignmentInCondition,
+            ErrorKind::UnusedVariable("x".to_owned()),
+            ErrorKind::UnusedParameter("x".to_owned()),
+            ErrorKind::UnreachableCode,
+            ErrorKind::MissingReturn("f".to_owned()),
+            ErrorKind::SelfAssignment,
+            ErrorKind::EmptyLoopBody,
+            ErrorKind::Shadowing("x".to_owned()),
+            ErrorKind::InvalidLintComment("x".to_owned()),
+            ErrorKind::UninitializedVariable("x".to_owned()),
+            ErrorKind::Unsupported("x".to_owned()),
+        ];
+        let codes: HashSet<_> = kinds.iter().map(ErrorKind::code).collect();
+        assert_eq!(codes.len(), kinds.len());
+    }
 }
@@ -0,0 +1,166 @@
+//! `minic fmt`: rewrite C files as [`printer::format`] lays them out,
+//! keeping their comments.
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    diagnostic::{self, DiagnosticsFormat},
+    error::Error,
+    parser::parse,
+    printer::{self, DEFAULT_WIDTH},
+    trivia::scan_lossless,
+};
+
+#[derive(clap::Args)]
+pub struct FmtArgs {
+    /// Only check that the files are formatted, and exit with 1 if some are
+    /// not
+    #[arg(long)]
+    check: bool,
+    /// Wrap lines longer than this where possible
+    #[arg(long, default_value_t = DEFAULT_WIDTH)]
+    width: usize,
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+/// `src` formatted with lines of at most `width` columns where possible.
+pub fn format_source(src: &str, width: usize) -> Result<String, Error> {
+    let output = scan_lossless(src)?;
+    let ast = parse(&output.lexer_output.tokens)?;
+    Ok(printer::format(
+        &ast,
+        &output.lexer_output,
+        src,
+        &output.comments(),
+        width,
+    ))
+}
+
+/// Format or check the files. Returns whether all of them are formatted,
+/// or were formatted without errors.
+pub fn run(args: &FmtArgs) -> Result<bool> {
+    let mut ok = true;
+    for path in &args.files {
+        let src = fs::read_to_string(path)
+            .with_context(|| format!("cannot open \"{}\"", path.display()))?;
+        let formatted = match format_source(&src, args.width) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                diagnostic::emit(
+                    &[(&e).into()],
+                    path,
+                    DiagnosticsFormat::Human,
+                )?;
+                ok = false;
+                continue;
+            }
+        };
+        if formatted == src {
+            continue;
+        }
+        if args.check {
+            eprintln!("\"{}\" is not formatted", path.display());
+            ok = false;
+        } else {
+            fs::write(path, formatted).with_context(|| {
+                format!("cannot write \"{}\"", path.display())
+            })?;
+        }
+    }
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_comments() {
+        let src = "// Header.
+int  g ;   /* trailing */
+
+/* Before f. */
+int f(int a) {
+  // Leading.
+  int b = a; // Trailing.
+
+
+  if (a /* inside */) { return b; } // After the if.
+  // At the end.
+}
+// At the end of the file.
+";
+        assert_eq!(
+            format_source(src, 80).unwrap(),
+            "// Header.
+int g; /* trailing */
+
+/* Before f. */
+int f(int a)
+{
+    // Leading.
+    int b = a; // Trailing.
+
+    /* inside */
+    if (a) {
+        return b;
+    } // After the if.
+    // At the end.
+}
+
+// At the end of the file.
+"
+        );
+    }
+
+    #[test]
+    fn test_width() {
+        let src =
+            "int f(int a) { return g(a + 1000000, a * 2000000, a - 3000000); }";
+        assert_eq!(
+            format_source(src, 40).unwrap(),
+            "int f(int a)
+{
+    return g(
+        a + 1000000,
+        a * 2000000,
+        a - 3000000
+    );
+}
+"
+        );
+        assert_eq!(
+            format_source(src, 80).unwrap(),
+            "int f(int a)
+{
+    return g(a + 1000000, a * 2000000, a - 3000000);
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_idempotent() {
+        for src in [
+            include_str!("../testcase/c/1.c"),
+            include_str!("../testcase/c/2.c"),
+            include_str!("../testcase/comment/block/1.c"),
+        ] {
+            let Ok(formatted) = format_source(src, 60) else {
+                continue;
+            };
+            assert_eq!(format_source(&formatted, 60).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn test_error() {
+        assert!(format_source("int a /* b", 80).is_err());
+        assert!(format_source("int a", 80).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Incremental reparsing for editors.
+//!
+//! An edit only scans and parses again the top-level declarations it
+//! touches. The tokens and AST nodes of the others are kept, with their
+//! spans moved past the edit, and the result is the same as parsing the new
+//! source from scratch, except that the identifier table keeps the names of
+//! identifiers that are no longer used.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    ast::{TranslationUnit, VisitorMut},
+    error::Error,
+    lexer::{scan, scan_at, LexerOutput},
+    parser::parse,
+    preprocess::{preprocess, unterminated_comment},
+    suggest::suggest_keyword,
+    token::{Position, Span},
+};
+
+#[derive(Debug)]
+pub struct Parsed {
+    pub lexer_output: LexerOutput,
+    pub ast: TranslationUnit,
+}
+
+/// How much of the source an edit parsed again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reparse {
+    /// All of it
+    Full,
+    /// Only these external declarations, by index after the edit, which
+    /// is empty if the edit only changed the space between two of them
+    Declarations(Range<usize>),
+}
+
+/// A source file kept parsed as it is edited.
+#[derive(Debug)]
+pub struct SourceFile {
+    src: String,
+    parsed: Result<Parsed, Error>,
+    /// The indices of the names in the identifier table
+    known_ids: HashMap<String, usize>,
+}
+
+impl SourceFile {
+    pub fn new(src: String) -> Self {
+        let (parsed, known_ids) = parse_src(&src);
+        SourceFile {
+            src,
+            parsed,
+            known_ids,
+        }
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    /// The tokens and AST, or the first error found.
+    pub fn parsed(&self) -> Result<&Parsed, &Error> {
+        self.parsed.as_ref()
+    }
+
+    /// Replace the bytes in `range` with `text`.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Reparse {
+        let old_len = self.src.len();
+        self.src.replace_range(range.clone(), text);
+        let Ok(parsed) = &mut self.parsed else {
+            // Where the error was may say little about what the edit
+            // fixed.
+            self.parse_all();
+            return Reparse::Full;
+        };
+        let declarations = &parsed.ast.external_declarations;
+        // The declarations the edit touches. One ends with `;` or `}`,
+        // which nothing can join onto, but it can join onto the keyword
+        // the next one starts with.
+        let first =
+            declarations.partition_point(|d| d.span().end <= range.start);
+        let mut last =
+            declarations.partition_point(|d| d.span().start < range.end);
+        let start = match first {
+            0 => 0,
+            _ => declarations[first - 1].span().end,
+        };
+        let delta = text.len() as isize - range.len() as isize;
+        let old_end = |last: usize| match declarations.get(last) {
+            Some(d) => d.span().start,
+            None => old_len,
+        };
+        while last < declarations.len()
+            && self.src[..shift(old_end(last), delta)]
+                .ends_with(|c: char| is_identifier_char(c))
+        {
+            last += 1;
+        }
+        let old_end = old_end(last);
+        let new_end = shift(old_end, delta);
+        // The text of a region after a token and before a declaration is
+        // not in a comment, so it can be preprocessed and scanned alone.
+        let start_pos = match first {
+            0 => Position { line: 1, col: 1 },
+            _ => declarations[first - 1].span().end_pos,
+        };
+        let region = &self.src[start..new_end];
+        let Ok(preprocessed) = preprocess(region.char_indices()) else {
+            self.parse_all();
+            return Reparse::Full;
+        };
+        let lexer_output = &mut parsed.lexer_output;
+        let Ok(tokens) = scan_at(
+            &preprocessed,
+            start,
+            start_pos,
+            &mut lexer_output.text,
+            &mut self.known_ids,
+            &mut lexer_output.id_table,
+        ) else {
+            self.parse_all();
+            return Reparse::Full;
+        };
+        // A declaration ends with `;` or `}`, so it is the same however the
+        // tokens after it go on. An error is reported from a full parse,
+        // which may find it elsewhere.
+        let Ok(reparsed) = parse(&tokens) else {
+            self.parse_all();
+            return Reparse::Full;
+        };
+
+        let old_end_pos = match declarations.get(last) {
+            Some(d) => d.span().start_pos,
+            None => Position { line: 0, col: 0 },
+        };
+        let mut shift = Shift {
+            delta,
+            old_end_pos,
+            new_end_pos: end_pos(start_pos, region),
+        };
+        let old_tokens =
+            lexer_output.tokens.partition_point(|t| t.span.end <= start)
+                ..lexer_output
+                    .tokens
+                    .partition_point(|t| t.span.start < old_end);
+        let after = old_tokens.end;
+        for t in &mut lexer_output.tokens[after..] {
+            shift.visit_span(&mut t.span);
+        }
+        lexer_output.tokens.splice(old_tokens, tokens);
+
+        let ast = &mut parsed.ast;
+        for d in &mut ast.external_declarations[last..] {
+            shift.visit_external_declaration(d);
+        }
+        let count = reparsed.external_declarations.len();
+        ast.external_declarations
+            .splice(first..last, reparsed.external_declarations);
+        ast.span = match (
+            ast.external_declarations.first(),
+            ast.external_declarations.last(),
+        ) {
+            (Some(first), Some(last)) => first.span().to(last.span()),
+            _ => Span::default(),
+        };
+        Reparse::Declarations(first..first + count)
+    }
+
+    fn parse_all(&mut self) {
+        (self.parsed, self.known_ids) = parse_src(&self.src);
+    }
+}
+
+/// Parse `src` from scratch, also returning the indices of the names in
+/// the identifier table.
+fn parse_src(src: &str) -> (Result<Parsed, Error>, HashMap<String, usize>) {
+    let parsed = (|| {
+        let preprocessed = preprocess(src.char_indices())
+            .map_err(|span| unterminated_comment(span, src))?;
+        let lexer_output = scan(&preprocessed)?;
+        let ast = parse(&lexer_output.tokens)
+            .map_err(|e| suggest_keyword(e, &lexer_output))?;
+        Ok(Parsed { lexer_output, ast })
+    })();
+    let known_ids = match &parsed {
+        Ok(Parsed { lexer_output, .. }) => (0..lexer_output.id_table.len())
+            .map(|id| (lexer_output.name(id).to_owned(), id))
+            .collect(),
+        Err(_) => HashMap::new(),
+    };
+    (parsed, known_ids)
+}
+
+/// Whether the lexer takes `c` as part of an identifier or keyword.
+fn is_identifier_char(c: char) -> bool {
+    !c.is_whitespace() && (c == '_' || !c.is_ascii_punctuation())
+}
+
+fn shift(offset: usize, delta: isize) -> usize {
+    offset.checked_add_signed(delta).unwrap()
+}
+
+/// The position after `text` if it starts at `start`.
+fn end_pos(start: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(i) => Position {
+            line: start.line + text.matches('\n').count(),
+            col: text.len() - i,
+        },
+        None => Position {
+            line: start.line,
+            col: start.col + text.len(),
+        },
+    }
+}
+
+/// Moves spans after an edit to where they are after it.
+struct Shift {
+    /// The change in byte offsets
+    delta: isize,
+    /// Where the first thing after the edit was before it
+    old_end_pos: Position,
+    new_end_pos: Position,
+}
+
+impl Shift {
+    fn position(&self, p: &mut Position) {
+        // Columns only change on the line the edit ends on.
+        if p.line == self.old_end_pos.line {
+            p.col = p.col - self.old_end_pos.col + self.new_end_pos.col;
+        }
+        p.line = p.line - self.old_end_pos.line + self.new_end_pos.line;
+    }
+}
+
+impl VisitorMut for Shift {
+    fn visit_span(&mut self, span: &mut Span) {
+        span.start = shift(span.start, self.delta);
+        span.end = shift(span.end, self.delta);
+        self.position(&mut span.start_pos);
+        self.position(&mut span.end_pos);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{
+        ast::{with_context, SerdeContext},
+        token::TokenKind,
+    };
+
+    /// The tokens and AST with identifiers as names and spans, which are
+    /// the same for equal parses whatever the identifier table is.
+    fn canonical(parsed: Result<&Parsed, &Error>) -> String {
+        let Parsed { lexer_output, ast } = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => return format!("{e:?}"),
+        };
+        let names: Vec<_> = (0..lexer_output.id_table.len())
+            .map(|id| lexer_output.name(id).to_owned())
+            .collect();
+        let mut s = String::new();
+        for t in &lexer_output.tokens {
+            match t.kind {
+                TokenKind::Id(id) => s += &names[id],
+                ref kind => s += &format!("{kind:?}"),
+            }
+            s += &format!(" {:?}\n", t.span);
+        }
+        let context = SerdeContext {
+            spans: true,
+            names: Some(names),
+        };
+        s + &with_context(context, || serde_json::to_string_pretty(ast))
+            .unwrap()
+    }
+
+    /// Replace the first `from` in `file` with `to`, checking the result
+    /// against a parse from scratch.
+    fn edit(file: &mut SourceFile, from: &str, to: &str) -> Reparse {
+        let start = file.src().find(from).unwrap();
+        let reparse = file.edit(start..start + from.len(), to);
+        let expected = SourceFile::new(file.src().to_owned());
+        assert_eq!(canonical(file.parsed()), canonical(expected.parsed()));
+        reparse
+    }
+
+    const SRC: &str = "\
+int a;
+
+int f(int x)
+{
+    return a + x; /* é */
+}
+
+int b[2] = {1, 2}; int g(int y) { return 0; }
+";
+
+    #[test_case("a + x", "a - x", Reparse::Declarations(1..2); "in function")]
+    #[test_case("return a", "return\n\n  a", Reparse::Declarations(1..2); "add lines")]
+    #[test_case("{1, 2}", "{1}", Reparse::Declarations(2..3); "columns after")]
+    #[test_case("int a;", "int abc;", Reparse::Declarations(0..1); "first")]
+    #[test_case("\n\n", "\nint c;\n", Reparse::Declarations(1..2); "new declaration")]
+    #[test_case("\n\n", "\n/* f */\n", Reparse::Declarations(1..1); "between")]
+    #[test_case("x; /*", "x;\n}\nint h(int y) { /*", Reparse::Declarations(1..3); "split function")]
+    #[test_case("a;\n\nint f(int x)\n{\n    return a + x; /* é */\n}", "c;", Reparse::Declarations(0..1); "join")]
+    #[test_case("0; }\n", "0; }", Reparse::Declarations(3..4); "last")]
+    #[test_case("int g", "xint g", Reparse::Full; "join onto keyword")]
+    #[test_case("a + x;", "a + x", Reparse::Full; "parse error")]
+    #[test_case("/* é */", "/* é", Reparse::Full; "unterminated comment")]
+    #[test_case("return", "return @", Reparse::Full; "scan error")]
+    fn test_edit(from: &str, to: &str, expected: Reparse) {
+        let mut file = SourceFile::new(SRC.to_owned());
+        assert_eq!(edit(&mut file, from, to), expected);
+    }
+
+    #[test]
+    fn test_recover() {
+        let mut file = SourceFile::new(SRC.to_owned());
+        assert_eq!(edit(&mut file, "}\n\nint b", "\n\nint b"), Reparse::Full);
+        assert!(file.parsed().is_err());
+        assert_eq!(edit(&mut file, "\n\nint b", "}\n\nint b"), Reparse::Full);
+        assert!(file.parsed().is_ok());
+        assert_eq!(
+            edit(&mut file, "return 0", "return b[1]"),
+            Reparse::Declarations(3..4)
+        );
+    }
+
+    #[test]
+    fn test_unused_identifier_kept() {
+        let mut file = SourceFile::new(SRC.to_owned());
+        edit(&mut file, "int b[2]", "int c[2]");
+        let lexer_output = &file.parsed().unwrap().lexer_output;
+        let names: Vec<_> = (0..lexer_output.id_table.len())
+            .map(|id| lexer_output.name(id))
+            .collect();
+        assert_eq!(names, ["a", "f", "x", "b", "g", "y", "c"]);
+    }
+
+    /// Insert and then remove a line break before line breaks throughout
+    /// each test case, which can neither split a token nor end a comment.
+    #[test]
+    fn test_testcases() {
+        let mut count = 0;
+        for entry in fs::read_dir("testcase/c").unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                continue;
+            }
+            let src = fs::read_to_string(path).unwrap();
+            let mut file = SourceFile::new(src.clone());
+            if file.parsed().is_err() {
+                continue;
+            }
+            for (offset, _) in src.match_indices('\n').step_by(17) {
+                file.edit(offset..offset, " \n ");
+                let expected = SourceFile::new(file.src().to_owned());
+                assert_eq!(
+                    canonical(file.parsed()),
+                    canonical(expected.parsed())
+                );
+                file.edit(offset..offset + 3, "");
+                assert_eq!(file.src(), src);
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+}
@@ -0,0 +1,1087 @@
+//! A tree-walking interpreter for the AST.
+//!
+//! Memory is a flat array of scalars: an `int[2][3]` takes 6 cells and a
+//! pointer is the index of a cell. Cell 0 is never allocated, so it is the
+//! null pointer. Globals stay allocated, and locals are allocated above them
+//! and freed when their block ends.
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::*,
+    error::{Error, ErrorKind},
+    lexer::LexerOutput,
+    token::Span,
+    types::Type,
+};
+
+/// How deep calls can nest.
+const MAX_DEPTH: usize = 1000;
+
+/// The native stack a thread needs to run calls [`MAX_DEPTH`] deep, even
+/// in a debug build.
+pub const STACK_SIZE: usize = 64 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i32),
+    Double(f64),
+    /// The index of a cell, or 0 for the null pointer
+    Pointer(usize),
+    /// A function, or a pointer to it, by identifier
+    Function(usize),
+    Void,
+}
+
+/// A value with its type. The value of an array is a pointer to its first
+/// element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Typed {
+    pub value: Value,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone)]
+struct Variable {
+    ty: Type,
+    /// 0 for a function
+    address: usize,
+}
+
+/// Globals and function definitions, kept from one run to the next.
+#[derive(Debug)]
+pub struct Interpreter {
+    memory: Vec<Value>,
+    globals: HashMap<usize, Variable>,
+    functions: HashMap<usize, Rc<FunctionDefinition>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter {
+            memory: vec![Value::Void],
+            globals: HashMap::new(),
+            functions: HashMap::new(),
+        }
+    }
+}
+
+impl Interpreter {
+    /// Define a function, or declare and initialize globals. A name that
+    /// is already declared is declared again.
+    pub fn declare(
+        &mut self,
+        d: ExternalDeclaration,
+        lexer_output: &LexerOutput,
+    ) -> Result<(), Error> {
+        match d {
+            ExternalDeclaration::FunctionDeclaration(f) => {
+                let ty = Type::of(&f.declaration_specifier, &f.declarator);
+                let (id, _) = f.declarator.identifier();
+                self.globals.insert(id, Variable { ty, address: 0 });
+                self.functions.insert(id, Rc::from(f));
+                Ok(())
+            }
+            ExternalDeclaration::Declaration(d) => {
+                self.machine(lexer_output, |m| m.declare(&d))
+            }
+        }
+    }
+
+    /// Run `items` as a block.
+    pub fn execute(
+        &mut self,
+        items: &[BlockItem],
+        lexer_output: &LexerOutput,
+    ) -> Result<(), Error> {
+        self.machine(lexer_output, |m| match m.block(items)? {
+            Flow::Normal => Ok(()),
+            Flow::Break(span) => Err(misplaced("break", span)),
+            Flow::Continue(span) => Err(misplaced("continue", span)),
+            Flow::Return(_, span) => Err(misplaced("return", span)),
+        })
+    }
+
+    pub fn evaluate(
+        &mut self,
+        e: &Expression,
+        lexer_output: &LexerOutput,
+    ) -> Result<Typed, Error> {
+        self.machine(lexer_output, |m| m.rvalue(e))
+    }
+
+    /// The type of `e`, without evaluating it.
+    pub fn type_of(
+        &mut self,
+        e: &Expression,
+        lexer_output: &LexerOutput,
+    ) -> Result<Type, Error> {
+        self.machine(lexer_output, |m| m.type_of(e))
+    }
+
+    /// Show `t` as C would write it, with arrays as initializer lists.
+    pub fn show(&self, t: &Typed, lexer_output: &LexerOutput) -> String {
+        match (t.value, &t.ty) {
+            (Value::Pointer(address), Type::Array(element, len)) => {
+                let elements: Vec<_> = (0..*len)
+                    .map(|i| {
+                        let address = address + i * element.size();
+                        let value = match **element {
+                            Type::Array(..) => Value::Pointer(address),
+                            _ => self.memory[address],
+                        };
+                        let t = Typed {
+                            value,
+                            ty: (**element).clone(),
+                        };
+                        self.show(&t, lexer_output)
+                    })
+                    .collect();
+                format!("{{{}}}", elements.join(", "))
+            }
+            (Value::Int(n), _) => n.to_string(),
+            (Value::Double(x), _) => format!("{x:?}"),
+            (Value::Pointer(address), _) => format!("{address:#x}"),
+            (Value::Function(id), _) => lexer_output.name(id).to_owned(),
+            (Value::Void, _) => String::new(),
+        }
+    }
+
+    fn machine<T>(
+        &mut self,
+        lexer_output: &LexerOutput,
+        run: impl FnOnce(&mut Machine) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut machine = Machine {
+            interpreter: self,
+            lexer_output,
+            scopes: vec![],
+            frame: 0,
+            depth: 0,
+        };
+        run(&mut machine)
+    }
+}
+
+/// How a statement finished.
+enum Flow {
+    Normal,
+    Break(Span),
+    Continue(Span),
+    Return(Option<Typed>, Span),
+}
+
+fn misplaced(jump: &'static str, span: Span) -> Error {
+    error(span, ErrorKind::MisplacedJump(jump))
+}
+
+fn error(span: Span, kind: ErrorKind) -> Error {
+    Error::new(span, kind)
+}
+
+fn mismatch(span: Span, message: String) -> Error {
+    error(span, ErrorKind::TypeMismatch(message))
+}
+
+/// The type of `l op r` for operands of types `l` and `r` after decaying.
+pub(crate) fn binary_type(
+    op: &BinaryOperator,
+    l: &Type,
+    r: &Type,
+) -> Option<Type> {
+    use BinaryOperator::*;
+    let arithmetic = l.is_arithmetic() && r.is_arithmetic();
+    let promoted = if *l == Type::Double || *r == Type::Double {
+        Type::Double
+    } else {
+        Type::Int
+    };
+    let sized = |t: &Type| t.size() > 0;
+    match (op, l, r) {
+        (Add | Minus | Multiply | DivideBy, _, _) if arithmetic => {
+            Some(promoted)
+        }
+        (Add | Minus, Type::Pointer(t), Type::Int) if sized(t) => {
+            Some(l.clone())
+        }
+        (Add, Type::Int, Type::Pointer(t)) if sized(t) => Some(r.clone()),
+        (Minus, Type::Pointer(t), Type::Pointer(u)) if t == u && sized(t) => {
+            Some(Type::Int)
+        }
+        (And | Or, Type::Int, Type::Int) => Some(Type::Int),
+        (Lt | Gt | Le | Ge | Eq | Neq, _, _) if arithmetic => Some(Type::Int),
+        (Lt | Gt | Le | Ge | Eq | Neq, Type::Pointer(_), Type::Pointer(_))
+            if l == r =>
+        {
+            Some(Type::Int)
+        }
+        // Comparing with a null pointer constant
+        (Eq | Neq, Type::Pointer(_), Type::Int)
+        | (Eq | Neq, Type::Int, Type::Pointer(_)) => Some(Type::Int),
+        (LogicalAnd | LogicalOr, _, _) if l.is_scalar() && r.is_scalar() => {
+            Some(Type::Int)
+        }
+        (Comma, _, _) => Some(r.clone()),
+        _ => None,
+    }
+}
+
+/// The type of `op operand` for an operand of type `t` after decaying,
+/// except for `&` and `*`.
+pub(crate) fn unary_type(op: &UnaryOperator, t: &Type) -> Option<Type> {
+    match op {
+        UnaryOperator::Positive | UnaryOperator::Negative
+            if t.is_arithmetic() =>
+        {
+            Some(t.clone())
+        }
+        UnaryOperator::LogicalNot if t.is_scalar() => Some(Type::Int),
+        UnaryOperator::Not if *t == Type::Int => Some(Type::Int),
+        _ => None,
+    }
+}
+
+/// Runs code with the scopes of the calls in progress.
+struct Machine<'a> {
+    interpreter: &'a mut Interpreter,
+    lexer_output: &'a LexerOutput,
+    /// The scopes of the blocks being run, innermost last
+    scopes: Vec<HashMap<usize, Variable>>,
+    /// The first scope of the innermost call
+    frame: usize,
+    depth: usize,
+}
+
+impl Machine<'_> {
+    fn name(&self, id: usize) -> String {
+        self.lexer_output.name(id).to_owned()
+    }
+
+    fn lookup(&self, id: usize, span: Span) -> Result<Variable, Error> {
+        self.scopes[self.frame..]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&id))
+            .or_else(|| self.interpreter.globals.get(&id))
+            .cloned()
+            .ok_or_else(|| {
+                error(span, ErrorKind::UndeclaredIdentifier(self.name(id)))
+            })
+    }
+
+    fn allocate(&mut self, t: &Type) -> usize {
+        fn fill(t: &Type, memory: &mut Vec<Value>) {
+            match t {
+                Type::Int => memory.push(Value::Int(0)),
+                Type::Double => memory.push(Value::Double(0.)),
+                Type::Pointer(_) => memory.push(Value::Pointer(0)),
+                Type::Array(t, len) => (0..*len).for_each(|_| fill(t, memory)),
+                Type::Void | Type::Function(..) => {}
+            }
+        }
+        let address = self.interpreter.memory.len();
+        fill(t, &mut self.interpreter.memory);
+        address
+    }
+
+    fn load(
+        &self,
+        address: usize,
+        ty: Type,
+        span: Span,
+    ) -> Result<Typed, Error> {
+        if let Type::Array(..) = ty {
+            return Ok(Typed {
+                value: Value::Pointer(address),
+                ty,
+            });
+        }
+        match self.interpreter.memory.get(address) {
+            Some(&value) if address > 0 => Ok(Typed { value, ty }),
+            _ => Err(error(span, ErrorKind::InvalidMemoryAccess)),
+        }
+    }
+
+    fn store(
+        &mut self,
+        address: usize,
+        value: Value,
+        span: Span,
+    ) -> Result<(), Error> {
+        match self.interpreter.memory.get_mut(address) {
+            Some(cell) if address > 0 => {
+                *cell = value;
+                Ok(())
+            }
+            _ => Err(error(span, ErrorKind::InvalidMemoryAccess)),
+        }
+    }
+
+    /// Convert `t` to a value of type `to`, as assignment does.
+    fn convert(&self, t: Typed, to: &Type, span: Span) -> Result<Value, Error> {
+        let from = t.ty.decay();
+        match (t.value, to) {
+            (Value::Int(n), Type::Int) => Ok(Value::Int(n)),
+            (Value::Double(x), Type::Int) => Ok(Value::Int(x as i32)),
+            (Value::Int(n), Type::Double) => Ok(Value::Double(n as f64)),
+            (Value::Double(x), Type::Double) => Ok(Value::Double(x)),
+            // A null pointer constant
+            (Value::Int(0), Type::Pointer(_)) => Ok(Value::Pointer(0)),
+            (
+                v @ (Value::Pointer(_) | Value::Function(_)),
+                Type::Pointer(_),
+            ) if from == *to => Ok(v),
+            _ => Err(mismatch(
+                span,
+                format!("cannot convert '{from}' to '{to}'"),
+            )),
+        }
+    }
+
+    fn truthy(&self, t: Typed, span: Span) -> Result<bool, Error> {
+        match t.value {
+            Value::Int(n) => Ok(n != 0),
+            Value::Double(x) => Ok(x != 0.),
+            Value::Pointer(p) if t.ty.is_scalar() => Ok(p != 0),
+            Value::Function(_) => Ok(true),
+            _ => Err(mismatch(
+                span,
+                format!("expect a scalar, found '{}'", t.ty),
+            )),
+        }
+    }
+
+    /// Declare the identifiers of `d` in the innermost scope, or as globals
+    /// outside any block.
+    fn declare(&mut self, d: &Declaration) -> Result<(), Error> {
+        for i in &d.init_declarator_list.0 {
+            let ty = Type::of(&d.declaration_specifier, &i.declarator);
+            let (id, span) = i.declarator.identifier();
+            let address = match ty {
+                Type::Function(..) => 0,
+                Type::Void => {
+                    return Err(mismatch(
+                        span,
+                        format!("'{}' cannot have type 'void'", self.name(id)),
+                    ))
+                }
+                _ => self.allocate(&ty),
+            };
+            let variable = Variable {
+                ty: ty.clone(),
+                address,
+            };
+            // In scope from the end of its declarator, as in C
+            match self.scopes.last_mut() {
+                Some(scope) => scope.insert(id, variable),
+                None => self.interpreter.globals.insert(id, variable),
+            };
+            if let Some(initializer) = &i.initializer {
+                self.initialize(address, &ty, initializer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn initialize(
+        &mut self,
+        address: usize,
+        ty: &Type,
+        initializer: &Initializer,
+    ) -> Result<(), Error> {
+        match (initializer, ty) {
+            (Initializer::List(list, span), Type::Array(element, len)) => {
+                if list.len() > *len {
+                    return Err(mismatch(
+                        *span,
+                        format!("too many initializers for '{ty}'"),
+                    ));
+                }
+                for (i, initializer) in list.iter().enumerate() {
+                    let address = address + i * element.size();
+                    self.initialize(address, element, initializer)?;
+                }
+                Ok(())
+            }
+            (Initializer::List(list, _), _) if list.len() == 1 => {
+                self.initialize(address, ty, &list[0])
+            }
+            (Initializer::Expression(e), _) if ty.is_scalar() => {
+                let t = self.rvalue(e)?;
+                let value = self.convert(t, ty, e.span())?;
+                self.store(address, value, e.span())
+            }
+            _ => Err(mismatch(
+                initializer.span(),
+                format!("cannot initialize '{ty}' with this"),
+            )),
+        }
+    }
+
+    fn block(&mut self, items: &[BlockItem]) -> Result<Flow, Error> {
+        let memory_end = self.interpreter.memory.len();
+        self.scopes.push(HashMap::new());
+        let flow = self.items(items);
+        self.scopes.pop();
+        self.interpreter.memory.truncate(memory_end);
+        flow
+    }
+
+    fn items(&mut self, items: &[BlockItem]) -> Result<Flow, Error> {
+        for item in items {
+            let flow = match item {
+                BlockItem::Declaration(d) => {
+                    self.declare(d)?;
+                    Flow::Normal
+                }
+                BlockItem::Statement(s) => self.statement(s)?,
+            };
+            if !matches!(flow, Flow::Normal) {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn condition(&mut self, e: &Expression) -> Result<bool, Error> {
+        let t = self.rvalue(e)?;
+        self.truthy(t, e.span())
+    }
+
+    fn statement(&mut self, s: &Statement) -> Result<Flow, Error> {
+        match s {
+            Statement::Compound(c) => self.block(&c.block_items),
+            Statement::Expression(e, _) => {
+                if let Some(e) = e {
+                    self.rvalue(e)?;
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Selection(s) => {
+                if self.condition(&s.condition)? {
+                    self.statement(&s.consequent)
+                } else if let Some(alternative) = &s.alternative {
+                    self.statement(alternative)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            Statement::Iteration(IterationStatement::While(s)) => {
+                while self.condition(&s.condition)? {
+                    match self.statement(&s.body)? {
+                        Flow::Break(_) => break,
+                        Flow::Normal | Flow::Continue(_) => {}
+                        flow => return Ok(flow),
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Iteration(IterationStatement::Do(s)) => {
+                loop {
+                    match self.statement(&s.body)? {
+                        Flow::Break(_) => break,
+                        Flow::Normal | Flow::Continue(_) => {}
+                        flow => return Ok(flow),
+                    }
+                    if !self.condition(&s.condition)? {
+                        break;
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Iteration(IterationStatement::For(s)) => {
+                let memory_end = self.interpreter.memory.len();
+                self.scopes.push(HashMap::new());
+                let flow = self.for_statement(s);
+                self.scopes.pop();
+                self.interpreter.memory.truncate(memory_end);
+                flow
+            }
+            Statement::Jump(JumpStatement::Break, span) => {
+                Ok(Flow::Break(*span))
+            }
+            Statement::Jump(JumpStatement::Continue, span) => {
+                Ok(Flow::Continue(*span))
+            }
+            Statement::Jump(JumpStatement::Return(e), span) => {
+                let value = e.as_ref().map(|e| self.rvalue(e)).transpose()?;
+                Ok(Flow::Return(value, *span))
+            }
+        }
+    }
+
+    fn for_statement(&mut self, s: &ForStatement) -> Result<Flow, Error> {
+        match &s.initialization {
+            ForInitialization::Expression(e, _) => {
+                if let Some(e) = e {
+                    self.rvalue(e)?;
+                }
+            }
+            ForInitialization::Declaration(d) => self.declare(d)?,
+        }
+        loop {
+            if let Some(condition) = &s.condition {
+                if !self.condition(condition)? {
+                    break;
+                }
+            }
+            match self.statement(&s.body)? {
+                Flow::Break(_) => break,
+                Flow::Normal | Flow::Continue(_) => {}
+                flow => return Ok(flow),
+            }
+            if let Some(update) = &s.update {
+                self.rvalue(update)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// The address and type of the object `e` designates.
+    fn lvalue(&mut self, e: &Expression) -> Result<(usize, Type), Error> {
+        let span = e.span();
+        let pointer = match e {
+            Expression::Atom(AtomExpression::Identifier(id), span) => {
+                let v = self.lookup(*id, *span)?;
+                if let Type::Function(..) = v.ty {
+                    return Err(mismatch(
+                        *span,
+                        format!("a function is not an object: '{}'", v.ty),
+                    ));
+                }
+                return Ok((v.address, v.ty));
+            }
+            Expression::Unary(UnaryExpression {
+                operator: UnaryOperator::Indirection,
+                operand,
+                ..
+            }) => self.rvalue(operand)?,
+            Expression::Postfix(PostfixExpression {
+                operand,
+                postfix: PostfixExpressionPostfix::Subscript(index),
+                ..
+            }) => {
+                let base = self.rvalue(operand)?;
+                let index = self.rvalue(index)?;
+                self.binary(&BinaryOperator::Add, base, index, span)?
+            }
+            _ => {
+                return Err(mismatch(
+                    span,
+                    "expect an object to assign to or take the address of"
+                        .to_owned(),
+                ))
+            }
+        };
+        match (pointer.value, pointer.ty.decay()) {
+            (Value::Pointer(address), Type::Pointer(t)) => Ok((address, *t)),
+            (_, t) => Err(mismatch(span, format!("cannot dereference '{t}'"))),
+        }
+    }
+
+    fn rvalue(&mut self, e: &Expression) -> Result<Typed, Error> {
+        let span = e.span();
+        match e {
+            Expression::Atom(AtomExpression::Integer(n), _) => Ok(Typed {
+                value: Value::Int(*n as i32),
+                ty: Type::Int,
+            }),
+            Expression::Atom(AtomExpression::Floating(x), _) => Ok(Typed {
+                value: Value::Double(*x),
+                ty: Type::Double,
+            }),
+            Expression::Atom(AtomExpression::Identifier(id), _) => {
+                let v = self.lookup(*id, span)?;
+                match v.ty {
+                    Type::Function(..) => Ok(Typed {
+                        value: Value::Function(*id),
+                        ty: v.ty,
+                    }),
+                    ty => self.load(v.address, ty, span),
+                }
+            }
+            Expression::Assignment(a) => {
+                let (address, ty) = self.lvalue(&a.left)?;
+                if !ty.is_scalar() {
+                    return Err(mismatch(
+                        a.left.span(),
+                        format!("cannot assign to '{ty}'"),
+                    ));
+                }
+                let right = self.rvalue(&a.right)?;
+                let value = self.convert(right, &ty, a.right.span())?;
+                self.store(address, value, span)?;
+                Ok(Typed { value, ty })
+            }
+            Expression::Binary(b) => match b.operator {
+                BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                    let or = matches!(b.operator, BinaryOperator::LogicalOr);
+                    let mut result = self.condition(&b.left)?;
+                    if result != or {
+                        result = self.condition(&b.right)?;
+                    }
+                    Ok(Typed {
+                        value: Value::Int(result as i32),
+                        ty: Type::Int,
+                    })
+                }
+                BinaryOperator::Comma => {
+                    self.rvalue(&b.left)?;
+                    self.rvalue(&b.right)
+                }
+                _ => {
+                    let left = self.rvalue(&b.left)?;
+                    let right = self.rvalue(&b.right)?;
+                    self.binary(&b.operator, left, right, span)
+                }
+            },
+            Expression::Unary(u) => self.unary(u),
+            Expression::Postfix(p) => match &p.postfix {
+                PostfixExpressionPostfix::Subscript(_) => {
+                    let (address, ty) = self.lvalue(e)?;
+                    self.load(address, ty, span)
+                }
+                PostfixExpressionPostfix::Call(arguments) => {
+                    self.call(&p.operand, arguments, span)
+                }
+            },
+        }
+    }
+
+    fn binary(
+        &self,
+        op: &BinaryOperator,
+        left: Typed,
+        right: Typed,
+        span: Span,
+    ) -> Result<Typed, Error> {
+        use BinaryOperator::*;
+        let (lt, rt) = (left.ty.decay(), right.ty.decay());
+        let Some(ty) = binary_type(op, &lt, &rt) else {
+            return Err(mismatch(
+                span,
+                format!(
+                    "invalid operands to '{}': '{lt}' and '{rt}'",
+                    op.symbol()
+                ),
+            ));
+        };
+        let element_size = |t: &Type| match t {
+            Type::Pointer(t) => t.size(),
+            _ => unreachable!(),
+        };
+        let value = match (left.value, right.value) {
+            (Value::Int(l), Value::Int(r)) => Value::Int(match op {
+                Add => l.wrapping_add(r),
+                Minus => l.wrapping_sub(r),
+                Multiply => l.wrapping_mul(r),
+                DivideBy if r == 0 => {
+                    return Err(error(span, ErrorKind::DivisionByZero))
+                }
+                DivideBy => l.wrapping_div(r),
+                And => l & r,
+                Or => l | r,
+                _ => compare(op, l, r),
+            }),
+            (
+                l @ (Value::Int(_) | Value::Double(_)),
+                r @ (Value::Int(_) | Value::Double(_)),
+            ) => {
+                let as_double = |v| match v {
+                    Value::Int(n) => n as f64,
+                    Value::Double(x) => x,
+                    _ => unreachable!(),
+                };
+                let (l, r) = (as_double(l), as_double(r));
+                match op {
+                    Add => Value::Double(l + r),
+                    Minus => Value::Double(l - r),
+                    Multiply => Value::Double(l * r),
+                    DivideBy => Value::Double(l / r),
+                    _ => Value::Int(compare(op, l, r)),
+                }
+            }
+            (Value::Pointer(p), Value::Int(n))
+            | (Value::Int(n), Value::Pointer(p))
+                if matches!(op, Add | Minus) =>
+            {
+                let offset = n as isize * element_size(&ty) as isize;
+                let offset = if matches!(op, Minus) { -offset } else { offset };
+                Value::Pointer(p.wrapping_add_signed(offset))
+            }
+            (Value::Pointer(p), Value::Pointer(q)) if matches!(op, Minus) => {
+                let size = element_size(&lt) as isize;
+                Value::Int(((p as isize - q as isize) / size) as i32)
+            }
+            (l, r) => Value::Int(compare(op, address(l), address(r))),
+        };
+        Ok(Typed { value, ty })
+    }
+
+    fn unary(&mut self, u: &UnaryExpression) -> Result<Typed, Error> {
+        let span = u.span;
+        match u.operator {
+            UnaryOperator::Address => {
+                if let Expression::Atom(AtomExpression::Identifier(id), _) =
+                    &*u.operand
+                {
+                    let t = self.rvalue(&u.operand)?;
+                    if let Type::Function(..) = t.ty {
+                        return Ok(Typed {
+                            value: Value::Function(*id),
+                            ty: t.ty.decay(),
+                        });
+                    }
+                }
+                let (address, ty) = self.lvalue(&u.operand)?;
+                Ok(Typed {
+                    value: Value::Pointer(address),
+                    ty: Type::Pointer(Box::new(ty)),
+                })
+            }
+            UnaryOperator::Indirection => {
+                let t = self.rvalue(&u.operand)?;
+                match (t.value, t.ty.decay()) {
+                    (value @ Value::Function(_), Type::Pointer(ty)) => {
+                        Ok(Typed { value, ty: *ty })
+                    }
+                    (Value::Pointer(address), Type::Pointer(ty)) => {
+                        self.load(address, *ty, span)
+                    }
+                    (_, ty) => Err(mismatch(
+                        span,
+                        format!("cannot dereference '{ty}'"),
+                    )),
+                }
+            }
+            ref op => {
+                let t = self.rvalue(&u.operand)?;
+                let operand_type = t.ty.clone().decay();
+                let Some(ty) = unary_type(op, &operand_type) else {
+                    return Err(mismatch(
+                        span,
+                        format!(
+                            "invalid operand to '{}': '{operand_type}'",
+                            op.symbol()
+                        ),
+                    ));
+                };
+                let value = match (op, t.value) {
+                    (UnaryOperator::Positive, v) => v,
+                    (UnaryOperator::Negative, Value::Int(n)) => {
+                        Value::Int(n.wrapping_neg())
+                    }
+                    (UnaryOperator::Negative, Value::Double(x)) => {
+                        Value::Double(-x)
+                    }
+                    (UnaryOperator::Not, Value::Int(n)) => Value::Int(!n),
+                    _ => Value::Int(!self.truthy(t, span)? as i32),
+                };
+                Ok(Typed { value, ty })
+            }
+        }
+    }
+
+    fn call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        span: Span,
+    ) -> Result<Typed, Error> {
+        let f = self.rvalue(callee)?;
+        let ty = f.ty.decay();
+        let (id, Type::Pointer(function)) = (f.value, &ty) else {
+            return Err(mismatch(span, format!("cannot call '{ty}'")));
+        };
+        let Type::Function(return_type, parameters) = &**function else {
+            return Err(mismatch(span, format!("cannot call '{ty}'")));
+        };
+        if arguments.len() != parameters.len() {
+            return Err(mismatch(
+                span,
+                format!(
+                    "expect {} arguments, found {}",
+                    parameters.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+        let mut values = vec![];
+        for (a, p) in arguments.iter().zip(parameters) {
+            let t = self.rvalue(a)?;
+            values.push(self.convert(t, p, a.span())?);
+        }
+        let Value::Function(id) = id else {
+            return Err(error(span, ErrorKind::InvalidMemoryAccess));
+        };
+        let Some(definition) = self.interpreter.functions.get(&id).cloned()
+        else {
+            return Err(error(
+                span,
+                ErrorKind::UndefinedFunction(self.name(id)),
+            ));
+        };
+        if self.depth == MAX_DEPTH {
+            return Err(error(span, ErrorKind::StackOverflow));
+        }
+
+        let memory_end = self.interpreter.memory.len();
+        let frame = self.frame;
+        self.frame = self.scopes.len();
+        self.depth += 1;
+        let mut scope = HashMap::new();
+        let declared = definition.declarator.parameters().unwrap_or_default();
+        for ((p, ty), value) in declared.iter().zip(parameters).zip(values) {
+            let address = self.allocate(ty);
+            self.interpreter.memory[address] = value;
+            let (id, _) = p.declarator.identifier();
+            scope.insert(
+                id,
+                Variable {
+                    ty: ty.clone(),
+                    address,
+                },
+            );
+        }
+        self.scopes.push(scope);
+        // The parameters and the body share a scope.
+        let flow = self.items(&definition.compound_statement.block_items);
+        self.scopes.pop();
+        self.depth -= 1;
+        self.frame = frame;
+        self.interpreter.memory.truncate(memory_end);
+
+        let value = match flow? {
+            Flow::Return(Some(t), span) => {
+                self.convert(t, return_type, span)?
+            }
+            Flow::Break(span) => return Err(misplaced("break", span)),
+            Flow::Continue(span) => return Err(misplaced("continue", span)),
+            // Running off the end returns zero.
+            _ => match **return_type {
+                Type::Void => Value::Void,
+                Type::Double => Value::Double(0.),
+                Type::Pointer(_) => Value::Pointer(0),
+                _ => Value::Int(0),
+            },
+        };
+        Ok(Typed {
+            value,
+            ty: (**return_type).clone(),
+        })
+    }
+
+    fn type_of(&mut self, e: &Expression) -> Result<Type, Error> {
+        let span = e.span();
+        match e {
+            Expression::Atom(AtomExpression::Integer(_), _) => Ok(Type::Int),
+            Expression::Atom(AtomExpression::Floating(_), _) => {
+                Ok(Type::Double)
+            }
+            Expression::Atom(AtomExpression::Identifier(id), _) => {
+                Ok(self.lookup(*id, span)?.ty)
+            }
+            Expression::Assignment(a) => {
+                self.type_of(&a.right)?;
+                self.type_of(&a.left)
+            }
+            Expression::Binary(b) => {
+                let l = self.type_of(&b.left)?.decay();
+                let r = self.type_of(&b.right)?.decay();
+                binary_type(&b.operator, &l, &r).ok_or_else(|| {
+                    mismatch(
+                        span,
+                        format!(
+                            "invalid operands to '{}': '{l}' and '{r}'",
+                            b.operator.symbol()
+                        ),
+                    )
+                })
+            }
+            Expression::Unary(u) => {
+                let t = self.type_of(&u.operand)?;
+                match u.operator {
+                    UnaryOperator::Address => Ok(match t {
+                        Type::Function(..) => t.decay(),
+                        t => Type::Pointer(Box::new(t)),
+                    }),
+                    UnaryOperator::Indirection => match t.decay() {
+                        Type::Pointer(t) => Ok(*t),
+                        t => Err(mismatch(
+                            span,
+                            format!("cannot dereference '{t}'"),
+                        )),
+                    },
+                    ref op => {
+                        let t = t.decay();
+                        unary_type(op, &t).ok_or_else(|| {
+                            mismatch(
+                                span,
+                                format!(
+                                    "invalid operand to '{}': '{t}'",
+                                    op.symbol()
+                                ),
+                            )
+                        })
+                    }
+                }
+            }
+            Expression::Postfix(p) => {
+                let t = self.type_of(&p.operand)?.decay();
+                match &p.postfix {
+                    PostfixExpressionPostfix::Subscript(index) => {
+                        let index = self.type_of(index)?.decay();
+                        match binary_type(&BinaryOperator::Add, &t, &index) {
+                            Some(Type::Pointer(t)) => Ok(*t),
+                            _ => Err(mismatch(
+                                span,
+                                format!("cannot subscript '{t}'"),
+                            )),
+                        }
+                    }
+                    PostfixExpressionPostfix::Call(_) => match t {
+                        Type::Pointer(f) => match *f {
+                            Type::Function(return_type, _) => Ok(*return_type),
+                            f => Err(mismatch(
+                                span,
+                                format!("cannot call '{}'", f.decay()),
+                            )),
+                        },
+                        t => Err(mismatch(span, format!("cannot call '{t}'"))),
+                    },
+                }
+            }
+        }
+    }
+}
+
+fn compare<T: PartialOrd>(op: &BinaryOperator, l: T, r: T) -> i32 {
+    (match op {
+        BinaryOperator::Lt => l < r,
+        BinaryOperator::Gt => l > r,
+        BinaryOperator::Le => l <= r,
+        BinaryOperator::Ge => l >= r,
+        BinaryOperator::Eq => l == r,
+        BinaryOperator::Neq => l != r,
+        _ => unreachable!(),
+    }) as i32
+}
+
+/// A pointer or null pointer constant as an address to compare.
+fn address(v: Value) -> usize {
+    match v {
+        Value::Pointer(p) | Value::Function(p) => p,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{
+        lexer::scan,
+        parser::{parse, parse_full_expression},
+    };
+
+    /// Declare `program`, then evaluate `expression` and show its type and
+    /// value.
+    fn evaluate(program: &str, expression: &str) -> Result<String, Error> {
+        let (program, expression) = (program.to_owned(), expression.to_owned());
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || evaluate_here(&program, &expression))
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    fn evaluate_here(program: &str, expression: &str) -> Result<String, Error> {
+        let declarations = scan(program).unwrap().tokens.len();
+        let lexer_output = scan(&format!("{program}\n{expression}")).unwrap();
+        let (tokens, rest) = lexer_output.tokens.split_at(declarations);
+        let ast = parse(tokens).unwrap();
+        let e = parse_full_expression(rest).unwrap();
+        let mut interpreter = Interpreter::default();
+        for d in ast.external_declarations {
+            interpreter.declare(d, &lexer_output)?;
+        }
+        let ty = interpreter.type_of(&e, &lexer_output)?;
+        let t = interpreter.evaluate(&e, &lexer_output)?;
+        assert_eq!(t.ty, ty);
+        Ok(format!("({ty}) {}", interpreter.show(&t, &lexer_output)))
+    }
+
+    #[test_case("", "1 + 2 * 3", "(int) 7")]
+    #[test_case("", "7 / 2 + 0.5", "(double) 3.5")]
+    #[test_case("", "-2147483647 - 2", "(int) 2147483647"; "wraps")]
+    #[test_case("", "!0 + (1 < 2) + (3 && 0) + (2 | 1)", "(int) 5")]
+    #[test_case("int a[2][3];", "a", "(int[2][3]) {{0, 0, 0}, {0, 0, 0}}")]
+    #[test_case("int a[3] = {1, 2};", "a[1] + *(a + 2)", "(int) 2")]
+    #[test_case("double x = 1;", "x = x / 4", "(double) 0.25")]
+    #[test_case("int a[3]; int *p = a;", "&a[2] - p", "(int) 2")]
+    #[test_case("int a[2][3];", "*(a + 1) - *a", "(int) 3")]
+    #[test_case(
+        "int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }",
+        "fib(15)",
+        "(int) 610"
+    )]
+    #[test_case(
+        "int sum(int n) { int s = 0; int i; for (i = 0; ; i = i + 1) { \
+         if (i == 3) continue; if (i > n) break; s = s + i; } return s; }",
+        "sum(5)",
+        "(int) 12"
+    )]
+    #[test_case(
+        "int n = 1; void bump(int *p) { *p = *p + 1; } \
+         int bumped(int x) { bump(&n); return n; }",
+        "bumped(0)",
+        "(int) 2"
+    )]
+    #[test_case(
+        "int twice(int x) { return 2 * x; } int (*f)(int x) = twice;",
+        "(*f)(f(3))",
+        "(int) 12"
+    )]
+    #[test_case(
+        "double half(int x) { return x / 2.0; }",
+        "half",
+        "(double (int)) half"
+    )]
+    #[test_case("int f(int x) {}", "f(1)", "(int) 0"; "no return")]
+    #[test_case("int n;", "n = 1, n + 0.5", "(double) 1.5"; "comma")]
+    fn test_evaluate(program: &str, expression: &str, expected: &str) {
+        assert_eq!(evaluate(program, expression).unwrap(), expected);
+    }
+
+    #[test_case("", "1 / 0", ErrorKind::DivisionByZero)]
+    #[test_case("int *p;", "*p", ErrorKind::InvalidMemoryAccess)]
+    #[test_case("int f(int n);", "f(1)", ErrorKind::UndefinedFunction("f".to_owned()))]
+    #[test_case(
+        "int f(int n) { return f(n); }",
+        "f(1)",
+        ErrorKind::StackOverflow
+    )]
+    #[test_case(
+        "int f(int x) { break; }",
+        "f(1)",
+        ErrorKind::MisplacedJump("break")
+    )]
+    #[test_case(
+        "int *p;",
+        "p + 1.0",
+        ErrorKind::TypeMismatch(
+            "invalid operands to '+': 'int *' and 'double'".to_owned()
+        )
+    )]
+    #[test_case(
+        "int f(int n);",
+        "f()",
+        ErrorKind::TypeMismatch("expect 1 arguments, found 0".to_owned())
+    )]
+    fn test_error(program: &str, expression: &str, expected: ErrorKind) {
+        let e = evaluate(program, expression).unwrap_err();
+        assert_eq!(e.error_kind, expected);
+    }
+}
@@ -0,0 +1,1614 @@
+//! A three-address intermediate representation of functions, lowered from
+//! their [`Cfg`]s.
+//!
+//! Instructions read and write numbered virtual registers. A scalar local
+//! whose address is never taken lives in a register that every assignment
+//! to it writes, so a register may have several definitions until
+//! [`crate::ssa::into_ssa`] gives each its own. Arrays and locals whose
+//! address is taken live in stack slots and globals in memory, which
+//! `load` and `store` reach through addresses.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    ast::*,
+    cfg::{self, BlockId, Cfg},
+    dataflow::{AddressTaken, Analysis, Direction},
+    error::{Error, ErrorKind},
+    interp::{binary_type, unary_type},
+    lexer::LexerOutput,
+    resolve::analyze,
+    token::Span,
+    types::Type,
+};
+
+pub type Reg = usize;
+
+/// The type of a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ty {
+    Int,
+    Double,
+    Pointer,
+}
+
+impl Ty {
+    /// The type of registers holding values of type `t` after decaying.
+    pub fn of(t: &Type) -> Self {
+        match t {
+            Type::Int => Ty::Int,
+            Type::Double => Ty::Double,
+            _ => Ty::Pointer,
+        }
+    }
+}
+
+/// An `int`, or a null pointer, or a `double`. Doubles are equal if their
+/// bits are, so that `0.0` and `-0.0` differ.
+#[derive(Debug, Clone, Copy)]
+pub enum Constant {
+    Int(i32),
+    Double(f64),
+}
+
+impl Constant {
+    pub fn zero(ty: Ty) -> Self {
+        match ty {
+            Ty::Double => Constant::Double(0.0),
+            Ty::Int | Ty::Pointer => Constant::Int(0),
+        }
+    }
+
+    pub fn is_true(self) -> bool {
+        match self {
+            Constant::Int(n) => n != 0,
+            Constant::Double(x) => x != 0.0,
+        }
+    }
+}
+
+impl PartialEq for Constant {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Constant::Int(a), Constant::Int(b)) => a == b,
+            (Constant::Double(a), Constant::Double(b)) => {
+                a.to_bits() == b.to_bits()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Constant {}
+
+impl Hash for Constant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Constant::Int(n) => (0, *n as u64).hash(state),
+            Constant::Double(x) => (1, x.to_bits()).hash(state),
+        }
+    }
+}
+
+/// Something with an address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    /// A stack slot of the function
+    Slot(usize),
+    Global(String),
+    Function(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Negate,
+    /// `!`
+    Not,
+    /// `~`
+    Complement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    And,
+    Or,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinaryOp {
+    pub fn is_commutative(self) -> bool {
+        use BinaryOp::*;
+        matches!(self, Add | Multiply | And | Or | Eq | Ne)
+    }
+
+    pub fn is_comparison(self) -> bool {
+        use BinaryOp::*;
+        matches!(self, Lt | Gt | Le | Ge | Eq | Ne)
+    }
+}
+
+/// What an instruction computes. Comparisons give an `int`; other
+/// operations give a value of the type of the destination.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Op {
+    Const(Constant),
+    Copy(Reg),
+    /// The argument at this index
+    Param(usize),
+    Unary(UnaryOp, Reg),
+    Binary(BinaryOp, Reg, Reg),
+    /// The value converted to the type of the destination
+    Convert(Reg),
+    Address(Symbol),
+    Load(Reg),
+    /// Store the value of the second register at the address in the first
+    Store(Reg, Reg),
+    Call(String, Vec<Reg>),
+}
+
+impl Op {
+    pub fn operands(&self) -> Vec<Reg> {
+        match self {
+            Op::Const(_) | Op::Param(_) | Op::Address(_) => vec![],
+            Op::Copy(r) | Op::Unary(_, r) | Op::Convert(r) | Op::Load(r) => {
+                vec![*r]
+            }
+            Op::Binary(_, a, b) | Op::Store(a, b) => vec![*a, *b],
+            Op::Call(_, arguments) => arguments.clone(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Reg> {
+        match self {
+            Op::Const(_) | Op::Param(_) | Op::Address(_) => vec![],
+            Op::Copy(r) | Op::Unary(_, r) | Op::Convert(r) | Op::Load(r) => {
+                vec![r]
+            }
+            Op::Binary(_, a, b) | Op::Store(a, b) => vec![a, b],
+            Op::Call(_, arguments) => arguments.iter_mut().collect(),
+        }
+    }
+
+    /// Whether running it does more than compute its result, or depends on
+    /// more than its operands.
+    pub fn is_pure(&self) -> bool {
+        !matches!(self, Op::Load(_) | Op::Store(..) | Op::Call(..))
+    }
+
+    /// Whether running it changes memory or does I/O.
+    pub fn has_effects(&self) -> bool {
+        matches!(self, Op::Store(..) | Op::Call(..))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// `None` for a store or a call whose value is not used
+    pub dest: Option<Reg>,
+    pub op: Op,
+}
+
+/// The value of an argument chosen by the predecessor control comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phi {
+    pub dest: Reg,
+    pub args: Vec<(BlockId, Reg)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Go to the first block if the register is not zero, or else to the
+    /// second
+    Branch(Reg, BlockId, BlockId),
+    Return(Option<Reg>),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match *self {
+            Terminator::Jump(b) => vec![b],
+            Terminator::Branch(_, then, otherwise) => vec![then, otherwise],
+            Terminator::Return(_) => vec![],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Reg> {
+        match self {
+            Terminator::Branch(r, ..) | Terminator::Return(Some(r)) => vec![r],
+            Terminator::Jump(_) | Terminator::Return(None) => vec![],
+        }
+    }
+
+    pub fn operand(&self) -> Option<Reg> {
+        match *self {
+            Terminator::Branch(r, ..) | Terminator::Return(Some(r)) => Some(r),
+            Terminator::Jump(_) | Terminator::Return(None) => None,
+        }
+    }
+}
+
+/// The phis of a block run at once on entry, before its instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub phis: Vec<Phi>,
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Ty>,
+    /// `None` for `void`
+    pub return_ty: Option<Ty>,
+    /// The type of each register
+    pub registers: Vec<Ty>,
+    /// The size in bytes of each stack slot
+    pub slots: Vec<usize>,
+    /// Block 0 is the entry, and every block is reachable from it.
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    pub fn new_register(&mut self, ty: Ty) -> Reg {
+        self.registers.push(ty);
+        self.registers.len() - 1
+    }
+
+    /// The successors of each block.
+    pub fn successors(&self) -> Vec<Vec<BlockId>> {
+        self.blocks
+            .iter()
+            .map(|b| b.terminator.successors())
+            .collect()
+    }
+
+    /// The predecessors of each block, in order, repeated for each edge.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for (b, block) in self.blocks.iter().enumerate() {
+            for s in block.terminator.successors() {
+                predecessors[s].push(b);
+            }
+        }
+        predecessors
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: Type,
+    /// The value of each scalar in it, or nothing if it is all zeros
+    pub init: Vec<Constant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
+
+/// The registers whose value may be used later, for
+/// [`crate::dataflow::solve`]. The arguments of a phi are used at the end of
+/// their predecessor, so they are live after it but not before the phi.
+pub struct Liveness<'a>(pub &'a Function);
+
+impl Analysis for Liveness<'_> {
+    type Domain = BTreeSet<Reg>;
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn bottom(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn transfer(&self, b: BlockId, state: &mut Self::Domain) {
+        let block = &self.0.blocks[b];
+        for s in block.terminator.successors() {
+            for phi in &self.0.blocks[s].phis {
+                let args = phi.args.iter().filter(|(p, _)| *p == b);
+                state.extend(args.map(|&(_, r)| r));
+            }
+        }
+        state.extend(block.terminator.operand());
+        for i in block.instructions.iter().rev() {
+            if let Some(d) = i.dest {
+                state.remove(&d);
+            }
+            state.extend(i.op.operands());
+        }
+        for phi in &block.phis {
+            state.remove(&phi.dest);
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Ty::Int => "int",
+            Ty::Double => "double",
+            Ty::Pointer => "ptr",
+        })
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constant::Int(n) => write!(f, "{n}"),
+            Constant::Double(x) => write!(f, "{x:?}"),
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Symbol::Slot(n) => write!(f, "slot{n}"),
+            Symbol::Global(name) | Symbol::Function(name) => {
+                write!(f, "@{name}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Const(c) => write!(f, "const {c}"),
+            Op::Copy(r) => write!(f, "copy %{r}"),
+            Op::Param(n) => write!(f, "param {n}"),
+            Op::Unary(op, r) => {
+                let op = match op {
+                    UnaryOp::Negate => "neg",
+                    UnaryOp::Not => "not",
+                    UnaryOp::Complement => "compl",
+                };
+                write!(f, "{op} %{r}")
+            }
+            Op::Binary(op, a, b) => {
+                let op = match op {
+                    BinaryOp::Add => "add",
+                    BinaryOp::Subtract => "sub",
+                    BinaryOp::Multiply => "mul",
+                    BinaryOp::Divide => "div",
+                    BinaryOp::And => "and",
+                    BinaryOp::Or => "or",
+                    BinaryOp::Lt => "lt",
+                    BinaryOp::Gt => "gt",
+                    BinaryOp::Le => "le",
+                    BinaryOp::Ge => "ge",
+                    BinaryOp::Eq => "eq",
+                    BinaryOp::Ne => "ne",
+                };
+                write!(f, "{op} %{a}, %{b}")
+            }
+            Op::Convert(r) => write!(f, "convert %{r}"),
+            Op::Address(s) => write!(f, "address {s}"),
+            Op::Load(r) => write!(f, "load %{r}"),
+            Op::Store(a, v) => write!(f, "store %{a}, %{v}"),
+            Op::Call(name, arguments) => {
+                let arguments: Vec<_> =
+                    arguments.iter().map(|r| format!("%{r}")).collect();
+                write!(f, "call @{name}({})", arguments.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<_> = self.params.iter().map(Ty::to_string).collect();
+        write!(f, "function @{}({})", self.name, params.join(", "))?;
+        if let Some(ty) = self.return_ty {
+            write!(f, " -> {ty}")?;
+        }
+        writeln!(f, " {{")?;
+        for (n, size) in self.slots.iter().enumerate() {
+            writeln!(f, "  slot{n}: {size} bytes")?;
+        }
+        for (b, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{b}:")?;
+            for phi in &block.phis {
+                let args: Vec<_> = phi
+                    .args
+                    .iter()
+                    .map(|(p, r)| format!("[b{p}: %{r}]"))
+                    .collect();
+                let ty = self.registers[phi.dest];
+                writeln!(f, "  %{}: {ty} = phi {}", phi.dest, args.join(", "))?;
+            }
+            for i in &block.instructions {
+                match i.dest {
+                    Some(d) => {
+                        writeln!(f, "  %{d}: {} = {}", self.registers[d], i.op)?
+                    }
+                    None => writeln!(f, "  {}", i.op)?,
+                }
+            }
+            match block.terminator {
+                Terminator::Jump(b) => writeln!(f, "  jump b{b}")?,
+                Terminator::Branch(r, then, otherwise) => {
+                    writeln!(f, "  branch %{r}, b{then}, b{otherwise}")?
+                }
+                Terminator::Return(Some(r)) => writeln!(f, "  return %{r}")?,
+                Terminator::Return(None) => writeln!(f, "  return")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for g in &self.globals {
+            write!(f, "global @{}: {} bytes", g.name, g.ty.bytes())?;
+            if !g.init.is_empty() {
+                let init: Vec<_> =
+                    g.init.iter().map(Constant::to_string).collect();
+                write!(f, " = {}", init.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        for (n, function) in self.functions.iter().enumerate() {
+            if n > 0 || !self.globals.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
+
+fn error(span: Span, kind: ErrorKind) -> Error {
+    Error::new(span, kind)
+}
+
+fn mismatch(span: Span, message: String) -> Error {
+    error(span, ErrorKind::TypeMismatch(message))
+}
+
+/// Lower the functions of `ast`, which has no errors [`analyze`] finds.
+pub fn lower(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+) -> Result<Program, Error> {
+    let declarations: HashMap<_, _> = analyze(ast, lexer_output)
+        .references
+        .iter()
+        .map(|r| (r.span.start, r.declaration.start))
+        .collect();
+    let mut globals = HashMap::new();
+    let mut program = Program::default();
+    for d in &ast.external_declarations {
+        match d {
+            ExternalDeclaration::Declaration(d) => {
+                for i in &d.init_declarator_list.0 {
+                    let ty = Type::of(&d.declaration_specifier, &i.declarator);
+                    let (id, span) = i.declarator.identifier();
+                    let name = lexer_output.name(id).to_owned();
+                    globals.insert(span.start, (name.clone(), ty.clone()));
+                    if let Type::Function(..) = ty {
+                        continue;
+                    }
+                    let init = match &i.initializer {
+                        Some(initializer) => global_init(&ty, initializer)?,
+                        None => vec![],
+                    };
+                    match program.globals.iter_mut().find(|g| g.name == name) {
+                        // A tentative definition
+                        Some(_) if init.is_empty() => {}
+                        Some(g) => g.init = init,
+                        None => program.globals.push(Global { name, ty, init }),
+                    }
+                }
+            }
+            ExternalDeclaration::FunctionDeclaration(f) => {
+                let ty = Type::of(&f.declaration_specifier, &f.declarator);
+                let (id, span) = f.declarator.identifier();
+                let name = lexer_output.name(id).to_owned();
+                globals.insert(span.start, (name.clone(), ty.clone()));
+                let lowerer =
+                    Lowerer::new(f, name, ty, &declarations, &globals);
+                program.functions.push(lowerer.lower(f, lexer_output)?);
+            }
+        }
+    }
+    Ok(program)
+}
+
+/// The scalars a global of type `ty` starts with, which must be constants.
+fn global_init(ty: &Type, i: &Initializer) -> Result<Vec<Constant>, Error> {
+    match (ty, i) {
+        (Type::Array(elem, len), Initializer::List(items, span)) => {
+            if items.len() > *len {
+                return Err(mismatch(
+                    *span,
+                    "excess elements in array initializer".to_owned(),
+                ));
+            }
+            let mut init = vec![];
+            for item in items {
+                init.extend(global_init(elem, item)?);
+            }
+            let zero = Constant::zero(Ty::of(&scalar_type(ty)));
+            init.resize(ty.size(), zero);
+            Ok(init)
+        }
+        (_, Initializer::List(items, _)) if items.len() == 1 => {
+            global_init(ty, &items[0])
+        }
+        (_, Initializer::Expression(e)) if ty.is_scalar() => {
+            let constant = constant(e).ok_or_else(|| {
+                mismatch(
+                    e.span(),
+                    "initializer element is not a compile-time constant"
+                        .to_owned(),
+                )
+            })?;
+            Ok(vec![match (Ty::of(ty), constant) {
+                (Ty::Double, Constant::Int(n)) => Constant::Double(n as f64),
+                (Ty::Int | Ty::Pointer, Constant::Double(x)) => {
+                    Constant::Int(x as i32)
+                }
+                (_, c) => c,
+            }])
+        }
+        _ => Err(mismatch(
+            i.span(),
+            format!("invalid initializer for '{ty}'"),
+        )),
+    }
+}
+
+/// The type of the scalars an object of type `ty` is made of.
+pub(crate) fn scalar_type(ty: &Type) -> Type {
+    match ty {
+        Type::Array(elem, _) => scalar_type(elem),
+        ty => ty.clone(),
+    }
+}
+
+/// The value of `e` if it is a number, maybe negated.
+fn constant(e: &Expression) -> Option<Constant> {
+    match e {
+        Expression::Atom(AtomExpression::Integer(n), _) => {
+            Some(Constant::Int(*n as i32))
+        }
+        Expression::Atom(AtomExpression::Floating(x), _) => {
+            Some(Constant::Double(*x))
+        }
+        Expression::Unary(UnaryExpression {
+            operator: UnaryOperator::Negative,
+            operand,
+            ..
+        }) => match constant(operand)? {
+            Constant::Int(n) => Some(Constant::Int(n.wrapping_neg())),
+            Constant::Double(x) => Some(Constant::Double(-x)),
+        },
+        _ => None,
+    }
+}
+
+enum Local {
+    Register(Reg, Type),
+    Slot(usize, Type),
+}
+
+/// Where an lvalue is.
+#[derive(Clone, Copy)]
+enum Place {
+    Register(Reg),
+    /// At the address in the register
+    Memory(Reg),
+}
+
+struct Lowerer<'a> {
+    /// The start of the declaration each identifier refers to
+    declarations: &'a HashMap<usize, usize>,
+    /// The name and type of each global and function, by the start of its
+    /// declaration
+    globals: HashMap<usize, (String, Type)>,
+    locals: HashMap<usize, Local>,
+    /// The declarations of the locals whose address is taken
+    address_taken: HashSet<usize>,
+    return_type: Type,
+    function: Function,
+    /// Where instructions go
+    current: BlockId,
+}
+
+impl<'a> Lowerer<'a> {
+    fn new(
+        f: &FunctionDefinition,
+        name: String,
+        ty: Type,
+        declarations: &'a HashMap<usize, usize>,
+        globals: &HashMap<usize, (String, Type)>,
+    ) -> Self {
+        let Type::Function(return_type, params) = ty else {
+            unreachable!("a function definition declares a function")
+        };
+        let mut address_taken = AddressTaken {
+            declarations,
+            starts: vec![],
+        };
+        address_taken.visit_compound_statement(&f.compound_statement);
+        Lowerer {
+            declarations,
+            globals: globals.clone(),
+            locals: HashMap::new(),
+            address_taken: address_taken.starts.into_iter().collect(),
+            function: Function {
+                name,
+                params: params.iter().map(Ty::of).collect(),
+                return_ty: (*return_type != Type::Void)
+                    .then(|| Ty::of(&return_type)),
+                registers: vec![],
+                slots: vec![],
+                blocks: vec![],
+            },
+            return_type: *return_type,
+            current: 0,
+        }
+    }
+
+    fn lower(
+        mut self,
+        f: &FunctionDefinition,
+        lexer_output: &LexerOutput,
+    ) -> Result<Function, Error> {
+        let cfg = Cfg::build(f)?;
+        // Block `b` of the CFG starts block `b` here; `&&` and `||` add more.
+        for _ in &cfg.blocks {
+            self.new_block();
+        }
+        let params = f.declarator.parameters().unwrap_or(&[]);
+        for (n, p) in params.iter().enumerate() {
+            let ty = Type::of(&p.specifier, &p.declarator).decay();
+            let (_, span) = p.declarator.identifier();
+            let value = self.emit(Ty::of(&ty), Op::Param(n));
+            self.declare(span, ty, Some(value));
+        }
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            self.current = b;
+            for &i in &block.instructions {
+                match i {
+                    cfg::Instruction::Declaration(d) => {
+                        self.declaration(d, lexer_output)?
+                    }
+                    cfg::Instruction::Expression(e) => self.effect(e)?,
+                }
+            }
+            let terminator = match block.terminator {
+                cfg::Terminator::Goto(b) => Terminator::Jump(b),
+                cfg::Terminator::Branch {
+                    condition,
+                    then,
+                    otherwise,
+                } => Terminator::Branch(
+                    self.condition(condition)?,
+                    then,
+                    otherwise,
+                ),
+                cfg::Terminator::Return(e) => self.ret(e)?,
+            };
+            self.terminate(terminator);
+        }
+        Ok(self.function)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(Block {
+            phis: vec![],
+            instructions: vec![],
+            terminator: Terminator::Return(None),
+        });
+        self.function.blocks.len() - 1
+    }
+
+    fn terminate(&mut self, t: Terminator) {
+        self.function.blocks[self.current].terminator = t;
+    }
+
+    fn push(&mut self, dest: Option<Reg>, op: Op) {
+        let instructions = &mut self.function.blocks[self.current].instructions;
+        instructions.push(Instruction { dest, op });
+    }
+
+    /// Add an instruction computing `op` into a new register.
+    fn emit(&mut self, ty: Ty, op: Op) -> Reg {
+        let dest = self.function.new_register(ty);
+        self.push(Some(dest), op);
+        dest
+    }
+
+    fn constant(&mut self, ty: Ty, c: Constant) -> Reg {
+        self.emit(ty, Op::Const(c))
+    }
+
+    /// Bring the local declared at `span` into scope, with `value` if any.
+    fn declare(&mut self, span: Span, ty: Type, value: Option<Reg>) {
+        let local =
+            if ty.is_scalar() && !self.address_taken.contains(&span.start) {
+                let reg = self.function.new_register(Ty::of(&ty));
+                if let Some(value) = value {
+                    self.push(Some(reg), Op::Copy(value));
+                }
+                Local::Register(reg, ty)
+            } else {
+                self.function.slots.push(ty.bytes());
+                let slot = self.function.slots.len() - 1;
+                if let Some(value) = value {
+                    let address =
+                        self.emit(Ty::Pointer, Op::Address(Symbol::Slot(slot)));
+                    self.push(None, Op::Store(address, value));
+                }
+                Local::Slot(slot, ty)
+            };
+        self.locals.insert(span.start, local);
+    }
+
+    fn declaration(
+        &mut self,
+        d: &Declaration,
+        lexer_output: &LexerOutput,
+    ) -> Result<(), Error> {
+        for i in &d.init_declarator_list.0 {
+            let ty = Type::of(&d.declaration_specifier, &i.declarator);
+            let (id, span) = i.declarator.identifier();
+            if let Type::Function(..) = ty {
+                let name = lexer_output.name(id).to_owned();
+                self.globals.insert(span.start, (name, ty));
+                continue;
+            }
+            match &i.initializer {
+                Some(Initializer::Expression(e)) if ty.is_scalar() => {
+                    let value = self.rvalue(e)?;
+                    let value = self.convert(value, &ty, e.span())?;
+                    self.declare(span, ty, Some(value));
+                }
+                Some(initializer) => {
+                    self.declare(span, ty.clone(), None);
+                    let Some(Local::Slot(slot, _)) =
+                        self.locals.get(&span.start)
+                    else {
+                        return Err(mismatch(
+                            initializer.span(),
+                            format!("invalid initializer for '{ty}'"),
+                        ));
+                    };
+                    let slot = *slot;
+                    let address =
+                        self.emit(Ty::Pointer, Op::Address(Symbol::Slot(slot)));
+                    self.initialize(address, &ty, initializer)?;
+                }
+                None => self.declare(span, ty, None),
+            }
+        }
+        Ok(())
+    }
+
+    /// Store `i` in the object of type `ty` at `address`.
+    fn initialize(
+        &mut self,
+        address: Reg,
+        ty: &Type,
+        i: &Initializer,
+    ) -> Result<(), Error> {
+        match (ty, i) {
+            (Type::Array(elem, len), Initializer::List(items, span)) => {
+                if items.len() > *len {
+                    return Err(mismatch(
+                        *span,
+                        "excess elements in array initializer".to_owned(),
+                    ));
+                }
+                for n in 0..*len {
+                    let index = self.constant(Ty::Int, Constant::Int(n as i32));
+                    let element = self.element(address, elem, index);
+                    match items.get(n) {
+                        Some(item) => self.initialize(element, elem, item)?,
+                        None => self.zero(element, elem),
+                    }
+                }
+                Ok(())
+            }
+            (_, Initializer::List(items, _)) if items.len() == 1 => {
+                self.initialize(address, ty, &items[0])
+            }
+            (_, Initializer::Expression(e)) if ty.is_scalar() => {
+                let value = self.rvalue(e)?;
+                let value = self.convert(value, ty, e.span())?;
+                self.push(None, Op::Store(address, value));
+                Ok(())
+            }
+            _ => Err(mismatch(
+                i.span(),
+                format!("invalid initializer for '{ty}'"),
+            )),
+        }
+    }
+
+    /// Store zeros in the object of type `ty` at `address`.
+    fn zero(&mut self, address: Reg, ty: &Type) {
+        match ty {
+            Type::Array(elem, len) => {
+                for n in 0..*len {
+                    let index = self.constant(Ty::Int, Constant::Int(n as i32));
+                    let element = self.element(address, elem, index);
+                    self.zero(element, elem);
+                }
+            }
+            _ => {
+                let ty = Ty::of(ty);
+                let zero = self.constant(ty, Constant::zero(ty));
+                self.push(None, Op::Store(address, zero));
+            }
+        }
+    }
+
+    /// The address of element `index` of an array of `elem` at `address`.
+    fn element(&mut self, address: Reg, elem: &Type, index: Reg) -> Reg {
+        let size = Constant::Int(elem.bytes() as i32);
+        let size = self.constant(Ty::Int, size);
+        let offset =
+            self.emit(Ty::Int, Op::Binary(BinaryOp::Multiply, index, size));
+        let offset = self.emit(Ty::Pointer, Op::Convert(offset));
+        self.emit(Ty::Pointer, Op::Binary(BinaryOp::Add, address, offset))
+    }
+
+    fn ret(&mut self, e: Option<&Expression>) -> Result<Terminator, Error> {
+        let value = match (e, self.function.return_ty) {
+            (Some(e), Some(_)) => {
+                let value = self.rvalue(e)?;
+                let ty = self.return_type.clone();
+                Some(self.convert(value, &ty, e.span())?)
+            }
+            (Some(e), None) => {
+                return Err(mismatch(
+                    e.span(),
+                    "void function should not return a value".to_owned(),
+                ))
+            }
+            // Running off the end of a non-void function returns zero, so
+            // that `main` returns 0.
+            (None, Some(ty)) => Some(self.constant(ty, Constant::zero(ty))),
+            (None, None) => None,
+        };
+        Ok(Terminator::Return(value))
+    }
+
+    /// Lower `e` as a statement, where a `void` call may appear.
+    fn effect(&mut self, e: &Expression) -> Result<(), Error> {
+        match e {
+            Expression::Postfix(PostfixExpression {
+                operand,
+                postfix: PostfixExpressionPostfix::Call(arguments),
+                span,
+            }) => self.call(operand, arguments, *span).map(|_| ()),
+            Expression::Binary(BinaryExpression {
+                operator: BinaryOperator::Comma,
+                left,
+                right,
+                ..
+            }) => {
+                self.effect(left)?;
+                self.effect(right)
+            }
+            e => self.rvalue(e).map(|_| ()),
+        }
+    }
+
+    /// A register that is not zero if `e` is true, to branch on.
+    fn condition(&mut self, e: &Expression) -> Result<Reg, Error> {
+        let (value, ty) = self.rvalue(e)?;
+        match ty {
+            Type::Double => Ok(self.truth(value, Ty::Double)),
+            ty if ty.is_scalar() => Ok(value),
+            ty => Err(mismatch(
+                e.span(),
+                format!("used type '{ty}' where a scalar is required"),
+            )),
+        }
+    }
+
+    /// 1 if `value` is not zero and 0 if it is.
+    fn truth(&mut self, value: Reg, ty: Ty) -> Reg {
+        let zero = self.constant(ty, Constant::zero(ty));
+        self.emit(Ty::Int, Op::Binary(BinaryOp::Ne, value, zero))
+    }
+
+    /// Convert `value` to `to`, for an assignment, an argument or a return.
+    fn convert(
+        &mut self,
+        (value, from): (Reg, Type),
+        to: &Type,
+        span: Span,
+    ) -> Result<Reg, Error> {
+        let compatible = match (&from, to) {
+            (from, to) if from.is_arithmetic() && to.is_arithmetic() => true,
+            (Type::Pointer(_), Type::Pointer(_)) => true,
+            // A null pointer constant, or an address from an integer
+            (Type::Int, Type::Pointer(_)) => true,
+            _ => false,
+        };
+        if !compatible {
+            return Err(mismatch(
+                span,
+                format!("cannot convert '{from}' to '{to}'"),
+            ));
+        }
+        Ok(if Ty::of(&from) == Ty::of(to) {
+            value
+        } else {
+            self.emit(Ty::of(to), Op::Convert(value))
+        })
+    }
+
+    /// The global or function `span` refers to.
+    fn global(&self, span: Span) -> Option<&(String, Type)> {
+        self.globals.get(self.declarations.get(&span.start)?)
+    }
+
+    /// Lower `e` for its value, with arrays and functions decayed to
+    /// pointers.
+    fn rvalue(&mut self, e: &Expression) -> Result<(Reg, Type), Error> {
+        let span = e.span();
+        if let Some((name, ty)) = self.function_designator(e) {
+            let address =
+                self.emit(Ty::Pointer, Op::Address(Symbol::Function(name)));
+            return Ok((address, Type::Pointer(Box::new(ty))));
+        }
+        match e {
+            Expression::Atom(AtomExpression::Integer(n), _) => {
+                let value = self.constant(Ty::Int, Constant::Int(*n as i32));
+                Ok((value, Type::Int))
+            }
+            Expression::Atom(AtomExpression::Floating(x), _) => {
+                let value = self.constant(Ty::Double, Constant::Double(*x));
+                Ok((value, Type::Double))
+            }
+            Expression::Atom(AtomExpression::Identifier(_), _) => {
+                let (place, ty) = self.lvalue(e)?;
+                Ok(self.read(place, ty))
+            }
+            Expression::Assignment(a) => {
+                let (place, ty) = self.lvalue(&a.left)?;
+                if !ty.is_scalar() {
+                    return Err(mismatch(
+                        a.left.span(),
+                        format!("cannot assign to an object of type '{ty}'"),
+                    ));
+                }
+                let value = self.rvalue(&a.right)?;
+                let value = self.convert(value, &ty, a.right.span())?;
+                match place {
+                    Place::Register(r) => self.push(Some(r), Op::Copy(value)),
+                    Place::Memory(address) => {
+                        self.push(None, Op::Store(address, value))
+                    }
+                }
+                Ok((value, ty))
+            }
+            Expression::Binary(b) => match b.operator {
+                BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                    self.logical(b)
+                }
+                BinaryOperator::Comma => {
+                    self.effect(&b.left)?;
+                    self.rvalue(&b.right)
+                }
+                _ => {
+                    let left = self.rvalue(&b.left)?;
+                    let right = self.rvalue(&b.right)?;
+                    self.binary(&b.operator, left, right, span)
+                }
+            },
+            Expression::Unary(u) => match u.operator {
+                UnaryOperator::Address => {
+                    let (place, ty) = self.lvalue(&u.operand)?;
+                    let Place::Memory(address) = place else {
+                        unreachable!(
+                            "locals whose address is taken are in memory"
+                        )
+                    };
+                    Ok((address, Type::Pointer(Box::new(ty))))
+                }
+                UnaryOperator::Indirection => {
+                    let (place, ty) = self.lvalue(e)?;
+                    Ok(self.read(place, ty))
+                }
+                ref op => {
+                    let (value, ty) = self.rvalue(&u.operand)?;
+                    let result = unary_type(op, &ty).ok_or_else(|| {
+                        mismatch(
+                            span,
+                            format!(
+                                "invalid operand to '{}': '{ty}'",
+                                op.symbol()
+                            ),
+                        )
+                    })?;
+                    let ty = Ty::of(&ty);
+                    let value = match op {
+                        UnaryOperator::Negative => {
+                            self.emit(ty, Op::Unary(UnaryOp::Negate, value))
+                        }
+                        UnaryOperator::LogicalNot => {
+                            self.emit(Ty::Int, Op::Unary(UnaryOp::Not, value))
+                        }
+                        UnaryOperator::Not => {
+                            self.emit(ty, Op::Unary(UnaryOp::Complement, value))
+                        }
+                        _ => value,
+                    };
+                    Ok((value, result))
+                }
+            },
+            Expression::Postfix(p) => match &p.postfix {
+                PostfixExpressionPostfix::Subscript(_) => {
+                    let (place, ty) = self.lvalue(e)?;
+                    Ok(self.read(place, ty))
+                }
+                PostfixExpressionPostfix::Call(arguments) => {
+                    match self.call(&p.operand, arguments, span)? {
+                        (Some(value), ty) => Ok((value, ty)),
+                        (None, _) => Err(mismatch(
+                            span,
+                            "void value not ignored as it ought to be"
+                                .to_owned(),
+                        )),
+                    }
+                }
+            },
+        }
+    }
+
+    /// The function `e` names, as in `f` or `&f`.
+    fn function_designator(&self, e: &Expression) -> Option<(String, Type)> {
+        let span = match e {
+            Expression::Atom(AtomExpression::Identifier(_), span) => *span,
+            Expression::Unary(u) => match (&u.operator, &*u.operand) {
+                (
+                    UnaryOperator::Address,
+                    Expression::Atom(AtomExpression::Identifier(_), span),
+                ) => *span,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match self.global(span)? {
+            global @ (_, Type::Function(..)) => Some(global.clone()),
+            _ => None,
+        }
+    }
+
+    /// The value of the object of type `ty` at `place`, or its address if
+    /// it is an array.
+    fn read(&mut self, place: Place, ty: Type) -> (Reg, Type) {
+        match (place, ty) {
+            (Place::Memory(address), Type::Array(elem, _)) => {
+                (address, Type::Pointer(elem))
+            }
+            (Place::Register(r), ty) => (r, ty),
+            (Place::Memory(address), ty) => {
+                (self.emit(Ty::of(&ty), Op::Load(address)), ty)
+            }
+        }
+    }
+
+    /// Lower `e` for the object it designates.
+    fn lvalue(&mut self, e: &Expression) -> Result<(Place, Type), Error> {
+        let span = e.span();
+        match e {
+            Expression::Atom(AtomExpression::Identifier(_), _) => {
+                let Some(declaration) = self.declarations.get(&span.start)
+                else {
+                    return Err(error(
+                        span,
+                        ErrorKind::Unsupported(
+                            "an identifier that is not declared".to_owned(),
+                        ),
+                    ));
+                };
+                match self.locals.get(declaration) {
+                    Some(Local::Register(r, ty)) => {
+                        Ok((Place::Register(*r), ty.clone()))
+                    }
+                    Some(Local::Slot(slot, ty)) => {
+                        let (slot, ty) = (*slot, ty.clone());
+                        let address = self
+                            .emit(Ty::Pointer, Op::Address(Symbol::Slot(slot)));
+                        Ok((Place::Memory(address), ty))
+                    }
+                    None => {
+                        let (name, ty) = self.globals[declaration].clone();
+                        let address = self.emit(
+                            Ty::Pointer,
+                            Op::Address(Symbol::Global(name)),
+                        );
+                        Ok((Place::Memory(address), ty))
+                    }
+                }
+            }
+            Expression::Unary(UnaryExpression {
+                operator: UnaryOperator::Indirection,
+                operand,
+                ..
+            }) => match self.rvalue(operand)? {
+                (_, Type::Pointer(ty)) if matches!(*ty, Type::Function(..)) => {
+                    Err(error(
+                        span,
+                        ErrorKind::Unsupported(
+                            "dereferencing a function pointer".to_owned(),
+                        ),
+                    ))
+                }
+                (address, Type::Pointer(ty)) => {
+                    Ok((Place::Memory(address), *ty))
+                }
+                (_, ty) => {
+                    Err(mismatch(span, format!("cannot dereference '{ty}'")))
+                }
+            },
+            Expression::Postfix(PostfixExpression {
+                operand,
+                postfix: PostfixExpressionPostfix::Subscript(index),
+                ..
+            }) => {
+                let base = self.rvalue(operand)?;
+                let index = self.rvalue(index)?;
+                let Some(Type::Pointer(elem)) =
+                    binary_type(&BinaryOperator::Add, &base.1, &index.1)
+                else {
+                    return Err(mismatch(
+                        span,
+                        format!("cannot subscript '{}'", base.1),
+                    ));
+                };
+                let (address, index) = match base.1 {
+                    Type::Pointer(_) => (base.0, index.0),
+                    _ => (index.0, base.0),
+                };
+                Ok((Place::Memory(self.element(address, &elem, index)), *elem))
+            }
+            _ => Err(mismatch(span, "expression is not assignable".to_owned())),
+        }
+    }
+
+    /// Lower `&&` or `||`, which only evaluates its right operand if the
+    /// left one does not decide the result.
+    fn logical(&mut self, b: &BinaryExpression) -> Result<(Reg, Type), Error> {
+        let result = self.function.new_register(Ty::Int);
+        let left = self.boolean(&b.left)?;
+        self.push(Some(result), Op::Copy(left));
+        let right_block = self.new_block();
+        let join = self.new_block();
+        self.terminate(match b.operator {
+            BinaryOperator::LogicalAnd => {
+                Terminator::Branch(left, right_block, join)
+            }
+            _ => Terminator::Branch(left, join, right_block),
+        });
+        self.current = right_block;
+        let right = self.boolean(&b.right)?;
+        self.push(Some(result), Op::Copy(right));
+        self.terminate(Terminator::Jump(join));
+        self.current = join;
+        Ok((result, Type::Int))
+    }
+
+    /// 1 if `e` is true and 0 if not.
+    fn boolean(&mut self, e: &Expression) -> Result<Reg, Error> {
+        let (value, ty) = self.rvalue(e)?;
+        if !ty.is_scalar() {
+            return Err(mismatch(
+                e.span(),
+                format!("used type '{ty}' where a scalar is required"),
+            ));
+        }
+        Ok(self.truth(value, Ty::of(&ty)))
+    }
+
+    fn binary(
+        &mut self,
+        op: &BinaryOperator,
+        (left, lt): (Reg, Type),
+        (right, rt): (Reg, Type),
+        span: Span,
+    ) -> Result<(Reg, Type), Error> {
+        let Some(result) = binary_type(op, &lt, &rt) else {
+            return Err(mismatch(
+                span,
+                format!(
+                    "invalid operands to '{}': '{lt}' and '{rt}'",
+                    op.symbol()
+                ),
+            ));
+        };
+        let op = match op {
+            BinaryOperator::Add => BinaryOp::Add,
+            BinaryOperator::Minus => BinaryOp::Subtract,
+            BinaryOperator::Multiply => BinaryOp::Multiply,
+            BinaryOperator::DivideBy => BinaryOp::Divide,
+            BinaryOperator::And => BinaryOp::And,
+            BinaryOperator::Or => BinaryOp::Or,
+            BinaryOperator::Lt => BinaryOp::Lt,
+            BinaryOperator::Gt => BinaryOp::Gt,
+            BinaryOperator::Le => BinaryOp::Le,
+            BinaryOperator::Ge => BinaryOp::Ge,
+            BinaryOperator::Eq => BinaryOp::Eq,
+            BinaryOperator::Neq => BinaryOp::Ne,
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                unreachable!("lowered by `logical`")
+            }
+            BinaryOperator::Comma => unreachable!("lowered by `rvalue`"),
+        };
+        let value = match (&lt, &rt) {
+            (Type::Pointer(elem), Type::Int) if !op.is_comparison() => {
+                let index = match op {
+                    BinaryOp::Subtract => {
+                        self.emit(Ty::Int, Op::Unary(UnaryOp::Negate, right))
+                    }
+                    _ => right,
+                };
+                self.element(left, elem, index)
+            }
+            (Type::Int, Type::Pointer(elem)) if !op.is_comparison() => {
+                self.element(right, elem, left)
+            }
+            (Type::Pointer(elem), Type::Pointer(_)) if !op.is_comparison() => {
+                let bytes = self.emit(
+                    Ty::Pointer,
+                    Op::Binary(BinaryOp::Subtract, left, right),
+                );
+                let bytes = self.emit(Ty::Int, Op::Convert(bytes));
+                let size = Constant::Int(elem.bytes() as i32);
+                let size = self.constant(Ty::Int, size);
+                self.emit(Ty::Int, Op::Binary(BinaryOp::Divide, bytes, size))
+            }
+            _ => {
+                // Both operands convert to the type of the operation, or of
+                // the comparison: `double` if either is, and a pointer if
+                // either is.
+                let common = [&lt, &rt]
+                    .into_iter()
+                    .map(Ty::of)
+                    .max_by_key(|ty| match ty {
+                        Ty::Int => 0,
+                        Ty::Double => 1,
+                        Ty::Pointer => 2,
+                    })
+                    .unwrap();
+                let left = self.widen(left, Ty::of(&lt), common);
+                let right = self.widen(right, Ty::of(&rt), common);
+                let ty = if op.is_comparison() { Ty::Int } else { common };
+                self.emit(ty, Op::Binary(op, left, right))
+            }
+        };
+        Ok((value, result))
+    }
+
+    fn widen(&mut self, value: Reg, from: Ty, to: Ty) -> Reg {
+        if from == to {
+            value
+        } else {
+            self.emit(to, Op::Convert(value))
+        }
+    }
+
+    /// Lower a call, whose value is `None` if the function returns `void`.
+    fn call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        span: Span,
+    ) -> Result<(Option<Reg>, Type), Error> {
+        let Some((name, Type::Function(return_type, params))) =
+            self.function_designator(callee)
+        else {
+            return Err(error(
+                callee.span(),
+                ErrorKind::Unsupported(
+                    "a call through a function pointer".to_owned(),
+                ),
+            ));
+        };
+        if arguments.len() != params.len() {
+            return Err(mismatch(
+                span,
+                format!(
+                    "expect {} arguments, found {}",
+                    params.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+        let mut values = vec![];
+        for (a, ty) in arguments.iter().zip(&params) {
+            let value = self.rvalue(a)?;
+            values.push(self.convert(value, ty, a.span())?);
+        }
+        let call = Op::Call(name, values);
+        if *return_type == Type::Void {
+            self.push(None, call);
+            Ok((None, Type::Void))
+        } else {
+            Ok((Some(self.emit(Ty::of(&return_type), call)), *return_type))
+        }
+    }
+}
+
+/// Run function `name` of `program` with `args`, to test the passes. An
+/// address is an `int`, into a memory that holds each scalar whole at its
+/// address.
+#[cfg(test)]
+pub(crate) fn run(
+    program: &Program,
+    name: &str,
+    args: &[Constant],
+) -> Option<Constant> {
+    let mut machine = Machine {
+        program,
+        memory: HashMap::new(),
+        globals: HashMap::new(),
+        top: 16,
+    };
+    for g in &program.globals {
+        machine.globals.insert(g.name.clone(), machine.top);
+        let size = scalar_type(&g.ty).bytes();
+        for (n, c) in g.init.iter().enumerate() {
+            machine.memory.insert(machine.top + n * size, *c);
+        }
+        machine.top += g.ty.bytes();
+    }
+    machine.call(name, args)
+}
+
+#[cfg(test)]
+struct Machine<'a> {
+    program: &'a Program,
+    memory: HashMap<usize, Constant>,
+    /// The address of each global
+    globals: HashMap<String, usize>,
+    /// Where the next stack slot goes
+    top: usize,
+}
+
+#[cfg(test)]
+impl Machine<'_> {
+    fn call(&mut self, name: &str, args: &[Constant]) -> Option<Constant> {
+        let program = self.program;
+        let f = program.functions.iter().find(|f| f.name == name).unwrap();
+        let top = self.top;
+        let slots: Vec<_> = f
+            .slots
+            .iter()
+            .map(|size| {
+                self.top += size;
+                self.top - size
+            })
+            .collect();
+        let mut registers = vec![None; f.registers.len()];
+        let get =
+            |registers: &[Option<Constant>], r: Reg| registers[r].unwrap();
+        let (mut b, mut previous) = (0, usize::MAX);
+        loop {
+            let block = &f.blocks[b];
+            let values: Vec<_> = block
+                .phis
+                .iter()
+                .map(|phi| {
+                    let (_, r) =
+                        phi.args.iter().find(|(p, _)| *p == previous).unwrap();
+                    get(&registers, *r)
+                })
+                .collect();
+            for (phi, value) in block.phis.iter().zip(values) {
+                registers[phi.dest] = Some(value);
+            }
+            for i in &block.instructions {
+                let address = |n: usize| Constant::Int(n as i32);
+                let value = match &i.op {
+                    Op::Const(c) => Some(*c),
+                    Op::Copy(r) => Some(get(&registers, *r)),
+                    Op::Param(n) => Some(args[*n]),
+                    Op::Unary(..) | Op::Binary(..) | Op::Convert(_) => {
+                        let ty = match f.registers[i.dest.unwrap()] {
+                            Ty::Pointer => Ty::Int,
+                            ty => ty,
+                        };
+                        let operands: Vec<_> =
+                            i.op.operands()
+                                .iter()
+                                .map(|&r| get(&registers, r))
+                                .collect();
+                        crate::opt::fold(&i.op, ty, &operands)
+                    }
+                    Op::Address(Symbol::Slot(n)) => Some(address(slots[*n])),
+                    Op::Address(Symbol::Global(name)) => {
+                        Some(address(self.globals[name]))
+                    }
+                    Op::Address(Symbol::Function(_)) => unimplemented!(),
+                    Op::Load(a) => {
+                        let Constant::Int(a) = get(&registers, *a) else {
+                            panic!("a double as an address")
+                        };
+                        let ty = f.registers[i.dest.unwrap()];
+                        let value = self.memory.get(&(a as usize)).copied();
+                        Some(value.unwrap_or(Constant::zero(ty)))
+                    }
+                    Op::Store(a, v) => {
+                        let Constant::Int(a) = get(&registers, *a) else {
+                            panic!("a double as an address")
+                        };
+                        self.memory.insert(a as usize, get(&registers, *v));
+                        None
+                    }
+                    Op::Call(name, arguments) => {
+                        let arguments: Vec<_> = arguments
+                            .iter()
+                            .map(|&r| get(&registers, r))
+                            .collect();
+                        self.call(name, &arguments)
+                    }
+                };
+                if let Some(d) = i.dest {
+                    registers[d] = value;
+                }
+            }
+            previous = b;
+            b = match block.terminator {
+                Terminator::Jump(t) => t,
+                Terminator::Branch(r, then, otherwise) => {
+                    if get(&registers, r).is_true() {
+                        then
+                    } else {
+                        otherwise
+                    }
+                }
+                Terminator::Return(r) => {
+                    self.top = top;
+                    return r.map(|r| get(&registers, r));
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{lexer::scan, parser::parse};
+
+    pub(crate) fn lower_source(src: &str) -> Result<Program, Error> {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        lower(&ast, &lexer_output)
+    }
+
+    #[test]
+    fn test_lower() {
+        let program = lower_source(
+            "int g; int f(int a) { int b[2]; b[1] = a; \
+             if (a && g) a = 2.5; return a; }",
+        )
+        .unwrap();
+        assert_eq!(
+            program.to_string(),
+            "\
+global @g: 4 bytes
+
+function @f(int) -> int {
+  slot0: 8 bytes
+b0:
+  %0: int = param 0
+  %1: int = copy %0
+  %2: ptr = address slot0
+  %3: int = const 1
+  %4: int = const 4
+  %5: int = mul %3, %4
+  %6: ptr = convert %5
+  %7: ptr = add %2, %6
+  store %7, %1
+  %9: int = const 0
+  %10: int = ne %1, %9
+  %8: int = copy %10
+  branch %10, b3, b4
+b1:
+  %15: double = const 2.5
+  %16: int = convert %15
+  %1: int = copy %16
+  jump b2
+b2:
+  return %1
+b3:
+  %11: ptr = address @g
+  %12: int = load %11
+  %13: int = const 0
+  %14: int = ne %12, %13
+  %8: int = copy %14
+  jump b4
+b4:
+  branch %8, b1, b2
+}
+"
+        );
+    }
+
+    #[test_case(
+        "int f(int a) { return g(a); }",
+        "E0031";
+        "undeclared"
+    )]
+    #[test_case(
+        "void g(int a) { } int f(int a) { return g(a); }",
+        "E0015";
+        "void value"
+    )]
+    #[test_case(
+        "int g(int a) { return a; } int f(int a) { return (*g)(a); }",
+        "E0031";
+        "call through a pointer"
+    )]
+    #[test_case("int f(int a) { break; }", "E0020")]
+    fn test_lower_error(src: &str, code: &str) {
+        assert_eq!(lower_source(src).unwrap_err().error_kind.code(), code);
+    }
+
+    #[test_case("int f(int a) { return a * 2 + 1; }", 3, 7)]
+    #[test_case(
+        "int f(int n) { int a[3] = {1}; int *p = a; p[n] = 5; \
+         return *(p + 1) + a[0] + (&a[2] - p); }",
+        1,
+        8
+    )]
+    #[test_case(
+        "double h = 0.5; int f(int n) { double x = n; return x * h; }",
+        9,
+        4
+    )]
+    #[test_case(
+        "int f(int n) { int k = 0; while (n > 0 || k < 0) { \
+         k = k + n; n = n - 1; } return k; }",
+        4,
+        10
+    )]
+    #[test_case(
+        "void g(int *p) { *p = *p + 1; } int f(int n) { int k; \
+         for (k = 0, n; n; n = n - 1, g(&k)) ; return k, k * 2; }",
+        3,
+        6
+    )]
+    fn test_run(src: &str, arg: i32, expected: i32) {
+        let program = lower_source(src).unwrap();
+        let f = &program.functions.last().unwrap().name;
+        assert_eq!(
+            run(&program, f, &[Constant::Int(arg)]),
+            Some(Constant::Int(expected))
+        );
+    }
+}
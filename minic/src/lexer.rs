@@ -15,43 +15,113 @@ pub struct LexerOutput {
     pub text: String,
 }
 
+impl LexerOutput {
+    /// The name of the identifier with index `id` in `id_table`.
+    pub fn name(&self, id: usize) -> &str {
+        let Identifier {
+            text_begin,
+            text_len,
+        } = self.id_table[id];
+        &self.text[text_begin..text_begin + text_len]
+    }
+}
+
 pub fn scan(s: &str) -> Result<LexerOutput, Error> {
-    let lines = s.split('\n');
     let mut text = String::with_capacity(1024);
-    let mut known_ids = HashMap::new();
+    let mut id_table = Vec::new();
+    let tokens = scan_at(
+        s,
+        0,
+        Position { line: 1, col: 1 },
+        &mut text,
+        &mut HashMap::new(),
+        &mut id_table,
+    )?;
+    Ok(LexerOutput {
+        tokens,
+        id_table,
+        text,
+    })
+}
+
+/// Scan `s`, which is found at byte `offset` and position `start` of a
+/// larger source. New identifiers are added to `text` and `id_table`, and
+/// `known_ids` maps the names of those already there to their indices.
+pub fn scan_at(
+    s: &str,
+    offset: usize,
+    start: Position,
+    text: &mut String,
+    known_ids: &mut HashMap<String, usize>,
+    id_table: &mut Vec<Identifier>,
+) -> Result<Vec<Token>, Error> {
     let mut tokens = Vec::new();
     let keyword_map = keyword_map();
-    let mut id_table = Vec::new();
-    for (line_num, line) in lines.enumerate() {
-        let mut i = line.char_indices().peekable();
+    // Only the first line can start after the beginning of a line.
+    let mut start_col = start.col - 1;
+    let mut line_offset = offset - start_col;
+    for (line_num, line) in s.split('\n').enumerate() {
+        let mut i = line
+            .char_indices()
+            .map(|(col, c)| (start_col + col, c))
+            .peekable();
         loop {
             if i.peek().is_none() {
                 break;
             }
             if let Some(token) = get_token(
                 &mut i,
-                &mut text,
+                text,
                 &keyword_map,
-                &mut known_ids,
-                &mut id_table,
-                line_num,
+                known_ids,
+                id_table,
+                Line {
+                    num: start.line - 1 + line_num,
+                    offset: line_offset,
+                    len: start_col + line.len(),
+                },
             )? {
                 tokens.push(token);
             }
         }
+        line_offset += start_col + line.len() + 1;
+        start_col = 0;
+    }
+    Ok(tokens)
+}
+
+/// The line currently being scanned.
+#[derive(Clone, Copy)]
+struct Line {
+    /// Zero-based line number
+    num: usize,
+    /// Byte offset of the first character of the line in the source
+    offset: usize,
+    len: usize,
+}
+
+impl Line {
+    fn span(&self, start_col: usize, end_col: usize) -> Span {
+        Span {
+            start: self.offset + start_col,
+            end: self.offset + end_col,
+            start_pos: Position {
+                line: self.num + 1,
+                col: start_col + 1,
+            },
+            end_pos: Position {
+                line: self.num + 1,
+                col: end_col + 1,
+            },
+        }
     }
-    Ok(LexerOutput {
-        tokens,
-        id_table,
-        text,
-    })
 }
 
 fn get_token_after_decimal_point(
     i: &mut Peekable<impl Iterator<Item = (usize, char)>>,
     last_match_int: Option<u32>,
 ) -> Option<TokenKind> {
-    let integer = if let Some(n) = last_match_int { n } else { 0 };
+    let integer = last_match_int.unwrap_or_default();
     let mut floating_constant: f64 = 0.;
     let mut n = 0.1;
     match ignore_num_ref(i.peek()) {
@@ -141,7 +211,7 @@ fn get_token(
     keyword_map: &HashMap<&str, TokenKind>,
     known_ids: &mut HashMap<String, usize>,
     id_table: &mut Vec<Identifier>,
-    line_num: usize,
+    line: Line,
 ) -> Result<Option<Token>, Error> {
     let _text_offset = text.len();
     let (token_start_col, c) = i.next().unwrap();
@@ -152,13 +222,10 @@ fn get_token(
             match token {
                 Some(t) => Some(t),
                 None => {
-                    return Err(Error {
-                        pos: Position {
-                            line: line_num + 1,
-                            col: token_start_col + 1,
-                        },
-                        error_kind: ErrorKind::ExpectDigit,
-                    })
+                    return Err(Error::new(
+                        line.span(token_start_col, token_start_col + 1),
+                        ErrorKind::ExpectDigit,
+                    ));
                 }
             }
         }
@@ -250,25 +317,29 @@ fn get_token(
             }
         }
     }
-    .map(|kind| Token {
-        kind,
-        pos: Position {
-            line: line_num + 1,
-            col: token_start_col + 1,
-        },
+    .map(|kind| {
+        let token_end_col = i.peek().map_or(line.len, |&(col, _)| col);
+        Token {
+            kind,
+            span: line.span(token_start_col, token_end_col),
+        }
     }))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::token_dump::{dumped_tokens, parse_tokens};
     use anyhow::Result;
     use pretty_assertions::assert_eq;
     use test_case::test_case;
-    use QualifierKind::*;
-    use RelopKind::*;
     use TokenKind::*;
 
+    /// A kind and the line and column it starts at
+    type Located<T> = (T, usize, usize);
+    /// Start and end offsets and (line, column) positions
+    type SpanTuple = (usize, usize, (usize, usize), (usize, usize));
+
     #[test_case("123", Ok(vec![(IntegerConstant(123), 1, 1)]))]
     #[test_case("\n123", Ok(vec![(IntegerConstant(123), 2, 1)]))]
     #[test_case("0", Ok(vec![(IntegerConstant(0), 1, 1)]))]
@@ -353,75 +424,117 @@ mod test {
         (Relop(RelopKind::Assign), 1, 5),
         (IntegerConstant(2), 1, 6)
     ]))]
-    #[test_case(".", Err(Error{pos: Position{line: 1, col: 1}, error_kind: ErrorKind::ExpectDigit}))]
-    #[test_case(".a", Err(Error{pos: Position{line: 1, col: 1}, error_kind: ErrorKind::ExpectDigit}))]
-    #[test_case("b.", Err(Error{pos: Position{line: 1, col: 2}, error_kind: ErrorKind::ExpectDigit}))]
-    #[test_case("b.a", Err(Error{pos: Position{line: 1, col: 2}, error_kind: ErrorKind::ExpectDigit}))]
-    #[test_case(". 1", Err(Error{pos: Position{line: 1, col: 1}, error_kind: ErrorKind::ExpectDigit}))]
+    #[test_case(".", Err((ErrorKind::ExpectDigit, 1, 1)))]
+    #[test_case(".a", Err((ErrorKind::ExpectDigit, 1, 1)))]
+    #[test_case("b.", Err((ErrorKind::ExpectDigit, 1, 2)))]
+    #[test_case("b.a", Err((ErrorKind::ExpectDigit, 1, 2)))]
+    #[test_case(". 1", Err((ErrorKind::ExpectDigit, 1, 1)))]
     fn test_scan_without_text(
         s: &str,
-        ans: Result<Vec<(TokenKind, usize, usize)>, Error>,
+        ans: Result<Vec<Located<TokenKind>>, Located<ErrorKind>>,
     ) -> Result<()> {
         assert_eq!(
-            scan(s).map(|x| x.tokens),
-            ans.map(|v| {
-                v.into_iter()
-                    .map(|(kind, line, col)| Token {
-                        kind,
-                        pos: Position { line, col },
-                    })
-                    .collect()
-            })
+            scan(s)
+                .map(|x| {
+                    x.tokens
+                        .into_iter()
+                        .map(|t| {
+                            let Position { line, col } = t.span.start_pos;
+                            (t.kind, line, col)
+                        })
+                        .collect()
+                })
+                .map_err(|e| {
+                    let Position { line, col } = e.span.start_pos;
+                    (e.error_kind, line, col)
+                }),
+            ans
         );
         Ok(())
     }
 
-    struct TokenTestcase {
-        s: &'static str,
-        ans: LexerOutput,
-    }
-
-    macro_rules! test_dir {
-        () => {
-            "../testcase/token/"
-        };
-    }
-
-    macro_rules! include_test_str {
-        ($file:expr) => {
-            include_str!(concat!(test_dir!(), $file))
-        };
-    }
-
-    macro_rules! include_test {
-        ($file:expr) => {
-            include!(concat!(test_dir!(), $file))
-        };
+    #[test_case("abc", vec![(0, 3, (1, 1), (1, 4))])]
+    #[test_case("a\n  >= 1.5", vec![
+        (0, 1, (1, 1), (1, 2)),
+        (4, 6, (2, 3), (2, 5)),
+        (7, 10, (2, 6), (2, 9))
+    ])]
+    #[test_case("x;", vec![(0, 1, (1, 1), (1, 2)), (1, 2, (1, 2), (1, 3))])]
+    fn test_scan_span(s: &str, ans: Vec<SpanTuple>) -> Result<()> {
+        let spans: Vec<_> = scan(s)?
+            .tokens
+            .into_iter()
+            .map(|t| {
+                let Span {
+                    start,
+                    end,
+                    start_pos,
+                    end_pos,
+                } = t.span;
+                (
+                    start,
+                    end,
+                    (start_pos.line, start_pos.col),
+                    (end_pos.line, end_pos.col),
+                )
+            })
+            .collect();
+        assert_eq!(spans, ans);
+        Ok(())
     }
 
     macro_rules! token_testcase {
         ($name:literal) => {
-            TokenTestcase {
-                s: include_test_str!(concat!($name, ".in")),
-                ans: LexerOutput {
-                    tokens: include_test!(concat!($name, ".token.in")),
-                    id_table: include_test!(concat!($name, ".id.in")),
-                    text: include_test_str!(concat!($name, ".text.in"))
-                        .to_owned(),
-                },
-            }
+            (
+                include_str!(concat!("../testcase/token/", $name, ".in")),
+                include_str!(concat!("../testcase/token/", $name, ".tokens")),
+            )
         };
     }
 
-    #[test_case(token_testcase!{1})]
-    #[test_case(token_testcase!{2})]
-    #[test_case(token_testcase!{3})]
-    #[test_case(token_testcase!{4})]
-    #[test_case(token_testcase!{5})]
-    #[test_case(token_testcase!{6})]
-    fn test_scan(t: TokenTestcase) -> Result<()> {
-        let result = scan(t.s)?;
-        assert_eq!(result, t.ans);
+    #[test_case(token_testcase!("1"))]
+    #[test_case(token_testcase!("2"))]
+    #[test_case(token_testcase!("3"))]
+    #[test_case(token_testcase!("4"))]
+    #[test_case(token_testcase!("5"))]
+    #[test_case(token_testcase!("6"))]
+    fn test_scan((src, tokens): (&str, &str)) -> Result<()> {
+        let result = scan(src)?;
+        assert_eq!(dumped_tokens(&result, src), parse_tokens(tokens)?);
+        let mut names = Vec::new();
+        for t in parse_tokens(tokens)? {
+            if let Id(id) = t.kind {
+                assert_eq!(result.name(id), t.lexeme);
+                if id == names.len() {
+                    names.push(t.lexeme);
+                }
+            }
+        }
+        assert_eq!(result.id_table.len(), names.len());
+        assert_eq!(result.text, names.concat());
         Ok(())
     }
+
+    #[test]
+    fn test_scan_at() {
+        let src = "int a;\nint f() { return a + b; }\nint b;";
+        let full = scan(src).unwrap();
+        // Scan the rest of the file after `int f() {` into the same table.
+        let offset = src.find('{').unwrap() + 1;
+        let mut output = scan(&src[..offset]).unwrap();
+        let mut known_ids = (0..output.id_table.len())
+            .map(|id| (output.name(id).to_owned(), id))
+            .collect();
+        let tokens = scan_at(
+            &src[offset..],
+            offset,
+            output.tokens.last().unwrap().span.end_pos,
+            &mut output.text,
+            &mut known_ids,
+            &mut output.id_table,
+        )
+        .unwrap();
+        output.tokens.extend(tokens);
+        assert_eq!(output, full);
+    }
 }
@@ -0,0 +1,41 @@
+//! A compiler front end for a subset of C.
+//!
+//! Source goes through [`preprocess::preprocess`], which blanks out
+//! comments, [`lexer::scan`] and [`parser::parse`], which gives the
+//! [`ast::TranslationUnit`]. Errors are [`error::Error`]s, which
+//! [`diagnostic`] prints for people and tools.
+#![cfg_attr(all(test, feature = "nightly"), feature(test))]
+
+pub mod ast;
+pub mod ast_file;
+pub mod ast_json;
+pub mod cfg;
+pub mod cst;
+pub mod dataflow;
+pub mod diagnostic;
+mod doc;
+pub mod dot;
+pub mod error;
+pub mod fmt;
+pub mod incremental;
+pub mod interp;
+pub mod ir;
+pub mod lexer;
+pub mod lint;
+pub mod lsp;
+pub mod opt;
+pub mod parser;
+pub mod persist;
+pub mod preprocess;
+pub mod printer;
+pub mod regalloc;
+pub mod repl;
+pub mod resolve;
+pub mod ssa;
+pub mod suggest;
+pub mod token;
+pub mod token_dump;
+pub mod trivia;
+pub mod types;
+mod util;
+pub mod x86;
@@ -0,0 +1,710 @@
+//! `minic lint`: warn about code that compiles but is likely a mistake.
+//!
+//! Each rule can be turned off in a JSON config file, e.g.
+//! `{ "rules": { "shadowing": false } }`, or in the source with comments:
+//!
+//! - `// minic-lint: disable rule, ...` up to a matching `enable`
+//! - `// minic-lint: disable-line rule, ...` on the line of the comment
+//! - `// minic-lint: disable-next-line rule, ...` on the line after it
+//!
+//! Without rules, a comment applies to all of them.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    ast::{
+        visit::{
+            walk_assignment_expression, walk_compound_statement,
+            walk_do_statement, walk_for_statement, walk_selection_statement,
+            walk_while_statement,
+        },
+        *,
+    },
+    cst::LineIndex,
+    diagnostic::{self, Diagnostic, DiagnosticsFormat, FixIt, Note},
+    error::{Error, ErrorKind},
+    lexer::LexerOutput,
+    parser::parse,
+    resolve::analyze,
+    suggest::{most_similar, suggest_keyword},
+    token::{Position, RelopKind, Span, TokenKind},
+    trivia::scan_lossless,
+    types::Type,
+};
+
+/// The config file used if there is one in the working directory.
+pub const CONFIG_FILE: &str = "minic-lint.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Rule {
+    /// `if (a = b)`, which is a typo for `==` more often than not
+    AssignmentInCondition,
+    UnusedVariable,
+    UnusedParameter,
+    /// Statements after a `return`, `break` or `continue`
+    UnreachableCode,
+    MissingReturn,
+    SelfAssignment,
+    /// `while (c);`, where the `;` is the whole body
+    EmptyLoopBody,
+    /// A local declaration hiding another of the same name
+    Shadowing,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 8] = [
+        Rule::AssignmentInCondition,
+        Rule::UnusedVariable,
+        Rule::UnusedParameter,
+        Rule::UnreachableCode,
+        Rule::MissingReturn,
+        Rule::SelfAssignment,
+        Rule::EmptyLoopBody,
+        Rule::Shadowing,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|r| r.to_string() == name)
+    }
+
+    /// The rule that reports errors of kind `kind`.
+    fn of(kind: &ErrorKind) -> Option<Rule> {
+        Some(match kind {
+            ErrorKind::AssignmentInCondition => Rule::AssignmentInCondition,
+            ErrorKind::UnusedVariable(_) => Rule::UnusedVariable,
+            ErrorKind::UnusedParameter(_) => Rule::UnusedParameter,
+            ErrorKind::UnreachableCode => Rule::UnreachableCode,
+            ErrorKind::MissingReturn(_) => Rule::MissingReturn,
+            ErrorKind::SelfAssignment => Rule::SelfAssignment,
+            ErrorKind::EmptyLoopBody => Rule::EmptyLoopBody,
+            ErrorKind::Shadowing(_) => Rule::Shadowing,
+            _ => return None,
+        })
+    }
+}
+
+/// Which rules are on. All are by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    rules: HashMap<String, bool>,
+}
+
+impl Config {
+    pub fn parse(json: &str) -> Result<Self> {
+        let config: Config = serde_json::from_str(json)?;
+        if let Some(name) =
+            config.rules.keys().find(|n| Rule::from_name(n).is_none())
+        {
+            return Err(anyhow!("unknown rule \"{name}\""));
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("cannot open \"{}\"", path.display()))?;
+        Config::parse(&json)
+            .with_context(|| format!("invalid config \"{}\"", path.display()))
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.rules.get(&rule.to_string()).copied().unwrap_or(true)
+    }
+}
+
+#[derive(clap::Args)]
+pub struct LintArgs {
+    /// Read which rules are on from this file instead of minic-lint.json
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// How to print warnings and errors
+    #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Human)]
+    diagnostics_format: DiagnosticsFormat,
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+/// The warnings about `src`, in source order, or the error that stops it
+/// from parsing.
+pub fn lint_source(src: &str, config: &Config) -> Result<Vec<Error>, Error> {
+    let output = scan_lossless(src)?;
+    let lexer_output = &output.lexer_output;
+    let ast = parse(&lexer_output.tokens)
+        .map_err(|e| suggest_keyword(e, lexer_output))?;
+    let mut uses = HashMap::new();
+    for r in analyze(&ast, lexer_output).references {
+        if r.span != r.declaration {
+            *uses.entry(r.declaration.start).or_insert(0) += 1;
+        }
+    }
+    let mut linter = Linter {
+        lexer_output,
+        uses,
+        scopes: vec![HashMap::new()],
+        warnings: vec![],
+    };
+    linter.visit_translation_unit(&ast);
+
+    let lines = LineIndex::new(src);
+    let mut directives = Directives::default();
+    let mut warnings = vec![];
+    for comment in output.comments() {
+        let span = lines.span(comment.clone());
+        if let Err(e) = directives.add(src, span, &lines) {
+            warnings.push(e);
+        }
+    }
+    warnings.extend(linter.warnings.into_iter().filter(|e| {
+        let rule = Rule::of(&e.error_kind).unwrap();
+        config.is_enabled(rule)
+            && directives.is_enabled(rule, e.span.start_pos.line)
+    }));
+    warnings.sort_by_key(|e| e.span.start);
+    Ok(warnings)
+}
+
+/// Lint the files. Returns whether there were no warnings or errors.
+pub fn run(args: &LintArgs) -> Result<bool> {
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None if Path::new(CONFIG_FILE).exists() => {
+            Config::load(Path::new(CONFIG_FILE))?
+        }
+        None => Config::default(),
+    };
+    let mut ok = true;
+    for path in &args.files {
+        let src = fs::read_to_string(path)
+            .with_context(|| format!("cannot open \"{}\"", path.display()))?;
+        let errors = lint_source(&src, &config).unwrap_or_else(|e| vec![e]);
+        let diagnostics: Vec<Diagnostic> =
+            errors.iter().map(Into::into).collect();
+        if !diagnostics.is_empty()
+            || args.diagnostics_format != DiagnosticsFormat::Human
+        {
+            diagnostic::emit(&diagnostics, path, args.diagnostics_format)?;
+        }
+        ok &= diagnostics.is_empty();
+    }
+    Ok(ok)
+}
+
+/// The rules turned on and off by comments.
+#[derive(Debug, Default)]
+struct Directives {
+    /// From which line rules are turned off or back on, in source order.
+    /// `None` stands for all rules.
+    ranges: Vec<(usize, Option<Rule>, bool)>,
+    /// Single lines on which rules are off
+    lines: Vec<(usize, Option<Rule>)>,
+}
+
+impl Directives {
+    /// Follow the comment at `span` if it is a `minic-lint:` one.
+    fn add(
+        &mut self,
+        src: &str,
+        span: Span,
+        lines: &LineIndex,
+    ) -> Result<(), Error> {
+        let comment = &src[span.start..span.end];
+        let text = match comment.strip_prefix("//") {
+            Some(text) => text,
+            None => &comment[2..comment.len() - 2],
+        };
+        let Some(text) = text.trim().strip_prefix("minic-lint:") else {
+            return Ok(());
+        };
+        let text = text.trim_start();
+        let (directive, list) =
+            text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mut rules = vec![];
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match Rule::from_name(name) {
+                Some(rule) => rules.push(Some(rule)),
+                None => {
+                    let start = name.as_ptr() as usize - src.as_ptr() as usize;
+                    let name_span = lines.span(start..start + name.len());
+                    return Err(unknown_rule(name, name_span));
+                }
+            }
+        }
+        if rules.is_empty() {
+            rules.push(None);
+        }
+        let line = span.start_pos.line;
+        for rule in rules {
+            match directive {
+                "disable" => self.ranges.push((line, rule, false)),
+                "enable" => self.ranges.push((line, rule, true)),
+                "disable-line" => self.lines.push((line, rule)),
+                "disable-next-line" => {
+                    self.lines.push((span.end_pos.line + 1, rule))
+                }
+                _ => {
+                    return Err(lint_error(
+                        span,
+                        ErrorKind::InvalidLintComment(format!(
+                            "unknown directive '{directive}'"
+                        )),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_enabled(&self, rule: Rule, line: usize) -> bool {
+        let applies = |r: &Option<Rule>| r.is_none_or(|r| r == rule);
+        if self.lines.iter().any(|(l, r)| *l == line && applies(r)) {
+            return false;
+        }
+        self.ranges
+            .iter()
+            .rev()
+            .find(|(l, r, _)| *l <= line && applies(r))
+            .is_none_or(|(_, _, enabled)| *enabled)
+    }
+}
+
+fn unknown_rule(name: &str, span: Span) -> Error {
+    let mut e = lint_error(
+        span,
+        ErrorKind::InvalidLintComment(format!("unknown rule '{name}'")),
+    );
+    let names: Vec<_> = Rule::ALL.iter().map(Rule::to_string).collect();
+    if let Some(similar) = most_similar(name, names.iter().map(String::as_str))
+    {
+        e.details.fix_its.push(FixIt::replace(span, similar));
+    }
+    e
+}
+
+fn lint_error(span: Span, kind: ErrorKind) -> Error {
+    Error::new(span, kind)
+}
+
+/// Whether control never goes on to the statement after `s`.
+fn diverges(s: &Statement) -> bool {
+    match s {
+        Statement::Jump(..) => true,
+        Statement::Compound(c) => c.block_items.iter().any(|i| match i {
+            BlockItem::Statement(s) => diverges(s),
+            BlockItem::Declaration(_) => false,
+        }),
+        Statement::Selection(s) => s
+            .alternative
+            .as_ref()
+            .is_some_and(|a| diverges(&s.consequent) && diverges(a)),
+        Statement::Iteration(i) => {
+            let (condition, body) = match i {
+                IterationStatement::While(s) => (Some(&s.condition), &s.body),
+                IterationStatement::Do(s) => (Some(&s.condition), &s.body),
+                IterationStatement::For(s) => (s.condition.as_ref(), &s.body),
+            };
+            let infinite = condition.is_none_or(|c| {
+                matches!(c, Expression::Atom(AtomExpression::Integer(n), _) if *n != 0)
+            });
+            // The body of a `do` runs at least once, so it diverges if the
+            // body does without going back to the condition.
+            let once = matches!(i, IterationStatement::Do(_))
+                && diverges(body)
+                && !continues(body);
+            (infinite || once) && !breaks(body)
+        }
+        Statement::Expression(..) => false,
+    }
+}
+
+/// Whether `s` has a `break` out of the loop around it.
+fn breaks(s: &Statement) -> bool {
+    has_jump(s, &|j| matches!(j, JumpStatement::Break))
+}
+
+/// Whether `s` has a `continue` of the loop around it.
+fn continues(s: &Statement) -> bool {
+    has_jump(s, &|j| matches!(j, JumpStatement::Continue))
+}
+
+/// Whether `s` has a jump `is` accepts that is not in a nested loop.
+fn has_jump(s: &Statement, is: &impl Fn(&JumpStatement) -> bool) -> bool {
+    match s {
+        Statement::Jump(j, _) => is(j),
+        Statement::Compound(c) => c.block_items.iter().any(|i| match i {
+            BlockItem::Statement(s) => has_jump(s, is),
+            BlockItem::Declaration(_) => false,
+        }),
+        Statement::Selection(s) => {
+            has_jump(&s.consequent, is)
+                || s.alternative.as_deref().is_some_and(|a| has_jump(a, is))
+        }
+        // A `break` or `continue` in a nested loop is for that loop.
+        Statement::Iteration(_) | Statement::Expression(..) => false,
+    }
+}
+
+/// Whether `a` and `b` are the same object, e.g. `*p` and `*p`.
+fn same_object(a: &Expression, b: &Expression) -> bool {
+    match (a, b) {
+        (
+            Expression::Atom(AtomExpression::Identifier(a), _),
+            Expression::Atom(AtomExpression::Identifier(b), _),
+        ) => a == b,
+        (
+            Expression::Atom(AtomExpression::Integer(a), _),
+            Expression::Atom(AtomExpression::Integer(b), _),
+        ) => a == b,
+        (Expression::Unary(a), Expression::Unary(b)) => {
+            matches!(a.operator, UnaryOperator::Indirection)
+                && matches!(b.operator, UnaryOperator::Indirection)
+                && same_object(&a.operand, &b.operand)
+        }
+        (Expression::Postfix(a), Expression::Postfix(b)) => {
+            match (&a.postfix, &b.postfix) {
+                (
+                    PostfixExpressionPostfix::Subscript(i),
+                    PostfixExpressionPostfix::Subscript(j),
+                ) => same_object(&a.operand, &b.operand) && same_object(i, j),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+struct Linter<'a> {
+    lexer_output: &'a LexerOutput,
+    /// How many times each declaration is referred to, by the offset of its
+    /// identifier
+    uses: HashMap<usize, usize>,
+    /// Identifiers declared in each enclosing scope, outermost first
+    scopes: Vec<HashMap<usize, Span>>,
+    warnings: Vec<Error>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, span: Span, kind: ErrorKind) -> &mut Error {
+        self.warnings.push(lint_error(span, kind));
+        self.warnings.last_mut().unwrap()
+    }
+
+    fn name(&self, id: usize) -> String {
+        self.lexer_output.name(id).to_owned()
+    }
+
+    fn is_used(&self, declarator: &Declarator) -> bool {
+        let (_, span) = declarator.identifier();
+        self.uses.contains_key(&span.start)
+    }
+
+    fn in_scope<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// Declare the identifier of `declarator` in the innermost scope,
+    /// warning if it hides one in an enclosing scope.
+    fn declare(&mut self, declarator: &Declarator) {
+        let (id, span) = declarator.identifier();
+        let (innermost, enclosing) = self.scopes.split_last_mut().unwrap();
+        let hidden = enclosing.iter().rev().find_map(|s| s.get(&id).copied());
+        innermost.insert(id, span);
+        if let Some(hidden) = hidden {
+            let name = self.name(id);
+            self.warn(span, ErrorKind::Shadowing(name.clone()))
+                .details
+                .notes
+                .push(Note {
+                    span: hidden,
+                    message: format!("'{name}' is declared here"),
+                });
+        }
+    }
+
+    /// Warn about the first item that cannot be reached, if any.
+    fn check_reachable(&mut self, items: &[BlockItem]) {
+        let diverging = items.iter().position(|i| match i {
+            BlockItem::Statement(s) => diverges(s),
+            BlockItem::Declaration(_) => false,
+        });
+        if let Some(next) = diverging.and_then(|i| items.get(i + 1)) {
+            self.warn(next.span(), ErrorKind::UnreachableCode);
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expression) {
+        let Expression::Assignment(a) = condition else {
+            return;
+        };
+        // The `=` is the last one before the right operand.
+        let tokens = &self.lexer_output.tokens;
+        let right = a.right.span().start;
+        let before = tokens.partition_point(|t| t.span.start < right);
+        let assign = tokens[..before]
+            .iter()
+            .rev()
+            .find(|t| t.kind == TokenKind::Relop(RelopKind::Assign))
+            .map(|t| t.span);
+        let e = self.warn(a.span, ErrorKind::AssignmentInCondition);
+        if let Some(assign) = assign {
+            e.details.fix_its.push(FixIt::insert_after(assign, "="));
+        }
+    }
+
+    fn check_loop_body(&mut self, body: &Statement) {
+        if let Statement::Expression(None, span) = body {
+            self.warn(*span, ErrorKind::EmptyLoopBody);
+        }
+    }
+}
+
+impl Visitor for Linter<'_> {
+    fn visit_function_definition(&mut self, f: &FunctionDefinition) {
+        self.declare(&f.declarator);
+        let parameters = f.declarator.parameters().unwrap_or_default();
+        // The parameters are in the same scope as the body.
+        self.in_scope(|l| {
+            for p in parameters {
+                l.declare(&p.declarator);
+                if !l.is_used(&p.declarator) {
+                    let (id, span) = p.declarator.identifier();
+                    l.warn(span, ErrorKind::UnusedParameter(l.name(id)));
+                }
+            }
+            l.check_reachable(&f.compound_statement.block_items);
+            walk_compound_statement(l, &f.compound_statement);
+        });
+
+        let (id, _) = f.declarator.identifier();
+        let returns_value =
+            match Type::of(&f.declaration_specifier, &f.declarator) {
+                Type::Function(t, _) => *t != Type::Void,
+                _ => false,
+            };
+        let falls_off = !f
+            .compound_statement
+            .block_items
+            .iter()
+            .any(|i| matches!(i, BlockItem::Statement(s) if diverges(s)));
+        // Like C99, `main` returns 0 if it runs off the end.
+        if returns_value && falls_off && self.lexer_output.name(id) != "main" {
+            let end = f.compound_statement.span;
+            let closing_brace = Span {
+                start: end.end - 1,
+                start_pos: Position {
+                    col: end.end_pos.col - 1,
+                    ..end.end_pos
+                },
+                ..end
+            };
+            self.warn(closing_brace, ErrorKind::MissingReturn(self.name(id)));
+        }
+    }
+
+    fn visit_init_declarator(&mut self, d: &InitDeclarator) {
+        // Globals may be used in other files.
+        if self.scopes.len() > 1 {
+            self.declare(&d.declarator);
+            let is_function = d.declarator.parameters().is_some();
+            if !is_function && !self.is_used(&d.declarator) {
+                let (id, span) = d.declarator.identifier();
+                self.warn(span, ErrorKind::UnusedVariable(self.name(id)));
+            }
+        } else {
+            let (id, span) = d.declarator.identifier();
+            self.scopes[0].insert(id, span);
+        }
+        if let Some(initializer) = &d.initializer {
+            self.visit_initializer(initializer);
+        }
+    }
+
+    fn visit_compound_statement(&mut self, c: &CompoundStatement) {
+        self.check_reachable(&c.block_items);
+        self.in_scope(|l| walk_compound_statement(l, c));
+    }
+
+    fn visit_selection_statement(&mut self, s: &SelectionStatement) {
+        self.check_condition(&s.condition);
+        walk_selection_statement(self, s);
+    }
+
+    fn visit_while_statement(&mut self, s: &WhileStatement) {
+        self.check_condition(&s.condition);
+        self.check_loop_body(&s.body);
+        walk_while_statement(self, s);
+    }
+
+    fn visit_do_statement(&mut self, s: &DoStatement) {
+        self.check_condition(&s.condition);
+        self.check_loop_body(&s.body);
+        walk_do_statement(self, s);
+    }
+
+    fn visit_for_statement(&mut self, s: &ForStatement) {
+        if let Some(condition) = &s.condition {
+            self.check_condition(condition);
+        }
+        self.check_loop_body(&s.body);
+        self.in_scope(|l| walk_for_statement(l, s));
+    }
+
+    fn visit_assignment_expression(&mut self, e: &AssignmentExpression) {
+        if same_object(&e.left, &e.right) {
+            self.warn(e.span, ErrorKind::SelfAssignment);
+        }
+        walk_assignment_expression(self, e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::diagnostic::apply_fix_its;
+
+    /// The codes of the warnings about `src` with all rules on, and the
+    /// lines they are on.
+    fn lint(src: &str) -> Vec<(&'static str, usize)> {
+        lint_source(src, &Config::default())
+            .unwrap()
+            .iter()
+            .map(|e| (e.error_kind.code(), e.span.start_pos.line))
+            .collect()
+    }
+
+    #[test_case("int f(int a) { if (a = 1) return a; return 0; }", "E0021")]
+    #[test_case(
+        "int f(int a) { while (a = a - 1) ; return 0; }",
+        "E0021, E0027"
+    )]
+    #[test_case("int f(int a) { int b; return a; }", "E0022")]
+    #[test_case("int f(int a, int b) { return a; }", "E0023")]
+    #[test_case("int f(int a) { return a; a; }", "E0024")]
+    #[test_case("int f(int a) { for (;;) { break; a; } return a; }", "E0024")]
+    #[test_case("int f(int a) { if (a) return 1; }", "E0025")]
+    #[test_case("int f(int x) { do { return x; } while (x); x; }", "E0024")]
+    #[test_case(
+        "int f(int x) { do { if (x) continue; return x; } while (x); }",
+        "E0025"
+    )]
+    #[test_case(
+        "int f(int x) { do { if (x) break; return x; } while (1); }",
+        "E0025"
+    )]
+    #[test_case("int f(int a) { a = a; return a; }", "E0026")]
+    #[test_case(
+        "int f(int *a) { a[0] = a[0]; *a = *a; return 0; }",
+        "E0026, E0026"
+    )]
+    #[test_case("int f(int a) { for (; a; a = a - 1) ; return a; }", "E0027")]
+    #[test_case("int a; int f(int b) { int a = b; return a; }", "E0028")]
+    #[test_case(
+        "int f(int a) { if (a) { int a = 1; return a; } return a; }",
+        "E0028"
+    )]
+    fn test_rule(src: &str, expected: &str) {
+        let codes: Vec<_> = lint(src).iter().map(|(code, _)| *code).collect();
+        assert_eq!(codes.join(", "), expected);
+    }
+
+    #[test_case("int g; int f(int a) { g = a; return g; }")]
+    #[test_case("int f(int a) { if (a) return 1; else return 2; }")]
+    #[test_case("int f(int a) { while (1) { if (a) return a; } }")]
+    #[test_case("int f(int a) { do { return a; } while (a); }")]
+    #[test_case("void f(int a) { a = a + 1; }")]
+    #[test_case("int main(int a) { a; }")]
+    #[test_case("int f(int a) { if (a == 1) a = 2; return a; }")]
+    #[test_case("int f(int a) { int g(int b); return g(a); }")]
+    fn test_clean(src: &str) {
+        assert_eq!(lint(src), vec![]);
+    }
+
+    #[test]
+    fn test_fix_it() {
+        let src = "int f(int a) { if (a = 1) return a; return 0; }";
+        let e = &lint_source(src, &Config::default()).unwrap()[0];
+        assert_eq!(
+            apply_fix_its(src, &e.details.fix_its),
+            "int f(int a) { if (a == 1) return a; return 0; }"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let src = "int f(int a, int b)
+{
+    a = a; // minic-lint: disable-line
+    // minic-lint: disable-next-line self-assignment, unused-variable
+    a = a;
+    a = a;
+    /* minic-lint: disable self-assignment */
+    a = a;
+    // minic-lint: enable
+    a = a;
+    return a;
+}
+";
+        assert_eq!(lint(src), [("E0023", 1), ("E0026", 6), ("E0026", 10)]);
+    }
+
+    #[test_case(
+        "// minic-lint: disable shadowng",
+        "E0029",
+        "// minic-lint: disable shadowing"
+    )]
+    #[test_case("// minic-lint: turn-off", "E0029", "// minic-lint: turn-off")]
+    #[test_case("// minic-lint disable", "", "// minic-lint disable"; "not a directive")]
+    fn test_invalid_comment(comment: &str, code: &str, fixed: &str) {
+        let src = format!("{comment}\nint a;");
+        let warnings = lint_source(&src, &Config::default()).unwrap();
+        let codes: Vec<_> =
+            warnings.iter().map(|e| e.error_kind.code()).collect();
+        assert_eq!(codes.join(""), code);
+        let fix_its: Vec<_> = warnings
+            .iter()
+            .flat_map(|e| e.details.fix_its.clone())
+            .collect();
+        assert_eq!(apply_fix_its(&src, &fix_its), format!("{fixed}\nint a;"));
+    }
+
+    #[test]
+    fn test_config() {
+        let config =
+            Config::parse(r#"{ "rules": { "unused-parameter": false } }"#)
+                .unwrap();
+        assert!(!config.is_enabled(Rule::UnusedParameter));
+        assert!(config.is_enabled(Rule::Shadowing));
+        let src = "int f(int a, int b) { int c; return a; }";
+        let codes: Vec<_> = lint_source(src, &config)
+            .unwrap()
+            .iter()
+            .map(|e| e.error_kind.code())
+            .collect();
+        assert_eq!(codes, ["E0022"]);
+
+        let e = Config::parse(r#"{ "rules": { "shadow": false } }"#);
+        assert_eq!(e.unwrap_err().to_string(), "unknown rule \"shadow\"");
+        assert!(Config::parse(r#"{ "rule": {} }"#).is_err());
+    }
+
+    #[test]
+    fn test_rule_names() {
+        for rule in Rule::ALL {
+            assert_eq!(Rule::from_name(&rule.to_string()), Some(rule));
+        }
+        assert_eq!(Rule::EmptyLoopBody.to_string(), "empty-loop-body");
+    }
+}
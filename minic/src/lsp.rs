@@ -0,0 +1,580 @@
+//! `minic lsp`: a Language Server Protocol server over stdin and stdout.
+//!
+//! It publishes the diagnostics of open documents whenever they change, and
+//! answers go to definition, find references, hover and document symbol
+//! requests from the references [`analyze`] finds. Documents are synced
+//! incrementally and reparsed with [`SourceFile`], and positions are in
+//! UTF-16 code units as the protocol says.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    ast::{
+        visit::{walk_function_definition, walk_parameter_declaration},
+        Declaration, DeclarationSpecifier, Declarator, ExternalDeclaration,
+        FunctionDefinition, ParameterDeclaration, Visitor,
+    },
+    dataflow::uninitialized,
+    diagnostic::{Diagnostic, Severity},
+    incremental::{Parsed, SourceFile},
+    lexer::LexerOutput,
+    printer::print_declarator,
+    resolve::{analyze, Reference},
+    token::Span,
+};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const SYMBOL_KIND_FUNCTION: u32 = 12;
+const SYMBOL_KIND_VARIABLE: u32 = 13;
+
+/// Read a message framed by a `Content-Length` header. Returns `None` at the
+/// end of the input.
+pub fn read_message(r: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+    let len = len.context("expect a Content-Length header")?;
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub fn write_message(w: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Serve messages from `input` until the `exit` notification. Returns
+/// whether `shutdown` was requested before it.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> Result<bool> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut input)? {
+        if message["method"] == "exit" {
+            return Ok(server.shut_down);
+        }
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    Err(anyhow!("the input ended before the exit notification"))
+}
+
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+fn invalid_params(message: impl Into<String>) -> ResponseError {
+    ResponseError {
+        code: INVALID_PARAMS,
+        message: message.into(),
+    }
+}
+
+struct Document {
+    file: SourceFile,
+    /// Empty if the document does not parse
+    references: Vec<Reference>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        Document {
+            file: SourceFile::new(text),
+            references: vec![],
+        }
+    }
+
+    fn text(&self) -> &str {
+        self.file.src()
+    }
+
+    fn parsed(&self) -> Option<&Parsed> {
+        self.file.parsed().ok()
+    }
+
+    /// Apply a content change, returning whether it is well formed.
+    fn change(&mut self, change: &Value) -> bool {
+        let Some(text) = change["text"].as_str() else {
+            return false;
+        };
+        let Some(range) = change.get("range") else {
+            *self = Document::new(text.to_owned());
+            return true;
+        };
+        let (Some(start), Some(end)) = (
+            offset(self.text(), &range["start"]),
+            offset(self.text(), &range["end"]),
+        ) else {
+            return false;
+        };
+        if start > end {
+            return false;
+        }
+        self.file.edit(start..end, text);
+        true
+    }
+
+    /// Analyze the document, returning its diagnostics.
+    fn check(&mut self) -> Vec<Diagnostic> {
+        match self.file.parsed() {
+            Ok(Parsed { ast, lexer_output }) => {
+                let analysis = analyze(ast, lexer_output);
+                let mut errors = analysis.errors;
+                errors.extend(uninitialized(
+                    ast,
+                    lexer_output,
+                    &analysis.references,
+                ));
+                errors.sort_by_key(|e| e.span.start);
+                self.references = analysis.references;
+                errors.iter().map(Into::into).collect()
+            }
+            Err(e) => {
+                self.references = vec![];
+                vec![e.into()]
+            }
+        }
+    }
+
+    /// The reference at `offset`, including the one the cursor is right
+    /// after.
+    fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|r| r.span.start <= offset && offset <= r.span.end)
+    }
+}
+
+/// The LSP position of the byte `offset` in `text`.
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+/// The byte offset of the LSP `position` in `text`, clamped to the end of
+/// its line.
+fn offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let line_start = if line == 0 {
+        0
+    } else {
+        text.match_indices('\n').nth(line - 1)?.0 + 1
+    };
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+fn range(text: &str, span: Span) -> Value {
+    json!({
+        "start": position(text, span.start),
+        "end": position(text, span.end),
+    })
+}
+
+fn location(uri: &str, text: &str, span: Span) -> Value {
+    json!({ "uri": uri, "range": range(text, span) })
+}
+
+fn lsp_diagnostic(uri: &str, text: &str, d: &Diagnostic) -> Value {
+    let related: Vec<_> = d
+        .notes
+        .iter()
+        .map(|note| {
+            json!({
+                "location": location(uri, text, note.span),
+                "message": note.message,
+            })
+        })
+        .collect();
+    json!({
+        "range": range(text, d.span),
+        "severity": match d.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        },
+        "code": d.code,
+        "source": "minic",
+        "message": d.message,
+        "relatedInformation": related,
+    })
+}
+
+/// The declarations of the identifiers in an AST as C, by the start of the
+/// identifier.
+struct Declarations<'a> {
+    lexer_output: &'a LexerOutput,
+    found: HashMap<usize, String>,
+}
+
+impl Declarations<'_> {
+    fn add(
+        &mut self,
+        specifier: &DeclarationSpecifier,
+        declarator: &Declarator,
+    ) {
+        let (_, span) = declarator.identifier();
+        let declaration =
+            print_declarator(specifier, declarator, self.lexer_output);
+        self.found.insert(span.start, declaration);
+    }
+}
+
+impl Visitor for Declarations<'_> {
+    fn visit_function_definition(&mut self, f: &FunctionDefinition) {
+        self.add(&f.declaration_specifier, &f.declarator);
+        walk_function_definition(self, f);
+    }
+
+    fn visit_declaration(&mut self, d: &Declaration) {
+        for i in &d.init_declarator_list.0 {
+            self.add(&d.declaration_specifier, &i.declarator);
+            self.visit_init_declarator(i);
+        }
+    }
+
+    fn visit_parameter_declaration(&mut self, p: &ParameterDeclaration) {
+        self.add(&p.specifier, &p.declarator);
+        walk_parameter_declaration(self, p);
+    }
+}
+
+fn symbol(
+    text: &str,
+    specifier: &DeclarationSpecifier,
+    declarator: &Declarator,
+    span: Span,
+    lexer_output: &LexerOutput,
+) -> Value {
+    let (id, identifier) = declarator.identifier();
+    let kind = match declarator.parameters() {
+        Some(_) => SYMBOL_KIND_FUNCTION,
+        None => SYMBOL_KIND_VARIABLE,
+    };
+    json!({
+        "name": lexer_output.name(id),
+        "detail": print_declarator(specifier, declarator, lexer_output),
+        "kind": kind,
+        "range": range(text, span),
+        "selectionRange": range(text, identifier),
+    })
+}
+
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shut_down: bool,
+}
+
+impl Server {
+    /// Handle a request or notification, returning the messages to send.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // A response, but no requests are sent.
+            return vec![];
+        };
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+        let response = match self.request(method, params) {
+            Ok(result) => {
+                json!({ "jsonrpc": "2.0", "id": id, "result": result })
+            }
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": e.code, "message": e.message },
+            }),
+        };
+        vec![response]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let document = match method {
+            "textDocument/didOpen" => {
+                let Some(text) = params["textDocument"]["text"].as_str() else {
+                    return vec![];
+                };
+                self.documents
+                    .entry(uri.to_owned())
+                    .insert_entry(Document::new(text.to_owned()))
+                    .into_mut()
+            }
+            "textDocument/didChange" => {
+                let Some(document) = self.documents.get_mut(uri) else {
+                    return vec![];
+                };
+                let changes = params["contentChanges"].as_array();
+                for change in changes.into_iter().flatten() {
+                    if !document.change(change) {
+                        // Out of sync, so start over from the next full
+                        // change.
+                        self.documents.remove(uri);
+                        return vec![];
+                    }
+                }
+                document
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, vec![])];
+            }
+            _ => return vec![],
+        };
+        let diagnostics = document
+            .check()
+            .iter()
+            .map(|d| lsp_diagnostic(uri, document.text(), d))
+            .collect();
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    fn request(
+        &mut self,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, ResponseError> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 2,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {
+                    "name": "minic",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, document, offset) = self.document_position(params)?;
+                Ok(match document.reference_at(offset) {
+                    Some(r) => location(uri, document.text(), r.declaration),
+                    None => Value::Null,
+                })
+            }
+            "textDocument/references" => {
+                let (uri, document, offset) = self.document_position(params)?;
+                let Some(r) = document.reference_at(offset) else {
+                    return Ok(Value::Null);
+                };
+                let include_declaration =
+                    params["context"]["includeDeclaration"] == true;
+                let locations: Vec<_> = document
+                    .references
+                    .iter()
+                    .filter(|s| s.declaration == r.declaration)
+                    .filter(|s| include_declaration || s.span != s.declaration)
+                    .map(|s| location(uri, document.text(), s.span))
+                    .collect();
+                Ok(locations.into())
+            }
+            "textDocument/hover" => {
+                let (_, document, offset) = self.document_position(params)?;
+                let Some(r) = document.reference_at(offset) else {
+                    return Ok(Value::Null);
+                };
+                let parsed = document.parsed().unwrap();
+                let mut declarations = Declarations {
+                    lexer_output: &parsed.lexer_output,
+                    found: HashMap::new(),
+                };
+                declarations.visit_translation_unit(&parsed.ast);
+                let value = match declarations
+                    .found
+                    .remove(&r.declaration.start)
+                {
+                    Some(declaration) => format!("```c\n{declaration}\n```"),
+                    // Calls to undeclared functions declare them.
+                    None => format!(
+                        "```c\nint {}()\n```\nimplicitly declared",
+                        parsed.lexer_output.name(r.id)
+                    ),
+                };
+                Ok(json!({
+                    "contents": { "kind": "markdown", "value": value },
+                    "range": range(document.text(), r.span),
+                }))
+            }
+            "textDocument/documentSymbol" => {
+                let (document, _) = self.document(params)?;
+                let Some(parsed) = document.parsed() else {
+                    return Ok(Value::Null);
+                };
+                let text = document.text();
+                let lexer_output = &parsed.lexer_output;
+                let mut symbols = vec![];
+                for d in &parsed.ast.external_declarations {
+                    match d {
+                        ExternalDeclaration::FunctionDeclaration(f) => symbols
+                            .push(symbol(
+                                text,
+                                &f.declaration_specifier,
+                                &f.declarator,
+                                f.span,
+                                lexer_output,
+                            )),
+                        ExternalDeclaration::Declaration(d) => {
+                            for i in &d.init_declarator_list.0 {
+                                symbols.push(symbol(
+                                    text,
+                                    &d.declaration_specifier,
+                                    &i.declarator,
+                                    i.span,
+                                    lexer_output,
+                                ))
+                            }
+                        }
+                    }
+                }
+                Ok(symbols.into())
+            }
+            _ => Err(ResponseError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method '{method}'"),
+            }),
+        }
+    }
+
+    fn document<'a>(
+        &'a self,
+        params: &'a Value,
+    ) -> Result<(&'a Document, &'a str), ResponseError> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| invalid_params("expect textDocument.uri"))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| invalid_params(format!("'{uri}' is not open")))?;
+        Ok((document, uri))
+    }
+
+    /// The URI, document and byte offset of a text document position.
+    fn document_position<'a>(
+        &'a self,
+        params: &'a Value,
+    ) -> Result<(&'a str, &'a Document, usize), ResponseError> {
+        let (document, uri) = self.document(params)?;
+        let offset =
+            offset(document.text(), &params["position"]).ok_or_else(|| {
+                invalid_params("expect a position in the document")
+            })?;
+        Ok((uri, document, offset))
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(0, 0, 0; "start")]
+    #[test_case(4, 0, 4; "first line")]
+    #[test_case(7, 1, 0; "second line")]
+    // `é` is two bytes but one UTF-16 code unit, `𝕏` four bytes but two.
+    #[test_case(11, 1, 3; "after two byte char")]
+    #[test_case(20, 1, 10; "after four byte char")]
+    fn test_position(offset: usize, line: u64, character: u64) {
+        let text = "int a;\n/*é*/ /*𝕏*/ b";
+        let p = position(text, offset);
+        assert_eq!(p, json!({ "line": line, "character": character }));
+        assert_eq!(super::offset(text, &p), Some(offset));
+    }
+
+    #[test]
+    fn test_offset_clamped() {
+        let text = "ab\ncd";
+        assert_eq!(offset(text, &json!({"line": 0, "character": 9})), Some(2));
+        assert_eq!(offset(text, &json!({"line": 1, "character": 9})), Some(5));
+        assert_eq!(offset(text, &json!({"line": 2, "character": 0})), None);
+    }
+
+    #[test]
+    fn test_framing() {
+        let message = json!({"jsonrpc": "2.0", "method": "exit"});
+        let mut buf = vec![];
+        write_message(&mut buf, &message).unwrap();
+        write_message(&mut buf, &message).unwrap();
+        let mut r = buf.as_slice();
+        assert_eq!(read_message(&mut r).unwrap(), Some(message.clone()));
+        assert_eq!(read_message(&mut r).unwrap(), Some(message));
+        assert_eq!(read_message(&mut r).unwrap(), None);
+        assert!(read_message(&mut &b"Foo: 1\r\n\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_hover_parameter() {
+        let mut server = Server::default();
+        let uri = "file:///a.c";
+        server.handle(&json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text":
+                "int f(int (*g)(int a)) { return g(1); }" } },
+        }));
+        let reply = server.handle(&json!({
+            "id": 1,
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": 0, "character": 32 },
+            },
+        }));
+        assert_eq!(
+            reply[0]["result"]["contents"]["value"],
+            "```c\nint (*g)(int a)\n```"
+        );
+    }
+}
@@ -1,29 +1,75 @@
-#![feature(new_uninit)]
-#![feature(never_type)]
-#![feature(assert_matches)]
-mod ast;
-mod error;
-mod lexer;
-mod parser;
-mod persist;
-mod preprocess;
-mod token;
-mod util;
-
 use std::{
-    fs::File,
-    io::{Read, Write},
+    convert::Infallible,
+    fs::{self, File},
+    io::{IsTerminal, Read, Write},
     path::{Path, PathBuf},
+    process::exit,
 };
 
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use lexer::LexerOutput;
-use parser::parse;
-use persist::output;
+use clap::{Parser, Subcommand, ValueEnum};
+use minic::{
+    ast::TranslationUnit,
+    ast_file::{load_ast, output_ast},
+    ast_json::{to_json, JsonFormat},
+    cfg,
+    dataflow::uninitialized,
+    diagnostic::{
+        self, apply_fix_its, Diagnostic, DiagnosticsFormat, Severity,
+    },
+    dot::to_dot,
+    error, fmt, interp, ir,
+    lexer::{self, LexerOutput},
+    lint, lsp,
+    opt::{self, Pass},
+    parser::parse,
+    persist::{output, MappedLexerOutput},
+    preprocess::{self, unterminated_comment},
+    printer,
+    regalloc::{Allocator, Stats},
+    repl,
+    resolve::analyze,
+    suggest::suggest_keyword,
+    token_dump::write_tokens,
+    x86,
+};
+
+/// What to print instead of the JSON AST
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Emit {
+    /// The AST as a Graphviz graph
+    Dot,
+    /// The AST printed back as C source
+    C,
+    /// The control-flow graph of each function as a Graphviz graph
+    CfgDot,
+    /// The intermediate representation after the passes
+    Ir,
+    /// x86-64 assembly in AT&T syntax
+    Asm,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Rewrite C files in a consistent style, keeping comments
+    Fmt(fmt::FmtArgs),
+    /// Warn about code that is likely a mistake
+    Lint(lint::LintArgs),
+    /// Run a language server on stdin and stdout
+    Lsp,
+    /// Evaluate declarations, statements and expressions interactively
+    Repl,
+}
 
 #[derive(Parser)]
+#[command(
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Only run the preprocessor
     #[arg(short = 'E')]
     preprocessor_only: bool,
@@ -33,22 +79,88 @@ struct Args {
     /// Output human-readable tokens as well
     #[arg(short('H'), long)]
     human_readable: bool,
+    /// Print tokens as `line:col KIND lexeme` lines and stop
+    #[arg(long)]
+    dump_tokens: bool,
     /// Read and show lexer output only
     #[arg(short, long)]
     show_output: bool,
 
     #[arg(short, long)]
     output: Option<String>,
+    /// How to print errors
+    #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Human)]
+    diagnostics_format: DiagnosticsFormat,
+    /// Apply suggested fixes to the file if that fixes all errors
+    #[arg(long)]
+    fix: bool,
+    /// Save the AST to a .ast file as well
+    #[arg(long)]
+    save_ast: bool,
+    /// Start from an AST saved by --save-ast instead of source code
+    #[arg(long, conflicts_with_all = ["preprocessor_only", "fix", "save_ast"])]
+    from_ast: bool,
+    /// Print the AST as a versioned JSON document with identifier names, as
+    /// described by schema/ast.schema.json
+    #[arg(long)]
+    json_names: bool,
+    /// Include spans in that document
+    #[arg(long, requires = "json_names")]
+    json_spans: bool,
+    #[arg(long, value_enum, conflicts_with = "json_names")]
+    emit: Option<Emit>,
+    /// Leave trivial nodes out of the graph, like `Pointer(0)`, empty lists
+    /// and enum variants that only wrap another node
+    #[arg(long, requires = "emit")]
+    collapse: bool,
+    /// Optimization level of the IR: 1 runs sccp and dce, and 2 also gvn
+    #[arg(
+        short = 'O',
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=2)
+    )]
+    opt_level: u8,
+    /// Run these passes in this order instead of those of -O
+    #[arg(long = "pass", value_enum, value_delimiter = ',')]
+    passes: Option<Vec<Pass>>,
+    /// Print the IR to stderr before and after each pass
+    #[arg(long)]
+    dump_ir: bool,
+    /// How to allocate registers for the assembly
+    #[arg(long, value_enum, default_value_t = Allocator::GraphColoring)]
+    regalloc: Allocator,
+    /// Print register allocation statistics of each function to stderr
+    #[arg(long)]
+    stats: bool,
 
-    file: String,
+    #[arg(required = true)]
+    file: Option<String>,
 }
 
 impl Args {
-    fn output_file<F>(self, rename: F) -> Result<File, std::io::Error>
+    fn json_format(&self) -> JsonFormat {
+        if self.json_names {
+            JsonFormat::Named {
+                spans: self.json_spans,
+            }
+        } else {
+            JsonFormat::Compact
+        }
+    }
+
+    /// The input file, which is required without a subcommand
+    fn file(&self) -> &str {
+        self.file.as_deref().unwrap_or_default()
+    }
+
+    fn output_file<F>(&self, rename: F) -> Result<File, std::io::Error>
     where
         F: Fn(&str) -> String,
     {
-        File::create(self.output.unwrap_or_else(|| rename(&self.file)))
+        match &self.output {
+            Some(output) => File::create(output),
+            None => File::create(rename(self.file())),
+        }
     }
 }
 
@@ -65,31 +177,215 @@ fn preprocessed_path(original_path: &str) -> String {
 fn lexer_output_path(original_path: &str) -> String {
     modify_ext(original_path, ".lexeroutput")
 }
+
+fn ast_path(original_path: &str) -> String {
+    modify_ext(original_path, ".ast")
+}
+
+fn preprocess_source(
+    src: String,
+    preprocessed: bool,
+) -> Result<String, error::Error> {
+    if preprocessed {
+        return Ok(src);
+    }
+    preprocess::preprocess(src.char_indices())
+        .map_err(|span| unterminated_comment(span, &src))
+}
+
+fn check(src: &str, preprocessed: bool) -> Result<(), error::Error> {
+    let preprocessed = preprocess_source(src.to_owned(), preprocessed)?;
+    let lexer_output = lexer::scan(&preprocessed)?;
+    parse(&lexer_output.tokens)
+        .map_err(|e| suggest_keyword(e, &lexer_output))?;
+    Ok(())
+}
+
+const MAX_FIX_ROUNDS: usize = 64;
+
+/// Apply the fix-its of the first error in `src` until it parses. Returns
+/// the fixed source and the number of fix-its applied, or `None` if some
+/// error cannot be fixed.
+fn fix(src: &str, preprocessed: bool) -> Option<(String, usize)> {
+    let mut src = src.to_owned();
+    let mut applied = 0;
+    for _ in 0..MAX_FIX_ROUNDS {
+        match check(&src, preprocessed) {
+            Ok(()) => return Some((src, applied)),
+            Err(e) if e.details.fix_its.is_empty() => return None,
+            Err(e) => {
+                src = apply_fix_its(&src, &e.details.fix_its);
+                applied += e.details.fix_its.len();
+            }
+        }
+    }
+    None
+}
+
+/// Print `e` and exit with 1. Returns only if printing fails.
+fn report(
+    e: &error::Error,
+    file_path: &Path,
+    format: DiagnosticsFormat,
+) -> Result<Infallible> {
+    diagnostic::emit(&[e.into()], file_path, format)?;
+    exit(1)
+}
+
+/// The IR of `ast` after the passes `args` ask for. Reports an error and
+/// exits if the IR cannot express `ast`.
+fn optimized(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+    file_path: &Path,
+    args: &Args,
+) -> Result<ir::Program> {
+    let mut program = match ir::lower(ast, lexer_output) {
+        Ok(program) => program,
+        Err(e) => match report(&e, file_path, args.diagnostics_format)? {},
+    };
+    let passes = match &args.passes {
+        Some(passes) => passes.clone(),
+        None => opt::passes(args.opt_level),
+    };
+    let mut stderr = std::io::stderr();
+    let dump = args.dump_ir.then_some(&mut stderr as &mut dyn Write);
+    opt::optimize(&mut program, &passes, dump)?;
+    Ok(program)
+}
+
+/// Everything after parsing: check `ast` and print it as `args` say.
+fn back_end(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+    file_path: &Path,
+    args: &Args,
+) -> Result<()> {
+    let diagnostics_format = args.diagnostics_format;
+    // Name resolution only feeds the definite assignment analysis here; its
+    // errors and "did you mean" notes are for `minic lsp`.
+    let references = analyze(ast, lexer_output).references;
+    let errors = uninitialized(ast, lexer_output, &references);
+    let diagnostics: Vec<Diagnostic> = errors.iter().map(Into::into).collect();
+    if !diagnostics.is_empty() || diagnostics_format != DiagnosticsFormat::Human
+    {
+        diagnostic::emit(&diagnostics, file_path, diagnostics_format)?;
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        exit(1);
+    }
+    match args.emit {
+        Some(Emit::Dot) => {
+            print!("{}", to_dot(ast, lexer_output, args.collapse))
+        }
+        Some(Emit::C) => print!("{}", printer::print(ast, lexer_output)),
+        Some(Emit::CfgDot) => match cfg::to_dot(ast, lexer_output) {
+            Ok(dot) => print!("{dot}"),
+            Err(e) => match report(&e, file_path, diagnostics_format)? {},
+        },
+        Some(Emit::Ir) => {
+            let program = optimized(ast, lexer_output, file_path, args)?;
+            print!("{program}");
+        }
+        Some(Emit::Asm) => {
+            let program = optimized(ast, lexer_output, file_path, args)?;
+            let (asm, stats) = x86::compile(&program, args.regalloc);
+            if args.stats {
+                let mut total = Stats::default();
+                for (name, stats) in &stats {
+                    eprintln!("{name}: {stats}");
+                    total += stats;
+                }
+                eprintln!("total ({}): {total}", args.regalloc);
+            }
+            print!("{asm}");
+        }
+        None => print!(
+            "{}",
+            to_json(ast, lexer_output, file_path, args.json_format())
+        ),
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let file_path = Path::new(&args.file);
-    let mut file = File::open(&args.file)
-        .with_context(|| format!("cannot open \"{}\"", args.file))?;
+    let mut args = Args::parse();
+    match args.command.take() {
+        Some(Command::Fmt(fmt_args)) => {
+            if !fmt::run(&fmt_args)? {
+                exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Lint(lint_args)) => {
+            if !lint::run(&lint_args)? {
+                exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Lsp) => {
+            let stdin = std::io::stdin();
+            if !lsp::run(stdin.lock(), std::io::stdout().lock())? {
+                exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Repl) => {
+            // Deep recursion in the interpreted code needs a large stack.
+            std::thread::Builder::new()
+                .stack_size(interp::STACK_SIZE)
+                .spawn(|| {
+                    let stdin = std::io::stdin();
+                    let interactive = stdin.is_terminal();
+                    repl::run(
+                        stdin.lock(),
+                        std::io::stdout().lock(),
+                        interactive,
+                    )
+                })?
+                .join()
+                .unwrap()
+        }
+        None => compile(args),
+    }
+}
+
+fn compile(args: Args) -> Result<()> {
+    let file_path = Path::new(args.file());
+    let mut file = File::open(file_path)
+        .with_context(|| format!("cannot open \"{}\"", args.file()))?;
+    if args.from_ast {
+        let saved = load_ast(file).context("corrupted AST file")?;
+        return back_end(&saved.ast, &saved.lexer_output, &saved.source, &args);
+    }
     if args.show_output {
-        let lexer_output = LexerOutput::try_from(file)
+        let mapped = MappedLexerOutput::open(&file)
             .context("corrupted lexer output file")?;
-        println!("{lexer_output:#?}");
+        println!("{:#?}", mapped.view().to_lexer_output());
         return Ok(());
     }
     let mut src = String::new();
     file.read_to_string(&mut src)?;
-    let preprocessed = if !args.preprocessed {
-        preprocess::preprocess(src.char_indices()).map_err(|e| {
-            error::Error {
-                pos: e,
-                error_kind: error::ErrorKind::UnterminatedComment,
+    if args.fix {
+        match fix(&src, args.preprocessed) {
+            Some((fixed, applied)) if applied > 0 => {
+                fs::write(file_path, &fixed).with_context(|| {
+                    format!("cannot write fixes to \"{}\"", args.file())
+                })?;
+                eprintln!("applied {applied} fix(es) to \"{}\"", args.file());
+                src = fixed;
             }
-            .report(file_path)
-            .unwrap_err()
-        })?
-    } else {
-        src
-    };
+            Some(_) => {}
+            None => eprintln!(
+                "cannot fix all errors in \"{}\"; leaving it unchanged",
+                args.file()
+            ),
+        }
+    }
+    let preprocessed =
+        preprocess_source(src, args.preprocessed).map_err(|e| {
+            report(&e, file_path, args.diagnostics_format).unwrap_err()
+        })?;
 
     if args.preprocessor_only {
         let mut output = args
@@ -103,17 +399,37 @@ fn main() -> Result<()> {
         return Err(anyhow!("expect input to not have been preprocessed"));
     }
 
-    let lexer_output = lexer::scan(&preprocessed)
-        .unwrap_or_else(|e| e.report(file_path).unwrap());
+    let lexer_output = match lexer::scan(&preprocessed) {
+        Ok(lexer_output) => lexer_output,
+        Err(e) => match report(&e, file_path, args.diagnostics_format)? {},
+    };
 
     if args.human_readable {
         println!("{lexer_output:#?}");
     }
 
-    let ast = parse(&lexer_output.tokens)
-        .unwrap_or_else(|e| e.report(file_path).unwrap());
-    let serialized = serde_json::to_string(&ast).unwrap();
-    print!("{serialized}");
+    if args.dump_tokens {
+        write_tokens(
+            &mut std::io::stdout().lock(),
+            &lexer_output,
+            &preprocessed,
+        )?;
+        return Ok(());
+    }
+
+    let ast = match parse(&lexer_output.tokens) {
+        Ok(ast) => ast,
+        Err(e) => {
+            let e = suggest_keyword(e, &lexer_output);
+            match report(&e, file_path, args.diagnostics_format)? {}
+        }
+    };
+    if args.save_ast {
+        let mut ast_file = File::create(ast_path(args.file()))
+            .context("cannot create file for AST")?;
+        output_ast(&mut ast_file, &ast, &lexer_output, file_path)?;
+    }
+    back_end(&ast, &lexer_output, file_path, &args)?;
 
     let mut output_file = args
         .output_file(lexer_output_path)
@@ -0,0 +1,637 @@
+//! Optimization passes over functions in SSA form, and the pipeline that
+//! runs them.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, Write},
+};
+
+use crate::{
+    cfg::{BlockId, Dominators},
+    ir::{
+        BinaryOp, Constant, Function, Instruction, Op, Program, Reg,
+        Terminator, Ty, UnaryOp,
+    },
+    ssa::{into_ssa, out_of_ssa},
+};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, strum_macros::Display,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Pass {
+    /// Sparse conditional constant propagation
+    Sccp,
+    /// Global value numbering
+    Gvn,
+    /// Dead code elimination
+    Dce,
+}
+
+impl Pass {
+    pub fn run(self, f: &mut Function) {
+        match self {
+            Pass::Sccp => sccp(f),
+            Pass::Gvn => gvn(f),
+            Pass::Dce => dce(f),
+        }
+    }
+}
+
+/// The passes `-O<level>` runs.
+pub fn passes(level: u8) -> Vec<Pass> {
+    match level {
+        0 => vec![],
+        1 => vec![Pass::Sccp, Pass::Dce],
+        _ => vec![Pass::Sccp, Pass::Gvn, Pass::Dce],
+    }
+}
+
+/// A named transformation of each function
+type Step = (String, Box<dyn Fn(&mut Function)>);
+
+/// Translate the functions of `program` into SSA form, run `passes` on
+/// them and translate them back. If `dump` is given, the program is
+/// written to it before and after each of these steps.
+pub fn optimize(
+    program: &mut Program,
+    passes: &[Pass],
+    mut dump: Option<&mut dyn Write>,
+) -> io::Result<()> {
+    let mut steps: Vec<Step> =
+        vec![("into-ssa".to_owned(), Box::new(into_ssa))];
+    for &pass in passes {
+        steps.push((pass.to_string(), Box::new(move |f| pass.run(f))));
+    }
+    steps.push(("out-of-ssa".to_owned(), Box::new(out_of_ssa)));
+    for (name, run) in steps {
+        if let Some(dump) = &mut dump {
+            write!(dump, "; IR before {name}\n{program}\n")?;
+        }
+        program.functions.iter_mut().for_each(run);
+        if let Some(dump) = &mut dump {
+            write!(dump, "; IR after {name}\n{program}\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// What is known about the value of a register.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    /// Nothing yet: no definition that reaches it has run.
+    Unknown,
+    Constant(Constant),
+    Varying,
+}
+
+impl Cell {
+    fn meet(self, other: Cell) -> Cell {
+        match (self, other) {
+            (Cell::Unknown, c) | (c, Cell::Unknown) => c,
+            (Cell::Constant(a), Cell::Constant(b)) if a == b => self,
+            _ => Cell::Varying,
+        }
+    }
+}
+
+/// Sparse conditional constant propagation, as Wegman and Zadeck describe:
+/// find the registers that are constant assuming that only the edges a
+/// branch can take run, and fold them, the branches on them and the blocks
+/// that cannot run.
+pub fn sccp(f: &mut Function) {
+    let mut cells = vec![Cell::Unknown; f.registers.len()];
+    // The blocks using each register
+    let mut users = vec![vec![]; f.registers.len()];
+    for (b, block) in f.blocks.iter().enumerate() {
+        let phi_args = block.phis.iter().flat_map(|phi| &phi.args);
+        let operands = block.instructions.iter().flat_map(|i| i.op.operands());
+        for r in phi_args.map(|&(_, r)| r).chain(operands) {
+            users[r].push(b);
+        }
+        if let Some(r) = block.terminator.operand() {
+            users[r].push(b);
+        }
+    }
+
+    let mut executable = vec![false; f.blocks.len()];
+    let mut edges = HashSet::new();
+    let mut queued = vec![false; f.blocks.len()];
+    let mut worklist = VecDeque::from([0]);
+    executable[0] = true;
+    queued[0] = true;
+    while let Some(b) = worklist.pop_front() {
+        queued[b] = false;
+        let block = &f.blocks[b];
+        let mut changed = vec![];
+        let mut update = |r: Reg, cell: Cell, cells: &mut Vec<Cell>| {
+            let cell = cells[r].meet(cell);
+            if cells[r] != cell {
+                cells[r] = cell;
+                changed.push(r);
+            }
+        };
+        for phi in &block.phis {
+            let cell = phi
+                .args
+                .iter()
+                .filter(|&&(p, _)| edges.contains(&(p, b)))
+                .fold(Cell::Unknown, |cell, &(_, r)| cell.meet(cells[r]));
+            update(phi.dest, cell, &mut cells);
+        }
+        for i in &block.instructions {
+            if let Some(d) = i.dest {
+                let cell = evaluate(&i.op, d, f, &cells);
+                update(d, cell, &mut cells);
+            }
+        }
+        let targets = match block.terminator {
+            Terminator::Jump(t) => vec![t],
+            Terminator::Branch(r, then, otherwise) => match cells[r] {
+                Cell::Unknown => vec![],
+                Cell::Constant(c) if c.is_true() => vec![then],
+                Cell::Constant(_) => vec![otherwise],
+                Cell::Varying => vec![then, otherwise],
+            },
+            Terminator::Return(_) => vec![],
+        };
+        let mut schedule = |b: BlockId| {
+            if !queued[b] {
+                queued[b] = true;
+                worklist.push_back(b);
+            }
+        };
+        for t in targets {
+            if edges.insert((b, t)) {
+                executable[t] = true;
+                schedule(t);
+            }
+        }
+        for r in changed {
+            for &u in &users[r] {
+                if executable[u] {
+                    schedule(u);
+                }
+            }
+        }
+    }
+
+    for (b, block) in f.blocks.iter_mut().enumerate() {
+        let mut constants = vec![];
+        block.phis.retain_mut(|phi| {
+            if let Cell::Constant(c) = cells[phi.dest] {
+                constants.push(Instruction {
+                    dest: Some(phi.dest),
+                    op: Op::Const(c),
+                });
+                return false;
+            }
+            phi.args.retain(|&(p, _)| edges.contains(&(p, b)));
+            true
+        });
+        for i in &mut block.instructions {
+            if let Some(Cell::Constant(c)) = i.dest.map(|d| cells[d]) {
+                i.op = Op::Const(c);
+            }
+        }
+        block.instructions.splice(0..0, constants);
+        if let Terminator::Branch(r, then, otherwise) = block.terminator {
+            if let Cell::Constant(c) = cells[r] {
+                let target = if c.is_true() { then } else { otherwise };
+                block.terminator = Terminator::Jump(target);
+            }
+        }
+    }
+    remove_blocks(f, &executable);
+}
+
+/// What `op` gives `d` given `cells`.
+fn evaluate(op: &Op, d: Reg, f: &Function, cells: &[Cell]) -> Cell {
+    let foldable = |r: &Reg| f.registers[*r] != Ty::Pointer;
+    match op {
+        Op::Const(c) => Cell::Constant(*c),
+        Op::Copy(r) => cells[*r],
+        Op::Unary(..) | Op::Binary(..) | Op::Convert(_)
+            if foldable(&d) && op.operands().iter().all(foldable) =>
+        {
+            let operands: Vec<_> =
+                op.operands().iter().map(|&r| cells[r]).collect();
+            if operands.contains(&Cell::Varying) {
+                return Cell::Varying;
+            }
+            let constants: Vec<_> = operands
+                .iter()
+                .filter_map(|cell| match cell {
+                    Cell::Constant(c) => Some(*c),
+                    _ => None,
+                })
+                .collect();
+            if constants.len() < operands.len() {
+                return Cell::Unknown;
+            }
+            fold(op, f.registers[d], &constants)
+                .map_or(Cell::Varying, Cell::Constant)
+        }
+        _ => Cell::Varying,
+    }
+}
+
+/// The value of `op` with `operands` for a destination of type `ty`, unless
+/// it is undefined, like a division by zero.
+pub(crate) fn fold(op: &Op, ty: Ty, operands: &[Constant]) -> Option<Constant> {
+    use Constant::{Double, Int};
+    Some(match (op, operands) {
+        (Op::Convert(_), [c]) => match (ty, *c) {
+            (Ty::Int, Double(x)) => Int(x as i32),
+            (Ty::Double, Int(n)) => Double(n as f64),
+            (_, c) => c,
+        },
+        (Op::Unary(op, _), [c]) => match (op, *c) {
+            (UnaryOp::Negate, Int(n)) => Int(n.wrapping_neg()),
+            (UnaryOp::Negate, Double(x)) => Double(-x),
+            (UnaryOp::Not, c) => Int(!c.is_true() as i32),
+            (UnaryOp::Complement, Int(n)) => Int(!n),
+            (UnaryOp::Complement, Double(_)) => return None,
+        },
+        (Op::Binary(op, ..), [Int(a), Int(b)]) => {
+            let (a, b) = (*a, *b);
+            match op {
+                BinaryOp::Add => Int(a.wrapping_add(b)),
+                BinaryOp::Subtract => Int(a.wrapping_sub(b)),
+                BinaryOp::Multiply => Int(a.wrapping_mul(b)),
+                BinaryOp::Divide => Int(a.checked_div(b)?),
+                BinaryOp::And => Int(a & b),
+                BinaryOp::Or => Int(a | b),
+                op => Int(compare(*op, a, b)),
+            }
+        }
+        (Op::Binary(op, ..), [Double(a), Double(b)]) => {
+            let (a, b) = (*a, *b);
+            match op {
+                BinaryOp::Add => Double(a + b),
+                BinaryOp::Subtract => Double(a - b),
+                BinaryOp::Multiply => Double(a * b),
+                BinaryOp::Divide => Double(a / b),
+                BinaryOp::And | BinaryOp::Or => return None,
+                op => Int(compare(*op, a, b)),
+            }
+        }
+        _ => return None,
+    })
+}
+
+fn compare<T: PartialOrd>(op: BinaryOp, a: T, b: T) -> i32 {
+    (match op {
+        BinaryOp::Lt => a < b,
+        BinaryOp::Gt => a > b,
+        BinaryOp::Le => a <= b,
+        BinaryOp::Ge => a >= b,
+        BinaryOp::Eq => a == b,
+        BinaryOp::Ne => a != b,
+        _ => unreachable!(),
+    }) as i32
+}
+
+/// Remove the blocks that are not `kept`, which nothing kept jumps to, and
+/// number the rest in order.
+fn remove_blocks(f: &mut Function, kept: &[bool]) {
+    let mut number = vec![usize::MAX; f.blocks.len()];
+    let mut n = 0;
+    for (b, &kept) in kept.iter().enumerate() {
+        if kept {
+            number[b] = n;
+            n += 1;
+        }
+    }
+    let blocks = std::mem::take(&mut f.blocks);
+    for (b, mut block) in blocks.into_iter().enumerate() {
+        if !kept[b] {
+            continue;
+        }
+        for phi in &mut block.phis {
+            for (p, _) in &mut phi.args {
+                *p = number[*p];
+            }
+        }
+        block.terminator = match block.terminator {
+            Terminator::Jump(t) => Terminator::Jump(number[t]),
+            Terminator::Branch(r, then, otherwise) => {
+                Terminator::Branch(r, number[then], number[otherwise])
+            }
+            t => t,
+        };
+        f.blocks.push(block);
+    }
+}
+
+/// Global value numbering over the dominator tree: an instruction that
+/// computes what one in a dominating block already did is replaced by the
+/// register of that one, as are copies and phis whose arguments are all
+/// the same.
+pub fn gvn(f: &mut Function) {
+    let children = Dominators::new(&f.successors()).children();
+    let mut replacements: Vec<Reg> = (0..f.registers.len()).collect();
+    let find = |replacements: &[Reg], mut r: Reg| {
+        while replacements[r] != r {
+            r = replacements[r];
+        }
+        r
+    };
+    let mut table: HashMap<(Op, Ty), Reg> = HashMap::new();
+    enum Visit {
+        Enter(BlockId),
+        /// Forget what a block added to the table
+        Leave(Vec<(Op, Ty)>),
+    }
+    let mut stack = vec![Visit::Enter(0)];
+    while let Some(visit) = stack.pop() {
+        let b = match visit {
+            Visit::Enter(b) => b,
+            Visit::Leave(added) => {
+                for key in added {
+                    table.remove(&key);
+                }
+                continue;
+            }
+        };
+        let block = &mut f.blocks[b];
+        let mut added = vec![];
+        block.phis.retain_mut(|phi| {
+            for (_, r) in &mut phi.args {
+                *r = find(&replacements, *r);
+            }
+            let mut args = phi.args.iter().map(|&(_, r)| r);
+            let first = args.next().unwrap_or(phi.dest);
+            if args.all(|r| r == first || r == phi.dest) {
+                replacements[phi.dest] = first;
+                return false;
+            }
+            true
+        });
+        block.instructions.retain_mut(|i| {
+            for r in i.op.operands_mut() {
+                *r = find(&replacements, *r);
+            }
+            let Some(d) = i.dest else {
+                return true;
+            };
+            if let Op::Copy(r) = i.op {
+                replacements[d] = r;
+                return false;
+            }
+            if !i.op.is_pure() {
+                return true;
+            }
+            if let Op::Binary(op, a, b) = &mut i.op {
+                if op.is_commutative() && a > b {
+                    std::mem::swap(a, b);
+                }
+            }
+            let key = (i.op.clone(), f.registers[d]);
+            match table.get(&key) {
+                Some(&leader) => {
+                    replacements[d] = leader;
+                    false
+                }
+                None => {
+                    table.insert(key.clone(), d);
+                    added.push(key);
+                    true
+                }
+            }
+        });
+        for r in block.terminator.operands_mut() {
+            *r = find(&replacements, *r);
+        }
+        stack.push(Visit::Leave(added));
+        for &c in children[b].iter().rev() {
+            stack.push(Visit::Enter(c));
+        }
+    }
+    // Phi arguments along back edges were seen before their definitions.
+    for block in &mut f.blocks {
+        for phi in &mut block.phis {
+            for (_, r) in &mut phi.args {
+                *r = find(&replacements, *r);
+            }
+        }
+    }
+}
+
+/// Remove the instructions and phis whose value is never used, other than
+/// stores and calls, which run for their effects.
+pub fn dce(f: &mut Function) {
+    // The operands of the definition of each register
+    let mut operands = vec![vec![]; f.registers.len()];
+    let mut worklist = vec![];
+    for block in &f.blocks {
+        for phi in &block.phis {
+            operands[phi.dest] = phi.args.iter().map(|&(_, r)| r).collect();
+        }
+        for i in &block.instructions {
+            match i.dest {
+                Some(d) if !i.op.has_effects() => operands[d] = i.op.operands(),
+                _ => worklist.extend(i.op.operands()),
+            }
+        }
+        worklist.extend(block.terminator.operand());
+    }
+    let mut live = vec![false; f.registers.len()];
+    while let Some(r) = worklist.pop() {
+        if !live[r] {
+            live[r] = true;
+            worklist.extend(&operands[r]);
+        }
+    }
+    for block in &mut f.blocks {
+        block.phis.retain(|phi| live[phi.dest]);
+        block.instructions.retain(|i| match i.dest {
+            Some(d) if !i.op.has_effects() => live[d],
+            _ => true,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::ValueEnum;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{
+        ir::{lower, run},
+        lexer::scan,
+        parser::parse,
+    };
+
+    fn lower_source(src: &str) -> Program {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        lower(&ast, &lexer_output).unwrap()
+    }
+
+    /// The number of instructions in `f` whose operation `is` matches.
+    fn count(f: &Function, is: impl Fn(&Op) -> bool) -> usize {
+        let instructions = f.blocks.iter().flat_map(|b| &b.instructions);
+        instructions.filter(|i| is(&i.op)).count()
+    }
+
+    #[test_case(
+        "int f(int n) { int s = 0; int i; \
+         for (i = 0; i < n; i = i + 1) s = s + i; return s; }",
+        5,
+        10;
+        "sum"
+    )]
+    #[test_case(
+        "int f(int n) { int a = 1; int b = 2; int t; \
+         while (n > 0) { t = a; a = b; b = t; n = n - 1; } \
+         return a * 10 + b; }",
+        3,
+        21;
+        "swap"
+    )]
+    #[test_case(
+        "int f(int n) { int a[4] = {1, 2}; int *p = &a[1]; \
+         *p = *p + n; a[3] = a[0] + a[1]; return a[3] + a[2]; }",
+        5,
+        8;
+        "array"
+    )]
+    #[test_case(
+        "int f(int n) { double x = n; x = x / 2; return x * 4; }",
+        3,
+        6;
+        "double"
+    )]
+    #[test_case(
+        "int g(int *p) { *p = *p + 1; return 1; } \
+         int f(int n) { int c = 0; if (n && g(&c)) c = c + 10; return c; }",
+        1,
+        11;
+        "short circuit"
+    )]
+    #[test_case(
+        "int k = 3; int fib(int n) { if (n < 2) return n; \
+         return fib(n - 1) + fib(n - 2); } int f(int n) { return fib(n) + k; }",
+        10,
+        58;
+        "recursion"
+    )]
+    #[test_case(
+        "int f(int n) { int x = 4; if (x > 3) n = n + 1; else n = n - 1; \
+         int y = n + 1; int z = n + 1; return y * z; }",
+        1,
+        9;
+        "constant branch"
+    )]
+    #[test_case(
+        "int f(int n) { int d = 0; if (n > 100) return n / d; return n; }",
+        7,
+        7;
+        "division by zero"
+    )]
+    fn test_optimize(src: &str, arg: i32, expected: i32) {
+        let pipelines: [&[Pass]; 7] = [
+            &[],
+            &[Pass::Sccp],
+            &[Pass::Gvn],
+            &[Pass::Dce],
+            &passes(1),
+            &passes(2),
+            &[Pass::Gvn, Pass::Sccp, Pass::Dce, Pass::Gvn, Pass::Dce],
+        ];
+        for passes in pipelines {
+            let mut program = lower_source(src);
+            optimize(&mut program, passes, None).unwrap();
+            let f = &program.functions.last().unwrap().name;
+            assert_eq!(
+                run(&program, f, &[Constant::Int(arg)]),
+                Some(Constant::Int(expected)),
+                "{passes:?} gave\n{program}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sccp() {
+        let mut program = lower_source(
+            "int f(int n) { int x = 4; int y; \
+             if (x > 3) y = x * 2; else y = n; return y + 1; }",
+        );
+        let f = &mut program.functions[0];
+        into_ssa(f);
+        sccp(f);
+        dce(f);
+        assert!(f.blocks.iter().all(|b| b.phis.is_empty()));
+        assert!(f
+            .blocks
+            .iter()
+            .all(|b| !matches!(b.terminator, Terminator::Branch(..))));
+        let Terminator::Return(Some(r)) = f.blocks.last().unwrap().terminator
+        else {
+            panic!("no return in\n{f}")
+        };
+        let mut instructions = f.blocks.iter().flat_map(|b| &b.instructions);
+        let defining = instructions.find(|i| i.dest == Some(r)).unwrap();
+        assert_eq!(defining.op, Op::Const(Constant::Int(9)));
+    }
+
+    #[test]
+    fn test_gvn() {
+        let mut program = lower_source(
+            "int f(int a) { int x = a + 1; int y; if (a) y = 1 + a; \
+             else y = a + 1; return x * y + (a + 1); }",
+        );
+        let f = &mut program.functions[0];
+        into_ssa(f);
+        gvn(f);
+        dce(f);
+        let adds = count(f, |op| matches!(op, Op::Binary(BinaryOp::Add, ..)));
+        assert_eq!(adds, 2, "{f}");
+        assert!(f.blocks.iter().all(|b| b.phis.is_empty()), "{f}");
+    }
+
+    #[test]
+    fn test_dce() {
+        let mut program = lower_source(
+            "int g; int f(int a) { int x = a * 2; int y = x + 3; \
+             g = a; y = a; return y; }",
+        );
+        let f = &mut program.functions[0];
+        into_ssa(f);
+        dce(f);
+        assert_eq!(count(f, |op| matches!(op, Op::Binary(..))), 0, "{f}");
+        assert_eq!(count(f, |op| matches!(op, Op::Store(..))), 1, "{f}");
+    }
+
+    #[test_case("sccp", Pass::Sccp)]
+    #[test_case("gvn", Pass::Gvn)]
+    #[test_case("dce", Pass::Dce)]
+    fn test_pass_name(name: &str, pass: Pass) {
+        assert_eq!(Pass::from_str(name, false), Ok(pass));
+        assert_eq!(pass.to_string(), name);
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut program = lower_source("int f(int a) { return a; }");
+        let mut dump = vec![];
+        optimize(&mut program, &[Pass::Dce], Some(&mut dump)).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let headers: Vec<_> =
+            dump.lines().filter(|l| l.starts_with(';')).collect();
+        assert_eq!(
+            headers,
+            [
+                "; IR before into-ssa",
+                "; IR after into-ssa",
+                "; IR before dce",
+                "; IR after dce",
+                "; IR before out-of-ssa",
+                "; IR after out-of-ssa",
+            ]
+        );
+    }
+}
@@ -1,14 +1,174 @@
-use crate::error::ErrorKind;
+use crate::cst::{self, SyntaxKind};
+use crate::diagnostic::{FixIt, Note};
+use crate::error::{Details, ErrorKind};
 use crate::token::*;
 use crate::{ast::*, error::Error, token::Token};
-use trace::trace;
 use util::*;
 
 trace::init_depth_var!();
 
-pub(crate) fn parse(tokens: &[Token]) -> Result<TranslationUnit, Error> {
+/// The tokens being parsed, with the [`cst::Recorder`] to report the nodes
+/// they form to when a CST is being built.
+struct Input<'a> {
+    tokens: &'a [Token],
+    recorder: Option<&'a cst::Recorder>,
+}
+
+impl Input<'_> {
+    fn node(&self, kind: SyntaxKind, start: usize, end: usize) {
+        if let Some(recorder) = self.recorder {
+            recorder.node(kind, start, end);
+        }
+    }
+
+    fn mark(&self) -> usize {
+        self.recorder.map_or(0, cst::Recorder::mark)
+    }
+
+    fn rollback(&self, mark: usize) {
+        if let Some(recorder) = self.recorder {
+            recorder.rollback(mark);
+        }
+    }
+}
+
+impl std::ops::Deref for Input<'_> {
+    type Target = [Token];
+
+    fn deref(&self) -> &[Token] {
+        self.tokens
+    }
+}
+
+impl<'a> From<&'a [Token]> for Input<'a> {
+    fn from(tokens: &'a [Token]) -> Self {
+        Input {
+            tokens,
+            recorder: None,
+        }
+    }
+}
+
+pub fn parse(tokens: &[Token]) -> Result<TranslationUnit, Error> {
+    parse_input(tokens.into())
+}
+
+/// [`parse`], reporting the nodes to `recorder`.
+pub(crate) fn parse_recording(
+    tokens: &[Token],
+    recorder: &cst::Recorder,
+) -> Result<TranslationUnit, Error> {
+    parse_input(Input {
+        tokens,
+        recorder: Some(recorder),
+    })
+}
+
+fn parse_input(tokens: Input) -> Result<TranslationUnit, Error> {
     let mut i = 0;
-    parse_translation_unit(&mut i, tokens)
+    parse_translation_unit(&mut i, &tokens).map_err(|mut e| {
+        if let Some(guess) = guess_missing_brace(&tokens) {
+            // A missing brace can only cause errors after it.
+            if guess.insert_before.start <= e.span.start {
+                e.details.notes.push(Note {
+                    span: guess.insert_before,
+                    message: format!(
+                        "the '}}' closing the '{{' on line {} most likely \
+                         belongs before this",
+                        guess.open.start_pos.line
+                    ),
+                });
+                let indent = " ".repeat(guess.insert_before.start_pos.col - 1);
+                // Fixes after the missing brace were made without it, but
+                // those before it still apply.
+                e.details
+                    .fix_its
+                    .retain(|f| f.span.end <= guess.insert_before.start);
+                e.details.fix_its.push(FixIt::insert_before(
+                    guess.insert_before,
+                    format!("}}\n{indent}"),
+                ));
+            }
+        }
+        e
+    })
+}
+
+/// Parse `tokens` as the declarations and statements of a block, without
+/// the braces.
+pub fn parse_block_items(tokens: &[Token]) -> Result<Vec<BlockItem>, Error> {
+    let mut i = 0;
+    let mut items = vec![];
+    while i < tokens.len() {
+        items.push(stmt::parse_block_item(&mut i, &tokens.into())?);
+    }
+    Ok(items)
+}
+
+/// Parse `tokens` as one expression that is not part of another, such as
+/// the expression of an expression statement without its `;`.
+pub fn parse_full_expression(tokens: &[Token]) -> Result<Expression, Error> {
+    let mut i = 0;
+    let e = expr::parse_expression(&mut i, &tokens.into())?;
+    if i < tokens.len() {
+        return Err(error(i, tokens, ErrorKind::ExpectStr(";".to_owned())));
+    }
+    Ok(e)
+}
+
+struct MissingBrace {
+    /// The `{` that is not closed
+    open: Span,
+    /// The token the `}` should be put before
+    insert_before: Span,
+}
+
+/// Guess which `{` is not closed by comparing the indentation of each brace
+/// pair, assuming the closing brace of a block is indented like the line its
+/// opening brace is on. The missing `}` is placed before the first line after
+/// the `{` that is not indented deeper than it. Returns `None` if there are
+/// as many `}` as `{`, however they are indented, or if the `}` is only
+/// missing at the end of the file.
+fn guess_missing_brace(tokens: &[Token]) -> Option<MissingBrace> {
+    let count = |kind| tokens.iter().filter(|t| t.kind == kind).count();
+    if count(TokenKind::LeftBrace) <= count(TokenKind::RightBrace) {
+        return None;
+    }
+    // The column of the first token on each line
+    let mut indents = std::collections::HashMap::new();
+    for t in tokens {
+        indents
+            .entry(t.span.start_pos.line)
+            .or_insert(t.span.start_pos.col);
+    }
+    let indent = |t: &Token| indents[&t.span.start_pos.line];
+    let mut open_braces = Vec::new();
+    let mut unclosed = None;
+    for (n, t) in tokens.iter().enumerate() {
+        match t.kind {
+            TokenKind::LeftBrace => open_braces.push(n),
+            TokenKind::RightBrace => {
+                let open = open_braces.pop()?;
+                if indent(&tokens[open]) != indent(t) {
+                    unclosed = Some(open);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let open = unclosed.or_else(|| open_braces.pop())?;
+    let open_line = tokens[open].span.start_pos.line;
+    let open_indent = indent(&tokens[open]);
+    let insert_before = tokens[open + 1..].iter().find(|t| {
+        t.span.start_pos.line != open_line
+            && t.span.start_pos.col == indent(t)
+            && indent(t) <= open_indent
+    })?;
+    Some(MissingBrace {
+        open: tokens[open].span,
+        insert_before: insert_before.span,
+    })
 }
 
 mod util {
@@ -18,9 +178,22 @@ mod util {
     // #[trace::trace]
     pub fn parse_left<const C: char>(
         i: &mut usize,
-        tokens: &[Token],
-    ) -> Result<Position, Error> {
-        let e = || error(*i, tokens, ErrorKind::ExpectStr(C.into()));
+        tokens: &Input,
+    ) -> Result<Span, Error> {
+        let e = || {
+            let mut e = error(*i, tokens, ErrorKind::ExpectStr(C.into()));
+            let fix_it = match tokens.get(*i) {
+                // A closing bracket where a statement should end has nothing
+                // to match with.
+                Some(Token {
+                    kind: TokenKind::RightParen | TokenKind::RightSqBracket,
+                    span,
+                }) if C == ';' => FixIt::delete(*span),
+                _ => FixIt::insert_after(e.span, C),
+            };
+            e.details.fix_its.push(fix_it);
+            e
+        };
         let token = tokens.get(*i).ok_or_else(e)?;
         let token_kind = if C == '(' {
             TokenKind::LeftParen
@@ -33,19 +206,16 @@ mod util {
             return Err(e());
         };
         *i += 1;
-        Ok(token.pos)
+        Ok(token.span)
     }
 
     // #[trace]
     pub fn parse_right<const C: char>(
         i: &mut usize,
-        tokens: &[Token],
-        left_pos: Position,
+        tokens: &Input,
+        left: Span,
     ) -> Result<(), Error> {
-        let e = || Error {
-            error_kind: ErrorKind::UnmatchedParenthesis(left_pos),
-            pos: left_pos,
-        };
+        let e = || unmatched(*i, tokens, left, C);
         let token = &tokens.get(*i).ok_or_else(e)?.kind;
         let k = if C == ')' {
             TokenKind::RightParen
@@ -56,20 +226,48 @@ mod util {
         } else {
             unreachable!()
         };
-        (&k == token)
-            .then(|| {
-                *i += 1;
-            })
-            .ok_or_else(e)
+        if &k != token {
+            return Err(e());
+        }
+        *i += 1;
+        Ok(())
+    }
+
+    /// The error for a missing `close` matching the bracket at `left`,
+    /// found at the token `i`.
+    pub fn unmatched(
+        i: usize,
+        tokens: &[Token],
+        left: Span,
+        close: char,
+    ) -> Error {
+        let last = tokens[i - 1].span;
+        let open = match close {
+            ')' => '(',
+            ']' => '[',
+            '}' => '{',
+            _ => unreachable!(),
+        };
+        Error {
+            span: tokens.get(i).map_or_else(|| last.after(), |t| t.span),
+            error_kind: ErrorKind::UnmatchedParenthesis(close),
+            details: Box::new(Details {
+                notes: vec![Note {
+                    span: left,
+                    message: format!("to match this '{open}'"),
+                }],
+                fix_its: vec![FixIt::insert_after(last, close)],
+            }),
+        }
     }
 
     pub fn parse_optional_list<
         const D: char,
         T,
-        F: Fn(&mut usize, &[Token]) -> Result<T, Error>,
+        F: Fn(&mut usize, &Input) -> Result<T, Error>,
     >(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
         first_item: T,
         parse: F,
     ) -> Result<Vec<T>, Error> {
@@ -100,21 +298,27 @@ mod util {
     pub fn parse_non_empty_list<
         const D: char,
         T,
-        F: Fn(&mut usize, &[Token]) -> Result<T, Error>,
+        F: Fn(&mut usize, &Input) -> Result<T, Error>,
     >(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
         parse: F,
     ) -> Result<Vec<T>, Error> {
         let first_item = parse(i, tokens)?;
         parse_optional_list::<D, _, _>(i, tokens, first_item, parse)
     }
+
+    /// The span from the token at `start` to the last consumed token.
+    pub fn span_since(start: usize, i: usize, tokens: &[Token]) -> Span {
+        tokens[start].span.to(tokens[i - 1].span)
+    }
 }
 
 fn parse_translation_unit(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<TranslationUnit, Error> {
+    let start = *i;
     let mut v = Vec::new();
     loop {
         if tokens.get(*i).is_none() {
@@ -122,8 +326,14 @@ fn parse_translation_unit(
         }
         v.push(parse_external_declaration(i, tokens)?);
     }
+    let span = if *i == start {
+        Span::default()
+    } else {
+        span_since(start, *i, tokens)
+    };
     Ok(TranslationUnit {
         external_declarations: v,
+        span,
     })
 }
 
@@ -133,25 +343,31 @@ fn parse_translation_unit(
 // DeclarationSpecifier (Declarator | Declarator = Initializer) {, InitDeclarator} ;
 fn parse_external_declaration(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<ExternalDeclaration, Error> {
+    let start = *i;
     let specifier = parse_declaration_specifier(i, tokens)?;
+    let declarator_start = *i;
     let declarator = parse_declarator(i, tokens)?;
     let token = tokens.get(*i).map(|t| &t.kind);
     match token {
         Some(TokenKind::LeftBrace) => {
             let compound_statement = stmt::parse_compound_statement(i, tokens)?;
-            Ok(ExternalDeclaration::FunctionDeclaration(
+            tokens.node(SyntaxKind::FunctionDefinition, start, *i);
+            Ok(ExternalDeclaration::FunctionDeclaration(Box::new(
                 FunctionDefinition {
                     declaration_specifier: specifier,
                     declarator,
                     compound_statement,
+                    span: span_since(start, *i, tokens),
                 },
-            ))
+            )))
         }
         Some(_) => {
             let initializer = parse_assign_initializer(token, i, tokens)?;
+            tokens.node(SyntaxKind::InitDeclarator, declarator_start, *i);
             let init_declarator = InitDeclarator {
+                span: declarator.span.to(tokens[*i - 1].span),
                 declarator,
                 initializer,
             };
@@ -163,13 +379,18 @@ fn parse_external_declaration(
                     parse_init_declarator,
                 )?);
             parse_left::<';'>(i, tokens)?;
+            tokens.node(SyntaxKind::Declaration, start, *i);
             Ok(ExternalDeclaration::Declaration(Declaration {
                 declaration_specifier: specifier,
                 init_declarator_list,
+                span: span_since(start, *i, tokens),
             }))
         }
         None => {
-            Err(error(*i, tokens, ErrorKind::ExpectStr("; or {".to_owned())))
+            let mut e =
+                error(*i, tokens, ErrorKind::ExpectStr("; or {".to_owned()));
+            e.details.fix_its.push(FixIt::insert_after(e.span, ';'));
+            Err(e)
         }
     }
 }
@@ -177,8 +398,9 @@ fn parse_external_declaration(
 // #[trace]
 fn parse_declaration(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<Declaration, Error> {
+    let start = *i;
     let declaration_specifier = parse_declaration_specifier(i, tokens)?;
     let init_declarator_list =
         InitDeclaratorList(parse_non_empty_list::<',', _, _>(
@@ -187,9 +409,11 @@ fn parse_declaration(
             parse_init_declarator,
         )?);
     parse_left::<';'>(i, tokens)?;
+    tokens.node(SyntaxKind::Declaration, start, *i);
     Ok(Declaration {
         declaration_specifier,
         init_declarator_list,
+        span: span_since(start, *i, tokens),
     })
 }
 
@@ -197,7 +421,7 @@ fn parse_declaration(
 fn parse_assign_initializer(
     t: Option<&TokenKind>,
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<Option<Initializer>, Error> {
     let initializer = if let Some(TokenKind::Relop(RelopKind::Assign)) = t {
         *i += 1;
@@ -210,32 +434,37 @@ fn parse_assign_initializer(
 
 fn parse_init_declarator(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<InitDeclarator, Error> {
+    let start = *i;
     let declarator = parse_declarator(i, tokens)?;
     let token = tokens.get(*i).map(|t| &t.kind);
     let initializer = parse_assign_initializer(token, i, tokens)?;
+    tokens.node(SyntaxKind::InitDeclarator, start, *i);
     Ok(InitDeclarator {
         declarator,
         initializer,
+        span: span_since(start, *i, tokens),
     })
 }
 
 fn parse_initializer(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<Initializer, Error> {
     let token = tokens.get(*i).map(|t| &t.kind);
     Ok(if let Some(TokenKind::LeftBrace) = token {
-        let left_pos = tokens[*i].pos;
+        let start = *i;
+        let left = tokens[*i].span;
         *i += 1;
         let list =
             parse_non_empty_list::<',', _, _>(i, tokens, parse_initializer)?;
-        parse_right::<'}'>(i, tokens, left_pos)?;
-        Initializer::List(list)
+        parse_right::<'}'>(i, tokens, left)?;
+        tokens.node(SyntaxKind::InitializerList, start, *i);
+        Initializer::List(list, span_since(start, *i, tokens))
     } else {
-        let expr = expr::parse_expression(i, tokens)?;
-        Initializer::Expression(expr)
+        let expr = expr::parse_assignment_expression(i, tokens)?;
+        Initializer::Expression(*expr)
     })
 }
 
@@ -243,8 +472,9 @@ fn parse_initializer(
 // Pointer DirectDeclarator | DirectDeclarator
 fn parse_declarator(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<Declarator, Error> {
+    let start = *i;
     let e = || error(*i, tokens, ErrorKind::ExpectDeclarator);
     let token = tokens.get(*i).ok_or_else(e)?;
     let pointer = if let TokenKind::Star = token.kind {
@@ -253,9 +483,11 @@ fn parse_declarator(
         0
     };
     let direct = parse_direct_declarator(i, tokens)?;
+    tokens.node(SyntaxKind::Declarator, start, *i);
     Ok(Declarator {
         pointer: Pointer(pointer),
         direct,
+        span: span_since(start, *i, tokens),
     })
 }
 
@@ -264,59 +496,68 @@ fn parse_declarator(
 // `(` Declarator `)` DirectDeclaratorModifiers
 fn parse_direct_declarator(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<DirectDeclarator, Error> {
+    let start = *i;
     let e = || error(*i, tokens, ErrorKind::ExpectDirectDeclarator);
     let token = tokens.get(*i).ok_or_else(e)?;
     let simple_declarator = match token.kind {
         TokenKind::LeftParen => {
-            let left_pos = token.pos;
+            let left = token.span;
             *i += 1;
             let s = SimpleDirectDeclarator::Declarator(Box::new(
                 parse_declarator(i, tokens)?,
             ));
-            parse_right::<')'>(i, tokens, left_pos)?;
+            parse_right::<')'>(i, tokens, left)?;
             s
         }
         TokenKind::Id(identifier) => {
             *i += 1;
-            SimpleDirectDeclarator::Identifier(identifier)
+            SimpleDirectDeclarator::Identifier(identifier, token.span)
         }
         _ => return Err(e()),
     };
 
     let modifiers = parse_direct_declarator_modifiers(i, tokens)?;
+    tokens.node(SyntaxKind::DirectDeclarator, start, *i);
 
     Ok(DirectDeclarator {
         simple_declarator,
         modifiers,
+        span: span_since(start, *i, tokens),
     })
 }
 
 // #[trace]
 fn parse_direct_declarator_modifiers(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<Vec<DirectDeclaratorModifier>, Error> {
     let mut ret = Vec::new();
     loop {
         match tokens.get(*i) {
             Some(t) => match t.kind {
                 TokenKind::LeftParen => {
-                    let left_pos = t.pos;
+                    let start = *i;
                     *i += 1;
                     let parameter_list = parse_parameter_list(i, tokens)?;
+                    parse_right::<')'>(i, tokens, t.span)?;
+                    tokens.node(SyntaxKind::FunctionModifier, start, *i);
                     ret.push(DirectDeclaratorModifier::Function(
                         parameter_list,
+                        span_since(start, *i, tokens),
                     ));
-                    parse_right::<')'>(i, tokens, left_pos)?;
                 }
                 TokenKind::LeftSqBracket => {
-                    let left_pos = t.pos;
+                    let start = *i;
                     *i += 1;
                     let integer_constant = parse_integer_constant(i, tokens)?;
-                    ret.push(DirectDeclaratorModifier::Array(integer_constant));
-                    parse_right::<']'>(i, tokens, left_pos)?;
+                    parse_right::<']'>(i, tokens, t.span)?;
+                    tokens.node(SyntaxKind::ArrayModifier, start, *i);
+                    ret.push(DirectDeclaratorModifier::Array(
+                        integer_constant,
+                        span_since(start, *i, tokens),
+                    ));
                 }
                 _ => return Ok(ret),
             },
@@ -327,7 +568,7 @@ fn parse_direct_declarator_modifiers(
 
 fn parse_integer_constant(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<usize, Error> {
     let e = || error(*i, tokens, ErrorKind::ExpectIntegerConstant);
     let token = tokens.get(*i).ok_or_else(e)?;
@@ -342,20 +583,23 @@ fn parse_integer_constant(
 // ParameterDeclaration {, ParameterDeclaration}
 fn parse_parameter_list(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<Vec<ParameterDeclaration>, Error> {
     parse_non_empty_list::<',', _, _>(i, tokens, parse_parameter_declaration)
 }
 
 fn parse_parameter_declaration(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<ParameterDeclaration, Error> {
+    let start = *i;
     let specifier = parse_declaration_specifier(i, tokens)?;
     let declarator = parse_declarator(i, tokens)?;
+    tokens.node(SyntaxKind::ParameterDeclaration, start, *i);
     Ok(ParameterDeclaration {
         specifier,
         declarator,
+        span: span_since(start, *i, tokens),
     })
 }
 
@@ -372,20 +616,21 @@ fn parse_pointer(i: &mut usize, tokens: &[Token]) -> usize {
 }
 
 fn error(i: usize, tokens: &[Token], kind: ErrorKind) -> Error {
-    let pos = if i == 0 {
-        Position { line: 1, col: 1 }
+    let span = if i == 0 {
+        Span {
+            start_pos: Position { line: 1, col: 1 },
+            end_pos: Position { line: 1, col: 1 },
+            ..Default::default()
+        }
     } else {
-        tokens[(i as u32 - 1) as usize].pos
+        tokens[(i as u32 - 1) as usize].span
     };
-    Error {
-        pos,
-        error_kind: kind,
-    }
+    Error::new(span, kind)
 }
 
 fn parse_declaration_specifier(
     i: &mut usize,
-    tokens: &[Token],
+    tokens: &Input,
 ) -> Result<DeclarationSpecifier, Error> {
     let e = || error(*i, tokens, ErrorKind::ExpectDeclarationSpecifier);
     let token = tokens.get(*i).ok_or_else(e)?;
@@ -398,11 +643,11 @@ fn parse_declaration_specifier(
         _ => return Err(e()),
     };
     *i += 1;
-    Ok(DeclarationSpecifier::TypeSpecifier(specifier))
+    tokens.node(SyntaxKind::DeclarationSpecifier, *i - 1, *i);
+    Ok(DeclarationSpecifier::TypeSpecifier(specifier, token.span))
 }
 
 mod stmt {
-    use std::assert_matches::assert_matches;
 
     use super::expr::*;
     use super::*;
@@ -410,8 +655,9 @@ mod stmt {
     // #[trace::trace]
     fn parse_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Statement, Error> {
+        let start = *i;
         let e = || error(*i, tokens, ErrorKind::ExpectStatement);
         let token = tokens.get(*i).ok_or_else(e)?;
         Ok(match token.kind {
@@ -425,20 +671,25 @@ mod stmt {
                 Statement::Iteration(parse_iteration_statement(i, tokens)?)
             }
             TokenKind::Continue | TokenKind::Break | TokenKind::Return => {
-                Statement::Jump(parse_jump_statement(i, tokens)?)
+                let jump = parse_jump_statement(i, tokens)?;
+                Statement::Jump(jump, span_since(start, *i, tokens))
+            }
+            _ => {
+                let expression = parse_expression_statement(i, tokens)?;
+                Statement::Expression(expression, span_since(start, *i, tokens))
             }
-            _ => Statement::Expression(parse_expression_statement(i, tokens)?),
         })
     }
     // #[trace::trace]
-    pub(crate) fn parse_compound_statement(
+    pub fn parse_compound_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<CompoundStatement, Error> {
         let e = || error(*i, tokens, ErrorKind::ExpectCompoundStatement);
         let token = tokens.get(*i).ok_or_else(e)?;
         if let TokenKind::LeftBrace = token.kind {
-            let left_pos = token.pos;
+            let start = *i;
+            let left = token.span;
             *i += 1;
             let mut list = vec![];
             loop {
@@ -448,24 +699,24 @@ mod stmt {
                     break;
                 } else {
                     if tokens.get(*i).is_none() {
-                        return Err(error(
-                            *i,
-                            tokens,
-                            ErrorKind::UnmatchedParenthesis(left_pos),
-                        ));
+                        return Err(unmatched(*i, tokens, left, '}'));
                     }
                     list.push(parse_block_item(i, tokens)?);
                 }
             }
-            Ok(CompoundStatement(list))
+            tokens.node(SyntaxKind::CompoundStatement, start, *i);
+            Ok(CompoundStatement {
+                block_items: list,
+                span: span_since(start, *i, tokens),
+            })
         } else {
             Err(e())
         }
     }
 
-    fn parse_block_item(
+    pub fn parse_block_item(
         i: &mut usize, // cannot get none
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<BlockItem, Error> {
         Ok(if let TokenKind::Qualifier(_) = &tokens[*i].kind {
             BlockItem::Declaration(parse_declaration(i, tokens)?)
@@ -477,30 +728,34 @@ mod stmt {
     // #[trace::trace]
     fn parse_expression_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Option<Expression>, Error> {
+        let start = *i;
         let token = tokens.get(*i).unwrap();
-        Ok(if let TokenKind::Semicolon = &token.kind {
+        let expression = if let TokenKind::Semicolon = &token.kind {
             *i += 1;
             None
         } else {
             let ret = Some(parse_expression(i, tokens)?);
             parse_left::<';'>(i, tokens)?;
             ret
-        })
+        };
+        tokens.node(SyntaxKind::ExpressionStatement, start, *i);
+        Ok(expression)
     }
 
     // #[trace::trace]
     fn parse_selection_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<SelectionStatement, Error> {
+        let start = *i;
         let token = tokens.get(*i).unwrap();
-        assert_matches!(token.kind, TokenKind::If);
+        assert!(matches!(token.kind, TokenKind::If));
         *i += 1;
-        let left_pos = parse_left::<'('>(i, tokens)?;
+        let left = parse_left::<'('>(i, tokens)?;
         let condition = parse_expression(i, tokens)?;
-        parse_right::<')'>(i, tokens, left_pos)?;
+        parse_right::<')'>(i, tokens, left)?;
         let consequent = Box::new(parse_statement(i, tokens)?);
         let token = tokens.get(*i);
         let alternative = if let Some(TokenKind::Else) = token.map(|t| &t.kind)
@@ -510,25 +765,27 @@ mod stmt {
         } else {
             None
         };
+        tokens.node(SyntaxKind::SelectionStatement, start, *i);
         Ok(SelectionStatement {
             condition,
             consequent,
             alternative,
+            span: span_since(start, *i, tokens),
         })
     }
 
     fn parse_iteration_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<IterationStatement, Error> {
         let token = tokens.get(*i).unwrap();
         Ok(match token.kind {
             TokenKind::While => {
                 IterationStatement::While(parse_while_statement(i, tokens)?)
             }
-            TokenKind::For => {
-                IterationStatement::For(parse_for_statement(i, tokens)?)
-            }
+            TokenKind::For => IterationStatement::For(Box::new(
+                parse_for_statement(i, tokens)?,
+            )),
             TokenKind::Do => {
                 IterationStatement::Do(parse_do_statement(i, tokens)?)
             }
@@ -540,76 +797,99 @@ mod stmt {
 
     fn parse_while_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<WhileStatement, Error> {
+        let start = *i;
         *i += 1;
-        let left_pos = parse_left::<'('>(i, tokens)?;
+        let left = parse_left::<'('>(i, tokens)?;
         let condition = parse_expression(i, tokens)?;
-        parse_right::<')'>(i, tokens, left_pos)?;
+        parse_right::<')'>(i, tokens, left)?;
         let body = Box::new(parse_statement(i, tokens)?);
-        Ok(WhileStatement { condition, body })
+        tokens.node(SyntaxKind::WhileStatement, start, *i);
+        Ok(WhileStatement {
+            condition,
+            body,
+            span: span_since(start, *i, tokens),
+        })
     }
 
     // #[trace::trace]
     fn parse_for_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<ForStatement, Error> {
+        let start = *i;
         *i += 1;
-        let left_pos = parse_left::<'('>(i, tokens)?;
+        let left = parse_left::<'('>(i, tokens)?;
         let e = || error(*i, tokens, ErrorKind::ExpectForInitialization);
         let token = tokens.get(*i).ok_or_else(e)?;
         let initialization = match &token.kind {
             TokenKind::Qualifier(_) => {
                 ForInitialization::Declaration(parse_declaration(i, tokens)?)
             }
-            _ => ForInitialization::Expression(parse_expression_statement(
-                i, tokens,
-            )?),
+            _ => {
+                let init_start = *i;
+                let expression = parse_expression_statement(i, tokens)?;
+                ForInitialization::Expression(
+                    expression,
+                    span_since(init_start, *i, tokens),
+                )
+            }
         };
         let condition = parse_expression_statement(i, tokens)?;
 
-        let e = || error(*i, tokens, ErrorKind::UnmatchedParenthesis(left_pos));
+        let e = || unmatched(*i, tokens, left, ')');
         let token = tokens.get(*i).ok_or_else(e)?;
         let update = if let TokenKind::RightParen = &token.kind {
             None
         } else {
             Some(parse_expression(i, tokens)?)
         };
-        parse_right::<')'>(i, tokens, left_pos)?;
+        parse_right::<')'>(i, tokens, left)?;
         let body = Box::new(parse_statement(i, tokens)?);
+        tokens.node(SyntaxKind::ForStatement, start, *i);
         Ok(ForStatement {
             initialization,
             condition,
             update,
             body,
+            span: span_since(start, *i, tokens),
         })
     }
 
     fn parse_do_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<DoStatement, Error> {
+        let start = *i;
         *i += 1;
         let body = Box::new(parse_statement(i, tokens)?);
         let e = || error(*i, tokens, ErrorKind::ExpectStr("while".to_owned()));
         let token = tokens.get(*i).ok_or_else(e)?;
         let TokenKind::While = &token.kind else {
-            return Err(e())
+            return Err(e());
         };
-        let left_pos = parse_left::<'('>(i, tokens)?;
+        *i += 1;
+        let left = parse_left::<'('>(i, tokens)?;
         let condition = parse_expression(i, tokens)?;
-        parse_right::<')'>(i, tokens, left_pos)?;
-        Ok(DoStatement { body, condition })
+        parse_right::<')'>(i, tokens, left)?;
+        parse_left::<';'>(i, tokens)?;
+        tokens.node(SyntaxKind::DoStatement, start, *i);
+        Ok(DoStatement {
+            body,
+            condition,
+            span: span_since(start, *i, tokens),
+        })
     }
 
     // #[trace::trace]
     fn parse_jump_statement(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<JumpStatement, Error> {
+        let start = *i;
         let token = tokens.get(*i).unwrap();
-        Ok(match &token.kind {
+        let jump = match &token.kind {
             TokenKind::Continue => {
                 *i += 1;
                 parse_left::<';'>(i, tokens)?;
@@ -625,7 +905,9 @@ mod stmt {
                 JumpStatement::Return(parse_expression_statement(i, tokens)?)
             }
             _ => unreachable!(),
-        })
+        };
+        tokens.node(SyntaxKind::JumpStatement, start, *i);
+        Ok(jump)
     }
 }
 
@@ -634,13 +916,14 @@ mod expr {
     use super::*;
 
     fn parse_left_associate_binary_expr<
-        F: Fn(&mut usize, &[Token]) -> Result<Box<Expression>, Error>,
+        F: Fn(&mut usize, &Input) -> Result<Box<Expression>, Error>,
     >(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
         operators: &'static [TokenKind],
         parse: F,
     ) -> Result<Box<Expression>, Error> {
+        let start = *i;
         let mut ret = parse(i, tokens)?;
         loop {
             let token = tokens.get(*i);
@@ -649,10 +932,12 @@ mod expr {
                     let operator: BinaryOperator = op.into();
                     *i += 1;
                     let right = parse(i, tokens)?;
+                    tokens.node(SyntaxKind::BinaryExpression, start, *i);
                     ret = Box::new(Expression::Binary(BinaryExpression {
                         operator,
                         left: ret,
                         right,
+                        span: span_since(start, *i, tokens),
                     }))
                 } else {
                     return Ok(ret);
@@ -664,9 +949,9 @@ mod expr {
     }
 
     // #[trace::trace]
-    pub(crate) fn parse_expression(
+    pub fn parse_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Expression, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -677,30 +962,34 @@ mod expr {
         .map(|e| *e)
     }
 
-    pub(crate) fn parse_assignment_expression(
+    pub fn parse_assignment_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         let i_saved = *i;
+        let mark = tokens.mark();
         let left = parse_unary_expression(i, tokens)?;
-        let e = || error(*i, tokens, ErrorKind::ExpectStr('='.into()));
-        let token = tokens.get(*i).ok_or_else(e)?; //todo
-        let TokenKind::Relop(RelopKind::Assign) = &token.kind else {
-                *i = i_saved;
-                return parse_logical_or_expression(i, tokens);
-            };
+        let Some(TokenKind::Relop(RelopKind::Assign)) =
+            tokens.get(*i).map(|t| &t.kind)
+        else {
+            *i = i_saved;
+            tokens.rollback(mark);
+            return parse_logical_or_expression(i, tokens);
+        };
         *i += 1;
         let right = parse_assignment_expression(i, tokens)?;
+        tokens.node(SyntaxKind::AssignmentExpression, i_saved, *i);
         Ok(Box::new(Expression::Assignment(AssignmentExpression {
             left,
             right,
+            span: span_since(i_saved, *i, tokens),
         })))
     }
 
     // #[trace::trace]
     fn parse_logical_or_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -712,7 +1001,7 @@ mod expr {
 
     fn parse_logical_and_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -724,7 +1013,7 @@ mod expr {
 
     fn parse_or_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -736,7 +1025,7 @@ mod expr {
 
     fn parse_and_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -748,7 +1037,7 @@ mod expr {
 
     fn parse_equality_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -764,7 +1053,7 @@ mod expr {
     // #[trace::trace]
     fn parse_relational_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -782,7 +1071,7 @@ mod expr {
     // #[trace::trace]
     fn parse_additive_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -794,7 +1083,7 @@ mod expr {
 
     fn parse_multipplicative_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         parse_left_associate_binary_expr(
             i,
@@ -818,19 +1107,22 @@ mod expr {
     }
 
     // #[trace::trace]
-    pub(crate) fn parse_unary_expression(
+    pub fn parse_unary_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         let e = || error(*i, tokens, ErrorKind::ExpectExpression);
         let token = tokens.get(*i).ok_or_else(e)?;
         if first_unary_operator(token) {
+            let start = *i;
             let operator: UnaryOperator = (&token.kind).into();
             *i += 1;
             let operand = parse_unary_expression(i, tokens)?;
+            tokens.node(SyntaxKind::UnaryExpression, start, *i);
             return Ok(Box::new(Expression::Unary(UnaryExpression {
                 operator,
                 operand,
+                span: span_since(start, *i, tokens),
             })));
         }
         parse_postfix_expression(i, tokens)
@@ -839,22 +1131,25 @@ mod expr {
     // #[trace::trace]
     fn parse_postfix_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
+        let start = *i;
         let mut ret = parse_primary_expression(i, tokens)?;
 
         loop {
             let token = tokens.get(*i);
-            let left_pos = token.map(|t| t.pos);
+            let left = token.map(|t| t.span);
             match token.map(|t| &t.kind) {
                 Some(TokenKind::LeftSqBracket) => {
                     *i += 1;
                     let subscript = Box::new(parse_expression(i, tokens)?);
+                    parse_right::<']'>(i, tokens, left.unwrap())?;
+                    tokens.node(SyntaxKind::SubscriptExpression, start, *i);
                     ret = Box::new(Expression::Postfix(PostfixExpression {
                         operand: ret,
                         postfix: PostfixExpressionPostfix::Subscript(subscript),
+                        span: span_since(start, *i, tokens),
                     }));
-                    parse_right::<']'>(i, tokens, left_pos.unwrap())?;
                 }
                 Some(TokenKind::LeftParen) => {
                     *i += 1;
@@ -862,19 +1157,23 @@ mod expr {
                     if let Some(TokenKind::RightParen) = token.map(|t| &t.kind)
                     {
                         *i += 1;
+                        tokens.node(SyntaxKind::CallExpression, start, *i);
                         ret =
                             Box::new(Expression::Postfix(PostfixExpression {
                                 operand: ret,
                                 postfix: PostfixExpressionPostfix::Call(vec![]),
+                                span: span_since(start, *i, tokens),
                             }));
                         continue;
                     }
                     let arguments = parse_argument_expression_list(i, tokens)?;
+                    parse_right::<')'>(i, tokens, left.unwrap())?;
+                    tokens.node(SyntaxKind::CallExpression, start, *i);
                     ret = Box::new(Expression::Postfix(PostfixExpression {
                         operand: ret,
                         postfix: PostfixExpressionPostfix::Call(arguments),
+                        span: span_since(start, *i, tokens),
                     }));
-                    parse_right::<')'>(i, tokens, left_pos.unwrap())?;
                 }
                 _ => return Ok(ret),
             }
@@ -883,7 +1182,7 @@ mod expr {
 
     fn parse_argument_expression_list(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Vec<Expression>, Error> {
         parse_non_empty_list::<',', _, _>(
             i,
@@ -896,32 +1195,36 @@ mod expr {
     // #[trace::trace]
     fn parse_primary_expression(
         i: &mut usize,
-        tokens: &[Token],
+        tokens: &Input,
     ) -> Result<Box<Expression>, Error> {
         let e = || error(*i, tokens, ErrorKind::ExpectExpression);
         let token = tokens.get(*i).ok_or_else(e)?;
-        Ok(Box::new(match &token.kind {
+        let start = *i;
+        let expression = match &token.kind {
             TokenKind::Id(id) => {
                 *i += 1;
-                Expression::Atom(AtomExpression::Identifier(*id))
+                Expression::Atom(AtomExpression::Identifier(*id), token.span)
             }
             TokenKind::IntegerConstant(n) => {
                 *i += 1;
-                Expression::Atom(AtomExpression::Integer(*n))
+                Expression::Atom(AtomExpression::Integer(*n), token.span)
             }
             TokenKind::FloatingConstant(n) => {
                 *i += 1;
-                Expression::Atom(AtomExpression::Floating(*n))
+                Expression::Atom(AtomExpression::Floating(*n), token.span)
             }
             TokenKind::LeftParen => {
                 *i += 1;
-                let left_pos = token.pos;
+                let left = token.span;
                 let expression = parse_expression(i, tokens)?;
-                parse_right::<')'>(i, tokens, left_pos)?;
-                expression
+                parse_right::<')'>(i, tokens, left)?;
+                tokens.node(SyntaxKind::ParenthesizedExpression, start, *i);
+                return Ok(Box::new(expression));
             }
             _ => return Err(e()),
-        }))
+        };
+        tokens.node(SyntaxKind::AtomExpression, start, *i);
+        Ok(Box::new(expression))
     }
 }
 
@@ -930,7 +1233,13 @@ mod test {
     use crate::{lexer::scan, preprocess::preprocess, token::Token};
     use anyhow::Result;
 
-    use super::parse_translation_unit;
+    use super::{parse, parse_translation_unit};
+    use crate::ast::{ExternalDeclaration, Statement};
+    use crate::diagnostic::apply_fix_its;
+    use crate::error::ErrorKind;
+    use crate::token::Span;
+    use test_case::test_case;
+
     fn str_to_tokens(s: &str) -> Vec<Token> {
         let s = preprocess(s.char_indices()).unwrap();
         scan(&s).unwrap().tokens
@@ -940,7 +1249,89 @@ mod test {
     fn test() -> Result<()> {
         let tokens = str_to_tokens("");
         let mut i = 0;
-        parse_translation_unit(&mut i, &tokens)?;
+        parse_translation_unit(&mut i, &tokens[..].into())?;
+        Ok(())
+    }
+    #[test]
+    fn test_span() -> Result<()> {
+        let src = "int f(int a)\n{\n    return a;\n}\nint b;";
+        let ast = parse(&str_to_tokens(src))?;
+        let text = |span: Span| &src[span.start..span.end];
+        assert_eq!(text(ast.span), src);
+        let ExternalDeclaration::FunctionDeclaration(f) =
+            &ast.external_declarations[0]
+        else {
+            panic!()
+        };
+        assert_eq!(text(f.declarator.span), "f(int a)");
+        let block_item = &f.compound_statement.block_items[0];
+        assert_eq!(text(block_item.span()), "return a;");
+        let crate::ast::BlockItem::Statement(Statement::Jump(_, span)) =
+            block_item
+        else {
+            panic!()
+        };
+        assert_eq!((span.start_pos.line, span.start_pos.col), (3, 5));
+        assert_eq!(text(ast.external_declarations[1].span()), "int b;");
+        Ok(())
+    }
+    #[test]
+    fn test_do() -> Result<()> {
+        let src =
+            "int f(int a)\n{\n    do a = a - 1; while (a);\n    return a;\n}";
+        let ast = parse(&str_to_tokens(src))?;
+        let ExternalDeclaration::FunctionDeclaration(f) =
+            &ast.external_declarations[0]
+        else {
+            panic!()
+        };
+        let text = |span: Span| &src[span.start..span.end];
+        let block_items = &f.compound_statement.block_items;
+        assert_eq!(block_items.len(), 2);
+        assert_eq!(text(block_items[0].span()), "do a = a - 1; while (a);");
         Ok(())
     }
+
+    #[test]
+    fn test_unmatched() {
+        let src = "int a[2] = {f(1, 2;";
+        let e = parse(&str_to_tokens(src)).unwrap_err();
+        assert_eq!(e.error_kind, ErrorKind::UnmatchedParenthesis(')'));
+        assert_eq!(&src[e.span.start..e.span.end], ";");
+        assert_eq!(e.details.notes.len(), 1);
+        assert_eq!(
+            &src[e.details.notes[0].span.start..e.details.notes[0].span.end],
+            "("
+        );
+        assert_eq!(e.details.notes[0].message, "to match this '('");
+    }
+
+    #[test_case(
+        "int f(int a)\n{\n    if (a) {\n        a = 1;\n    a = 2;\n}\n",
+        Some((5, 5))
+    )]
+    #[test_case(
+        "int f(int a)\n{\n    a = 1;\nint g(int b)\n{\n}\n",
+        Some((4, 1))
+    )]
+    #[test_case("int f(int a)\n{\n    a = 1;\n", None)]
+    #[test_case(
+        "int f(int a, int b)\n{\n    if (a > 0 &&\n        b > 0) {\n        \
+         a = 1;\n        }\n    return a\n}\n",
+        None
+    )]
+    fn test_missing_brace(src: &str, guess: Option<(usize, usize)>) {
+        let e = parse(&str_to_tokens(src)).unwrap_err();
+        let note = e
+            .details
+            .notes
+            .iter()
+            .find(|n| n.message.contains("most likely"));
+        assert_eq!(
+            note.map(|n| (n.span.start_pos.line, n.span.start_pos.col)),
+            guess
+        );
+        let fixed = apply_fix_its(src, &e.details.fix_its);
+        parse(&str_to_tokens(&fixed)).unwrap();
+    }
 }
@@ -1,36 +1,96 @@
+//! The `.lexeroutput` file format.
+//!
+//! All integers are little-endian. A file is a 64-byte header followed by
+//! three sections, in order and without gaps:
+//!
+//! | bytes  | header field                                              |
+//! |--------|-----------------------------------------------------------|
+//! | 0..8   | magic number `MINICLEX`                                   |
+//! | 8..12  | format version, `u32`                                     |
+//! | 12..16 | reserved, written as zero and ignored                     |
+//! | 16..64 | `offset` and `len` as `u64`s of the token, identifier and text sections |
+//!
+//! A token is 8 `u64`s: the discriminant of its [`TokenKind`], the value of
+//! the kind (an integer, the bits of a double, an identifier index or the
+//! discriminant of a [`QualifierKind`] or [`RelopKind`]), then `start`,
+//! `end`, `start_pos.line`, `start_pos.col`, `end_pos.line` and
+//! `end_pos.col` of its span. An identifier is `text_begin` and `text_len`
+//! as `u64`s. The text is UTF-8 and `len` counts bytes.
+
 use std::{
+    fs::File,
     io::{Read, Write},
-    mem::{size_of, transmute, MaybeUninit},
-    slice,
+    mem::size_of,
 };
 
 use anyhow::Result;
+use memmap2::Mmap;
 
 use crate::{
     lexer::LexerOutput,
-    token::{Identifier, TokenKind},
+    token::{
+        Identifier, Position, QualifierKind, RelopKind, Span, Token, TokenKind,
+    },
 };
 
-#[repr(C)]
-struct Section {
-    offset: usize,
-    len: usize,
+pub const MAGIC: &[u8; 8] = b"MINICLEX";
+pub const VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 64;
+pub const TOKEN_LEN: usize = 64;
+pub const IDENTIFIER_LEN: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FormatError {
+    #[error("cannot read lexer output")]
+    Io(#[from] std::io::Error),
+    #[error("not a lexer output file")]
+    BadMagic,
+    #[error("unsupported format version {0}, expect {VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("file is {actual} bytes long, expect {expected}")]
+    LengthMismatch { expected: u64, actual: u64 },
+    #[error("{section} section starts at byte {found}, expect {expected}")]
+    MisplacedSection {
+        section: &'static str,
+        expected: u64,
+        found: u64,
+    },
+    #[error("{0} does not fit in memory")]
+    TooLarge(u64),
+    #[error("token {index} has unknown kind {tag}")]
+    InvalidTokenKind { index: usize, tag: u64 },
+    #[error("token {index} has invalid value {value:#x} for its kind")]
+    InvalidTokenValue { index: usize, value: u64 },
+    #[error("token {index} refers to identifier {id}, which does not exist")]
+    DanglingIdentifier { index: usize, id: usize },
+    #[error("token {index} has a span that ends before it starts")]
+    InvalidSpan { index: usize },
+    #[error("identifier {index} is not within the text or splits a character")]
+    InvalidIdentifier { index: usize },
+    #[error("text is not valid UTF-8")]
+    InvalidUtf8(#[from] std::str::Utf8Error),
 }
 
-#[repr(C)]
-struct Header {
-    token: Section,
-    id: Section,
-    text: Section,
+/// Where a section is in the file. `len` counts records, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Section {
+    pub offset: usize,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub token: Section,
+    pub id: Section,
+    pub text: Section,
 }
 
 impl Header {
     fn new(lexer_output: &LexerOutput) -> Self {
-        let token_offset = size_of::<Header>();
-        let id_offset =
-            token_offset + lexer_output.tokens.len() * size_of::<TokenKind>();
+        let token_offset = HEADER_LEN;
+        let id_offset = token_offset + lexer_output.tokens.len() * TOKEN_LEN;
         let text_offset =
-            id_offset + lexer_output.id_table.len() * size_of::<Identifier>();
+            id_offset + lexer_output.id_table.len() * IDENTIFIER_LEN;
         Header {
             token: Section {
                 offset: token_offset,
@@ -46,100 +106,465 @@ impl Header {
             },
         }
     }
+
+    fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0; HEADER_LEN];
+        buf[..8].copy_from_slice(MAGIC);
+        buf[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        let sections = [self.token, self.id, self.text];
+        for (i, s) in sections.iter().enumerate() {
+            put_u64(&mut buf[16 + 16 * i..], s.offset);
+            put_u64(&mut buf[24 + 16 * i..], s.len);
+        }
+        buf
+    }
+
+    /// Read the header of `file` and check that the sections exactly cover
+    /// the rest of it.
+    pub fn decode(file: &[u8]) -> Result<Self, FormatError> {
+        if file.len() < 12 || &file[..8] != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        let version = u32::from_le_bytes(file[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+        if file.len() < HEADER_LEN {
+            return Err(FormatError::LengthMismatch {
+                expected: HEADER_LEN as u64,
+                actual: file.len() as u64,
+            });
+        }
+        let mut expected = HEADER_LEN as u64;
+        let mut sections = [Section { offset: 0, len: 0 }; 3];
+        for (i, (name, record_len)) in [
+            ("token", TOKEN_LEN),
+            ("identifier", IDENTIFIER_LEN),
+            ("text", 1),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let offset = get_u64(&file[16 + 16 * i..]);
+            let len = get_u64(&file[24 + 16 * i..]);
+            if offset != expected {
+                return Err(FormatError::MisplacedSection {
+                    section: name,
+                    expected,
+                    found: offset,
+                });
+            }
+            let byte_len = len
+                .checked_mul(record_len as u64)
+                .ok_or(FormatError::TooLarge(len))?;
+            expected = offset
+                .checked_add(byte_len)
+                .ok_or(FormatError::TooLarge(byte_len))?;
+            sections[i] = Section {
+                offset: to_usize(offset)?,
+                len: to_usize(len)?,
+            };
+        }
+        if expected != file.len() as u64 {
+            return Err(FormatError::LengthMismatch {
+                expected,
+                actual: file.len() as u64,
+            });
+        }
+        let [token, id, text] = sections;
+        Ok(Header { token, id, text })
+    }
 }
 
-fn write_slice<T>(w: &mut impl Write, t: &[T]) -> Result<()>
-where
-    T: Sized,
-{
-    let ptr: *const u8 = t.as_ptr() as *const u8;
-    let len = size_of::<T>() * t.len();
-    let buf = unsafe { slice::from_raw_parts(ptr, len) };
-    w.write_all(buf)?;
-    Ok(())
+fn put_u64(buf: &mut [u8], x: usize) {
+    buf[..8].copy_from_slice(&(x as u64).to_le_bytes());
+}
+
+fn get_u64(buf: &[u8]) -> u64 {
+    u64::from_le_bytes(buf[..8].try_into().unwrap())
+}
+
+fn to_usize(x: u64) -> Result<usize, FormatError> {
+    usize::try_from(x).map_err(|_| FormatError::TooLarge(x))
+}
+
+/// A token as laid out in the file. It is only made of bytes, so a
+/// validated file can be viewed as a slice of records without copying.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TokenRecord {
+    tag: [u8; 8],
+    value: [u8; 8],
+    span: [[u8; 8]; 6],
+}
+
+/// An identifier as laid out in the file.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct IdentifierRecord {
+    text_begin: [u8; 8],
+    text_len: [u8; 8],
+}
+
+const _: () = assert!(size_of::<TokenRecord>() == TOKEN_LEN);
+const _: () = assert!(size_of::<IdentifierRecord>() == IDENTIFIER_LEN);
+
+fn to_le(x: usize) -> [u8; 8] {
+    (x as u64).to_le_bytes()
+}
+
+impl TokenRecord {
+    pub fn new(token: &Token) -> Self {
+        let (tag, value): (u64, u64) = match &token.kind {
+            TokenKind::IntegerConstant(x) => (0, (*x).into()),
+            TokenKind::FloatingConstant(x) => (1, x.to_bits()),
+            TokenKind::Id(id) => (2, *id as u64),
+            TokenKind::If => (3, 0),
+            TokenKind::Else => (4, 0),
+            TokenKind::While => (5, 0),
+            TokenKind::For => (6, 0),
+            TokenKind::Do => (7, 0),
+            TokenKind::Qualifier(q) => (8, q.clone() as u64),
+            TokenKind::Plus => (9, 0),
+            TokenKind::Minus => (10, 0),
+            TokenKind::Star => (11, 0),
+            TokenKind::Divide => (12, 0),
+            TokenKind::Relop(r) => (13, r.clone() as u64),
+            TokenKind::LeftBrace => (14, 0),
+            TokenKind::RightBrace => (15, 0),
+            TokenKind::LeftParen => (16, 0),
+            TokenKind::RightParen => (17, 0),
+            TokenKind::LeftSqBracket => (18, 0),
+            TokenKind::RightSqBracket => (19, 0),
+            TokenKind::Semicolon => (20, 0),
+            TokenKind::Comma => (21, 0),
+            TokenKind::Not => (22, 0),
+            TokenKind::Return => (23, 0),
+            TokenKind::And => (24, 0),
+            TokenKind::Or => (25, 0),
+            TokenKind::BitAnd => (26, 0),
+            TokenKind::BitOr => (27, 0),
+            TokenKind::BitNot => (28, 0),
+            TokenKind::Continue => (29, 0),
+            TokenKind::Break => (30, 0),
+        };
+        let Span {
+            start,
+            end,
+            start_pos,
+            end_pos,
+        } = token.span;
+        TokenRecord {
+            tag: tag.to_le_bytes(),
+            value: value.to_le_bytes(),
+            span: [
+                start,
+                end,
+                start_pos.line,
+                start_pos.col,
+                end_pos.line,
+                end_pos.col,
+            ]
+            .map(to_le),
+        }
+    }
+
+    /// Decode the record of the `index`th token. Identifier indices are not
+    /// checked against the identifier table.
+    pub fn decode(&self, index: usize) -> Result<Token, FormatError> {
+        let tag = u64::from_le_bytes(self.tag);
+        let value = u64::from_le_bytes(self.value);
+        let invalid_value = FormatError::InvalidTokenValue { index, value };
+        // Only constants, identifiers and kinds with a subkind have a value.
+        let no_value = |kind: TokenKind| {
+            if value == 0 {
+                Ok(kind)
+            } else {
+                Err(FormatError::InvalidTokenValue { index, value })
+            }
+        };
+        let kind = match tag {
+            0 => TokenKind::IntegerConstant(
+                u32::try_from(value).map_err(|_| invalid_value)?,
+            ),
+            1 => TokenKind::FloatingConstant(f64::from_bits(value)),
+            2 => TokenKind::Id(to_usize(value)?),
+            3 => no_value(TokenKind::If)?,
+            4 => no_value(TokenKind::Else)?,
+            5 => no_value(TokenKind::While)?,
+            6 => no_value(TokenKind::For)?,
+            7 => no_value(TokenKind::Do)?,
+            8 => TokenKind::Qualifier(match value {
+                0 => QualifierKind::Void,
+                1 => QualifierKind::Int,
+                2 => QualifierKind::Double,
+                _ => return Err(invalid_value),
+            }),
+            9 => no_value(TokenKind::Plus)?,
+            10 => no_value(TokenKind::Minus)?,
+            11 => no_value(TokenKind::Star)?,
+            12 => no_value(TokenKind::Divide)?,
+            13 => TokenKind::Relop(match value {
+                0 => RelopKind::Assign,
+                1 => RelopKind::Gt,
+                2 => RelopKind::Lt,
+                3 => RelopKind::Ge,
+                4 => RelopKind::Le,
+                5 => RelopKind::Neq,
+                6 => RelopKind::Eq,
+                _ => return Err(invalid_value),
+            }),
+            14 => no_value(TokenKind::LeftBrace)?,
+            15 => no_value(TokenKind::RightBrace)?,
+            16 => no_value(TokenKind::LeftParen)?,
+            17 => no_value(TokenKind::RightParen)?,
+            18 => no_value(TokenKind::LeftSqBracket)?,
+            19 => no_value(TokenKind::RightSqBracket)?,
+            20 => no_value(TokenKind::Semicolon)?,
+            21 => no_value(TokenKind::Comma)?,
+            22 => no_value(TokenKind::Not)?,
+            23 => no_value(TokenKind::Return)?,
+            24 => no_value(TokenKind::And)?,
+            25 => no_value(TokenKind::Or)?,
+            26 => no_value(TokenKind::BitAnd)?,
+            27 => no_value(TokenKind::BitOr)?,
+            28 => no_value(TokenKind::BitNot)?,
+            29 => no_value(TokenKind::Continue)?,
+            30 => no_value(TokenKind::Break)?,
+            _ => return Err(FormatError::InvalidTokenKind { index, tag }),
+        };
+        let field = |i: usize| to_usize(u64::from_le_bytes(self.span[i]));
+        let span = Span {
+            start: field(0)?,
+            end: field(1)?,
+            start_pos: Position {
+                line: field(2)?,
+                col: field(3)?,
+            },
+            end_pos: Position {
+                line: field(4)?,
+                col: field(5)?,
+            },
+        };
+        if span.end < span.start {
+            return Err(FormatError::InvalidSpan { index });
+        }
+        Ok(Token { kind, span })
+    }
+}
+
+impl IdentifierRecord {
+    pub fn new(id: &Identifier) -> Self {
+        IdentifierRecord {
+            text_begin: to_le(id.text_begin),
+            text_len: to_le(id.text_len),
+        }
+    }
+
+    /// Decode the record of the `index`th identifier and check that it is a
+    /// string in `text`.
+    pub fn decode(
+        &self,
+        index: usize,
+        text: &str,
+    ) -> Result<Identifier, FormatError> {
+        let text_begin = to_usize(u64::from_le_bytes(self.text_begin))?;
+        let text_len = to_usize(u64::from_le_bytes(self.text_len))?;
+        match text_begin.checked_add(text_len) {
+            Some(end) if text.get(text_begin..end).is_some() => {
+                Ok(Identifier {
+                    text_begin,
+                    text_len,
+                })
+            }
+            _ => Err(FormatError::InvalidIdentifier { index }),
+        }
+    }
 }
 
 pub fn output(w: &mut impl Write, lexer_output: &LexerOutput) -> Result<()> {
-    let header = Header::new(lexer_output);
-    let header: [u8; size_of::<Header>()] = unsafe { transmute(header) };
-    w.write_all(&header)?;
-    write_slice(w, &lexer_output.tokens)?;
-    write_slice(w, &lexer_output.id_table)?;
-    write_slice(w, lexer_output.text.as_bytes())?;
+    w.write_all(&Header::new(lexer_output).encode())?;
+    for token in &lexer_output.tokens {
+        w.write_all(bytemuck::bytes_of(&TokenRecord::new(token)))?;
+    }
+    for id in &lexer_output.id_table {
+        w.write_all(bytemuck::bytes_of(&IdentifierRecord::new(id)))?;
+    }
+    w.write_all(lexer_output.text.as_bytes())?;
     Ok(())
 }
 
-#[allow(clippy::uninit_assumed_init)]
-fn load_struct<T>(r: &mut impl Read) -> std::result::Result<T, std::io::Error> {
-    let mut s: T = unsafe { MaybeUninit::uninit().assume_init() };
-    unsafe {
-        let buffer: &mut [u8] = std::slice::from_raw_parts_mut(
-            &mut s as *mut T as *mut u8,
-            size_of::<T>(),
-        );
-        r.read_exact(buffer)?;
-    };
-    Ok(s)
+/// A validated lexer output file, borrowed instead of decoded. Tokens and
+/// identifiers are decoded on access, which cannot fail.
+#[derive(Debug, Clone, Copy)]
+pub struct LexerOutputView<'a> {
+    tokens: &'a [TokenRecord],
+    id_table: &'a [IdentifierRecord],
+    text: &'a str,
 }
 
-fn load_slice<T>(
-    r: &mut impl Read,
-    len: usize,
-) -> std::result::Result<Box<[T]>, std::io::Error> {
-    let buf: Box<[MaybeUninit<T>]> = Box::new_uninit_slice(len);
-    unsafe {
-        let byte_buf: &mut [u8] = std::slice::from_raw_parts_mut(
-            buf.as_ptr() as *mut u8,
-            len * size_of::<T>(),
-        );
-        r.read_exact(byte_buf)?;
-        Ok(buf.assume_init())
+impl<'a> LexerOutputView<'a> {
+    /// Validate `file` without copying anything out of it.
+    pub fn new(file: &'a [u8]) -> Result<Self, FormatError> {
+        Self::validate(file).map(|(_, view)| view)
+    }
+
+    fn validate(file: &'a [u8]) -> Result<(Header, Self), FormatError> {
+        let header = Header::decode(file)?;
+        let view = Self::split(file, &header)?;
+        for (index, id) in view.id_table.iter().enumerate() {
+            id.decode(index, view.text)?;
+        }
+        for (index, token) in view.tokens.iter().enumerate() {
+            if let TokenKind::Id(id) = token.decode(index)?.kind {
+                if id >= view.id_table.len() {
+                    return Err(FormatError::DanglingIdentifier { index, id });
+                }
+            }
+        }
+        Ok((header, view))
+    }
+
+    /// Split `file` into its sections, only checking that the text is UTF-8.
+    fn split(file: &'a [u8], header: &Header) -> Result<Self, FormatError> {
+        let (tokens, id_table, text) = Self::sections(file, header);
+        Ok(LexerOutputView {
+            tokens,
+            id_table,
+            text: std::str::from_utf8(text)?,
+        })
+    }
+
+    fn sections(
+        file: &'a [u8],
+        header: &Header,
+    ) -> (&'a [TokenRecord], &'a [IdentifierRecord], &'a [u8]) {
+        let section = |s: Section, record_len: usize| {
+            &file[s.offset..s.offset + s.len * record_len]
+        };
+        (
+            bytemuck::cast_slice(section(header.token, TOKEN_LEN)),
+            bytemuck::cast_slice(section(header.id, IDENTIFIER_LEN)),
+            section(header.text, 1),
+        )
     }
+
+    pub fn tokens(&self) -> &'a [TokenRecord] {
+        self.tokens
+    }
+
+    pub fn id_table(&self) -> &'a [IdentifierRecord] {
+        self.id_table
+    }
+
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    pub fn token(&self, index: usize) -> Token {
+        self.tokens[index].decode(index).expect("validated in new")
+    }
+
+    pub fn identifier(&self, index: usize) -> Identifier {
+        self.id_table[index]
+            .decode(index, self.text)
+            .expect("validated in new")
+    }
+
+    pub fn to_lexer_output(self) -> LexerOutput {
+        LexerOutput {
+            tokens: (0..self.tokens().len()).map(|i| self.token(i)).collect(),
+            id_table: (0..self.id_table().len())
+                .map(|i| self.identifier(i))
+                .collect(),
+            text: self.text().to_owned(),
+        }
+    }
+}
+
+/// A lexer output file mapped into memory and validated once.
+#[derive(Debug)]
+pub struct MappedLexerOutput {
+    mmap: Mmap,
+    header: Header,
 }
 
-fn load_vec<T>(
-    r: &mut impl Read,
-    len: usize,
-) -> std::result::Result<Vec<T>, std::io::Error> {
-    load_slice(r, len).map(Vec::from)
+impl MappedLexerOutput {
+    pub fn open(file: &File) -> Result<Self, FormatError> {
+        // SAFETY: the map is read-only. Like every mmap, it is undefined
+        // behaviour for another process to truncate or modify the file
+        // while it is mapped, which we cannot prevent.
+        let mmap = unsafe { Mmap::map(file)? };
+        let (header, _) = LexerOutputView::validate(&mmap)?;
+        Ok(MappedLexerOutput { mmap, header })
+    }
+
+    pub fn view(&self) -> LexerOutputView<'_> {
+        let (tokens, id_table, text) =
+            LexerOutputView::sections(&self.mmap, &self.header);
+        LexerOutputView {
+            tokens,
+            id_table,
+            // SAFETY: `open` checked that the text section is UTF-8, and the
+            // map is not modified afterwards.
+            text: unsafe { std::str::from_utf8_unchecked(text) },
+        }
+    }
 }
 
 impl LexerOutput {
-    pub fn try_from(mut r: impl Read) -> Result<Self> {
-        let header: Header = load_struct(&mut r)?;
-        let lexer_output = LexerOutput {
-            tokens: load_vec(&mut r, header.token.len)?,
-            id_table: load_vec(&mut r, header.id.len)?,
-            text: String::from_utf8(load_vec::<u8>(&mut r, header.text.len)?)?,
-        };
-        Ok(lexer_output)
+    pub fn try_from(mut r: impl Read) -> Result<Self, FormatError> {
+        let mut file = Vec::new();
+        r.read_to_end(&mut file)?;
+        Self::decode(&file)
+    }
+
+    /// Decode and validate a lexer output file.
+    pub fn decode(file: &[u8]) -> Result<Self, FormatError> {
+        LexerOutputView::new(file).map(|view| view.to_lexer_output())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::io::BufWriter;
+    use std::{
+        fs::{self, File},
+        io::BufWriter,
+        path::PathBuf,
+        process,
+    };
+
+    use test_case::test_case;
 
     use crate::{
-        lexer::LexerOutput,
-        token::{Identifier, Position, Token, TokenKind},
+        lexer::{scan, LexerOutput},
+        token::{Identifier, Position, Span, Token, TokenKind},
     };
 
-    use super::output;
+    use super::*;
 
     use anyhow::Result;
 
-    #[test]
-    fn test_output() -> Result<()> {
-        let lexer_output = LexerOutput {
+    fn lexer_output() -> LexerOutput {
+        LexerOutput {
             tokens: vec![
                 Token {
                     kind: TokenKind::Id(0),
-                    pos: Position { line: 1, col: 1 },
+                    span: Span {
+                        start: 0,
+                        end: 3,
+                        start_pos: Position { line: 1, col: 1 },
+                        end_pos: Position { line: 1, col: 4 },
+                    },
                 },
                 Token {
                     kind: TokenKind::Else,
-                    pos: Position { line: 1, col: 5 },
+                    span: Span {
+                        start: 4,
+                        end: 8,
+                        start_pos: Position { line: 1, col: 5 },
+                        end_pos: Position { line: 1, col: 9 },
+                    },
                 },
             ],
             id_table: vec![Identifier {
@@ -147,12 +572,253 @@ mod test {
                 text_len: 3,
             }],
             text: "abc".to_owned(),
-        };
+        }
+    }
+
+    fn encode(lexer_output: &LexerOutput) -> Vec<u8> {
         let mut w = BufWriter::new(Vec::new());
-        output(&mut w, &lexer_output)?;
-        let v = w.into_inner()?;
+        output(&mut w, lexer_output).unwrap();
+        w.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_output() -> Result<()> {
+        let lexer_output = lexer_output();
+        let v = encode(&lexer_output);
         let lexer_output_read = LexerOutput::try_from(v.as_slice())?;
         assert_eq!(lexer_output, lexer_output_read);
         Ok(())
     }
+
+    #[test]
+    fn test_output_all_tokens() -> Result<()> {
+        let lexer_output = scan(
+            "int a; double b = 1.5e3; void f(int c) { if (a >= 1 && b != 2) \
+             while (!c) { c = -c * 2 / 3 + 4 - (~5 | 6 & 7); } else do \
+             continue; for (;;) { break; } return a[0] || a < 1 > 2 <= 3 == 4; }",
+        )?;
+        let lexer_output_read =
+            LexerOutput::try_from(encode(&lexer_output).as_slice())?;
+        assert_eq!(lexer_output, lexer_output_read);
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout() {
+        let v = encode(&lexer_output());
+        assert_eq!(&v[..12], b"MINICLEX\x01\0\0\0");
+        assert_eq!(v.len(), HEADER_LEN + 2 * TOKEN_LEN + IDENTIFIER_LEN + 3);
+        // The `Else` token, little-endian
+        assert_eq!(v[HEADER_LEN + TOKEN_LEN..][..8], [4, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&v[v.len() - 3..], b"abc");
+    }
+
+    const TOKEN_1: usize = HEADER_LEN + TOKEN_LEN;
+    const ID_0: usize = HEADER_LEN + 2 * TOKEN_LEN;
+    const TEXT: usize = ID_0 + IDENTIFIER_LEN;
+
+    fn corrupt(at: usize, bytes: &[u8]) -> Result<LexerOutput, FormatError> {
+        let mut v = encode(&lexer_output());
+        v[at..at + bytes.len()].copy_from_slice(bytes);
+        LexerOutput::decode(&v)
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        assert!(matches!(
+            corrupt(0, b"MINICLEY"),
+            Err(FormatError::BadMagic)
+        ));
+        assert!(matches!(
+            LexerOutput::decode(b"MINIC"),
+            Err(FormatError::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        assert!(matches!(
+            corrupt(8, &2u32.to_le_bytes()),
+            Err(FormatError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let v = encode(&lexer_output());
+        assert!(matches!(
+            LexerOutput::decode(&v[..v.len() - 1]),
+            Err(FormatError::LengthMismatch { .. })
+        ));
+        assert!(matches!(
+            LexerOutput::decode(&v[..HEADER_LEN - 1]),
+            Err(FormatError::LengthMismatch { .. })
+        ));
+        let mut v = v;
+        v.push(0);
+        assert!(matches!(
+            LexerOutput::decode(&v),
+            Err(FormatError::LengthMismatch { .. })
+        ));
+    }
+
+    #[test_case(16, "token")]
+    #[test_case(32, "identifier")]
+    #[test_case(48, "text")]
+    fn test_misplaced_section(at: usize, name: &str) {
+        assert!(matches!(
+            corrupt(at, &1u64.to_le_bytes()),
+            Err(FormatError::MisplacedSection { section, .. }) if section == name
+        ));
+    }
+
+    #[test]
+    fn test_too_large() {
+        assert!(matches!(
+            corrupt(24, &u64::MAX.to_le_bytes()),
+            Err(FormatError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_token_kind() {
+        assert!(matches!(
+            corrupt(TOKEN_1, &31u64.to_le_bytes()),
+            Err(FormatError::InvalidTokenKind { index: 1, tag: 31 })
+        ));
+    }
+
+    #[test_case(4, 1; "value for a kind without one")]
+    #[test_case(8, 3; "unknown qualifier")]
+    #[test_case(13, 7; "unknown relop")]
+    #[test_case(0, 1 << 32; "integer overflow")]
+    fn test_invalid_token_value(tag: u64, value: u64) {
+        let mut bytes = tag.to_le_bytes().to_vec();
+        bytes.extend(value.to_le_bytes());
+        assert!(matches!(
+            corrupt(TOKEN_1, &bytes),
+            Err(FormatError::InvalidTokenValue { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_dangling_identifier() {
+        assert!(matches!(
+            corrupt(HEADER_LEN + 8, &1u64.to_le_bytes()),
+            Err(FormatError::DanglingIdentifier { index: 0, id: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_span() {
+        assert!(matches!(
+            corrupt(TOKEN_1 + 16, &9u64.to_le_bytes()),
+            Err(FormatError::InvalidSpan { index: 1 })
+        ));
+    }
+
+    #[test_case(0, 4; "past the end")]
+    #[test_case(u64::MAX, 2; "overflow")]
+    fn test_invalid_identifier(text_begin: u64, text_len: u64) {
+        let mut bytes = text_begin.to_le_bytes().to_vec();
+        bytes.extend(text_len.to_le_bytes());
+        assert!(matches!(
+            corrupt(ID_0, &bytes),
+            Err(FormatError::InvalidIdentifier { index: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        assert!(matches!(
+            corrupt(TEXT, &[0xff]),
+            Err(FormatError::InvalidUtf8(_))
+        ));
+    }
+
+    /// A file in the temporary directory, removed on drop.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("minic-{}-{name}.lexeroutput", process::id()));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_mapped() -> Result<()> {
+        let lexer_output = lexer_output();
+        let file = TempFile::new("mapped", &encode(&lexer_output));
+        let mapped = MappedLexerOutput::open(&File::open(&file.0)?)?;
+        let view = mapped.view();
+        assert_eq!(view.tokens().len(), 2);
+        assert_eq!(view.id_table().len(), 1);
+        assert_eq!(view.text(), "abc");
+        assert_eq!(view.token(1), lexer_output.tokens[1]);
+        assert_eq!(view.to_lexer_output(), lexer_output);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mapped_corrupted() -> Result<()> {
+        let mut v = encode(&lexer_output());
+        v[TOKEN_1] = 31;
+        let file = TempFile::new("mapped-corrupted", &v);
+        assert!(matches!(
+            MappedLexerOutput::open(&File::open(&file.0)?),
+            Err(FormatError::InvalidTokenKind { index: 1, tag: 31 })
+        ));
+        Ok(())
+    }
+
+    /// Benchmarks, which need a nightly compiler
+    #[cfg(feature = "nightly")]
+    mod bench {
+        extern crate test;
+
+        use std::hint::black_box;
+
+        use test::Bencher;
+
+        use super::*;
+
+        /// About a million tokens
+        fn big_file(name: &str) -> TempFile {
+            let src = include_str!("../testcase/c/2.c").repeat(400);
+            let src =
+                crate::preprocess::preprocess(src.char_indices()).unwrap();
+            TempFile::new(name, &encode(&scan(&src).unwrap()))
+        }
+
+        #[bench]
+        fn bench_load_owned(b: &mut Bencher) {
+            let file = big_file("bench-owned");
+            b.iter(|| {
+                let lexer_output =
+                    LexerOutput::try_from(File::open(&file.0).unwrap())
+                        .unwrap();
+                black_box(lexer_output.tokens.len())
+            });
+        }
+
+        #[bench]
+        fn bench_load_mapped(b: &mut Bencher) {
+            let file = big_file("bench-mapped");
+            b.iter(|| {
+                let mapped =
+                    MappedLexerOutput::open(&File::open(&file.0).unwrap())
+                        .unwrap();
+                black_box(mapped.view().tokens().len())
+            });
+        }
+    }
 }
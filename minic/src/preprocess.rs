@@ -1,36 +1,80 @@
-use crate::token::Position;
+use std::ops::Range;
 
+use crate::{
+    diagnostic::FixIt,
+    error::{Error, ErrorKind},
+    token::{Position, Span},
+};
+
+/// Replace comments with whitespace.
+///
+/// Every byte of a comment becomes a space (newlines are kept), so byte
+/// offsets, lines and columns in the output match those in the source.
 pub fn preprocess(
     src: impl IntoIterator<Item = (usize, char)>,
-) -> Result<String, Position> {
+) -> Result<String, Span> {
+    preprocess_keeping_comments(src).map(|(o, _)| o)
+}
+
+/// The error for a comment starting at `span` that `src` does not close.
+pub fn unterminated_comment(span: Span, src: &str) -> Error {
+    let mut e = Error::new(span, ErrorKind::UnterminatedComment);
+    e.details.fix_its = vec![FixIt::insert_after(Span::end_of(src), "*/")];
+    e
+}
+
+/// [`preprocess`], also returning the byte range of each comment in the
+/// source, `//` or `/*` and `*/` included but not the newline after a line
+/// comment.
+pub fn preprocess_keeping_comments(
+    src: impl IntoIterator<Item = (usize, char)>,
+) -> Result<(String, Vec<Range<usize>>), Span> {
     let mut i = src.into_iter();
     let mut o = String::new();
+    let mut comments = vec![];
     let mut line_num: usize = 1;
     let mut line_start = 0;
     'outer: while let Some(c) = i.next() {
         match c.1 {
             '/' => match i.next() {
                 Some(c) => match c.1 {
-                    '/' => loop {
-                        match i.next() {
-                            Some((len, '\n')) => {
-                                new_line(
-                                    &mut line_num,
-                                    &mut line_start,
-                                    len,
-                                    &mut o,
-                                );
-                                break;
+                    '/' => {
+                        let start = o.len();
+                        o.push_str("  ");
+                        loop {
+                            match i.next() {
+                                Some((len, '\n')) => {
+                                    comments.push(start..o.len());
+                                    new_line(
+                                        &mut line_num,
+                                        &mut line_start,
+                                        len,
+                                        &mut o,
+                                    );
+                                    break;
+                                }
+                                None => {
+                                    comments.push(start..o.len());
+                                    break 'outer;
+                                }
+                                Some((_, c)) => blank(c, &mut o),
                             }
-                            None => break 'outer,
-                            _ => {}
                         }
-                    },
+                    }
                     '*' => {
-                        let comment_start = Position {
-                            line: line_num,
-                            col: c.0 - line_start,
+                        let comment_start = Span {
+                            start: c.0 - 1,
+                            end: c.0 + 1,
+                            start_pos: Position {
+                                line: line_num,
+                                col: c.0 - line_start,
+                            },
+                            end_pos: Position {
+                                line: line_num,
+                                col: c.0 - line_start + 2,
+                            },
                         };
+                        let start = o.len();
                         o.push_str("  ");
                         loop {
                             match i.next() {
@@ -39,6 +83,7 @@ pub fn preprocess(
                                     match i.next() {
                                         Some((_, '/')) => {
                                             o.push(' ');
+                                            comments.push(start..o.len());
                                             break;
                                         }
                                         Some((len, '\n')) => {
@@ -50,7 +95,7 @@ pub fn preprocess(
                                             );
                                         }
                                         None => return Err(comment_start),
-                                        _ => o.push(' '),
+                                        Some((_, c)) => blank(c, &mut o),
                                     }
                                 }
                                 Some((len, '\n')) => {
@@ -62,7 +107,7 @@ pub fn preprocess(
                                     );
                                 }
                                 None => return Err(comment_start),
-                                _ => o.push(' '),
+                                Some((_, c)) => blank(c, &mut o),
                             }
                         }
                     }
@@ -85,7 +130,13 @@ pub fn preprocess(
             }
         }
     }
-    Ok(o)
+    Ok((o, comments))
+}
+
+fn blank(c: char, o: &mut String) {
+    for _ in 0..c.len_utf8() {
+        o.push(' ');
+    }
 }
 
 fn new_line(
@@ -167,9 +218,29 @@ mod test {
                 assert_eq!(preprocessed.unwrap(), ans);
             }
             SomeError { pos } => {
-                assert_eq!(preprocessed.unwrap_err(), pos)
+                assert_eq!(preprocessed.unwrap_err().start_pos, pos)
             }
         }
         Ok(())
     }
+    #[test_case("a // b\nc /* d\n*/ e //", &["// b", "/* d\n*/", "//"])]
+    #[test_case("a / b /**/", &["/**/"])]
+    #[test_case("/* a */ // b", &["/* a */", "// b"])]
+    fn test_comments(src: &str, expected: &[&str]) {
+        let (_, comments) =
+            preprocess_keeping_comments(src.char_indices()).unwrap();
+        let comments: Vec<_> = comments.into_iter().map(|r| &src[r]).collect();
+        assert_eq!(comments, expected);
+    }
+
+    #[test_case("a // é\nb")]
+    #[test_case("a /* é\n */ b")]
+    #[test_case("//")]
+    fn test_preprocess_keeps_offsets(src: &str) {
+        let preprocessed = preprocess(src.char_indices()).unwrap();
+        assert_eq!(preprocessed.len(), src.len());
+        for (a, b) in preprocessed.lines().zip(src.lines()) {
+            assert_eq!(a.len(), b.len());
+        }
+    }
 }
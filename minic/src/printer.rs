@@ -0,0 +1,743 @@
+//! Prints an AST back as canonical C source: four-space indentation, one
+//! statement per line and only the parentheses the grammar needs.
+
+use std::ops::Range;
+
+use crate::{
+    ast::*,
+    doc::{self, concat, group, join, list, nest, render, text, Doc},
+    lexer::LexerOutput,
+    token::Span,
+};
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// How tightly an expression binds, from the loosest. An operand is printed
+/// in parentheses if it binds more loosely than its position allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Comma,
+    Assignment,
+    LogicalOr,
+    LogicalAnd,
+    Or,
+    And,
+    Equality,
+    Relational,
+    Additive,
+    Multiplicative,
+    Unary,
+    Postfix,
+    Atom,
+}
+
+impl BinaryOperator {
+    fn precedence(&self) -> Precedence {
+        match self {
+            BinaryOperator::Comma => Precedence::Comma,
+            BinaryOperator::LogicalOr => Precedence::LogicalOr,
+            BinaryOperator::LogicalAnd => Precedence::LogicalAnd,
+            BinaryOperator::Or => Precedence::Or,
+            BinaryOperator::And => Precedence::And,
+            BinaryOperator::Eq | BinaryOperator::Neq => Precedence::Equality,
+            BinaryOperator::Lt
+            | BinaryOperator::Gt
+            | BinaryOperator::Le
+            | BinaryOperator::Ge => Precedence::Relational,
+            BinaryOperator::Add | BinaryOperator::Minus => Precedence::Additive,
+            BinaryOperator::Multiply | BinaryOperator::DivideBy => {
+                Precedence::Multiplicative
+            }
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::DivideBy => "/",
+            BinaryOperator::LogicalAnd => "&&",
+            BinaryOperator::LogicalOr => "||",
+            BinaryOperator::And => "&",
+            BinaryOperator::Or => "|",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::Eq => "==",
+            BinaryOperator::Neq => "!=",
+            BinaryOperator::Comma => ",",
+        }
+    }
+}
+
+impl UnaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOperator::Positive => "+",
+            UnaryOperator::Negative => "-",
+            UnaryOperator::Address => "&",
+            UnaryOperator::Indirection => "*",
+            UnaryOperator::LogicalNot => "!",
+            UnaryOperator::Not => "~",
+        }
+    }
+}
+
+impl Expression {
+    fn precedence(&self) -> Precedence {
+        match self {
+            Expression::Assignment(_) => Precedence::Assignment,
+            Expression::Binary(e) => e.operator.precedence(),
+            Expression::Unary(_) => Precedence::Unary,
+            Expression::Postfix(_) => Precedence::Postfix,
+            Expression::Atom(..) => Precedence::Atom,
+        }
+    }
+}
+
+struct Printer<'a> {
+    lexer_output: &'a LexerOutput,
+    /// The source, to place comments and blank lines
+    src: Option<&'a str>,
+    /// The comments not printed yet, first first
+    comments: &'a [Range<usize>],
+    /// Comments taken out of a statement to go before it
+    displaced: Vec<Range<usize>>,
+}
+
+impl Printer<'_> {
+    fn name(&self, id: usize) -> Doc {
+        text(self.lexer_output.name(id))
+    }
+
+    /// The comments before `pos`, each on a line of its own.
+    fn comments_before(&mut self, pos: usize, lines: &mut Lines) {
+        while let Some(c) = self.comments.first() {
+            if c.start >= pos {
+                break;
+            }
+            let c = c.clone();
+            self.comments = &self.comments[1..];
+            lines.push(self.src, c.clone(), text(&self.src.unwrap()[c]));
+        }
+    }
+
+    /// A comment after `end` on the same line, e.g. `a = 1; // one`.
+    fn trailing_comment(&mut self, end: usize) -> Option<Range<usize>> {
+        let src = self.src?;
+        let c = self.comments.first()?;
+        if c.start < end
+            || !src[end..c.start].trim_start_matches(' ').is_empty()
+        {
+            return None;
+        }
+        let c = c.clone();
+        self.comments = &self.comments[1..];
+        Some(c)
+    }
+
+    /// `items` one per line, with the comments among them. `blank` says
+    /// which items are set off by blank lines; others are where the source
+    /// has them.
+    fn lines<T>(
+        &mut self,
+        items: &[T],
+        end: usize,
+        span: impl Fn(&T) -> Span,
+        blank: impl Fn(&T) -> bool,
+        mut print: impl FnMut(&mut Self, &T) -> Doc,
+    ) -> Vec<Doc> {
+        // Those of the statement around, if any
+        let outer_displaced = std::mem::take(&mut self.displaced);
+        let mut lines = Lines::default();
+        for item in items {
+            let span = span(item);
+            if blank(item) {
+                lines.blank = true;
+            }
+            self.comments_before(span.start, &mut lines);
+            let doc = print(self, item);
+            // Comments inside the item but not in a nested block go before
+            // it.
+            for c in std::mem::take(&mut self.displaced) {
+                lines.push(self.src, c.clone(), text(&self.src.unwrap()[c]));
+            }
+            self.comments_before(span.end, &mut lines);
+            lines.push(self.src, span.start..span.end, doc);
+            if let Some(c) = self.trailing_comment(span.end) {
+                let comment = text(&self.src.unwrap()[c.clone()]);
+                lines.append(c.end, concat([text(" "), comment]));
+            }
+            lines.blank = blank(item);
+        }
+        self.comments_before(end, &mut lines);
+        self.displaced = outer_displaced;
+        lines.docs
+    }
+
+    fn translation_unit(&mut self, ast: &TranslationUnit) -> Doc {
+        let end = self.src.map_or(0, str::len);
+        let lines = self.lines(
+            &ast.external_declarations,
+            end,
+            ExternalDeclaration::span,
+            |d| matches!(d, ExternalDeclaration::FunctionDeclaration(_)),
+            |p, d| match d {
+                ExternalDeclaration::FunctionDeclaration(f) => concat([
+                    p.declaration_specifier(&f.declaration_specifier),
+                    text(" "),
+                    p.declarator(&f.declarator),
+                    Doc::HardLine,
+                    p.compound_statement(&f.compound_statement),
+                ]),
+                ExternalDeclaration::Declaration(d) => p.declaration(d),
+            },
+        );
+        if lines.is_empty() {
+            return text("");
+        }
+        concat([join(lines, || Doc::HardLine), Doc::HardLine])
+    }
+
+    fn declaration_specifier(&self, d: &DeclarationSpecifier) -> Doc {
+        let DeclarationSpecifier::TypeSpecifier(t, _) = d;
+        text(match t {
+            TypeSpecifier::Void => "void",
+            TypeSpecifier::Int => "int",
+            TypeSpecifier::Double => "double",
+        })
+    }
+
+    fn declaration(&self, d: &Declaration) -> Doc {
+        let init_declarators = d.init_declarator_list.0.iter().map(|i| {
+            let declarator = self.declarator(&i.declarator);
+            match &i.initializer {
+                Some(initializer) => group(concat([
+                    declarator,
+                    text(" ="),
+                    nest(concat([Doc::Line, self.initializer(initializer)])),
+                ])),
+                None => declarator,
+            }
+        });
+        group(concat([
+            self.declaration_specifier(&d.declaration_specifier),
+            text(" "),
+            nest(join(init_declarators, || concat([text(","), Doc::Line]))),
+            text(";"),
+        ]))
+    }
+
+    /// Parentheses in declarators are kept in the AST as nested
+    /// declarators, so they are printed as they are.
+    fn declarator(&self, d: &Declarator) -> Doc {
+        let mut docs = vec![text("*".repeat(d.pointer.0))];
+        docs.push(match &d.direct.simple_declarator {
+            SimpleDirectDeclarator::Identifier(id, _) => self.name(*id),
+            SimpleDirectDeclarator::Declarator(d) => {
+                concat([text("("), self.declarator(d), text(")")])
+            }
+        });
+        for m in &d.direct.modifiers {
+            docs.push(match m {
+                DirectDeclaratorModifier::Array(len, _) => {
+                    text(format!("[{len}]"))
+                }
+                DirectDeclaratorModifier::Function(params, _) => {
+                    let params = params.iter().map(|p| {
+                        concat([
+                            self.declaration_specifier(&p.specifier),
+                            text(" "),
+                            self.declarator(&p.declarator),
+                        ])
+                    });
+                    list("(", params.collect(), ")")
+                }
+            });
+        }
+        concat(docs)
+    }
+
+    fn initializer(&self, i: &Initializer) -> Doc {
+        match i {
+            Initializer::Expression(e) => {
+                self.expression(e, Precedence::Assignment)
+            }
+            Initializer::List(list, _) => doc::list(
+                "{",
+                list.iter().map(|i| self.initializer(i)).collect(),
+                "}",
+            ),
+        }
+    }
+
+    fn compound_statement(&mut self, c: &CompoundStatement) -> Doc {
+        while self
+            .comments
+            .first()
+            .is_some_and(|d| d.start < c.span.start)
+        {
+            self.displaced.push(self.comments[0].clone());
+            self.comments = &self.comments[1..];
+        }
+        let lines = self.lines(
+            &c.block_items,
+            c.span.end,
+            BlockItem::span,
+            |_| false,
+            |p, item| match item {
+                BlockItem::Declaration(d) => p.declaration(d),
+                BlockItem::Statement(s) => p.statement(s),
+            },
+        );
+        concat([
+            text("{"),
+            nest(concat(lines.into_iter().flat_map(|l| [Doc::HardLine, l]))),
+            Doc::HardLine,
+            text("}"),
+        ])
+    }
+
+    /// A statement after `if (...)`, `else` and the like: a compound
+    /// statement goes on the same line, anything else on an indented line.
+    /// Also returns whether it was a compound statement.
+    fn body(&mut self, s: &Statement) -> (Doc, bool) {
+        match s {
+            Statement::Compound(c) => {
+                (concat([text(" "), self.compound_statement(c)]), true)
+            }
+            s => (nest(concat([Doc::HardLine, self.statement(s)])), false),
+        }
+    }
+
+    /// `keyword (condition)`, with `condition` broken over lines if needed.
+    fn header(&self, keyword: &str, condition: &Expression) -> Doc {
+        group(concat([
+            text(format!("{keyword} (")),
+            nest(concat([
+                Doc::SoftLine,
+                self.expression(condition, Precedence::Comma),
+            ])),
+            Doc::SoftLine,
+            text(")"),
+        ]))
+    }
+
+    fn statement(&mut self, s: &Statement) -> Doc {
+        match s {
+            Statement::Compound(c) => self.compound_statement(c),
+            Statement::Expression(e, _) => concat([
+                e.as_ref().map_or(text(""), |e| {
+                    self.expression(e, Precedence::Comma)
+                }),
+                text(";"),
+            ]),
+            Statement::Selection(s) => self.selection(s),
+            Statement::Iteration(IterationStatement::While(s)) => concat([
+                self.header("while", &s.condition),
+                self.body(&s.body).0,
+            ]),
+            Statement::Iteration(IterationStatement::Do(s)) => {
+                let (body, compound) = self.body(&s.body);
+                concat([
+                    text("do"),
+                    body,
+                    if compound { text(" ") } else { Doc::HardLine },
+                    self.header("while", &s.condition),
+                    text(";"),
+                ])
+            }
+            Statement::Iteration(IterationStatement::For(s)) => {
+                let initialization = match &s.initialization {
+                    ForInitialization::Expression(e, _) => concat([
+                        e.as_ref().map_or(text(""), |e| {
+                            self.expression(e, Precedence::Comma)
+                        }),
+                        text(";"),
+                    ]),
+                    ForInitialization::Declaration(d) => self.declaration(d),
+                };
+                let clause = |e: &Option<Expression>| match e {
+                    Some(e) => concat([
+                        Doc::Line,
+                        self.expression(e, Precedence::Comma),
+                    ]),
+                    None => text(""),
+                };
+                let header = group(concat([
+                    text("for ("),
+                    nest(concat([
+                        Doc::SoftLine,
+                        initialization,
+                        clause(&s.condition),
+                        text(";"),
+                        clause(&s.update),
+                    ])),
+                    Doc::SoftLine,
+                    text(")"),
+                ]));
+                concat([header, self.body(&s.body).0])
+            }
+            Statement::Jump(JumpStatement::Continue, _) => text("continue;"),
+            Statement::Jump(JumpStatement::Break, _) => text("break;"),
+            Statement::Jump(JumpStatement::Return(e), _) => match e {
+                Some(e) => concat([
+                    text("return "),
+                    self.expression(e, Precedence::Comma),
+                    text(";"),
+                ]),
+                None => text("return;"),
+            },
+        }
+    }
+
+    fn selection(&mut self, s: &SelectionStatement) -> Doc {
+        let mut docs = vec![self.header("if", &s.condition)];
+        let (consequent, compound) = self.body(&s.consequent);
+        docs.push(consequent);
+        if let Some(alternative) = &s.alternative {
+            docs.push(if compound { text(" ") } else { Doc::HardLine });
+            docs.push(text("else"));
+            docs.push(match &**alternative {
+                Statement::Selection(s) => {
+                    concat([text(" "), self.selection(s)])
+                }
+                s => self.body(s).0,
+            });
+        }
+        concat(docs)
+    }
+
+    /// `e` where an expression binding at least as tightly as `min` can go
+    /// without parentheses.
+    fn expression(&self, e: &Expression, min: Precedence) -> Doc {
+        if e.precedence() < min {
+            return concat([
+                text("("),
+                self.expression(e, Precedence::Comma),
+                text(")"),
+            ]);
+        }
+        match e {
+            Expression::Assignment(e) => group(concat([
+                self.expression(&e.left, Precedence::Unary),
+                text(" ="),
+                nest(concat([
+                    Doc::Line,
+                    self.expression(&e.right, Precedence::Assignment),
+                ])),
+            ])),
+            Expression::Binary(e) => {
+                let precedence = e.operator.precedence();
+                // Binary operators are left-associative, so a right operand
+                // at the same level needs parentheses.
+                let right = if e.right.precedence() <= precedence {
+                    concat([
+                        text("("),
+                        self.expression(&e.right, Precedence::Comma),
+                        text(")"),
+                    ])
+                } else {
+                    self.expression(&e.right, precedence)
+                };
+                let symbol = e.operator.symbol();
+                group(concat([
+                    self.expression(&e.left, precedence),
+                    text(match e.operator {
+                        BinaryOperator::Comma => symbol.to_owned(),
+                        _ => format!(" {symbol}"),
+                    }),
+                    nest(concat([Doc::Line, right])),
+                ]))
+            }
+            Expression::Unary(e) => {
+                let symbol = e.operator.symbol();
+                // `- -a` and `& &a` must not become `--a` and `&&a`.
+                let space = matches!(
+                    &*e.operand,
+                    Expression::Unary(operand)
+                        if operand.operator.symbol().starts_with(symbol)
+                );
+                concat([
+                    text(symbol),
+                    text(if space { " " } else { "" }),
+                    self.expression(&e.operand, Precedence::Unary),
+                ])
+            }
+            Expression::Postfix(e) => {
+                let postfix = match &e.postfix {
+                    PostfixExpressionPostfix::Subscript(index) => concat([
+                        text("["),
+                        self.expression(index, Precedence::Comma),
+                        text("]"),
+                    ]),
+                    PostfixExpressionPostfix::Call(arguments) => list(
+                        "(",
+                        arguments
+                            .iter()
+                            .map(|a| self.expression(a, Precedence::Assignment))
+                            .collect(),
+                        ")",
+                    ),
+                };
+                concat([
+                    self.expression(&e.operand, Precedence::Postfix),
+                    postfix,
+                ])
+            }
+            Expression::Atom(AtomExpression::Identifier(id), _) => {
+                self.name(*id)
+            }
+            Expression::Atom(AtomExpression::Integer(n), _) => {
+                text(n.to_string())
+            }
+            Expression::Atom(AtomExpression::Floating(f), _) => {
+                // Without a `.` it would be an integer constant.
+                let s = f.to_string();
+                if s.contains('.') {
+                    text(s)
+                } else {
+                    text(s + ".0")
+                }
+            }
+        }
+    }
+}
+
+/// Lines of a block or the file being laid out.
+#[derive(Default)]
+struct Lines {
+    docs: Vec<Doc>,
+    /// The end of what the last line comes from in the source
+    last_end: Option<usize>,
+    /// Put a blank line before the next line
+    blank: bool,
+}
+
+impl Lines {
+    /// Add the line `doc` printed from `range` of `src`, after a blank line
+    /// if there is one in the source before it.
+    fn push(&mut self, src: Option<&str>, range: Range<usize>, doc: Doc) {
+        // Displaced comments come before what they were after.
+        let between = match (src, self.last_end) {
+            (Some(src), Some(last_end)) => src.get(last_end..range.start),
+            _ => None,
+        };
+        if between.is_some_and(|s| s.matches('\n').count() > 1) {
+            self.blank = true;
+        }
+        if self.blank && !self.docs.is_empty() {
+            self.docs.push(text(""));
+        }
+        self.blank = false;
+        self.docs.push(doc);
+        self.last_end = Some(range.end);
+    }
+
+    /// Add `doc` to the end of the last line.
+    fn append(&mut self, end: usize, doc: Doc) {
+        let last = self.docs.pop().unwrap();
+        self.docs.push(concat([last, doc]));
+        self.last_end = Some(end);
+    }
+}
+
+/// Print `ast` as C source, with identifiers from the identifier table of
+/// `lexer_output`.
+pub fn print(ast: &TranslationUnit, lexer_output: &LexerOutput) -> String {
+    let mut printer = Printer {
+        lexer_output,
+        src: None,
+        comments: &[],
+        displaced: vec![],
+    };
+    render(&printer.translation_unit(ast), DEFAULT_WIDTH)
+}
+
+/// Print the declaration of one declarator on a line, without initializer or
+/// `;`, e.g. `int (*f(int a))[2]`.
+pub fn print_declarator(
+    specifier: &DeclarationSpecifier,
+    declarator: &Declarator,
+    lexer_output: &LexerOutput,
+) -> String {
+    let printer = Printer {
+        lexer_output,
+        src: None,
+        comments: &[],
+        displaced: vec![],
+    };
+    let doc = concat([
+        printer.declaration_specifier(specifier),
+        text(" "),
+        printer.declarator(declarator),
+    ]);
+    render(&doc, usize::MAX)
+}
+
+/// Print `e` on one line, e.g. `a[i] = b + 1`.
+pub fn print_expression(e: &Expression, lexer_output: &LexerOutput) -> String {
+    let printer = Printer {
+        lexer_output,
+        src: None,
+        comments: &[],
+        displaced: vec![],
+    };
+    render(&printer.expression(e, Precedence::Comma), usize::MAX)
+}
+
+/// Print `d` on one line, with its `;`.
+pub fn print_declaration(
+    d: &Declaration,
+    lexer_output: &LexerOutput,
+) -> String {
+    let printer = Printer {
+        lexer_output,
+        src: None,
+        comments: &[],
+        displaced: vec![],
+    };
+    render(&printer.declaration(d), usize::MAX)
+}
+
+/// Print `ast` parsed from `src` in lines of at most `width` columns where
+/// possible, with the `comments` in `src` and the blank lines between
+/// statements and declarations. Comments inside a statement go before it.
+pub fn format(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+    src: &str,
+    comments: &[Range<usize>],
+    width: usize,
+) -> String {
+    let mut printer = Printer {
+        lexer_output,
+        src: Some(src),
+        comments,
+        displaced: vec![],
+    };
+    render(&printer.translation_unit(ast), width)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{lexer::scan, parser::parse, preprocess::preprocess};
+
+    fn parse_source(src: &str) -> (TranslationUnit, LexerOutput) {
+        let src = preprocess(src.char_indices()).unwrap();
+        let lexer_output = scan(&src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        (ast, lexer_output)
+    }
+
+    /// The AST as JSON with names instead of identifier indices and without
+    /// spans, which are what printing may change.
+    fn normalized(ast: &TranslationUnit, lexer_output: &LexerOutput) -> String {
+        let names = (0..lexer_output.id_table.len())
+            .map(|id| lexer_output.name(id).to_owned())
+            .collect();
+        let context = SerdeContext {
+            spans: false,
+            names: Some(names),
+        };
+        with_context(context, || serde_json::to_string_pretty(ast).unwrap())
+    }
+
+    /// Print `src` and check that the result parses to the same AST and
+    /// prints the same again.
+    fn round_trip(src: &str) -> String {
+        let (ast, lexer_output) = parse_source(src);
+        let printed = print(&ast, &lexer_output);
+        let (reparsed, reparsed_lexer_output) = parse_source(&printed);
+        assert_eq!(
+            normalized(&reparsed, &reparsed_lexer_output),
+            normalized(&ast, &lexer_output)
+        );
+        assert_eq!(print(&reparsed, &reparsed_lexer_output), printed);
+        printed
+    }
+
+    #[test]
+    fn test_testcases() {
+        let mut count = 0;
+        for entry in fs::read_dir("testcase/c").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "c") {
+                round_trip(&fs::read_to_string(&path).unwrap());
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test_case("int a = (1 + 2) * 3;", "int a = (1 + 2) * 3;\n"; "lower_left")]
+    #[test_case("int a = 1 + (2 * 3);", "int a = 1 + 2 * 3;\n"; "higher_right")]
+    #[test_case("int a = (1 - 2) - 3;", "int a = 1 - 2 - 3;\n"; "left_associative")]
+    #[test_case("int a = 1 - (2 - 3);", "int a = 1 - (2 - 3);\n"; "same_right")]
+    #[test_case("int a = (a || b) && !(c < d);", "int a = (a || b) && !(c < d);\n"; "logical")]
+    #[test_case("int a = (*p)[1] + *p[1] + (f)(1)(2);", "int a = (*p)[1] + *p[1] + f(1)(2);\n"; "postfix")]
+    #[test_case("int a = -(-1) + - -1 + + +a + & &a + -!a;",
+        "int a = - -1 + - -1 + + +a + & &a + -!a;\n"; "unary")]
+    #[test_case("int a = ((1, 2), 3) + (1, (2, 3)) + f((1, 2));",
+        "int a = (1, 2, 3) + (1, (2, 3)) + f((1, 2));\n"; "comma")]
+    #[test_case("int a = 0.5 + 2.;", "int a = 0.5 + 2.0;\n"; "floating")]
+    #[test_case("int a[2] = {1, {(2)}};", "int a[2] = {1, {2}};\n"; "initializer_list")]
+    #[test_case("int (*f(int a))[2];", "int (*f(int a))[2];\n"; "nested_declarator")]
+    #[test_case("int (a), **((b));", "int (a), **((b));\n"; "parenthesized_declarator")]
+    fn test_print(src: &str, expected: &str) {
+        assert_eq!(round_trip(src), expected);
+    }
+
+    #[test]
+    fn test_statements() {
+        let src = "int f(int a){int b;if(a)b=1;else if(b){a=b=2;}else;
+            while(a)a=a-1;do{a=1;}while(a);do a=1;while(a);
+            for(;;)break;for(int i=0;i<a;i=i+1){continue;}
+            {return (a=1);}}int g;int h(int x){return;}";
+        assert_eq!(
+            round_trip(src),
+            "int f(int a)
+{
+    int b;
+    if (a)
+        b = 1;
+    else if (b) {
+        a = b = 2;
+    } else
+        ;
+    while (a)
+        a = a - 1;
+    do {
+        a = 1;
+    } while (a);
+    do
+        a = 1;
+    while (a);
+    for (;;)
+        break;
+    for (int i = 0; i < a; i = i + 1) {
+        continue;
+    }
+    {
+        return a = 1;
+    }
+}
+
+int g;
+
+int h(int x)
+{
+    return;
+}
+"
+        );
+    }
+}
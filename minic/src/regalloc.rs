@@ -0,0 +1,739 @@
+//! Register allocation for [`MachineFunction`]s: liveness, live intervals,
+//! a linear-scan allocator and a Chaitin–Briggs graph-colouring allocator
+//! with conservative coalescing.
+//!
+//! Both allocators give each virtual register a register of its class or
+//! ask for some to be spilled. A spilled register lives in a spill slot:
+//! each instruction that uses it reads a reload of it into a new short-lived
+//! register, and each one that defines it writes a new register that is then
+//! stored, after which allocation starts over. Calls read their arguments
+//! and write their result wherever they live, so they need no spill code.
+
+use std::{collections::BTreeSet, fmt, ops::AddAssign};
+
+use crate::{
+    cfg::{BlockId, Dominators},
+    dataflow::{solve, Analysis, Direction},
+    ir::{Reg, Ty},
+    x86::{Inst, MachineFunction},
+};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, strum_macros::Display,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Allocator {
+    /// Poletto and Sarkar's linear scan over live intervals
+    LinearScan,
+    /// Chaitin–Briggs graph colouring with conservative coalescing
+    GraphColoring,
+}
+
+/// The kind of register a value needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    General,
+    Float,
+}
+
+impl Class {
+    pub fn of(ty: Ty) -> Self {
+        match ty {
+            Ty::Int | Ty::Pointer => Class::General,
+            Ty::Double => Class::Float,
+        }
+    }
+}
+
+/// Where a virtual register lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// The register with this number among those of its class
+    Register(usize),
+    /// The spill slot with this number
+    Stack(usize),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The virtual registers used before allocation
+    pub registers: usize,
+    /// The virtual registers that live in spill slots
+    pub spilled: usize,
+    /// The reloads and stores of spilled registers
+    pub spill_instructions: usize,
+    pub moves: usize,
+    /// The moves whose source and destination got the same location
+    pub moves_eliminated: usize,
+}
+
+impl AddAssign<&Stats> for Stats {
+    fn add_assign(&mut self, other: &Stats) {
+        self.registers += other.registers;
+        self.spilled += other.spilled;
+        self.spill_instructions += other.spill_instructions;
+        self.moves += other.moves;
+        self.moves_eliminated += other.moves_eliminated;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} registers, {} spilled, {} spill instructions, {} of {} moves \
+             eliminated",
+            self.registers,
+            self.spilled,
+            self.spill_instructions,
+            self.moves_eliminated,
+            self.moves
+        )
+    }
+}
+
+/// Give every virtual register of `f` a location, with `available[c]`
+/// registers of class `c`, at least three of each. Spill code is inserted
+/// into `f` as needed.
+pub fn allocate(
+    f: &mut MachineFunction,
+    allocator: Allocator,
+    available: [usize; 2],
+) -> (Vec<Location>, Stats) {
+    let registers: BTreeSet<_> = f
+        .blocks
+        .iter()
+        .flatten()
+        .flat_map(|i| i.uses().into_iter().chain(i.def()))
+        .collect();
+    let mut stats = Stats {
+        registers: registers.len(),
+        ..Stats::default()
+    };
+    // The spill slot of each spilled register
+    let mut slots: Vec<Option<usize>> = vec![None; f.registers.len()];
+    // The registers spill code introduced, which are never spilled
+    let mut temporary = vec![false; f.registers.len()];
+    let colors = loop {
+        let live = live_out(f);
+        let allocation = Allocation {
+            f,
+            live: &live,
+            available,
+            spilled: &slots,
+            temporary: &temporary,
+        };
+        let result = match allocator {
+            Allocator::LinearScan => allocation.linear_scan(),
+            Allocator::GraphColoring => allocation.graph_coloring(),
+        };
+        match result {
+            Ok(colors) => break colors,
+            Err(spills) => {
+                for v in spills {
+                    slots[v] = Some(f.spill_slots);
+                    f.spill_slots += 1;
+                    stats.spilled += 1;
+                    insert_spill_code(f, v);
+                }
+                slots.resize(f.registers.len(), None);
+                temporary.resize(f.registers.len(), true);
+            }
+        }
+    };
+    let locations: Vec<_> = (0..f.registers.len())
+        .map(|v| match slots[v] {
+            Some(slot) => Location::Stack(slot),
+            None => Location::Register(colors[v]),
+        })
+        .collect();
+    for i in f.blocks.iter().flatten() {
+        match *i {
+            Inst::Move(d, s) => {
+                stats.moves += 1;
+                if locations[d] == locations[s] {
+                    stats.moves_eliminated += 1;
+                }
+            }
+            Inst::Reload(..) | Inst::Spill(..) => stats.spill_instructions += 1,
+            _ => {}
+        }
+    }
+    (locations, stats)
+}
+
+/// Make spilled register `v` live in spill slot `f.spill_slots - 1`. A
+/// move from or to it becomes a reload or a store.
+fn insert_spill_code(f: &mut MachineFunction, v: Reg) {
+    let slot = f.spill_slots - 1;
+    let ty = f.registers[v];
+    for b in 0..f.blocks.len() {
+        let instructions = std::mem::take(&mut f.blocks[b]);
+        let mut rewritten = Vec::with_capacity(instructions.len());
+        for mut i in instructions {
+            match i {
+                Inst::Call(..) => {}
+                Inst::Move(d, s) if s == v => i = Inst::Reload(d, slot),
+                Inst::Move(d, s) if d == v => i = Inst::Spill(slot, s),
+                _ => {
+                    if i.uses().contains(&v) {
+                        let t = f.new_register(ty);
+                        for r in i.uses_mut() {
+                            if *r == v {
+                                *r = t;
+                            }
+                        }
+                        rewritten.push(Inst::Reload(t, slot));
+                    }
+                    if i.def() == Some(v) {
+                        let t = f.new_register(ty);
+                        *i.def_mut().unwrap() = t;
+                        rewritten.push(i);
+                        rewritten.push(Inst::Spill(slot, t));
+                        continue;
+                    }
+                }
+            }
+            rewritten.push(i);
+        }
+        f.blocks[b] = rewritten;
+    }
+}
+
+/// The virtual registers whose value may be used later, for
+/// [`crate::dataflow::solve`].
+struct Liveness<'a>(&'a MachineFunction);
+
+impl Analysis for Liveness<'_> {
+    type Domain = BTreeSet<Reg>;
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn bottom(&self) -> Self::Domain {
+        BTreeSet::new()
+    }
+
+    fn transfer(&self, b: BlockId, state: &mut Self::Domain) {
+        for i in self.0.blocks[b].iter().rev() {
+            step(i, state);
+        }
+    }
+}
+
+/// Update the registers live after `i` to those live before it.
+fn step(i: &Inst, live: &mut BTreeSet<Reg>) {
+    if let Some(d) = i.def() {
+        live.remove(&d);
+    }
+    live.extend(i.uses());
+}
+
+/// The registers live after each instruction of each block of `f`.
+pub fn live_out(f: &MachineFunction) -> Vec<Vec<BTreeSet<Reg>>> {
+    let after = solve(&Liveness(f), &f.successors()).after;
+    f.blocks
+        .iter()
+        .zip(after)
+        .map(|(block, mut live)| {
+            let mut sets = vec![BTreeSet::new(); block.len()];
+            for (k, i) in block.iter().enumerate().rev() {
+                sets[k] = live.clone();
+                step(i, &mut live);
+            }
+            sets
+        })
+        .collect()
+}
+
+/// The loop nesting depth of each block: the number of natural loops that
+/// contain it.
+fn loop_depths(successors: &[Vec<BlockId>]) -> Vec<u32> {
+    let dominators = Dominators::new(successors);
+    let mut predecessors = vec![vec![]; successors.len()];
+    for (b, s) in successors.iter().enumerate() {
+        for &s in s {
+            predecessors[s].push(b);
+        }
+    }
+    let mut depths = vec![0; successors.len()];
+    for (tail, s) in successors.iter().enumerate() {
+        for &header in s.iter().filter(|&&h| dominators.dominates(h, tail)) {
+            let mut body = vec![false; successors.len()];
+            body[header] = true;
+            let mut worklist = vec![tail];
+            while let Some(b) = worklist.pop() {
+                if !body[b] {
+                    body[b] = true;
+                    worklist.extend(&predecessors[b]);
+                }
+            }
+            for (depth, _) in depths.iter_mut().zip(body).filter(|(_, b)| *b) {
+                *depth += 1;
+            }
+        }
+    }
+    depths
+}
+
+/// The inputs of one round of allocation.
+struct Allocation<'a> {
+    f: &'a MachineFunction,
+    live: &'a [Vec<BTreeSet<Reg>>],
+    available: [usize; 2],
+    /// The registers that already live in spill slots, which need none
+    spilled: &'a [Option<usize>],
+    temporary: &'a [bool],
+}
+
+/// The register of its class for each virtual register, or the registers
+/// to spill.
+type Colors = Result<Vec<usize>, Vec<Reg>>;
+
+impl Allocation<'_> {
+    fn class(&self, v: Reg) -> Class {
+        Class::of(self.f.registers[v])
+    }
+
+    fn available(&self, v: Reg) -> usize {
+        self.available[self.class(v) as usize]
+    }
+
+    /// Whether `v` needs a register.
+    fn is_candidate(&self, v: Reg) -> bool {
+        self.spilled[v].is_none()
+    }
+
+    /// Each instruction with its block and the registers live after it.
+    fn instructions(
+        &self,
+    ) -> impl Iterator<Item = (BlockId, &Inst, &BTreeSet<Reg>)> {
+        self.f
+            .blocks
+            .iter()
+            .enumerate()
+            .flat_map(move |(b, block)| {
+                block.iter().zip(&self.live[b]).map(move |(i, l)| (b, i, l))
+            })
+    }
+
+    /// Allocate in one pass over the live intervals in order of their
+    /// start, spilling the interval that ends last when registers run out.
+    /// Instruction `n` of the linear order uses its operands at `2n` and
+    /// defines its result at `2n + 1`.
+    fn linear_scan(&self) -> Colors {
+        let n = self.f.registers.len();
+        let mut intervals: Vec<Option<(usize, usize)>> = vec![None; n];
+        let mut cover = |v: Reg, position: usize| {
+            let interval = intervals[v].get_or_insert((position, position));
+            interval.0 = interval.0.min(position);
+            interval.1 = interval.1.max(position);
+        };
+        let hints = self.hints();
+        for (position, (_, i, live)) in self.instructions().enumerate() {
+            for v in i.uses() {
+                cover(v, 2 * position);
+            }
+            for &v in live {
+                if Some(v) != i.def() {
+                    cover(v, 2 * position);
+                }
+                cover(v, 2 * position + 1);
+            }
+            if let Some(d) = i.def() {
+                cover(d, 2 * position + 1);
+            }
+        }
+        let mut order: Vec<_> = (0..n)
+            .filter(|&v| self.is_candidate(v))
+            .filter_map(|v| Some((intervals[v]?, v)))
+            .collect();
+        order.sort();
+
+        let mut colors = vec![0; n];
+        let mut assigned = vec![false; n];
+        let mut spills = vec![];
+        // The intervals in registers, by class
+        let mut active: [Vec<(usize, Reg)>; 2] = [vec![], vec![]];
+        for ((start, end), v) in order {
+            let class = self.class(v) as usize;
+            active[class].retain(|&(end, _)| end >= start);
+            let used: Vec<_> =
+                active[class].iter().map(|&(_, r)| colors[r]).collect();
+            let free = |c: &usize| !used.contains(c);
+            let hint = hints[v]
+                .iter()
+                .filter(|&&h| assigned[h])
+                .map(|&h| colors[h])
+                .find(free);
+            let color = hint.or_else(|| (0..self.available(v)).find(free));
+            if let Some(color) = color {
+                colors[v] = color;
+                assigned[v] = true;
+                active[class].push((end, v));
+                continue;
+            }
+            let victim = active[class]
+                .iter()
+                .copied()
+                .filter(|&(_, r)| !self.temporary[r])
+                .max();
+            match victim {
+                Some((victim_end, r))
+                    if victim_end > end || self.temporary[v] =>
+                {
+                    colors[v] = colors[r];
+                    assigned[v] = true;
+                    assigned[r] = false;
+                    active[class].retain(|&(_, a)| a != r);
+                    active[class].push((end, v));
+                    spills.push(r);
+                }
+                _ => spills.push(v),
+            }
+        }
+        if spills.is_empty() {
+            Ok(colors)
+        } else {
+            Err(spills)
+        }
+    }
+
+    /// Build the interference graph, coalesce the moves whose ends do not
+    /// interfere while Briggs's test says the graph stays colourable,
+    /// simplify it optimistically and select colours, spilling the nodes
+    /// that get none.
+    fn graph_coloring(&self) -> Colors {
+        let n = self.f.registers.len();
+        let mut graph: Vec<BTreeSet<Reg>> = vec![BTreeSet::new(); n];
+        let add_edge = |graph: &mut Vec<BTreeSet<Reg>>, a: Reg, b: Reg| {
+            if a != b && self.class(a) == self.class(b) {
+                graph[a].insert(b);
+                graph[b].insert(a);
+            }
+        };
+        let mut moves = vec![];
+        let depths = loop_depths(&self.f.successors());
+        let mut costs = vec![0.0; n];
+        for (b, i, live) in self.instructions() {
+            let weight = 10f64.powi(depths[b] as i32);
+            for v in i.uses().into_iter().chain(i.def()) {
+                costs[v] += weight;
+            }
+            let Some(d) = i.def().filter(|&d| self.is_candidate(d)) else {
+                continue;
+            };
+            let source = match *i {
+                Inst::Move(d, s) if self.is_candidate(s) => {
+                    moves.push((d, s));
+                    Some(s)
+                }
+                _ => None,
+            };
+            for &v in live {
+                if self.is_candidate(v) && Some(v) != source {
+                    add_edge(&mut graph, d, v);
+                }
+            }
+        }
+        // Registers read before any definition all hold garbage at once.
+        let entry: Vec<_> = self.live_in_entry().into_iter().collect();
+        for (k, &a) in entry.iter().enumerate() {
+            for &b in &entry[k + 1..] {
+                add_edge(&mut graph, a, b);
+            }
+        }
+
+        // Coalesce, keeping the first register of each pair.
+        let mut alias: Vec<Reg> = (0..n).collect();
+        let find = |alias: &[Reg], mut v: Reg| {
+            while alias[v] != v {
+                v = alias[v];
+            }
+            v
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(d, s) in &moves {
+                let (a, b) = (find(&alias, d), find(&alias, s));
+                if a == b
+                    || graph[a].contains(&b)
+                    || self.class(a) != self.class(b)
+                {
+                    continue;
+                }
+                let k = self.available(a);
+                let significant = graph[a]
+                    .union(&graph[b])
+                    .filter(|&&m| {
+                        let shared =
+                            graph[a].contains(&m) && graph[b].contains(&m);
+                        graph[m].len() - shared as usize >= k
+                    })
+                    .count();
+                if significant >= k {
+                    continue;
+                }
+                for m in std::mem::take(&mut graph[b]) {
+                    graph[m].remove(&b);
+                    add_edge(&mut graph, a, m);
+                }
+                alias[b] = a;
+                costs[a] += costs[b];
+                changed = true;
+            }
+        }
+        let mut temporary = vec![false; n];
+        for r in (0..n).filter(|&r| self.temporary[r]) {
+            temporary[find(&alias, r)] = true;
+        }
+
+        // Simplify, optimistically pushing the cheapest node to spill when
+        // every remaining one has as many neighbours as colours.
+        let nodes: Vec<_> = (0..n)
+            .filter(|&v| self.is_candidate(v) && alias[v] == v)
+            .collect();
+        let mut degrees: Vec<_> = graph.iter().map(BTreeSet::len).collect();
+        let mut removed = vec![false; n];
+        let mut stack = vec![];
+        while stack.len() < nodes.len() {
+            let remaining = nodes.iter().copied().filter(|&v| !removed[v]);
+            let v = match remaining
+                .clone()
+                .find(|&v| degrees[v] < self.available(v))
+            {
+                Some(v) => v,
+                None => remaining
+                    .min_by(|&a, &b| {
+                        let cost = |v: Reg| {
+                            if temporary[v] {
+                                f64::INFINITY
+                            } else {
+                                costs[v] / degrees[v] as f64
+                            }
+                        };
+                        cost(a).total_cmp(&cost(b))
+                    })
+                    .unwrap(),
+            };
+            removed[v] = true;
+            for &m in &graph[v] {
+                degrees[m] -= 1;
+            }
+            stack.push(v);
+        }
+
+        // Select, biased towards the colours of related registers.
+        let hints = self.hints();
+        let mut colors: Vec<Option<usize>> = vec![None; n];
+        let mut spills = vec![];
+        while let Some(v) = stack.pop() {
+            let used: Vec<_> =
+                graph[v].iter().filter_map(|&m| colors[m]).collect();
+            let members = (0..n).filter(|&r| find(&alias, r) == v);
+            let related = members.flat_map(|r| &hints[r]);
+            let hint = related.filter_map(|&h| colors[find(&alias, h)]);
+            match hint
+                .chain(0..self.available(v))
+                .find(|c| *c < self.available(v) && !used.contains(c))
+            {
+                Some(c) => colors[v] = Some(c),
+                None => spills.extend((0..n).filter(|&r| find(&alias, r) == v)),
+            }
+        }
+        if !spills.is_empty() {
+            return Err(spills);
+        }
+        Ok((0..n)
+            .map(|v| colors[find(&alias, v)].unwrap_or(0))
+            .collect())
+    }
+
+    /// The registers each register would best share a location with: the
+    /// other end of a move, and the first operand of an operation, which
+    /// x86 computes in place.
+    fn hints(&self) -> Vec<Vec<Reg>> {
+        let mut hints = vec![vec![]; self.f.registers.len()];
+        for i in self.f.blocks.iter().flatten() {
+            let (d, s) = match *i {
+                Inst::Move(d, s)
+                | Inst::Unary(_, d, s)
+                | Inst::Binary(_, d, s, _)
+                | Inst::Convert(d, s) => (d, s),
+                _ => continue,
+            };
+            if self.class(d) == self.class(s) {
+                hints[d].push(s);
+                hints[s].push(d);
+            }
+        }
+        hints
+    }
+
+    /// The candidates live on entry to the function.
+    fn live_in_entry(&self) -> BTreeSet<Reg> {
+        let mut live = match self.live[0].first() {
+            Some(live) => live.clone(),
+            None => return BTreeSet::new(),
+        };
+        step(&self.f.blocks[0][0], &mut live);
+        live.retain(|&v| self.is_candidate(v));
+        live
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::ValueEnum;
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{
+        ir::lower,
+        lexer::scan,
+        opt::{optimize, passes},
+        parser::parse,
+        x86::select,
+    };
+
+    /// The machine functions of `src` after `-O<level>`.
+    fn functions(src: &str, level: u8) -> Vec<MachineFunction> {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let mut program = lower(&ast, &lexer_output).unwrap();
+        optimize(&mut program, &passes(level), None).unwrap();
+        program.functions.iter().map(select).collect()
+    }
+
+    /// Check that no definition gets the location of another register of
+    /// its class live after it, unless it copies that register, and that
+    /// only calls read or write registers outside registers.
+    fn check(
+        f: &MachineFunction,
+        locations: &[Location],
+        available: [usize; 2],
+    ) {
+        let live = live_out(f);
+        let class = |v: Reg| Class::of(f.registers[v]);
+        for (b, block) in f.blocks.iter().enumerate() {
+            for (i, live) in block.iter().zip(&live[b]) {
+                for v in i.uses().into_iter().chain(i.def()) {
+                    match locations[v] {
+                        Location::Register(r) => {
+                            assert!(r < available[class(v) as usize])
+                        }
+                        Location::Stack(_) => {
+                            assert!(matches!(i, Inst::Call(..)), "{i:?}")
+                        }
+                    }
+                }
+                let Some(d) = i.def() else { continue };
+                for &v in live {
+                    let copied = matches!(*i, Inst::Move(_, s) if s == v);
+                    if v != d && !copied && class(v) == class(d) {
+                        assert_ne!(locations[v], locations[d], "{i:?} in b{b}");
+                    }
+                }
+            }
+        }
+    }
+
+    const SWAP: &str = "int f(int n) { int a = 1; int b = 2; int t; \
+                        while (n > 0) { t = a; a = b; b = t; n = n - 1; } \
+                        return a * 10 + b; }";
+    const PRESSURE: &str = "int g(int x) { return x; } \
+        int f(int n) { int a = n + 1; int b = n + 2; int c = n + 3; \
+        int d = n + 4; int e = g(a) + b; \
+        if (n) a = a * b; else b = c * d; \
+        return a + b + c + d + e + a * c + b * d; }";
+    const FLOAT: &str = "double f(double x, int n) { double a = x + 1; \
+        double b = x * 2; double c = a - b; double s = 0; \
+        while (n > 0) { s = s + a * b - c; a = b; b = c; c = a + s; \
+        n = n - 1; } return s + a + b + c; }";
+
+    #[test_case(SWAP, 0, [7, 7]; "swap")]
+    #[test_case(SWAP, 2, [3, 3]; "swap with three registers")]
+    #[test_case(PRESSURE, 0, [3, 3]; "pressure")]
+    #[test_case(PRESSURE, 2, [4, 3]; "pressure optimized")]
+    #[test_case(FLOAT, 1, [3, 3]; "float")]
+    fn test_allocate(src: &str, level: u8, available: [usize; 2]) {
+        for allocator in Allocator::value_variants() {
+            for mut f in functions(src, level) {
+                let (locations, _) = allocate(&mut f, *allocator, available);
+                check(&f, &locations, available);
+            }
+        }
+    }
+
+    #[test_case(Allocator::LinearScan)]
+    #[test_case(Allocator::GraphColoring)]
+    fn test_spill(allocator: Allocator) {
+        let mut f = functions(PRESSURE, 0).pop().unwrap();
+        let (locations, stats) = allocate(&mut f, allocator, [3, 3]);
+        assert!(stats.spilled > 0);
+        let slots = f.blocks.iter().flatten();
+        let spill_code = slots
+            .filter(|i| matches!(i, Inst::Reload(..) | Inst::Spill(..)))
+            .count();
+        assert_eq!(spill_code, stats.spill_instructions);
+        let stack =
+            locations.iter().filter(|l| matches!(l, Location::Stack(_)));
+        assert_eq!(stack.count(), stats.spilled);
+        assert_eq!(f.spill_slots, stats.spilled);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        // Out of SSA, each phi of the loop becomes three copies through a
+        // temporary, most of which coalescing removes. Linear scan only
+        // reuses the register of a move whose source ends there.
+        let stats = |allocator| {
+            let mut f = functions(SWAP, 2).pop().unwrap();
+            allocate(&mut f, allocator, [7, 7]).1
+        };
+        let linear_scan = stats(Allocator::LinearScan);
+        let graph_coloring = stats(Allocator::GraphColoring);
+        assert_eq!(graph_coloring.moves, 9);
+        assert_eq!(graph_coloring.spilled, 0);
+        assert!(graph_coloring.moves_eliminated >= 5, "{graph_coloring}");
+        assert!(
+            linear_scan.moves_eliminated < graph_coloring.moves_eliminated,
+            "{linear_scan}"
+        );
+    }
+
+    #[test]
+    fn test_loop_depths() {
+        // b0 -> b1 <-> b2, b1 -> b3, and b2 <-> b4 inside that loop
+        let successors = [vec![1], vec![2, 3], vec![1, 4], vec![], vec![2]];
+        assert_eq!(loop_depths(&successors), [0, 1, 2, 0, 2]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut total = Stats {
+            registers: 10,
+            spilled: 1,
+            spill_instructions: 3,
+            moves: 4,
+            moves_eliminated: 2,
+        };
+        total += &total.clone();
+        assert_eq!(
+            total.to_string(),
+            "20 registers, 2 spilled, 6 spill instructions, 4 of 8 moves \
+             eliminated"
+        );
+    }
+
+    #[test_case("linear-scan", Allocator::LinearScan)]
+    #[test_case("graph-coloring", Allocator::GraphColoring)]
+    fn test_allocator_name(name: &str, allocator: Allocator) {
+        assert_eq!(Allocator::from_str(name, false), Ok(allocator));
+        assert_eq!(allocator.to_string(), name);
+    }
+}
@@ -0,0 +1,370 @@
+//! `minic repl`: declarations, statements and expressions run as they are
+//! entered.
+//!
+//! Every input is scanned as if appended to the inputs before it, so spans
+//! point into [`Repl::session`] and identifiers keep their indices from one
+//! input to the next.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+
+use crate::{
+    ast::{with_context, BlockItem, Expression, SerdeContext, TranslationUnit},
+    diagnostic::{render_human, Diagnostic},
+    error::{Error, ErrorKind},
+    interp::{Interpreter, Value},
+    lexer::{scan_at, LexerOutput},
+    parser::{parse, parse_block_items, parse_full_expression},
+    preprocess::preprocess,
+    suggest::suggest_keyword,
+    token::{Position, Token, TokenKind},
+    token_dump::write_tokens,
+};
+
+const HELP: &str = "\
+Enter declarations, statements or an expression to evaluate.
+:ast <input>    print the AST of <input>
+:tokens <input> print the tokens of <input>
+:type <expr>    print the type of <expr> without evaluating it
+:help           print this
+:quit           exit";
+
+/// What a line of input did.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The input continues on the next line.
+    Incomplete,
+    /// The input ran, printing this, which may be empty.
+    Output(String),
+    Quit,
+}
+
+enum Command {
+    Run,
+    Ast,
+    Tokens,
+    Type,
+}
+
+/// An input parsed according to what it looks like.
+enum Input {
+    Declarations(TranslationUnit),
+    Statements(Vec<BlockItem>),
+    Expression(Expression),
+}
+
+pub struct Repl {
+    interpreter: Interpreter,
+    /// The identifiers of the whole session and the tokens of the last
+    /// input
+    lexer_output: LexerOutput,
+    known_ids: HashMap<String, usize>,
+    session: String,
+    /// The lines of an input that is not complete yet
+    pending: String,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl {
+            interpreter: Interpreter::default(),
+            lexer_output: LexerOutput {
+                tokens: vec![],
+                id_table: vec![],
+                text: String::new(),
+            },
+            known_ids: HashMap::new(),
+            session: String::new(),
+            pending: String::new(),
+        }
+    }
+}
+
+impl Repl {
+    /// All complete inputs so far, each ending with a newline.
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Whether the last line left an input open.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Take a line of input, without its newline.
+    pub fn feed(&mut self, line: &str) -> Result<Outcome, Error> {
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        let trimmed = self.pending.trim_start();
+        let (command, code) = match trimmed.strip_prefix(':') {
+            Some(command) => {
+                let end =
+                    command.find(char::is_whitespace).unwrap_or(command.len());
+                let name = &command[..end];
+                let prefix = self.pending.len() - command.len() + end;
+                let command = match name {
+                    "quit" | "q" => return Ok(Outcome::Quit),
+                    "help" | "h" => {
+                        self.pending.clear();
+                        return Ok(Outcome::Output(HELP.to_owned()));
+                    }
+                    "ast" => Command::Ast,
+                    "tokens" => Command::Tokens,
+                    "type" | "t" => Command::Type,
+                    name => {
+                        let message =
+                            format!("unknown command ':{name}', see :help");
+                        self.pending.clear();
+                        return Ok(Outcome::Output(message));
+                    }
+                };
+                // Blank out the command so that spans still match the line.
+                (command, " ".repeat(prefix) + &self.pending[prefix..])
+            }
+            None => (Command::Run, self.pending.clone()),
+        };
+        let Ok(preprocessed) = preprocess(code.char_indices()) else {
+            // The comment may end on a later line.
+            return Ok(Outcome::Incomplete);
+        };
+
+        let offset = self.session.len();
+        let start = Position {
+            line: self.session.matches('\n').count() + 1,
+            col: 1,
+        };
+        let scanned = scan_at(
+            &preprocessed,
+            offset,
+            start,
+            &mut self.lexer_output.text,
+            &mut self.known_ids,
+            &mut self.lexer_output.id_table,
+        );
+        let parsed = scanned.and_then(|tokens| {
+            self.lexer_output.tokens = tokens;
+            self.parse(&command)
+        });
+        if let Err(e) = &parsed {
+            if is_incomplete(e, &self.lexer_output.tokens) {
+                return Ok(Outcome::Incomplete);
+            }
+        }
+        self.session.push_str(&self.pending);
+        self.pending.clear();
+        let input = parsed?;
+        if let Command::Tokens = command {
+            let mut output = vec![];
+            write_tokens(&mut output, &self.lexer_output, &self.session)
+                .unwrap();
+            let output = String::from_utf8(output).unwrap();
+            return Ok(Outcome::Output(output.trim_end().to_owned()));
+        }
+        let Some(input) = input else {
+            return Ok(Outcome::Output(String::new()));
+        };
+        self.run(command, input).map(Outcome::Output)
+    }
+
+    /// Parse the tokens of the last input, or `None` if there are none or
+    /// `command` only needs the tokens.
+    fn parse(&self, command: &Command) -> Result<Option<Input>, Error> {
+        let tokens = &self.lexer_output.tokens;
+        let (Some(first), Some(last)) = (tokens.first(), tokens.last()) else {
+            return Ok(None);
+        };
+        let input = match (command, &first.kind, &last.kind) {
+            (Command::Tokens, ..) => return Ok(None),
+            (Command::Type, ..) => {
+                parse_full_expression(tokens).map(Input::Expression)
+            }
+            (_, TokenKind::Qualifier(_), _) => {
+                parse(tokens).map(Input::Declarations)
+            }
+            (_, _, TokenKind::Semicolon | TokenKind::RightBrace) => {
+                parse_block_items(tokens).map(Input::Statements)
+            }
+            _ => parse_full_expression(tokens).map(Input::Expression),
+        };
+        input.map(Some).map_err(|mut e| {
+            // An error at the first token has no span of its own.
+            if e.span.start < first.span.start {
+                e.span = first.span;
+            }
+            suggest_keyword(e, &self.lexer_output)
+        })
+    }
+
+    fn run(&mut self, command: Command, input: Input) -> Result<String, Error> {
+        let lexer_output = &self.lexer_output;
+        match (command, input) {
+            (Command::Ast, input) => {
+                let context = SerdeContext {
+                    spans: false,
+                    names: Some(
+                        (0..lexer_output.id_table.len())
+                            .map(|id| lexer_output.name(id).to_owned())
+                            .collect(),
+                    ),
+                };
+                Ok(with_context(context, || match &input {
+                    Input::Declarations(ast) => to_json(ast),
+                    Input::Statements(items) => to_json(items),
+                    Input::Expression(e) => to_json(e),
+                }))
+            }
+            (Command::Type, Input::Expression(e)) => {
+                let ty = self.interpreter.type_of(&e, lexer_output)?;
+                Ok(ty.to_string())
+            }
+            (_, Input::Declarations(ast)) => {
+                for d in ast.external_declarations {
+                    self.interpreter.declare(d, lexer_output)?;
+                }
+                Ok(String::new())
+            }
+            (_, Input::Statements(items)) => {
+                self.interpreter.execute(&items, lexer_output)?;
+                Ok(String::new())
+            }
+            (_, Input::Expression(e)) => {
+                let t = self.interpreter.evaluate(&e, lexer_output)?;
+                if t.value == Value::Void {
+                    return Ok(String::new());
+                }
+                let value = self.interpreter.show(&t, lexer_output);
+                Ok(format!("({}) {value}", t.ty))
+            }
+        }
+    }
+}
+
+fn to_json(node: &impl serde::Serialize) -> String {
+    serde_json::to_string_pretty(node).unwrap()
+}
+
+/// Whether `e` is about the end of `tokens` while a bracket is still open,
+/// so that the input may go on.
+fn is_incomplete(e: &Error, tokens: &[Token]) -> bool {
+    let Some(last) = tokens.last() else {
+        return false;
+    };
+    match e.error_kind {
+        // What `parse_right` reports when the tokens run out
+        ErrorKind::UnmatchedParenthesis(_) => e.span.start >= last.span.end,
+        // Something else missing at the end, e.g. an operand in `f(1 +`
+        _ => e.span.start >= last.span.start && is_open(tokens),
+    }
+}
+
+/// Whether `tokens` open more brackets than they close.
+fn is_open(tokens: &[Token]) -> bool {
+    let depth = tokens.iter().fold(0, |depth, t| match t.kind {
+        TokenKind::LeftParen
+        | TokenKind::LeftSqBracket
+        | TokenKind::LeftBrace => depth + 1,
+        TokenKind::RightParen
+        | TokenKind::RightSqBracket
+        | TokenKind::RightBrace => depth - 1,
+        _ => depth,
+    });
+    depth > 0
+}
+
+/// Read lines from `input` until it ends or `:quit`, printing results to
+/// `output` and errors to stderr. Prompts are printed only if `interactive`.
+pub fn run(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    interactive: bool,
+) -> Result<()> {
+    let mut repl = Repl::default();
+    loop {
+        if interactive {
+            let prompt = if repl.is_pending() { "... " } else { "> " };
+            write!(output, "{prompt}")?;
+            output.flush()?;
+        }
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let line = line.trim_end_matches(['\n', '\r']);
+        match repl.feed(line) {
+            Ok(Outcome::Quit) => return Ok(()),
+            Ok(Outcome::Output(s)) if !s.is_empty() => writeln!(output, "{s}")?,
+            Ok(_) => {}
+            Err(e) => {
+                let d = Diagnostic::from(&e);
+                render_human(&d, Path::new("<repl>"), repl.session())?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    /// Feed `lines`, showing each outcome as its output, `...` if the input
+    /// goes on or the code of its error.
+    fn feed(lines: &[&str]) -> Vec<String> {
+        let mut repl = Repl::default();
+        lines
+            .iter()
+            .map(|line| match repl.feed(line) {
+                Ok(Outcome::Incomplete) => "...".to_owned(),
+                Ok(Outcome::Output(s)) => s,
+                Ok(Outcome::Quit) => ":quit".to_owned(),
+                Err(e) => e.error_kind.code().to_owned(),
+            })
+            .collect()
+    }
+
+    #[test_case(&["int x = 2;", "x = x * 3;", "x"], &["", "", "(int) 6"])]
+    #[test_case(&["1 + 2.5", "void"], &["(double) 3.5", "E0004"])]
+    #[test_case(
+        &["int f(int n) {", "  return (n", "  + 1);", "}", "f(1)"],
+        &["...", "...", "...", "", "(int) 2"];
+        "multi-line"
+    )]
+    #[test_case(&["x", "int x;", "x"], &["E0013", "", "(int) 0"])]
+    #[test_case(&["/* a", "b */ 1"], &["...", "(int) 1"])]
+    #[test_case(&["{ int y = 1; }", "y"], &["", "E0013"]; "block scope")]
+    #[test_case(&["1 2"], &["E0012"])]
+    #[test_case(&[":type 1 + 2.0", ":t 1 +"], &["double", "E0005"])]
+    #[test_case(&["int a[2];", ":type a[1 ]"], &["", "int"])]
+    #[test_case(&[":tokens", "", ":tokens a+"], &["", "", "3:9 Id a\n3:10 Plus +"])]
+    #[test_case(&[":ast (1"], &["..."])]
+    #[test_case(&[":what", ":q"], &["unknown command ':what', see :help", ":quit"])]
+    fn test_feed(lines: &[&str], expected: &[&str]) {
+        assert_eq!(feed(lines), expected);
+    }
+
+    #[test]
+    fn test_ast() {
+        let output = feed(&["int x;", ":ast x"]);
+        assert_eq!(
+            output[1],
+            "{\n  \"Atom\": {\n    \"Identifier\": \"x\"\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn test_session() {
+        let mut repl = Repl::default();
+        repl.feed("int a;").unwrap();
+        repl.feed("(a +").unwrap();
+        let e = repl.feed("b)").unwrap_err();
+        assert_eq!(repl.session(), "int a;\n(a +\nb)\n");
+        assert_eq!(&repl.session()[e.span.start..e.span.end], "b");
+        assert_eq!(e.span.start_pos, Position { line: 3, col: 1 });
+    }
+}
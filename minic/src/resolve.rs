@@ -0,0 +1,251 @@
+//! Name resolution: check that every identifier used in an expression is
+//! declared in an enclosing scope.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        visit::{
+            walk_compound_statement, walk_for_statement,
+            walk_postfix_expression,
+        },
+        *,
+    },
+    error::{Error, ErrorKind},
+    lexer::LexerOutput,
+    suggest::{add_suggestion, most_similar},
+    token::{keyword_map, Span},
+};
+
+/// Resolve the identifiers in `ast`. Returns the errors and warnings found,
+/// in source order.
+pub fn resolve(
+    ast: &TranslationUnit,
+    lexer_output: &LexerOutput,
+) -> Vec<Error> {
+    analyze(ast, lexer_output).errors
+}
+
+/// An occurrence of an identifier that is declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub id: usize,
+    pub span: Span,
+    /// The span of the identifier in the declaration, `span` itself for
+    /// the declaration
+    pub declaration: Span,
+}
+
+#[derive(Debug)]
+pub struct Analysis {
+    pub errors: Vec<Error>,
+    /// In source order
+    pub references: Vec<Reference>,
+}
+
+/// [`resolve`], also returning what each identifier refers to.
+pub fn analyze(ast: &TranslationUnit, lexer_output: &LexerOutput) -> Analysis {
+    let mut resolver = Resolver {
+        lexer_output,
+        scopes: vec![HashMap::new()],
+        errors: vec![],
+        references: vec![],
+    };
+    resolver.visit_translation_unit(ast);
+    Analysis {
+        errors: resolver.errors,
+        references: resolver.references,
+    }
+}
+
+struct Resolver<'a> {
+    lexer_output: &'a LexerOutput,
+    /// Identifiers declared in each enclosing scope, outermost first, with
+    /// the spans of their declarations
+    scopes: Vec<HashMap<usize, Span>>,
+    errors: Vec<Error>,
+    references: Vec<Reference>,
+}
+
+impl Resolver<'_> {
+    fn declare(&mut self, declarator: &Declarator) {
+        let (id, span) = declarator.identifier();
+        self.scopes.last_mut().unwrap().insert(id, span);
+        self.refer(id, span, span);
+    }
+
+    fn lookup(&self, id: usize) -> Option<Span> {
+        self.scopes.iter().rev().find_map(|s| s.get(&id).copied())
+    }
+
+    fn refer(&mut self, id: usize, span: Span, declaration: Span) {
+        self.references.push(Reference {
+            id,
+            span,
+            declaration,
+        });
+    }
+
+    fn in_scope<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn undeclared(&mut self, id: usize, span: Span, called: bool) {
+        let name = self.lexer_output.name(id).to_owned();
+        let kind = if called {
+            ErrorKind::ImplicitFunctionDeclaration(name)
+        } else {
+            ErrorKind::UndeclaredIdentifier(name)
+        };
+        let mut e = Error::new(span, kind);
+        let visible: HashMap<_, _> = self
+            .scopes
+            .iter()
+            .flatten()
+            .map(|(&id, &span)| (self.lexer_output.name(id), span))
+            .collect();
+        // A misspelled keyword such as `retrun;` parses as an expression.
+        let keywords = keyword_map();
+        let keywords = keywords.keys().filter(|_| !called);
+        let name = self.lexer_output.name(id);
+        let candidates = visible.keys().chain(keywords).copied();
+        if let Some(similar) = most_similar(name, candidates) {
+            let origin = visible.get(similar).copied().unwrap_or(span);
+            add_suggestion(&mut e, similar, span, origin);
+        }
+        self.errors.push(e);
+    }
+}
+
+impl Visitor for Resolver<'_> {
+    fn visit_function_definition(&mut self, f: &FunctionDefinition) {
+        self.declare(&f.declarator);
+        // The parameters are in the same scope as the body.
+        self.in_scope(|r| {
+            for p in f.declarator.parameters().unwrap_or_default() {
+                r.declare(&p.declarator);
+            }
+            walk_compound_statement(r, &f.compound_statement);
+        });
+    }
+
+    fn visit_init_declarator(&mut self, d: &InitDeclarator) {
+        // The scope of an identifier starts right after its declarator, so
+        // it is visible in its own initializer.
+        self.declare(&d.declarator);
+        if let Some(initializer) = &d.initializer {
+            self.visit_initializer(initializer);
+        }
+    }
+
+    fn visit_compound_statement(&mut self, c: &CompoundStatement) {
+        self.in_scope(|r| walk_compound_statement(r, c));
+    }
+
+    fn visit_for_statement(&mut self, s: &ForStatement) {
+        self.in_scope(|r| walk_for_statement(r, s));
+    }
+
+    fn visit_postfix_expression(&mut self, e: &PostfixExpression) {
+        match (&*e.operand, &e.postfix) {
+            (
+                Expression::Atom(AtomExpression::Identifier(id), span),
+                PostfixExpressionPostfix::Call(arguments),
+            ) if self.lookup(*id).is_none() => {
+                self.undeclared(*id, *span, true);
+                // Like C89, treat the call as a declaration so it is only
+                // warned about once.
+                self.scopes[0].insert(*id, *span);
+                self.refer(*id, *span, *span);
+                for a in arguments {
+                    self.visit_expression(a);
+                }
+            }
+            _ => walk_postfix_expression(self, e),
+        }
+    }
+
+    /// Only reached for identifiers used in expressions, as declarators are
+    /// not walked.
+    fn visit_identifier(&mut self, id: &usize, span: Span) {
+        match self.lookup(*id) {
+            Some(declaration) => self.refer(*id, span, declaration),
+            None => self.undeclared(*id, span, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{diagnostic::apply_fix_its, lexer::scan, parser::parse};
+
+    fn resolve_src(src: &str) -> Vec<Error> {
+        let lexer_output = scan(src).unwrap();
+        resolve(&parse(&lexer_output.tokens).unwrap(), &lexer_output)
+    }
+
+    #[test_case("int f(int a) { return a; }")]
+    #[test_case("int g; int f(int a) { int b = a; { int c = b + g; } }")]
+    #[test_case("int f(int a) { for (int i = 0; i < a; i = i + 1) i; }")]
+    #[test_case("int f(int a) { return f(a); }")]
+    #[test_case("int (*f(int a))[2] { return a; }")]
+    fn test_resolve_ok(src: &str) {
+        assert_eq!(resolve_src(src), vec![]);
+    }
+
+    #[test]
+    fn test_references() {
+        let src =
+            "int a; int f(int a) { a; { int a; a; } g(a); g(); } int b = a;";
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let references: Vec<_> = analyze(&ast, &lexer_output)
+            .references
+            .iter()
+            .map(|r| (r.span.start, r.declaration.start))
+            .collect();
+        assert_eq!(
+            references,
+            [
+                (4, 4),
+                (11, 11),
+                (17, 17),
+                (22, 17),
+                (31, 31),
+                (34, 31),
+                (39, 39),
+                (41, 17),
+                (45, 39),
+                (56, 56),
+                (60, 4)
+            ]
+        );
+    }
+
+    #[test_case("int f(int a) { return b; }" => vec![("E0013", None)])]
+    #[test_case("int f(int a) { { int b; } return b; }" => vec![("E0013", None)])]
+    #[test_case("int f(int a) { for (int i = 0; i < a; i = i + 1) ; return i; }"
+        => vec![("E0013", None)])]
+    #[test_case("void (*f(int a, void (*g)(int b)))(int c) { return b; }"
+        => vec![("E0013", None)])]
+    #[test_case("int f(int count) { return cuont; }"
+        => vec![("E0013", Some("int f(int count) { return count; }".to_owned()))])]
+    #[test_case("int f(int x) { if (x) retrun; }"
+        => vec![("E0013", Some("int f(int x) { if (x) return; }".to_owned()))])]
+    #[test_case("int f(int a) { g(a); g(a); }" => vec![("E0014", None)])]
+    fn test_resolve_error(src: &str) -> Vec<(&'static str, Option<String>)> {
+        resolve_src(src)
+            .iter()
+            .map(|e| {
+                let fixed = (!e.details.fix_its.is_empty())
+                    .then(|| apply_fix_its(src, &e.details.fix_its));
+                (e.error_kind.code(), fixed)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,298 @@
+//! Conversion of [`Function`]s into static single assignment form, where
+//! every register has one definition, and back.
+
+use crate::{
+    cfg::{BlockId, Dominators},
+    dataflow::solve,
+    ir::{Constant, Function, Instruction, Liveness, Op, Phi, Reg, Ty},
+};
+
+/// Give every definition of a register defined more than once, or used
+/// where no definition may have run, a register of its own, with phis
+/// where definitions meet. Phis go in the iterated dominance frontiers of
+/// the definitions, where the register is live, as Cytron et al. describe.
+/// A use that no definition reaches reads zero.
+pub fn into_ssa(f: &mut Function) {
+    let successors = f.successors();
+    let predecessors = f.predecessors();
+    let dominators = Dominators::new(&successors);
+    let frontiers = dominators.frontiers(&successors);
+    let live_in = solve(&Liveness(f), &successors).before;
+
+    // The blocks defining each register, once for each definition
+    let mut definitions = vec![vec![]; f.registers.len()];
+    for (b, block) in f.blocks.iter().enumerate() {
+        for i in &block.instructions {
+            if let Some(d) = i.dest {
+                definitions[d].push(b);
+            }
+        }
+    }
+    let is_variable: Vec<_> = (0..f.registers.len())
+        .map(|r| definitions[r].len() > 1 || live_in[0].contains(&r))
+        .collect();
+
+    // The register each phi is for
+    let mut phi_variables = vec![vec![]; f.blocks.len()];
+    for v in (0..f.registers.len()).filter(|&r| is_variable[r]) {
+        let mut has_phi = vec![false; f.blocks.len()];
+        let mut queued = vec![false; f.blocks.len()];
+        let mut worklist = definitions[v].clone();
+        for &b in &worklist {
+            queued[b] = true;
+        }
+        while let Some(b) = worklist.pop() {
+            for &y in &frontiers[b] {
+                if has_phi[y] || !live_in[y].contains(&v) {
+                    continue;
+                }
+                has_phi[y] = true;
+                f.blocks[y].phis.push(Phi {
+                    dest: v,
+                    args: predecessors[y].iter().map(|&p| (p, v)).collect(),
+                });
+                phi_variables[y].push(v);
+                if !queued[y] {
+                    queued[y] = true;
+                    worklist.push(y);
+                }
+            }
+        }
+    }
+
+    let mut renamer = Renamer {
+        stacks: vec![vec![]; f.registers.len()],
+        is_variable,
+        zeros: vec![None; f.registers.len()],
+        phi_variables,
+    };
+    renamer.rename(f, &dominators.children());
+    let zeros: Vec<_> = renamer
+        .zeros
+        .iter()
+        .flatten()
+        .map(|&(dest, ty)| Instruction {
+            dest: Some(dest),
+            op: Op::Const(Constant::zero(ty)),
+        })
+        .collect();
+    f.blocks[0].instructions.splice(0..0, zeros);
+}
+
+struct Renamer {
+    /// The registers that stand for each variable, innermost last
+    stacks: Vec<Vec<Reg>>,
+    is_variable: Vec<bool>,
+    /// The register holding zero for each variable read before any
+    /// definition, with its type
+    zeros: Vec<Option<(Reg, Ty)>>,
+    phi_variables: Vec<Vec<Reg>>,
+}
+
+enum Visit {
+    Enter(BlockId),
+    /// Pop the definitions of a block
+    Leave(Vec<Reg>),
+}
+
+impl Renamer {
+    /// The register standing for `v` where it is used.
+    fn current(&mut self, f: &mut Function, v: Reg) -> Reg {
+        if !self.is_variable[v] {
+            return v;
+        }
+        if let Some(&r) = self.stacks[v].last() {
+            return r;
+        }
+        let ty = f.registers[v];
+        let (zero, _) =
+            *self.zeros[v].get_or_insert_with(|| (f.new_register(ty), ty));
+        zero
+    }
+
+    /// A new register for a definition of `v`.
+    fn define(
+        &mut self,
+        f: &mut Function,
+        v: Reg,
+        pushed: &mut Vec<Reg>,
+    ) -> Reg {
+        if !self.is_variable[v] {
+            return v;
+        }
+        let r = f.new_register(f.registers[v]);
+        self.stacks[v].push(r);
+        pushed.push(v);
+        r
+    }
+
+    /// Rename the blocks in a preorder walk of the dominator tree.
+    fn rename(&mut self, f: &mut Function, children: &[Vec<BlockId>]) {
+        let mut stack = vec![Visit::Enter(0)];
+        while let Some(visit) = stack.pop() {
+            let b = match visit {
+                Visit::Enter(b) => b,
+                Visit::Leave(pushed) => {
+                    for v in pushed {
+                        self.stacks[v].pop();
+                    }
+                    continue;
+                }
+            };
+            let mut pushed = vec![];
+            for k in 0..f.blocks[b].phis.len() {
+                let v = self.phi_variables[b][k];
+                f.blocks[b].phis[k].dest = self.define(f, v, &mut pushed);
+            }
+            for k in 0..f.blocks[b].instructions.len() {
+                let mut op = f.blocks[b].instructions[k].op.clone();
+                for r in op.operands_mut() {
+                    *r = self.current(f, *r);
+                }
+                let dest = f.blocks[b].instructions[k]
+                    .dest
+                    .map(|d| self.define(f, d, &mut pushed));
+                f.blocks[b].instructions[k] = Instruction { dest, op };
+            }
+            let mut terminator = f.blocks[b].terminator.clone();
+            for r in terminator.operands_mut() {
+                *r = self.current(f, *r);
+            }
+            f.blocks[b].terminator = terminator;
+            for s in f.blocks[b].terminator.successors() {
+                for k in 0..f.blocks[s].phis.len() {
+                    let v = self.phi_variables[s][k];
+                    let r = self.current(f, v);
+                    for arg in &mut f.blocks[s].phis[k].args {
+                        if arg.0 == b {
+                            arg.1 = r;
+                        }
+                    }
+                }
+            }
+            stack.push(Visit::Leave(pushed));
+            for &c in children[b].iter().rev() {
+                stack.push(Visit::Enter(c));
+            }
+        }
+    }
+}
+
+/// Replace the phis with copies. Each phi gets a new register that its
+/// predecessors copy their argument to at their end, and that it copies
+/// from at the start of its block, so that the copies for one edge cannot
+/// overwrite what others read.
+pub fn out_of_ssa(f: &mut Function) {
+    for b in 0..f.blocks.len() {
+        let phis = std::mem::take(&mut f.blocks[b].phis);
+        let mut copies = vec![];
+        for phi in phis {
+            let temporary = f.new_register(f.registers[phi.dest]);
+            for (p, r) in phi.args {
+                f.blocks[p].instructions.push(Instruction {
+                    dest: Some(temporary),
+                    op: Op::Copy(r),
+                });
+            }
+            copies.push(Instruction {
+                dest: Some(phi.dest),
+                op: Op::Copy(temporary),
+            });
+        }
+        f.blocks[b].instructions.splice(0..0, copies);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{
+        ir::{lower, run, Program},
+        lexer::scan,
+        parser::parse,
+    };
+
+    fn lower_source(src: &str) -> Program {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        lower(&ast, &lexer_output).unwrap()
+    }
+
+    const SUM: &str = "int f(int n) { int s = 0; int i; \
+                       for (i = 0; i < n; i = i + 1) s = s + i; return s; }";
+    const SWAP: &str = "int f(int n) { int a = 1; int b = 2; int t; \
+                        while (n > 0) { t = a; a = b; b = t; n = n - 1; } \
+                        return a * 10 + b; }";
+    const MAYBE: &str = "int f(int n) { int x; if (n) x = 5; return x; }";
+
+    #[test_case(SUM; "sum")]
+    #[test_case(SWAP; "swap")]
+    #[test_case(MAYBE; "maybe")]
+    fn test_single_definition(src: &str) {
+        let mut program = lower_source(src);
+        let f = &mut program.functions[0];
+        into_ssa(f);
+        let mut defined = HashSet::new();
+        for block in &f.blocks {
+            let phis = block.phis.iter().map(|phi| phi.dest);
+            let dests = block.instructions.iter().filter_map(|i| i.dest);
+            for d in phis.chain(dests) {
+                assert!(defined.insert(d), "%{d} is defined twice in\n{f}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_phis() {
+        let mut program = lower_source(SWAP);
+        let f = &mut program.functions[0];
+        into_ssa(f);
+        // Only the loop header merges definitions: `a`, `b` and `n`, but
+        // not `t`, which is dead there.
+        let phis: Vec<_> = f.blocks.iter().map(|b| b.phis.len()).collect();
+        let header = phis.iter().position(|&n| n > 0).unwrap();
+        assert_eq!(phis[header], 3);
+        assert_eq!(phis.iter().sum::<usize>(), 3);
+        assert_eq!(f.blocks[header].phis[0].args.len(), 2);
+    }
+
+    #[test]
+    fn test_zero() {
+        let mut program = lower_source(MAYBE);
+        let f = &mut program.functions[0];
+        into_ssa(f);
+        assert!(matches!(
+            f.blocks[0].instructions[0].op,
+            Op::Const(Constant::Int(0))
+        ));
+    }
+
+    #[test_case(SUM, 5, 10; "sum")]
+    #[test_case(SWAP, 3, 21; "swap odd")]
+    #[test_case(SWAP, 2, 12; "swap even")]
+    #[test_case(MAYBE, 0, 0; "maybe not")]
+    #[test_case(MAYBE, 1, 5; "maybe")]
+    fn test_round_trip(src: &str, arg: i32, expected: i32) {
+        let mut program = lower_source(src);
+        let f = &mut program.functions[0];
+        into_ssa(f);
+        assert_eq!(
+            run(&program, "f", &[Constant::Int(arg)]),
+            Some(Constant::Int(expected))
+        );
+        out_of_ssa(&mut program.functions[0]);
+        assert!(program.functions[0]
+            .blocks
+            .iter()
+            .all(|b| b.phis.is_empty()));
+        assert_eq!(
+            run(&program, "f", &[Constant::Int(arg)]),
+            Some(Constant::Int(expected))
+        );
+    }
+}
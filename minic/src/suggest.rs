@@ -0,0 +1,164 @@
+//! "Did you mean" suggestions for misspelled keywords and identifiers.
+
+use crate::{
+    diagnostic::{FixIt, Note},
+    error::Error,
+    lexer::LexerOutput,
+    token::{keyword_map, Span, Token, TokenKind},
+};
+
+/// The optimal string alignment distance between `a` and `b`: the number of
+/// insertions, deletions, substitutions and transpositions of adjacent
+/// characters needed to turn one into the other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<_> = a.chars().collect();
+    let b: Vec<_> = b.chars().collect();
+    // d[i][j] is the distance between a[..i] and b[..j].
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// The candidate closest to `name`, if any is close enough to be a likely
+/// typo. Ties are broken alphabetically so the result is deterministic.
+pub fn most_similar<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let len = name.chars().count();
+    let max_distance = len.max(3) / 3;
+    candidates
+        .into_iter()
+        .filter(|&c| c != name)
+        .map(|c| (edit_distance(name, c), c))
+        // Changing every character is a different word, not a typo.
+        .filter(|&(d, _)| d <= max_distance && d < len)
+        .min()
+        .map(|(_, c)| c)
+}
+
+/// Add a "did you mean" note and fix-it to a parse error caused by a
+/// misspelled keyword, e.g. `retrun x;` or `doubel y;`.
+///
+/// A keyword can only be misspelled where an identifier is out of place,
+/// so only identifiers starting the statement or declaration the error is
+/// in, or directly followed by another identifier, are considered.
+pub fn suggest_keyword(mut e: Error, lexer_output: &LexerOutput) -> Error {
+    let tokens = &lexer_output.tokens;
+    let end = tokens.partition_point(|t| t.span.start <= e.span.start);
+    let start = tokens[..end]
+        .iter()
+        .rposition(|t| {
+            matches!(
+                t.kind,
+                TokenKind::Semicolon
+                    | TokenKind::LeftBrace
+                    | TokenKind::RightBrace
+            )
+        })
+        .map_or(0, |i| i + 1);
+    let keywords = keyword_map();
+    let typo = (start..end).find_map(|i| {
+        let Token {
+            kind: TokenKind::Id(id),
+            span,
+        } = tokens[i]
+        else {
+            return None;
+        };
+        let misplaced = i == start
+            || matches!(
+                tokens.get(i + 1),
+                Some(Token {
+                    kind: TokenKind::Id(_),
+                    ..
+                })
+            );
+        if !misplaced {
+            return None;
+        }
+        most_similar(lexer_output.name(id), keywords.keys().copied())
+            .map(|keyword| (keyword, span))
+    });
+    if let Some((keyword, span)) = typo {
+        add_suggestion(&mut e, keyword, span, span);
+    }
+    e
+}
+
+/// Suggest replacing the text at `span` with `suggestion`. The note points
+/// at `origin`, where the suggestion comes from.
+pub fn add_suggestion(
+    e: &mut Error,
+    suggestion: &str,
+    span: Span,
+    origin: Span,
+) {
+    e.details.notes.push(Note {
+        span: origin,
+        message: format!("did you mean '{suggestion}'?"),
+    });
+    // The typo is the real problem, so the other fixes would only make
+    // things worse.
+    e.details.fix_its = vec![FixIt::replace(span, suggestion)];
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{diagnostic::apply_fix_its, lexer::scan, parser::parse};
+
+    #[test_case("return", "return" => 0)]
+    #[test_case("retrun", "return" => 1)]
+    #[test_case("doubel", "double" => 1)]
+    #[test_case("whle", "while" => 1)]
+    #[test_case("x", "yy" => 2)]
+    #[test_case("", "int" => 3)]
+    fn test_edit_distance(a: &str, b: &str) -> usize {
+        edit_distance(a, b)
+    }
+
+    #[test_case("retrun", &["return", "int"] => Some("return"))]
+    #[test_case("fo", &["foo", "fooo"] => Some("foo"))]
+    #[test_case("x", &["y"] => None)]
+    #[test_case("abcdef", &["uvwxyz"] => None)]
+    #[test_case("ab", &["ac", "bb"] => Some("ac"))]
+    fn test_most_similar(
+        name: &str,
+        candidates: &[&'static str],
+    ) -> Option<&'static str> {
+        most_similar(name, candidates.iter().copied())
+    }
+
+    #[test_case("int f(int x) { retrun x; }" => "int f(int x) { return x; }")]
+    #[test_case("doubel y;" => "double y;")]
+    #[test_case("int f(int x) { doubel y; }" => "int f(int x) { double y; }")]
+    #[test_case("int f(int x) { whiel (x) { } }" => "int f(int x) { while (x) { } }")]
+    fn test_suggest_keyword(src: &str) -> String {
+        let lexer_output = scan(src).unwrap();
+        let e = parse(&lexer_output.tokens).unwrap_err();
+        let e = suggest_keyword(e, &lexer_output);
+        assert_eq!(e.details.notes.len(), 1);
+        let fixed = apply_fix_its(src, &e.details.fix_its);
+        parse(&scan(&fixed).unwrap().tokens).unwrap();
+        fixed
+    }
+}
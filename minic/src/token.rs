@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use maplit::hashmap;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq)]
 #[repr(u32)]
@@ -58,20 +59,83 @@ pub enum TokenKind {
     Break = 30,
 }
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
-#[cfg_attr(test, derive(PartialEq))]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
 pub struct Position {
     pub line: usize,
     pub col: usize,
 }
 
-#[repr(C)]
+/// A half-open byte range `start..end` of the source, together with the
+/// line and column of both ends. `end_pos` is the position right after the
+/// last character.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub start_pos: Position,
+    pub end_pos: Position,
+}
+
+impl Span {
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        let (start, start_pos) = if other.start < self.start {
+            (other.start, other.start_pos)
+        } else {
+            (self.start, self.start_pos)
+        };
+        let (end, end_pos) = if other.end > self.end {
+            (other.end, other.end_pos)
+        } else {
+            (self.end, self.end_pos)
+        };
+        Span {
+            start,
+            end,
+            start_pos,
+            end_pos,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The empty span right after `self`.
+    pub fn after(self) -> Span {
+        Span {
+            start: self.end,
+            end: self.end,
+            start_pos: self.end_pos,
+            end_pos: self.end_pos,
+        }
+    }
+
+    /// The empty span at the end of `src`.
+    pub fn end_of(src: &str) -> Span {
+        let last_line = src.rsplit('\n').next().unwrap();
+        let pos = Position {
+            line: src.matches('\n').count() + 1,
+            col: last_line.len() + 1,
+        };
+        Span {
+            start: src.len(),
+            end: src.len(),
+            start_pos: pos,
+            end_pos: pos,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct Token {
     pub kind: TokenKind,
-    pub pos: Position,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,230 @@
+//! A line-oriented, human-readable token format: one `line:col KIND lexeme`
+//! line per token, e.g. `2:5 Relop >=`. `KIND` is the name of the
+//! [`TokenKind`] variant, without its value.
+
+use std::{collections::HashMap, io::Write};
+
+use crate::{
+    lexer::{scan, LexerOutput},
+    token::{Position, TokenKind},
+};
+
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(test, derive(PartialEq))]
+pub enum TokenDumpError {
+    #[error("line {0}: expect 'line:col KIND lexeme'")]
+    Malformed(usize),
+    #[error("line {0}: '{1}' is not exactly one token")]
+    NotOneToken(usize, String),
+    #[error("line {line}: '{lexeme}' is {found}, not {kind}")]
+    KindMismatch {
+        line: usize,
+        lexeme: String,
+        kind: String,
+        found: &'static str,
+    },
+}
+
+/// A token as it appears in a dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DumpedToken {
+    pub pos: Position,
+    pub kind: TokenKind,
+    pub lexeme: String,
+}
+
+pub fn kind_name(kind: &TokenKind) -> &'static str {
+    match kind {
+        TokenKind::IntegerConstant(_) => "IntegerConstant",
+        TokenKind::FloatingConstant(_) => "FloatingConstant",
+        TokenKind::Id(_) => "Id",
+        TokenKind::If => "If",
+        TokenKind::Else => "Else",
+        TokenKind::While => "While",
+        TokenKind::For => "For",
+        TokenKind::Do => "Do",
+        TokenKind::Qualifier(_) => "Qualifier",
+        TokenKind::Plus => "Plus",
+        TokenKind::Minus => "Minus",
+        TokenKind::Star => "Star",
+        TokenKind::Divide => "Divide",
+        TokenKind::Relop(_) => "Relop",
+        TokenKind::LeftBrace => "LeftBrace",
+        TokenKind::RightBrace => "RightBrace",
+        TokenKind::LeftParen => "LeftParen",
+        TokenKind::RightParen => "RightParen",
+        TokenKind::LeftSqBracket => "LeftSqBracket",
+        TokenKind::RightSqBracket => "RightSqBracket",
+        TokenKind::Semicolon => "Semicolon",
+        TokenKind::Comma => "Comma",
+        TokenKind::Not => "Not",
+        TokenKind::Return => "Return",
+        TokenKind::And => "And",
+        TokenKind::Or => "Or",
+        TokenKind::BitAnd => "BitAnd",
+        TokenKind::BitOr => "BitOr",
+        TokenKind::BitNot => "BitNot",
+        TokenKind::Continue => "Continue",
+        TokenKind::Break => "Break",
+    }
+}
+
+/// The tokens of `lexer_output`, scanned from `src`, as they appear in a
+/// dump.
+pub fn dumped_tokens(
+    lexer_output: &LexerOutput,
+    src: &str,
+) -> Vec<DumpedToken> {
+    lexer_output
+        .tokens
+        .iter()
+        .map(|t| DumpedToken {
+            pos: t.span.start_pos,
+            kind: t.kind.clone(),
+            lexeme: src[t.span.start..t.span.end].to_owned(),
+        })
+        .collect()
+}
+
+pub fn write_tokens(
+    w: &mut impl Write,
+    lexer_output: &LexerOutput,
+    src: &str,
+) -> std::io::Result<()> {
+    for t in dumped_tokens(lexer_output, src) {
+        let Position { line, col } = t.pos;
+        writeln!(w, "{line}:{col} {} {}", kind_name(&t.kind), t.lexeme)?;
+    }
+    Ok(())
+}
+
+/// Parse a dump. Each lexeme is scanned again to get the value of its
+/// token, and identifiers are numbered in order of first appearance like
+/// the lexer does.
+pub fn parse_tokens(dump: &str) -> Result<Vec<DumpedToken>, TokenDumpError> {
+    let mut ids = HashMap::new();
+    dump.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(n, l)| {
+            let line_num = n + 1;
+            let malformed = || TokenDumpError::Malformed(line_num);
+            let mut fields = l.trim().splitn(3, ' ');
+            let (Some(pos), Some(kind), Some(lexeme)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(malformed());
+            };
+            let (line, col) = pos.split_once(':').ok_or_else(malformed)?;
+            let pos = Position {
+                line: line.parse().map_err(|_| malformed())?,
+                col: col.parse().map_err(|_| malformed())?,
+            };
+            let not_one_token =
+                || TokenDumpError::NotOneToken(line_num, lexeme.to_owned());
+            let scanned = scan(lexeme).map_err(|_| not_one_token())?;
+            let [token] = scanned.tokens.as_slice() else {
+                return Err(not_one_token());
+            };
+            if kind_name(&token.kind) != kind {
+                return Err(TokenDumpError::KindMismatch {
+                    line: line_num,
+                    lexeme: lexeme.to_owned(),
+                    kind: kind.to_owned(),
+                    found: kind_name(&token.kind),
+                });
+            }
+            let kind = match token.kind {
+                TokenKind::Id(_) => {
+                    let next_id = ids.len();
+                    TokenKind::Id(*ids.entry(lexeme).or_insert(next_id))
+                }
+                ref kind => kind.clone(),
+            };
+            Ok(DumpedToken {
+                pos,
+                kind,
+                lexeme: lexeme.to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::token::RelopKind;
+
+    fn dump(src: &str) -> String {
+        let mut v = Vec::new();
+        write_tokens(&mut v, &scan(src).unwrap(), src).unwrap();
+        String::from_utf8(v).unwrap()
+    }
+
+    #[test]
+    fn test_write_tokens() {
+        assert_eq!(
+            dump("a\n  >= 1.5 a;"),
+            "1:1 Id a\n2:3 Relop >=\n2:6 FloatingConstant 1.5\n2:10 Id a\n\
+             2:11 Semicolon ;\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_tokens() {
+        let tokens =
+            parse_tokens("1:1 Id b\n\n2:3 Relop >=\n3:1 Id c\n4:1 Id b\n")
+                .unwrap();
+        let kinds: Vec<_> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Id(0),
+                TokenKind::Relop(RelopKind::Ge),
+                TokenKind::Id(1),
+                TokenKind::Id(0)
+            ]
+        );
+    }
+
+    #[test_case("1:1 Id" => TokenDumpError::Malformed(1))]
+    #[test_case("1 Id a" => TokenDumpError::Malformed(1))]
+    #[test_case("1:x Id a" => TokenDumpError::Malformed(1))]
+    #[test_case("1:1 Id a b" => TokenDumpError::NotOneToken(1, "a b".to_owned()))]
+    #[test_case("1:1 FloatingConstant ." => TokenDumpError::NotOneToken(1, ".".to_owned()))]
+    #[test_case("1:1 Id int" => TokenDumpError::KindMismatch {
+        line: 1,
+        lexeme: "int".to_owned(),
+        kind: "Id".to_owned(),
+        found: "Qualifier",
+    })]
+    fn test_parse_tokens_error(dump: &str) -> TokenDumpError {
+        parse_tokens(dump).unwrap_err()
+    }
+
+    macro_rules! token_testcase {
+        ($name:literal) => {
+            (
+                include_str!(concat!("../testcase/token/", $name, ".in")),
+                include_str!(concat!("../testcase/token/", $name, ".tokens")),
+            )
+        };
+    }
+
+    #[test_case(token_testcase!("1"))]
+    #[test_case(token_testcase!("2"))]
+    #[test_case(token_testcase!("3"))]
+    #[test_case(token_testcase!("4"))]
+    #[test_case(token_testcase!("5"))]
+    #[test_case(token_testcase!("6"))]
+    fn test_round_trip((src, expected): (&str, &str)) {
+        assert_eq!(dump(src), expected);
+        let lexer_output = scan(src).unwrap();
+        assert_eq!(
+            parse_tokens(expected).unwrap(),
+            dumped_tokens(&lexer_output, src)
+        );
+    }
+}
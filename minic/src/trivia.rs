@@ -0,0 +1,301 @@
+//! Lossless scanning: every token carries the whitespace and comments
+//! around it as trivia, so that the token texts and trivia, concatenated in
+//! order, are exactly the source.
+//!
+//! A token's trailing trivia is what follows it on the same line, up to and
+//! including the newline. Everything else before a token is its leading
+//! trivia, and what follows the last token's line is [`LosslessOutput::end`].
+
+use std::ops::Range;
+
+use crate::{
+    error::Error,
+    lexer::{scan, LexerOutput},
+    preprocess::{preprocess_keeping_comments, unterminated_comment},
+    token::{Position, Span, Token},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs and other blanks, but not newlines
+    Whitespace,
+    Newline,
+    /// `// ...`, without the newline
+    LineComment,
+    /// `/* ... */`, possibly over several lines
+    BlockComment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct LosslessToken {
+    pub token: Token,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug)]
+pub struct LosslessOutput {
+    pub src: String,
+    /// The usual lexer output, with the same tokens as `tokens`
+    pub lexer_output: LexerOutput,
+    pub tokens: Vec<LosslessToken>,
+    /// Trivia after the last token's trailing trivia, or of the whole file
+    /// if it has no tokens
+    pub end: Vec<Trivia>,
+}
+
+impl LosslessOutput {
+    pub fn text(&self, span: Span) -> &str {
+        &self.src[span.start..span.end]
+    }
+
+    /// The byte ranges of the comments, in order.
+    pub fn comments(&self) -> Vec<Range<usize>> {
+        self.tokens
+            .iter()
+            .flat_map(|t| t.leading.iter().chain(&t.trailing))
+            .chain(&self.end)
+            .filter(|t| {
+                matches!(
+                    t.kind,
+                    TriviaKind::LineComment | TriviaKind::BlockComment
+                )
+            })
+            .map(|t| t.span.start..t.span.end)
+            .collect()
+    }
+
+    /// The texts of all tokens and trivia in order.
+    pub fn pieces(&self) -> impl Iterator<Item = &str> {
+        self.tokens
+            .iter()
+            .flat_map(|t| {
+                let leading = t.leading.iter().map(|t| t.span);
+                let trailing = t.trailing.iter().map(|t| t.span);
+                leading.chain([t.token.span]).chain(trailing)
+            })
+            .chain(self.end.iter().map(|t| t.span))
+            .map(|span| self.text(span))
+    }
+}
+
+/// Scan `src`, which has not been preprocessed, keeping its comments and
+/// whitespace.
+pub fn scan_lossless(src: &str) -> Result<LosslessOutput, Error> {
+    let (preprocessed, comments) =
+        preprocess_keeping_comments(src.char_indices())
+            .map_err(|span| unterminated_comment(span, src))?;
+    let lexer_output = scan(&preprocessed)?;
+    let mut splitter = Splitter {
+        src,
+        comments: &comments,
+        offset: 0,
+        pos: Position { line: 1, col: 1 },
+    };
+    let mut tokens: Vec<LosslessToken> = vec![];
+    for token in &lexer_output.tokens {
+        let mut gap = splitter.split(token.span.start);
+        if let Some(previous) = tokens.last_mut() {
+            previous.trailing = take_line(&mut gap);
+        }
+        tokens.push(LosslessToken {
+            token: token.clone(),
+            leading: gap,
+            trailing: vec![],
+        });
+        splitter.offset = token.span.end;
+        splitter.pos = token.span.end_pos;
+    }
+    let mut end = splitter.split(src.len());
+    if let Some(last) = tokens.last_mut() {
+        last.trailing = take_line(&mut end);
+    }
+    Ok(LosslessOutput {
+        src: src.to_owned(),
+        lexer_output,
+        tokens,
+        end,
+    })
+}
+
+/// Remove and return the trivia up to and including the first newline, or
+/// all of it if there is none.
+fn take_line(trivia: &mut Vec<Trivia>) -> Vec<Trivia> {
+    let line_end = trivia
+        .iter()
+        .position(|t| t.kind == TriviaKind::Newline)
+        .map_or(trivia.len(), |i| i + 1);
+    trivia.drain(..line_end).collect()
+}
+
+/// Splits the text between tokens into trivia.
+struct Splitter<'a> {
+    src: &'a str,
+    /// Byte ranges of the comments, in order
+    comments: &'a [Range<usize>],
+    offset: usize,
+    pos: Position,
+}
+
+impl Splitter<'_> {
+    /// The trivia from the current offset to `end`.
+    fn split(&mut self, end: usize) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        while self.offset < end {
+            let rest = &self.src[self.offset..end];
+            let (kind, len) = match self.comments.first() {
+                Some(comment) if comment.start == self.offset => {
+                    self.comments = &self.comments[1..];
+                    let kind = if rest.starts_with("//") {
+                        TriviaKind::LineComment
+                    } else {
+                        TriviaKind::BlockComment
+                    };
+                    (kind, comment.len())
+                }
+                _ if rest.starts_with('\n') => (TriviaKind::Newline, 1),
+                comment => {
+                    let next_comment =
+                        comment.map_or(end, |c| c.start.min(end));
+                    let len = rest[..next_comment - self.offset]
+                        .find('\n')
+                        .unwrap_or(next_comment - self.offset);
+                    (TriviaKind::Whitespace, len)
+                }
+            };
+            trivia.push(Trivia {
+                kind,
+                span: self.advance(len),
+            });
+        }
+        trivia
+    }
+
+    /// The span of the next `len` bytes, moving past them.
+    fn advance(&mut self, len: usize) -> Span {
+        let start = self.offset;
+        let start_pos = self.pos;
+        for c in self.src[start..start + len].chars() {
+            if c == '\n' {
+                self.pos.line += 1;
+                self.pos.col = 1;
+            } else {
+                self.pos.col += c.len_utf8();
+            }
+        }
+        self.offset += len;
+        Span {
+            start,
+            end: self.offset,
+            start_pos,
+            end_pos: self.pos,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::token::{QualifierKind, TokenKind};
+
+    fn kinds(trivia: &[Trivia]) -> Vec<TriviaKind> {
+        trivia.iter().map(|t| t.kind).collect()
+    }
+
+    #[test_case(""; "empty")]
+    #[test_case(" /* a */ // b\n\n"; "only trivia")]
+    #[test_case("int a; // no newline"; "no newline")]
+    fn test_lossless(src: &str) {
+        round_trip(&scan_lossless(src).unwrap(), src);
+    }
+
+    fn round_trip(output: &LosslessOutput, src: &str) {
+        assert_eq!(output.pieces().collect::<String>(), src);
+        let tokens: Vec<_> = output.tokens.iter().map(|t| &t.token).collect();
+        assert_eq!(
+            tokens,
+            output.lexer_output.tokens.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_testcases() {
+        let mut count = 0;
+        for dir in [
+            "testcase/c",
+            "testcase/comment/block",
+            "testcase/comment/line",
+            "testcase/comment/mix",
+        ] {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    continue;
+                }
+                let src = fs::read_to_string(path).unwrap();
+                // Some are not C, or fail to scan on purpose.
+                if let Ok(output) = scan_lossless(&src) {
+                    round_trip(&output, &src);
+                    count += 1;
+                }
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_attachment() {
+        let src =
+            "// leading\nint a; /* same line */ // also\n\n/* next */ int b;\n";
+        let output = scan_lossless(src).unwrap();
+        use TriviaKind::*;
+        let [int, _, semicolon, int_b, _, last] = &output.tokens[..] else {
+            panic!("expect 6 tokens");
+        };
+        assert_eq!(int.token.kind, TokenKind::Qualifier(QualifierKind::Int));
+        assert_eq!(kinds(&int.leading), [LineComment, Newline]);
+        assert_eq!(output.text(int.leading[0].span), "// leading");
+        assert_eq!(kinds(&int.trailing), [Whitespace]);
+        assert_eq!(
+            kinds(&semicolon.trailing),
+            [Whitespace, BlockComment, Whitespace, LineComment, Newline]
+        );
+        assert_eq!(kinds(&int_b.leading), [Newline, BlockComment, Whitespace]);
+        assert_eq!(kinds(&last.trailing), [Newline]);
+        let comments = output.comments();
+        assert_eq!(comments.len(), 4);
+        assert_eq!(&src[comments[3].clone()], "/* next */");
+        assert!(output.end.is_empty());
+        let comment = semicolon.trailing[1].span;
+        assert_eq!(comment.start_pos, Position { line: 2, col: 8 });
+        assert_eq!(comment.end_pos, Position { line: 2, col: 23 });
+    }
+
+    #[test]
+    fn test_multi_line_comment_position() {
+        let output = scan_lossless("/* a\n b */ x").unwrap();
+        let comment = output.tokens[0].leading[0];
+        assert_eq!(comment.kind, TriviaKind::BlockComment);
+        assert_eq!(comment.span.end_pos, Position { line: 2, col: 6 });
+        assert_eq!(
+            output.tokens[0].token.span.start_pos,
+            Position { line: 2, col: 7 }
+        );
+    }
+
+    #[test]
+    fn test_unterminated() {
+        assert!(scan_lossless("int a; /* b").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! The types of C objects and functions, as declarations give them.
+
+use std::fmt;
+
+use crate::ast::{
+    DeclarationSpecifier, Declarator, DirectDeclaratorModifier,
+    SimpleDirectDeclarator, TypeSpecifier,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Void,
+    Int,
+    Double,
+    Pointer(Box<Type>),
+    Array(Box<Type>, usize),
+    /// The return type and parameter types
+    Function(Box<Type>, Vec<Type>),
+}
+
+impl Type {
+    /// The type `declarator` declares its identifier to have.
+    pub fn of(
+        specifier: &DeclarationSpecifier,
+        declarator: &Declarator,
+    ) -> Self {
+        let DeclarationSpecifier::TypeSpecifier(t, _) = specifier;
+        let base = match t {
+            TypeSpecifier::Void => Type::Void,
+            TypeSpecifier::Int => Type::Int,
+            TypeSpecifier::Double => Type::Double,
+        };
+        Type::declared(base, declarator)
+    }
+
+    fn declared(mut t: Type, declarator: &Declarator) -> Self {
+        for _ in 0..declarator.pointer.0 {
+            t = Type::Pointer(Box::new(t));
+        }
+        // The modifier nearest the identifier applies last, e.g. `a[2][3]`
+        // is an array of 2 arrays of 3.
+        for m in declarator.direct.modifiers.iter().rev() {
+            t = match m {
+                DirectDeclaratorModifier::Array(len, _) => {
+                    Type::Array(Box::new(t), *len)
+                }
+                DirectDeclaratorModifier::Function(parameters, _) => {
+                    let parameters = parameters
+                        .iter()
+                        .map(|p| Type::of(&p.specifier, &p.declarator).decay())
+                        .collect();
+                    Type::Function(Box::new(t), parameters)
+                }
+            };
+        }
+        match &declarator.direct.simple_declarator {
+            SimpleDirectDeclarator::Identifier(..) => t,
+            SimpleDirectDeclarator::Declarator(d) => Type::declared(t, d),
+        }
+    }
+
+    /// The type a value of this type converts to where it is used, e.g. as
+    /// an operand or a parameter: arrays become pointers to their first
+    /// element and functions pointers to them.
+    pub fn decay(self) -> Self {
+        match self {
+            Type::Array(t, _) => Type::Pointer(t),
+            Type::Function(..) => Type::Pointer(Box::new(self)),
+            t => t,
+        }
+    }
+
+    /// The size in scalars: 1 for `int`, `double` and pointers, and 0 for
+    /// `void` and functions.
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Void | Type::Function(..) => 0,
+            Type::Int | Type::Double | Type::Pointer(_) => 1,
+            Type::Array(t, len) => t.size() * len,
+        }
+    }
+
+    /// The size in bytes on x86-64, where `int` takes 4 and `double` and
+    /// pointers 8.
+    pub fn bytes(&self) -> usize {
+        match self {
+            Type::Void | Type::Function(..) => 0,
+            Type::Int => 4,
+            Type::Double | Type::Pointer(_) => 8,
+            Type::Array(t, len) => t.bytes() * len,
+        }
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(self, Type::Int | Type::Double)
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, Type::Int | Type::Double | Type::Pointer(_))
+    }
+
+    /// Write the type declaring `inner`, which is empty for an abstract
+    /// declarator.
+    fn write(&self, f: &mut fmt::Formatter, inner: &str) -> fmt::Result {
+        let base = match self {
+            Type::Void => "void",
+            Type::Int => "int",
+            Type::Double => "double",
+            Type::Pointer(t) => {
+                return match **t {
+                    Type::Array(..) | Type::Function(..) => {
+                        t.write(f, &format!("(*{inner})"))
+                    }
+                    _ => t.write(f, &format!("*{inner}")),
+                }
+            }
+            Type::Array(t, len) => {
+                return t.write(f, &format!("{inner}[{len}]"))
+            }
+            Type::Function(t, parameters) => {
+                let parameters: Vec<_> =
+                    parameters.iter().map(|p| p.to_string()).collect();
+                let parameters = parameters.join(", ");
+                return t.write(f, &format!("{inner}({parameters})"));
+            }
+        };
+        if inner.is_empty() || inner.starts_with('[') {
+            write!(f, "{base}{inner}")
+        } else {
+            write!(f, "{base} {inner}")
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, "")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::{ast::ExternalDeclaration, lexer::scan, parser::parse};
+
+    #[test_case("int a;", "int", 1)]
+    #[test_case("double *a;", "double *", 1)]
+    #[test_case("int a[2][3];", "int[2][3]", 6)]
+    #[test_case("int *a[2];", "int *[2]", 2)]
+    #[test_case("int (*a)[2];", "int (*)[2]", 1)]
+    #[test_case("void a(int b[2], double c);", "void (int *, double)", 0)]
+    #[test_case("int (*a(int b))[2];", "int (*(int))[2]", 0)]
+    #[test_case("int (*a)(int b);", "int (*)(int)", 1)]
+    fn test_type(src: &str, expected: &str, size: usize) {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let ExternalDeclaration::Declaration(d) = &ast.external_declarations[0]
+        else {
+            panic!("expect a declaration");
+        };
+        let t = Type::of(
+            &d.declaration_specifier,
+            &d.init_declarator_list.0[0].declarator,
+        );
+        assert_eq!(t.to_string(), expected);
+        assert_eq!(t.size(), size);
+    }
+
+    #[test]
+    fn test_decay() {
+        let array = Type::Array(Box::new(Type::Int), 2);
+        assert_eq!(array.decay().to_string(), "int *");
+        let function = Type::Function(Box::new(Type::Int), vec![]);
+        assert_eq!(function.decay().to_string(), "int (*)()");
+    }
+}
@@ -0,0 +1,970 @@
+//! An x86-64 back end for the System V ABI: selection of [`Inst`]s over
+//! virtual registers from the IR, and assembly in AT&T syntax once
+//! [`crate::regalloc`] has given the registers locations.
+//!
+//! `int`s take the low 32 bits of a general-purpose register, pointers all
+//! 64, and `double`s an SSE register. `rax`, `rcx`, `rdx` and `xmm15` are
+//! scratch registers and the argument registers are only written to pass
+//! arguments, so instructions need no register of their own. The parameters
+//! are read from their registers before anything else runs.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    cfg::BlockId,
+    ir::{
+        self, scalar_type, BinaryOp, Constant, Op, Program, Reg, Symbol,
+        Terminator, Ty, UnaryOp,
+    },
+    regalloc::{allocate, live_out, Allocator, Class, Location, Stats},
+};
+
+/// The general-purpose registers to allocate, as their 64-, 32- and 8-bit
+/// names. The first [`CALLER_SAVED`] are caller-saved and the rest
+/// callee-saved, so functions without calls that need few registers save
+/// none.
+const GENERAL: [[&str; 3]; 7] = [
+    ["r10", "r10d", "r10b"],
+    ["r11", "r11d", "r11b"],
+    ["rbx", "ebx", "bl"],
+    ["r12", "r12d", "r12b"],
+    ["r13", "r13d", "r13b"],
+    ["r14", "r14d", "r14b"],
+    ["r15", "r15d", "r15b"],
+];
+const CALLER_SAVED: usize = 2;
+/// The SSE registers to allocate, which are all caller-saved
+const FLOAT: [&str; 7] =
+    ["xmm8", "xmm9", "xmm10", "xmm11", "xmm12", "xmm13", "xmm14"];
+const GENERAL_ARGUMENTS: [[&str; 2]; 6] = [
+    ["rdi", "edi"],
+    ["rsi", "esi"],
+    ["rdx", "edx"],
+    ["rcx", "ecx"],
+    ["r8", "r8d"],
+    ["r9", "r9d"],
+];
+const FLOAT_ARGUMENTS: usize = 8;
+
+/// Where a parameter is passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Argument {
+    /// The argument register with this number among those of its class
+    Register(usize),
+    /// The eightbyte with this number among those passed on the stack
+    Stack(usize),
+}
+
+/// A machine instruction over virtual registers, destination first. The
+/// operations are those of [`Op`], on values of the types of their
+/// registers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Const(Reg, Constant),
+    Move(Reg, Reg),
+    Param(Reg, Argument),
+    Unary(UnaryOp, Reg, Reg),
+    Binary(BinaryOp, Reg, Reg, Reg),
+    Convert(Reg, Reg),
+    Address(Reg, Symbol),
+    Load(Reg, Reg),
+    /// Store the value of the second register at the address in the first
+    Store(Reg, Reg),
+    Call(Option<Reg>, String, Vec<Reg>),
+    /// Load the register from the spill slot
+    Reload(Reg, usize),
+    /// Store the register to the spill slot
+    Spill(usize, Reg),
+    Jump(BlockId),
+    Branch(Reg, BlockId, BlockId),
+    Return(Option<Reg>),
+}
+
+impl Inst {
+    pub fn def(&self) -> Option<Reg> {
+        self.clone().def_mut().copied()
+    }
+
+    pub fn def_mut(&mut self) -> Option<&mut Reg> {
+        match self {
+            Inst::Const(d, _)
+            | Inst::Move(d, _)
+            | Inst::Param(d, _)
+            | Inst::Unary(_, d, _)
+            | Inst::Binary(_, d, ..)
+            | Inst::Convert(d, _)
+            | Inst::Address(d, _)
+            | Inst::Load(d, _)
+            | Inst::Reload(d, _)
+            | Inst::Call(Some(d), ..) => Some(d),
+            Inst::Store(..)
+            | Inst::Call(None, ..)
+            | Inst::Spill(..)
+            | Inst::Jump(_)
+            | Inst::Branch(..)
+            | Inst::Return(_) => None,
+        }
+    }
+
+    pub fn uses(&self) -> Vec<Reg> {
+        self.clone().uses_mut().into_iter().map(|r| *r).collect()
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Reg> {
+        match self {
+            Inst::Const(..)
+            | Inst::Param(..)
+            | Inst::Address(..)
+            | Inst::Reload(..)
+            | Inst::Jump(_)
+            | Inst::Return(None) => vec![],
+            Inst::Move(_, s)
+            | Inst::Unary(_, _, s)
+            | Inst::Convert(_, s)
+            | Inst::Load(_, s)
+            | Inst::Spill(_, s)
+            | Inst::Branch(s, ..)
+            | Inst::Return(Some(s)) => vec![s],
+            Inst::Binary(_, _, a, b) | Inst::Store(a, b) => vec![a, b],
+            Inst::Call(_, _, arguments) => arguments.iter_mut().collect(),
+        }
+    }
+
+    pub fn successors(&self) -> Vec<BlockId> {
+        match *self {
+            Inst::Jump(b) => vec![b],
+            Inst::Branch(_, then, otherwise) => vec![then, otherwise],
+            _ => vec![],
+        }
+    }
+}
+
+/// A function of [`Inst`]s. Each block ends with its only jump, branch or
+/// return.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineFunction {
+    pub name: String,
+    /// The type of each virtual register
+    pub registers: Vec<Ty>,
+    /// The size in bytes of each stack slot of the IR
+    pub slots: Vec<usize>,
+    /// The number of eight-byte spill slots
+    pub spill_slots: usize,
+    pub blocks: Vec<Vec<Inst>>,
+}
+
+impl MachineFunction {
+    pub fn new_register(&mut self, ty: Ty) -> Reg {
+        self.registers.push(ty);
+        self.registers.len() - 1
+    }
+
+    pub fn successors(&self) -> Vec<Vec<BlockId>> {
+        self.blocks
+            .iter()
+            .map(|b| b.last().map_or(vec![], Inst::successors))
+            .collect()
+    }
+}
+
+/// Select the instructions of `f`, which has no phis.
+pub fn select(f: &ir::Function) -> MachineFunction {
+    let mut counts = [0, 0];
+    let mut stack = 0;
+    let arguments: Vec<_> = f
+        .params
+        .iter()
+        .map(|&ty| {
+            let class = Class::of(ty) as usize;
+            let limit = [GENERAL_ARGUMENTS.len(), FLOAT_ARGUMENTS][class];
+            counts[class] += 1;
+            if counts[class] <= limit {
+                Argument::Register(counts[class] - 1)
+            } else {
+                stack += 1;
+                Argument::Stack(stack - 1)
+            }
+        })
+        .collect();
+    let blocks = f
+        .blocks
+        .iter()
+        .map(|block| {
+            let (params, rest): (Vec<_>, Vec<_>) = block
+                .instructions
+                .iter()
+                .partition(|i| matches!(i.op, Op::Param(_)));
+            let mut instructions: Vec<_> = params
+                .into_iter()
+                .chain(rest)
+                .map(|i| {
+                    let d = i.dest;
+                    match &i.op {
+                        Op::Const(c) => Inst::Const(d.unwrap(), *c),
+                        Op::Copy(s) => Inst::Move(d.unwrap(), *s),
+                        Op::Param(n) => Inst::Param(d.unwrap(), arguments[*n]),
+                        Op::Unary(op, s) => Inst::Unary(*op, d.unwrap(), *s),
+                        Op::Binary(op, a, b) => {
+                            Inst::Binary(*op, d.unwrap(), *a, *b)
+                        }
+                        Op::Convert(s) => Inst::Convert(d.unwrap(), *s),
+                        Op::Address(symbol) => {
+                            Inst::Address(d.unwrap(), symbol.clone())
+                        }
+                        Op::Load(a) => Inst::Load(d.unwrap(), *a),
+                        Op::Store(a, v) => Inst::Store(*a, *v),
+                        Op::Call(name, arguments) => {
+                            Inst::Call(d, name.clone(), arguments.clone())
+                        }
+                    }
+                })
+                .collect();
+            instructions.push(match block.terminator {
+                Terminator::Jump(b) => Inst::Jump(b),
+                Terminator::Branch(r, then, otherwise) => {
+                    Inst::Branch(r, then, otherwise)
+                }
+                Terminator::Return(r) => Inst::Return(r),
+            });
+            instructions
+        })
+        .collect();
+    MachineFunction {
+        name: f.name.clone(),
+        registers: f.registers.clone(),
+        slots: f.slots.clone(),
+        spill_slots: 0,
+        blocks,
+    }
+}
+
+/// The assembly for `program`, whose functions have no phis, with the
+/// allocation statistics of each function.
+pub fn compile(
+    program: &Program,
+    allocator: Allocator,
+) -> (String, Vec<(String, Stats)>) {
+    let mut out = String::new();
+    let mut constants = vec![];
+    let mut stats = vec![];
+    if !program.functions.is_empty() {
+        out.push_str("\t.text\n");
+    }
+    for f in &program.functions {
+        let mut f = select(f);
+        let (locations, function_stats) =
+            allocate(&mut f, allocator, [GENERAL.len(), FLOAT.len()]);
+        Emitter::new(&f, &locations, &mut out, &mut constants).function();
+        stats.push((f.name, function_stats));
+    }
+    if !program.globals.is_empty() {
+        out.push_str("\t.data\n");
+    }
+    for g in &program.globals {
+        let size = match Ty::of(&scalar_type(&g.ty)) {
+            Ty::Int => 4,
+            Ty::Double | Ty::Pointer => 8,
+        };
+        writeln!(out, "\t.globl {0}\n\t.align 8\n{0}:", g.name).unwrap();
+        for c in &g.init {
+            match (c, size) {
+                (Constant::Int(n), 4) => writeln!(out, "\t.long {n}"),
+                (Constant::Int(n), _) => writeln!(out, "\t.quad {n}"),
+                (Constant::Double(x), _) => {
+                    writeln!(out, "\t.quad {:#x}", x.to_bits())
+                }
+            }
+            .unwrap();
+        }
+        let rest = g.ty.bytes() - size * g.init.len();
+        if rest > 0 {
+            writeln!(out, "\t.zero {rest}").unwrap();
+        }
+    }
+    if !constants.is_empty() {
+        out.push_str("\t.section .rodata\n\t.align 8\n");
+    }
+    for (n, bits) in constants.iter().enumerate() {
+        writeln!(out, ".Lconstant{n}:\n\t.quad {bits:#x}").unwrap();
+    }
+    // The stack is not executable.
+    out.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    (out, stats)
+}
+
+/// Writes the assembly for one function.
+struct Emitter<'a> {
+    f: &'a MachineFunction,
+    locations: &'a [Location],
+    out: &'a mut String,
+    /// The bits of each `double` constant in `.rodata`
+    constants: &'a mut Vec<u64>,
+    /// The callee-saved registers the function uses
+    saved: Vec<usize>,
+    /// The registers live after each instruction of each block
+    live: Vec<Vec<BTreeSet<Reg>>>,
+    /// The offset from `rbp` of each stack slot of the IR, each spill slot
+    /// and the place each caller-saved register, by class and number, is
+    /// kept during calls
+    slots: Vec<i64>,
+    spill_slots: Vec<i64>,
+    keep: BTreeMap<(usize, usize), i64>,
+    frame_size: i64,
+}
+
+/// The caller-saved registers, by class and number, that hold a register
+/// live across a call that defines `d` and after which `live` are live.
+fn caller_saved(
+    f: &MachineFunction,
+    locations: &[Location],
+    d: Option<Reg>,
+    live: &BTreeSet<Reg>,
+) -> BTreeSet<(usize, usize)> {
+    live.iter()
+        .filter(|&&v| Some(v) != d)
+        .filter_map(|&v| {
+            let Location::Register(r) = locations[v] else {
+                return None;
+            };
+            let class = Class::of(f.registers[v]);
+            (class == Class::Float || r < CALLER_SAVED)
+                .then_some((class as usize, r))
+        })
+        .collect()
+}
+
+impl<'a> Emitter<'a> {
+    fn new(
+        f: &'a MachineFunction,
+        locations: &'a [Location],
+        out: &'a mut String,
+        constants: &'a mut Vec<u64>,
+    ) -> Self {
+        let mut saved: Vec<_> = (0..f.registers.len())
+            .filter_map(|v| match locations[v] {
+                Location::Register(r)
+                    if Class::of(f.registers[v]) == Class::General
+                        && r >= CALLER_SAVED =>
+                {
+                    Some(r)
+                }
+                _ => None,
+            })
+            .collect();
+        saved.sort();
+        saved.dedup();
+        let mut offset = 8 * saved.len() as i64;
+        let mut allocate = |size: usize| {
+            offset += (size as i64 + 7) / 8 * 8;
+            -offset
+        };
+        let slots = f.slots.iter().map(|&size| allocate(size)).collect();
+        let spill_slots = (0..f.spill_slots).map(|_| allocate(8)).collect();
+        let live = live_out(f);
+        let mut kept = BTreeSet::new();
+        for (block, live) in f.blocks.iter().zip(&live) {
+            for (i, live) in block.iter().zip(live) {
+                if let Inst::Call(d, ..) = *i {
+                    kept.extend(caller_saved(f, locations, d, live));
+                }
+            }
+        }
+        let keep = kept.into_iter().map(|r| (r, allocate(8))).collect();
+        let frame_size = (offset + 15) / 16 * 16 - 8 * saved.len() as i64;
+        Emitter {
+            f,
+            locations,
+            out,
+            constants,
+            saved,
+            live,
+            slots,
+            spill_slots,
+            keep,
+            frame_size,
+        }
+    }
+
+    fn emit(&mut self, line: String) {
+        writeln!(self.out, "\t{line}").unwrap();
+    }
+
+    fn ty(&self, v: Reg) -> Ty {
+        self.f.registers[v]
+    }
+
+    /// The `l` or `q` suffix of general instructions on `v`.
+    fn suffix(&self, v: Reg) -> char {
+        match self.ty(v) {
+            Ty::Int => 'l',
+            Ty::Pointer | Ty::Double => 'q',
+        }
+    }
+
+    /// Where `v` is, as wide as its type.
+    fn operand(&self, v: Reg) -> String {
+        self.sized(v, if self.ty(v) == Ty::Int { 1 } else { 0 })
+    }
+
+    /// Where `v` is, with the name of index `width` in [`GENERAL`] if it is
+    /// a general register.
+    fn sized(&self, v: Reg, width: usize) -> String {
+        match self.locations[v] {
+            Location::Register(r) => match Class::of(self.ty(v)) {
+                Class::General => format!("%{}", GENERAL[r][width]),
+                Class::Float => format!("%{}", FLOAT[r]),
+            },
+            Location::Stack(slot) => {
+                format!("{}(%rbp)", self.spill_slots[slot])
+            }
+        }
+    }
+
+    /// The scratch register as wide as `v`.
+    fn scratch(&self, v: Reg) -> &'static str {
+        match self.ty(v) {
+            Ty::Int => "%eax",
+            Ty::Pointer => "%rax",
+            Ty::Double => "%xmm15",
+        }
+    }
+
+    /// The instruction that copies a value of the type of `v`.
+    fn mov(&self, v: Reg) -> &'static str {
+        match self.ty(v) {
+            Ty::Int => "movl",
+            Ty::Pointer => "movq",
+            Ty::Double => "movsd",
+        }
+    }
+
+    /// The instruction that copies all of the register of `v` to or from
+    /// memory.
+    fn mov_register(&self, v: Reg) -> &'static str {
+        match Class::of(self.ty(v)) {
+            Class::General => "movq",
+            Class::Float => "movsd",
+        }
+    }
+
+    fn label(&self, b: BlockId) -> String {
+        format!(".L{}_{b}", self.f.name)
+    }
+
+    fn function(&mut self) {
+        let name = &self.f.name;
+        writeln!(self.out, "\t.globl {name}\n{name}:").unwrap();
+        self.emit("pushq %rbp".to_owned());
+        self.emit("movq %rsp, %rbp".to_owned());
+        for r in self.saved.clone() {
+            self.emit(format!("pushq %{}", GENERAL[r][0]));
+        }
+        if self.frame_size > 0 {
+            self.emit(format!("subq ${}, %rsp", self.frame_size));
+        }
+        let live = std::mem::take(&mut self.live);
+        for (b, block) in self.f.blocks.iter().enumerate() {
+            writeln!(self.out, "{}:", self.label(b)).unwrap();
+            for (i, live) in block.iter().zip(&live[b]) {
+                self.instruction(i, b + 1, live);
+            }
+        }
+    }
+
+    /// Emit `i`, which is followed by block `next`.
+    fn instruction(&mut self, i: &Inst, next: BlockId, live: &BTreeSet<Reg>) {
+        match i {
+            &Inst::Const(d, c) => self.constant(d, c),
+            &Inst::Move(d, s) => self.copy(d, s),
+            &Inst::Param(d, argument) => {
+                let source = match (argument, Class::of(self.ty(d))) {
+                    (Argument::Register(k), Class::General) => {
+                        let width = if self.ty(d) == Ty::Int { 1 } else { 0 };
+                        format!("%{}", GENERAL_ARGUMENTS[k][width])
+                    }
+                    (Argument::Register(k), Class::Float) => format!("%xmm{k}"),
+                    (Argument::Stack(k), _) => format!("{}(%rbp)", 16 + 8 * k),
+                };
+                let (mov, d) = (self.mov(d), self.operand(d));
+                self.emit(format!("{mov} {source}, {d}"));
+            }
+            &Inst::Unary(op, d, s) => self.unary(op, d, s),
+            &Inst::Binary(op, d, a, b) => self.binary(op, d, a, b),
+            &Inst::Convert(d, s) => self.convert(d, s),
+            Inst::Address(d, symbol) => {
+                let address = match symbol {
+                    Symbol::Slot(k) => format!("{}(%rbp)", self.slots[*k]),
+                    Symbol::Global(name) | Symbol::Function(name) => {
+                        format!("{name}(%rip)")
+                    }
+                };
+                let d = self.operand(*d);
+                self.emit(format!("leaq {address}, {d}"));
+            }
+            &Inst::Load(d, a) => {
+                let (mov, d, a) =
+                    (self.mov(d), self.operand(d), self.operand(a));
+                self.emit(format!("{mov} ({a}), {d}"));
+            }
+            &Inst::Store(a, v) => {
+                let (mov, v, a) =
+                    (self.mov(v), self.operand(v), self.operand(a));
+                self.emit(format!("{mov} {v}, ({a})"));
+            }
+            Inst::Call(d, name, arguments) => {
+                self.call(*d, name, arguments, live)
+            }
+            &Inst::Reload(d, slot) => {
+                let mov = self.mov_register(d);
+                let (d, slot) = (self.sized(d, 0), self.spill_slots[slot]);
+                self.emit(format!("{mov} {slot}(%rbp), {d}"));
+            }
+            &Inst::Spill(slot, s) => {
+                let mov = self.mov_register(s);
+                let (s, slot) = (self.sized(s, 0), self.spill_slots[slot]);
+                self.emit(format!("{mov} {s}, {slot}(%rbp)"));
+            }
+            &Inst::Jump(b) => {
+                if b != next {
+                    let label = self.label(b);
+                    self.emit(format!("jmp {label}"));
+                }
+            }
+            &Inst::Branch(c, then, otherwise) => {
+                let (then, otherwise_label) =
+                    (self.label(then), self.label(otherwise));
+                if Class::of(self.ty(c)) == Class::Float {
+                    let c = self.operand(c);
+                    self.emit("xorpd %xmm15, %xmm15".to_owned());
+                    self.emit(format!("ucomisd %xmm15, {c}"));
+                    self.emit(format!("jp {then}"));
+                } else {
+                    let (suffix, c) = (self.suffix(c), self.operand(c));
+                    self.emit(format!("test{suffix} {c}, {c}"));
+                }
+                self.emit(format!("jne {then}"));
+                if otherwise != next {
+                    self.emit(format!("jmp {otherwise_label}"));
+                }
+            }
+            &Inst::Return(r) => {
+                if let Some(r) = r {
+                    let (mov, source) = (self.mov(r), self.operand(r));
+                    let target = match self.ty(r) {
+                        Ty::Int => "%eax",
+                        Ty::Pointer => "%rax",
+                        Ty::Double => "%xmm0",
+                    };
+                    self.emit(format!("{mov} {source}, {target}"));
+                }
+                if self.saved.is_empty() {
+                    self.emit("movq %rbp, %rsp".to_owned());
+                } else {
+                    let offset = 8 * self.saved.len();
+                    self.emit(format!("leaq -{offset}(%rbp), %rsp"));
+                }
+                for r in self.saved.clone().into_iter().rev() {
+                    self.emit(format!("popq %{}", GENERAL[r][0]));
+                }
+                self.emit("popq %rbp".to_owned());
+                self.emit("ret".to_owned());
+            }
+        }
+    }
+
+    fn constant(&mut self, d: Reg, c: Constant) {
+        let d_operand = self.operand(d);
+        match c {
+            Constant::Double(x) if x.to_bits() == 0 => {
+                self.emit(format!("xorpd {d_operand}, {d_operand}"))
+            }
+            Constant::Double(x) => {
+                let n = self.constants.len();
+                self.constants.push(x.to_bits());
+                self.emit(format!("movsd .Lconstant{n}(%rip), {d_operand}"));
+            }
+            Constant::Int(n) => {
+                let mov = self.mov(d);
+                self.emit(format!("{mov} ${n}, {d_operand}"));
+            }
+        }
+    }
+
+    /// Copy `s` to `d` unless they are in the same place.
+    fn copy(&mut self, d: Reg, s: Reg) {
+        if self.locations[d] == self.locations[s] {
+            return;
+        }
+        let mov = match self.ty(d) {
+            Ty::Double => "movapd",
+            _ => self.mov(d),
+        };
+        let (d, s) = (self.operand(d), self.operand(s));
+        self.emit(format!("{mov} {s}, {d}"));
+    }
+
+    /// Set `d` to 1 if the flags satisfy condition `cc` and to 0 otherwise.
+    /// For a float comparison, `parity` says whether an unordered result
+    /// satisfies it.
+    fn set(&mut self, cc: &str, parity: Option<bool>, d: Reg) {
+        self.emit(format!("set{cc} %al"));
+        match parity {
+            Some(true) => {
+                self.emit("setp %cl".to_owned());
+                self.emit("orb %cl, %al".to_owned());
+            }
+            Some(false) => {
+                self.emit("setnp %cl".to_owned());
+                self.emit("andb %cl, %al".to_owned());
+            }
+            None => {}
+        }
+        let d = self.sized(d, 1);
+        self.emit(format!("movzbl %al, {d}"));
+    }
+
+    fn unary(&mut self, op: UnaryOp, d: Reg, s: Reg) {
+        let float = self.ty(s) == Ty::Double;
+        match (op, float) {
+            (UnaryOp::Negate, true) => {
+                let (d, s) = (self.operand(d), self.operand(s));
+                self.emit("xorpd %xmm15, %xmm15".to_owned());
+                self.emit(format!("subsd {s}, %xmm15"));
+                self.emit(format!("movapd %xmm15, {d}"));
+            }
+            (UnaryOp::Not, true) => {
+                let s = self.operand(s);
+                self.emit("xorpd %xmm15, %xmm15".to_owned());
+                self.emit(format!("ucomisd %xmm15, {s}"));
+                self.set("e", Some(false), d);
+            }
+            (UnaryOp::Not, false) => {
+                let (suffix, s) = (self.suffix(s), self.operand(s));
+                self.emit(format!("cmp{suffix} $0, {s}"));
+                self.set("e", None, d);
+            }
+            (UnaryOp::Negate | UnaryOp::Complement, false) => {
+                self.copy(d, s);
+                let name = if op == UnaryOp::Negate { "neg" } else { "not" };
+                let (suffix, d) = (self.suffix(d), self.operand(d));
+                self.emit(format!("{name}{suffix} {d}"));
+            }
+            (UnaryOp::Complement, true) => unreachable!("~ on a double"),
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, d: Reg, a: Reg, b: Reg) {
+        use BinaryOp::*;
+        let float = self.ty(a) == Ty::Double;
+        if op.is_comparison() {
+            return self.compare(op, d, a, b, float);
+        }
+        if op == Divide && !float {
+            let (a, b, d) = (self.operand(a), self.operand(b), self.operand(d));
+            self.emit(format!("movl {a}, %eax"));
+            self.emit("cltd".to_owned());
+            self.emit(format!("idivl {b}"));
+            self.emit(format!("movl %eax, {d}"));
+            return;
+        }
+        let name = match (op, float) {
+            (Add, false) => "add",
+            (Subtract, false) => "sub",
+            (Multiply, false) => "imul",
+            (And, false) => "and",
+            (Or, false) => "or",
+            (Add, true) => "addsd",
+            (Subtract, true) => "subsd",
+            (Multiply, true) => "mulsd",
+            (Divide, true) => "divsd",
+            _ => unreachable!("{op:?} on these types"),
+        };
+        let suffix = if float {
+            String::new()
+        } else {
+            self.suffix(d).to_string()
+        };
+        let same = |x: Reg, y: Reg| self.locations[x] == self.locations[y];
+        if same(d, b) && !same(d, a) {
+            if op.is_commutative() {
+                let (a, d) = (self.operand(a), self.operand(d));
+                self.emit(format!("{name}{suffix} {a}, {d}"));
+            } else {
+                // `d` would overwrite `b` before it is read.
+                let mov = if float { "movapd" } else { self.mov(d) };
+                let scratch = self.scratch(d);
+                let (a, b, d) =
+                    (self.operand(a), self.operand(b), self.operand(d));
+                self.emit(format!("{mov} {a}, {scratch}"));
+                self.emit(format!("{name}{suffix} {b}, {scratch}"));
+                self.emit(format!("{mov} {scratch}, {d}"));
+            }
+            return;
+        }
+        self.copy(d, a);
+        let (b, d) = (self.operand(b), self.operand(d));
+        self.emit(format!("{name}{suffix} {b}, {d}"));
+    }
+
+    fn compare(&mut self, op: BinaryOp, d: Reg, a: Reg, b: Reg, float: bool) {
+        use BinaryOp::*;
+        if float {
+            // `ucomisd` sets the flags like an unsigned comparison, and
+            // only "above" excludes unordered operands, so `<` swaps them.
+            let ((x, y), cc, parity) = match op {
+                Lt => ((b, a), "a", None),
+                Le => ((b, a), "ae", None),
+                Gt => ((a, b), "a", None),
+                Ge => ((a, b), "ae", None),
+                Eq => ((a, b), "e", Some(false)),
+                _ => ((a, b), "ne", Some(true)),
+            };
+            let (x, y) = (self.operand(x), self.operand(y));
+            self.emit(format!("ucomisd {y}, {x}"));
+            return self.set(cc, parity, d);
+        }
+        let pointer = self.ty(a) == Ty::Pointer;
+        let cc = match (op, pointer) {
+            (Lt, false) => "l",
+            (Gt, false) => "g",
+            (Le, false) => "le",
+            (Ge, false) => "ge",
+            (Lt, true) => "b",
+            (Gt, true) => "a",
+            (Le, true) => "be",
+            (Ge, true) => "ae",
+            (Eq, _) => "e",
+            _ => "ne",
+        };
+        let (suffix, a, b) = (self.suffix(a), self.operand(a), self.operand(b));
+        self.emit(format!("cmp{suffix} {b}, {a}"));
+        self.set(cc, None, d);
+    }
+
+    fn convert(&mut self, d: Reg, s: Reg) {
+        let (from, to) = (self.ty(s), self.ty(d));
+        if from == to {
+            return self.copy(d, s);
+        }
+        let (d, s, s32) = (self.operand(d), self.operand(s), self.sized(s, 1));
+        self.emit(match (from, to) {
+            (Ty::Int, Ty::Double) => format!("cvtsi2sdl {s}, {d}"),
+            (Ty::Pointer, Ty::Double) => format!("cvtsi2sdq {s}, {d}"),
+            (Ty::Double, _) => format!("cvttsd2si {s}, {d}"),
+            (Ty::Int, _) => format!("movslq {s}, {d}"),
+            (Ty::Pointer, _) => format!("movl {s32}, {d}"),
+        });
+    }
+
+    /// Pass `arguments` to `name` and put its result in `d`, keeping the
+    /// caller-saved registers in `live` in the frame meanwhile.
+    fn call(
+        &mut self,
+        d: Option<Reg>,
+        name: &str,
+        arguments: &[Reg],
+        live: &BTreeSet<Reg>,
+    ) {
+        let kept: Vec<_> = caller_saved(self.f, self.locations, d, live)
+            .into_iter()
+            .map(|(class, r)| {
+                let name = match class {
+                    0 => ("movq", format!("%{}", GENERAL[r][0])),
+                    _ => ("movsd", format!("%{}", FLOAT[r])),
+                };
+                (name, self.keep[&(class, r)])
+            })
+            .collect();
+        for ((mov, r), offset) in &kept {
+            self.emit(format!("{mov} {r}, {offset}(%rbp)"));
+        }
+
+        let mut counts = [0, 0];
+        let mut registers = vec![];
+        let mut stack = vec![];
+        for &a in arguments {
+            let class = Class::of(self.ty(a)) as usize;
+            let limit = [GENERAL_ARGUMENTS.len(), FLOAT_ARGUMENTS][class];
+            if counts[class] < limit {
+                registers.push((a, counts[class]));
+                counts[class] += 1;
+            } else {
+                stack.push(a);
+            }
+        }
+        // The stack stays aligned to 16 bytes at the call.
+        let padding = stack.len() % 2 * 8;
+        if padding > 0 {
+            self.emit(format!("subq ${padding}, %rsp"));
+        }
+        for &a in stack.iter().rev() {
+            let operand = self.sized(a, 0);
+            match self.locations[a] {
+                Location::Register(_) if self.ty(a) == Ty::Double => {
+                    self.emit("subq $8, %rsp".to_owned());
+                    self.emit(format!("movsd {operand}, (%rsp)"));
+                }
+                _ => self.emit(format!("pushq {operand}")),
+            }
+        }
+        for (a, k) in registers {
+            let (mov, operand) = (self.mov(a), self.operand(a));
+            let target = match self.ty(a) {
+                Ty::Int => format!("%{}", GENERAL_ARGUMENTS[k][1]),
+                Ty::Pointer => format!("%{}", GENERAL_ARGUMENTS[k][0]),
+                Ty::Double => format!("%xmm{k}"),
+            };
+            self.emit(format!("{mov} {operand}, {target}"));
+        }
+        // A variadic callee expects the number of SSE registers in `al`.
+        self.emit(format!("movl ${}, %eax", counts[1]));
+        self.emit(format!("call {name}"));
+        if !stack.is_empty() {
+            self.emit(format!("addq ${}, %rsp", 8 * stack.len() + padding));
+        }
+        for ((mov, r), offset) in &kept {
+            self.emit(format!("{mov} {offset}(%rbp), {r}"));
+        }
+        if let Some(d) = d {
+            let source = match self.ty(d) {
+                Ty::Int => "%eax",
+                Ty::Pointer => "%rax",
+                Ty::Double => "%xmm0",
+            };
+            let (mov, d) = (self.mov(d), self.operand(d));
+            self.emit(format!("{mov} {source}, {d}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        ir::lower,
+        lexer::scan,
+        opt::{optimize, passes},
+        parser::parse,
+    };
+
+    fn compile_source(src: &str) -> String {
+        let lexer_output = scan(src).unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let mut program = lower(&ast, &lexer_output).unwrap();
+        optimize(&mut program, &passes(1), None).unwrap();
+        compile(&program, Allocator::GraphColoring).0
+    }
+
+    /// The lines of the assembly of function `name` in `asm`, without its
+    /// prologue.
+    fn body<'a>(asm: &'a str, name: &str) -> Vec<&'a str> {
+        let start = format!(".L{name}_0:");
+        let lines = asm.lines().skip_while(|l| *l != start).skip(1);
+        lines.take_while(|l| *l != "\tret").collect()
+    }
+
+    #[test]
+    fn test_compile() {
+        assert_eq!(
+            compile_source(
+                "int g[2] = {7}; double h = 0.5; \
+                 int f(int a, int b) { return (a - b) * g[1]; }"
+            ),
+            "\
+\t.text
+\t.globl f
+f:
+\tpushq %rbp
+\tmovq %rsp, %rbp
+\tpushq %rbx
+\tsubq $8, %rsp
+.Lf_0:
+\tmovl %edi, %ebx
+\tmovl %esi, %r10d
+\tsubl %r10d, %ebx
+\tleaq g(%rip), %r11
+\tmovl $4, %r10d
+\tmovslq %r10d, %r10
+\taddq %r11, %r10
+\tmovl (%r10), %r10d
+\timull %ebx, %r10d
+\tmovl %r10d, %eax
+\tleaq -8(%rbp), %rsp
+\tpopq %rbx
+\tpopq %rbp
+\tret
+\t.data
+\t.globl g
+\t.align 8
+g:
+\t.long 7
+\t.long 0
+\t.globl h
+\t.align 8
+h:
+\t.quad 0x3fe0000000000000
+\t.section .note.GNU-stack,\"\",@progbits
+"
+        );
+    }
+
+    #[test]
+    fn test_select_params_first() {
+        let lexer_output =
+            scan("int f(int a) { int b = a; return b; }").unwrap();
+        let ast = parse(&lexer_output.tokens).unwrap();
+        let mut program = lower(&ast, &lexer_output).unwrap();
+        optimize(&mut program, &[], None).unwrap();
+        let f = select(&program.functions[0]);
+        assert!(matches!(
+            f.blocks[0][0],
+            Inst::Param(_, Argument::Register(0))
+        ));
+    }
+
+    #[test]
+    fn test_stack_arguments() {
+        let asm = compile_source(
+            "int g(int a, int b, int c, int d, int e, int f, int h) \
+             { return h; } \
+             int f(int a) { return g(a, a, a, a, a, a, a + 1) + a; }",
+        );
+        assert_eq!(
+            body(&asm, "g"),
+            [
+                "\tmovl 16(%rbp), %r10d",
+                "\tmovl %r10d, %eax",
+                "\tmovq %rbp, %rsp",
+                "\tpopq %rbp"
+            ]
+        );
+        let call = body(&asm, "f");
+        let call: Vec<_> = call
+            .iter()
+            .skip_while(|l| !l.contains("subq"))
+            .take_while(|l| !l.contains("call"))
+            .collect();
+        // One argument on the stack, after eight bytes keeping it aligned
+        assert_eq!(call[0], &"\tsubq $8, %rsp");
+        assert!(call[1].starts_with("\tpushq %r"));
+        assert!(asm.contains("\tcall g\n\taddq $16, %rsp\n"));
+    }
+
+    #[test]
+    fn test_keep_across_call() {
+        let asm = compile_source(
+            "int g(int a) { return a; } \
+             int f(int a) { double x = a; return g(a) + x; }",
+        );
+        let f = body(&asm, "f");
+        let call = f.iter().position(|l| *l == "\tcall g").unwrap();
+        // `x` is in an SSE register, which the callee may change.
+        assert!(f[..call].iter().any(|l| l.starts_with("\tmovsd %xmm")));
+        assert!(f[call..].iter().any(|l| l.starts_with("\tmovsd -")));
+    }
+}
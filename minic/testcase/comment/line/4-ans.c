@@ -1 +1 @@
-int a = 0; 
\ No newline at end of file
+int a = 0;        
\ No newline at end of file
@@ -1,2 +1,2 @@
-
+      
 int a = 0;
\ No newline at end of file
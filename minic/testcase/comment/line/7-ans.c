@@ -1,2 +1,3 @@
-
+            
 int a = 0;
+            
\ No newline at end of file
@@ -0,0 +1,52 @@
+//! The library as other crates use it.
+
+use minic::{
+    ast::{ExternalDeclaration, Visitor},
+    diagnostic::Diagnostic,
+    error::ErrorKind,
+    lexer::scan,
+    parser::parse,
+    preprocess::preprocess,
+    resolve::resolve,
+    token::Span,
+};
+
+#[test]
+fn test_front_end() {
+    let src = "int f(int a) { /* comment */ return a + b; }";
+    let preprocessed = preprocess(src.char_indices()).unwrap();
+    let lexer_output = scan(&preprocessed).unwrap();
+    let ast = parse(&lexer_output.tokens).unwrap();
+    let ExternalDeclaration::FunctionDeclaration(f) =
+        &ast.external_declarations[0]
+    else {
+        panic!("expect a function");
+    };
+    let (id, _) = f.declarator.identifier();
+    assert_eq!(lexer_output.name(id), "f");
+
+    struct Count(usize);
+    impl Visitor for Count {
+        fn visit_identifier(&mut self, _id: &usize, _span: Span) {
+            self.0 += 1;
+        }
+    }
+    let mut count = Count(0);
+    count.visit_translation_unit(&ast);
+    assert_eq!(count.0, 4);
+
+    let errors = resolve(&ast, &lexer_output);
+    assert!(matches!(
+        &errors[..],
+        [e] if matches!(&e.error_kind, ErrorKind::UndeclaredIdentifier(name) if name == "b")
+    ));
+    let diagnostic: Diagnostic = (&errors[0]).into();
+    assert_eq!(diagnostic.code, "E0013");
+}
+
+#[test]
+fn test_parse_error() {
+    let lexer_output = scan("int a").unwrap();
+    let e = parse(&lexer_output.tokens).unwrap_err();
+    assert_eq!(e.error_kind.code(), "E0012");
+}
@@ -0,0 +1,206 @@
+//! `minic lsp` driven by a scripted JSON-RPC client.
+
+use std::{
+    io::BufReader,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use minic::lsp::{read_message, write_message};
+use serde_json::{json, Value};
+
+const URI: &str = "file:///test.c";
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn spawn() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_minic"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Client {
+            child,
+            stdin,
+            stdout,
+            next_id: 0,
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        write_message(&mut self.stdin, &message).unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        read_message(&mut self.stdout).unwrap().unwrap()
+    }
+
+    /// Send a request and return its response.
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        });
+        write_message(&mut self.stdin, &message).unwrap();
+        let response = self.receive();
+        assert_eq!(response["id"], self.next_id);
+        response
+    }
+
+    /// The diagnostics published after a change.
+    fn diagnostics(&mut self) -> Vec<Value> {
+        let message = self.receive();
+        assert_eq!(message["method"], "textDocument/publishDiagnostics");
+        assert_eq!(message["params"]["uri"], URI);
+        message["params"]["diagnostics"].as_array().unwrap().clone()
+    }
+}
+
+fn at(line: u32, character: u32) -> Value {
+    json!({
+        "textDocument": { "uri": URI },
+        "position": { "line": line, "character": character },
+    })
+}
+
+fn range(line: u32, start: u32, end: u32) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+#[test]
+fn test_session() {
+    let mut client = Client::spawn();
+    let response = client.request("initialize", json!({ "capabilities": {} }));
+    let capabilities = &response["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 2);
+    assert_eq!(capabilities["hoverProvider"], true);
+    client.notify("initialized", json!({}));
+
+    let src = "int a[2];\nint f(int x)\n{\n    return a[x] + b;\n}\n";
+    client.notify(
+        "textDocument/didOpen",
+        json!({ "textDocument": {
+            "uri": URI,
+            "languageId": "c",
+            "version": 1,
+            "text": src,
+        } }),
+    );
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "E0013");
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["range"], range(3, 18, 19));
+
+    let response = client.request("textDocument/definition", at(3, 11));
+    assert_eq!(
+        response["result"],
+        json!({ "uri": URI, "range": range(0, 4, 5) })
+    );
+
+    let mut params = at(3, 13);
+    params["context"] = json!({ "includeDeclaration": true });
+    let response = client.request("textDocument/references", params.clone());
+    let ranges: Vec<_> = response["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| &l["range"])
+        .collect();
+    assert_eq!(ranges, [&range(1, 10, 11), &range(3, 13, 14)]);
+    params["context"]["includeDeclaration"] = json!(false);
+    let response = client.request("textDocument/references", params);
+    assert_eq!(response["result"].as_array().unwrap().len(), 1);
+
+    let response = client.request("textDocument/hover", at(3, 12));
+    assert_eq!(
+        response["result"]["contents"]["value"],
+        "```c\nint a[2]\n```"
+    );
+    let response = client.request("textDocument/hover", at(2, 0));
+    assert_eq!(response["result"], Value::Null);
+
+    let response = client.request(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    let symbols: Vec<_> = response["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["name"].clone(), s["kind"].clone(), s["detail"].clone()))
+        .collect();
+    assert_eq!(
+        symbols,
+        [
+            (json!("a"), json!(13), json!("int a[2]")),
+            (json!("f"), json!(12), json!("int f(int x)")),
+        ]
+    );
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [
+                { "range": range(3, 15, 19), "text": "" },
+                { "range": range(0, 6, 7), "text": "3" },
+            ],
+        }),
+    );
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+    let response = client.request("textDocument/hover", at(3, 11));
+    assert_eq!(
+        response["result"]["contents"]["value"],
+        "```c\nint a[3]\n```"
+    );
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 3 },
+            "contentChanges": [{ "text": "int a" }],
+        }),
+    );
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics[0]["code"], "E0012");
+
+    let response = client.request("textDocument/formatting", json!({}));
+    assert_eq!(response["error"]["code"], -32601);
+    let response = client.request(
+        "textDocument/hover",
+        json!({ "textDocument": { "uri": "file:///closed.c" } }),
+    );
+    assert_eq!(response["error"]["code"], -32602);
+
+    let response = client.request("shutdown", Value::Null);
+    assert_eq!(response["result"], Value::Null);
+    client.notify("exit", Value::Null);
+    assert!(client.child.wait().unwrap().success());
+}
+
+#[test]
+fn test_exit_without_shutdown() {
+    let mut client = Client::spawn();
+    client.notify("exit", Value::Null);
+    assert_eq!(client.child.wait().unwrap().code(), Some(1));
+}
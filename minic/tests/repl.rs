@@ -0,0 +1,62 @@
+//! `minic repl` fed from a pipe.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Run `minic repl` on `input`, returning stdout and stderr.
+fn repl(input: &str) -> (String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minic"))
+        .arg("repl")
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_session() {
+    let input = "\
+int a[3] = {1, 2, 3};
+int sum(int *p, int n) {
+    int s = 0;
+    while (n > 0) { s = s + *p; p = p + 1; n = n - 1; }
+    return s;
+}
+sum(a, 3)
+a
+:type sum
+1 / 0
+:quit
+a
+";
+    let (stdout, stderr) = repl(input);
+    assert_eq!(stdout, "(int) 6\n(int[3]) {1, 2, 3}\nint (int *, int)\n");
+    assert!(stderr.starts_with("<repl>:10:1: error[E0016]"), "{stderr}");
+}
+
+#[test]
+fn test_deep_recursion() {
+    let input = "\
+int depth(int n) { if (n == 0) return 0; return 1 + depth(n - 1); }
+depth(999)
+";
+    let (stdout, stderr) = repl(input);
+    assert_eq!(stdout, "(int) 999\n");
+    assert_eq!(stderr, "");
+}